@@ -1,6 +1,12 @@
 import { PublicKey, TransactionInstruction } from '@solana/web3.js';
-import { OrderSide, OrderType, SelfTradeBehavior } from './state';
-import { Numberu128, Numberu16, Numberu32, Numberu64 } from './utils';
+import { OrderSide, OrderType, PoolMode, SelfTradeBehavior } from './state';
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  Numberu128,
+  Numberu16,
+  Numberu32,
+  Numberu64,
+} from './utils';
 import { BN } from 'bn.js';
 import bs58 from 'bs58';
 
@@ -13,23 +19,70 @@ export enum Instruction {
   SettleFunds,
   Redeem,
   CollectFees,
+  PublishStateHash,
+  RedeemUnwrapSol,
+  UpdateFeeRatio,
+  ValidateOrderAccounts,
+  RecreateAssetAccount,
+  SetPoolMode,
+  EmergencyWithdrawAsset,
+  Snapshot,
+  AddAllowedMint,
+  RemoveAllowedMint,
+  ToggleLock,
+  ChangeSignalProvider,
+  RedeemSingle,
+  AddMarket,
+  UpdateFeePeriod,
+  DepositExact,
+  CancelAllOrders,
+  GetPoolInfo,
+  ClosePool,
+  ConsumeEvents,
+  SetDepositCap,
+  SweepUntrackedAsset,
+  SetExtraFeeRecipient,
+  DepositAndLock,
+  SweepDust,
+  UpdateDexProgram,
+  InitOpenOrders,
+  SetSingleAssetDeposits,
+  DepositSingle,
+  SetMetadata,
+  SetReferrerFee,
 }
 
+// Packed in place of a SettleFunds pool asset index to leave its side out
+export const SETTLE_SKIPPED_INDEX = new BN('ffffffffffffffff', 16);
+export const DEFAULT_POOL_TOKEN_DECIMALS = 6;
+// Index of the pool account in the accounts of a deposit
+const DEPOSIT_POOL_ACCOUNT_INDEX = 6;
+
 export interface InitInstructionData {
   poolSeed: string;
   maxNumberOfAssets: number;
   numberOfMarkets: number;
+  decimals: number;
 }
 export interface CreateInstructionData {
   poolSeed: string;
   feeCollectionPeriod: number;
   feeRatio: number;
+  depositFeeRatio: number;
+  rebateRetentionRatio: number;
+  poolMode: PoolMode;
+  redeemRequiresProvider: boolean;
+  maturityTimestamp: number;
+  // The signal provider and Bonfida fee account shares of the fees in basis points, null
+  // when the pool gets the default split
+  feeSplitBps: [number, number] | null;
   depositAmounts: number[];
   markets: PublicKey[];
 }
 export interface DepositInstructionData {
   poolSeed: Buffer;
   poolTokenAmount: number;
+  withReferrer: boolean;
 }
 
 export interface CreateOrderInstructionData {
@@ -47,6 +100,7 @@ export interface CreateOrderInstructionData {
   pcLotSize: number;
   targetMint: string;
   serumLimit: number;
+  minExpectedLots: number;
 }
 
 export interface CancelOrderInstructionData {
@@ -57,8 +111,9 @@ export interface CancelOrderInstructionData {
 
 export interface SettleFundsInstructionData {
   poolSeed: string;
-  pcIndex: OrderSide;
-  coinIndex: number;
+  // Null when the side is left out of the settlement
+  pcIndex: number | null;
+  coinIndex: number | null;
 }
 
 export interface RedeemInstructionData {
@@ -102,10 +157,14 @@ export function decodeInstruction(
         'le',
       ).toNumber();
       offset += 2;
+      // The decimals can be omitted from the instruction data
+      let decimals =
+        offset < buffer.length ? buffer[offset] : DEFAULT_POOL_TOKEN_DECIMALS;
       return {
         poolSeed: bs58.encode(poolSeed),
         maxNumberOfAssets,
         numberOfMarkets,
+        decimals,
       };
     }
     case Instruction.Create: {
@@ -122,13 +181,38 @@ export function decodeInstruction(
       let feeRatio =
         new BN(buffer.slice(offset, offset + 2), 'le').toNumber() / 2 ** 16;
       offset += 2;
+      let depositFeeRatio =
+        new BN(buffer.slice(offset, offset + 2), 'le').toNumber() / 2 ** 16;
+      offset += 2;
+      let rebateRetentionRatio =
+        new BN(buffer.slice(offset, offset + 2), 'le').toNumber() / 2 ** 16;
+      offset += 2;
+      let poolMode: PoolMode = buffer[offset];
+      offset++;
+      let redeemRequiresProvider = buffer[offset] != 0;
+      offset++;
+      let maturityTimestamp = new BN(
+        buffer.slice(offset, offset + 8),
+        'le',
+      ).toNumber();
+      offset += 8;
       let markets: PublicKey[] = [];
       for (let i = 0; i < numberOfMarkets; i++) {
         markets.push(new PublicKey(buffer.slice(offset, offset + 32)));
         offset += 32;
       }
+      // The fee split is packed after the deposit amounts when set
+      let end = buffer.length;
+      let feeSplitBps: [number, number] | null = null;
+      if ((end - offset) % 8 == 4) {
+        end -= 4;
+        feeSplitBps = [
+          new BN(buffer.slice(end, end + 2), 'le').toNumber(),
+          new BN(buffer.slice(end + 2, end + 4), 'le').toNumber(),
+        ];
+      }
       let depositAmounts: number[] = [];
-      while (offset < buffer.length) {
+      while (offset < end) {
         depositAmounts.push(
           new BN(buffer.slice(offset, offset + 8), 'le').toNumber(),
         );
@@ -139,6 +223,12 @@ export function decodeInstruction(
         poolSeed: bs58.encode(poolSeed),
         feeCollectionPeriod,
         feeRatio,
+        depositFeeRatio,
+        rebateRetentionRatio,
+        poolMode,
+        redeemRequiresProvider,
+        maturityTimestamp,
+        feeSplitBps,
         depositAmounts,
         markets,
       };
@@ -148,9 +238,12 @@ export function decodeInstruction(
         buffer.slice(offset, offset + 8),
         'le',
       ).toNumber();
+      offset += 8;
+      let withReferrer = offset < buffer.length && buffer[offset] == 1;
       return {
         poolSeed: bs58.encode(poolSeed),
         poolTokenAmount,
+        withReferrer,
       };
     }
     case Instruction.CreateOrder: {
@@ -202,6 +295,12 @@ export function decodeInstruction(
         'le',
       ).toNumber();
       offset += 2;
+      // The minimum number of lots can be omitted from the instruction data
+      let minExpectedLots =
+        offset < buffer.length
+          ? new BN(buffer.slice(offset, offset + 8), 'le').toNumber()
+          : 0;
+      offset += 8;
 
       return {
         poolSeed: bs58.encode(poolSeed),
@@ -218,6 +317,7 @@ export function decodeInstruction(
         pcLotSize,
         targetMint: targetMint.toBase58(),
         serumLimit,
+        minExpectedLots,
       };
     }
     case Instruction.CancelOrder: {
@@ -233,10 +333,16 @@ export function decodeInstruction(
       };
     }
     case Instruction.SettleFunds: {
-      let pcIndex = new BN(buffer.slice(offset, offset + 8), 'le').toNumber();
+      let pcIndexBN = new BN(buffer.slice(offset, offset + 8), 'le');
       offset += 8;
-      let coinIndex = new BN(buffer.slice(offset, offset + 8), 'le').toNumber();
+      let coinIndexBN = new BN(buffer.slice(offset, offset + 8), 'le');
       offset += 8;
+      let pcIndex = pcIndexBN.eq(SETTLE_SKIPPED_INDEX)
+        ? null
+        : pcIndexBN.toNumber();
+      let coinIndex = coinIndexBN.eq(SETTLE_SKIPPED_INDEX)
+        ? null
+        : coinIndexBN.toNumber();
       return {
         poolSeed: bs58.encode(poolSeed),
         pcIndex,
@@ -274,6 +380,7 @@ export function initInstruction(
   poolSeed: Array<Buffer | Uint8Array>,
   maxNumberOfAssets: number,
   number_of_markets: Numberu16,
+  decimals: number = DEFAULT_POOL_TOKEN_DECIMALS,
 ): TransactionInstruction {
   let buffers = [
     Buffer.from(Int8Array.from([0])),
@@ -281,6 +388,7 @@ export function initInstruction(
     // @ts-ignore
     new Numberu32(maxNumberOfAssets).toBuffer(),
     number_of_markets.toBuffer(),
+    Buffer.from(Int8Array.from([decimals])),
   ];
 
   const data = Buffer.concat(buffers);
@@ -341,6 +449,14 @@ export function createInstruction(
   markets: Array<PublicKey>,
   feeCollectionPeriod: Numberu64,
   feeRatio: Numberu16,
+  depositFeeRatio: Numberu16,
+  rebateRetentionRatio: Numberu16,
+  poolMode: PoolMode,
+  redeemRequiresProvider: boolean,
+  maturityTimestamp: Numberu64,
+  // The signal provider and Bonfida fee account shares of the fees in basis points, the
+  // pool gets the default 1/2, 1/4, 1/4 split when null
+  feeSplitBps: [Numberu16, Numberu16] | null,
 ): TransactionInstruction {
   let buffers = [
     Buffer.from(Int8Array.from([1])),
@@ -349,6 +465,11 @@ export function createInstruction(
     new Numberu16(markets.length).toBuffer(),
     feeCollectionPeriod.toBuffer(),
     feeRatio.toBuffer(),
+    depositFeeRatio.toBuffer(),
+    rebateRetentionRatio.toBuffer(),
+    Buffer.from(Int8Array.from([poolMode])),
+    Buffer.from(Int8Array.from([redeemRequiresProvider ? 1 : 0])),
+    maturityTimestamp.toBuffer(),
   ];
   for (var market of markets) {
     // @ts-ignore
@@ -358,6 +479,9 @@ export function createInstruction(
     // @ts-ignore
     buffers.push(new Numberu64(amount).toBuffer());
   }
+  if (!!feeSplitBps) {
+    buffers.push(feeSplitBps[0].toBuffer(), feeSplitBps[1].toBuffer());
+  }
 
   const data = Buffer.concat(buffers);
   const keys = [
@@ -436,6 +560,10 @@ export function depositInstruction(
   targetPoolTokenKey: PublicKey,
   sourceOwnerKey: PublicKey,
   sourceAssetKeys: Array<PublicKey>,
+  // The referrer pooltoken account, rewarded on pools with a referrer fee
+  referrerPoolTokenKey: PublicKey | null,
+  // The pooltoken account of the extra fee recipient, when the pool has one
+  extraFeePoolTokenKey: PublicKey | null,
   poolSeed: Array<Buffer | Uint8Array>,
   poolTokenAmount: Numberu64,
 ): TransactionInstruction {
@@ -445,6 +573,9 @@ export function depositInstruction(
     // @ts-ignore
     poolTokenAmount.toBuffer(),
   ];
+  if (!!referrerPoolTokenKey) {
+    buffers.push(Buffer.from(Int8Array.from([1])));
+  }
 
   const data = Buffer.concat(buffers);
   const keys = [
@@ -503,6 +634,20 @@ export function depositInstruction(
       isWritable: true,
     });
   }
+  if (!!referrerPoolTokenKey) {
+    keys.push({
+      pubkey: referrerPoolTokenKey,
+      isSigner: false,
+      isWritable: true,
+    });
+  }
+  if (!!extraFeePoolTokenKey) {
+    keys.push({
+      pubkey: extraFeePoolTokenKey,
+      isSigner: false,
+      isWritable: true,
+    });
+  }
 
   return new TransactionInstruction({
     keys,
@@ -530,6 +675,7 @@ export function createOrderInstruction(
   dexProgramKey: PublicKey,
   rentProgramId: PublicKey,
   srmReferrerKey: PublicKey | null,
+  allowedMintsKey: PublicKey,
   poolSeed: Array<Buffer | Uint8Array>,
   side: OrderSide,
   limitPrice: Numberu64,
@@ -542,6 +688,8 @@ export function createOrderInstruction(
   clientId: Numberu64,
   selfTradeBehavior: SelfTradeBehavior,
   serumLimit: Numberu16,
+  // Minimum number of lots the order should trade, zero for no minimum
+  minExpectedLots: Numberu64,
 ): TransactionInstruction {
   let buffers = [
     Buffer.from(Int8Array.from([3])),
@@ -559,6 +707,7 @@ export function createOrderInstruction(
     pc_lot_size.toBuffer(),
     target_mint.toBuffer(),
     serumLimit.toBuffer(),
+    minExpectedLots.toBuffer(),
   ];
   const data = Buffer.concat(buffers);
 
@@ -641,6 +790,11 @@ export function createOrderInstruction(
       isWritable: true,
     });
   }
+  keys.push({
+    pubkey: allowedMintsKey,
+    isSigner: false,
+    isWritable: false,
+  });
 
   return new TransactionInstruction({
     keys,
@@ -705,7 +859,7 @@ export function cancelOrderInstruction(
     {
       pubkey: poolKey,
       isSigner: false,
-      isWritable: false,
+      isWritable: true,
     },
     {
       pubkey: dexProgramKey,
@@ -735,15 +889,18 @@ export function settleFundsInstruction(
   splTokenProgramId: PublicKey,
   dexProgramKey: PublicKey,
   srmReferrerKey: PublicKey | null,
+  // The signal provider's associated pc wallet, required along with the referrer
+  signalProviderPcKey: PublicKey | null,
   poolSeed: Array<Buffer | Uint8Array>,
-  pcPoolAssetIndex: Numberu64,
-  coinPoolAssetIndex: Numberu64,
+  // A null index leaves its side out of the settlement
+  pcPoolAssetIndex: Numberu64 | null,
+  coinPoolAssetIndex: Numberu64 | null,
 ): TransactionInstruction {
   let buffers = [
     Buffer.from(Int8Array.from([5])),
     Buffer.concat(poolSeed),
-    pcPoolAssetIndex.toBuffer(),
-    coinPoolAssetIndex.toBuffer(),
+    (pcPoolAssetIndex || SETTLE_SKIPPED_INDEX).toArrayLike(Buffer, 'le', 8),
+    (coinPoolAssetIndex || SETTLE_SKIPPED_INDEX).toArrayLike(Buffer, 'le', 8),
   ];
   const data = Buffer.concat(buffers);
 
@@ -811,6 +968,13 @@ export function settleFundsInstruction(
       isWritable: true,
     });
   }
+  if (!!signalProviderPcKey) {
+    keys.push({
+      pubkey: signalProviderPcKey,
+      isSigner: false,
+      isWritable: true,
+    });
+  }
 
   return new TransactionInstruction({
     keys,
@@ -829,6 +993,8 @@ export function redeemInstruction(
   sourcePoolTokenOwnerKey: PublicKey,
   sourcePoolTokenKey: PublicKey,
   targetAssetKeys: Array<PublicKey>,
+  // Signal provider co-signing the redemption, for pools requiring it before maturity
+  signalProviderKey: PublicKey | null,
   poolSeed: Array<Buffer | Uint8Array>,
   poolTokenAmount: Numberu64,
 ): TransactionInstruction {
@@ -886,6 +1052,13 @@ export function redeemInstruction(
       isWritable: true,
     });
   }
+  if (!!signalProviderKey) {
+    keys.push({
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    });
+  }
 
   return new TransactionInstruction({
     keys,
@@ -903,6 +1076,8 @@ export function collectFeesInstruction(
   signalProviderPoolTokenKey: PublicKey,
   bonfidaFeePoolTokenKey: PublicKey,
   bonfidaBnBPTKey: PublicKey,
+  // The pooltoken account of the extra fee recipient, when the pool has one
+  extraFeePoolTokenKey: PublicKey | null,
   poolSeed: Array<Buffer | Uint8Array>,
 ): TransactionInstruction {
   let buffers = [Buffer.from(Int8Array.from([7])), Buffer.concat(poolSeed)];
@@ -945,6 +1120,1327 @@ export function collectFeesInstruction(
       isWritable: true,
     },
   ];
+  if (!!extraFeePoolTokenKey) {
+    keys.push({
+      pubkey: extraFeePoolTokenKey,
+      isSigner: false,
+      isWritable: true,
+    });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function publishStateHashInstruction(
+  bonfidaBotProgramId: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([8])), Buffer.concat(poolSeed)];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function redeemUnwrapSolInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  clockSysvarKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKeys: Array<PublicKey>,
+  sourcePoolTokenOwnerKey: PublicKey,
+  sourcePoolTokenKey: PublicKey,
+  targetAssetKeys: Array<PublicKey>,
+  signalProviderKey: PublicKey | null,
+  poolSeed: Array<Buffer | Uint8Array>,
+  poolTokenAmount: Numberu64,
+): TransactionInstruction {
+  let instruction = redeemInstruction(
+    splTokenProgramId,
+    bonfidaBotProgramId,
+    clockSysvarKey,
+    mintKey,
+    poolKey,
+    poolAssetKeys,
+    sourcePoolTokenOwnerKey,
+    sourcePoolTokenKey,
+    targetAssetKeys,
+    signalProviderKey,
+    poolSeed,
+    poolTokenAmount,
+  );
+  instruction.data[0] = Instruction.RedeemUnwrapSol;
+  // The owner receives the unwrapped lamports
+  instruction.keys[3].isWritable = true;
+  return instruction;
+}
+
+export function updateFeeRatioInstruction(
+  bonfidaBotProgramId: PublicKey,
+  clockSysvarKey: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  newFeeRatio: Numberu16,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([10])),
+    Buffer.concat(poolSeed),
+    newFeeRatio.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: clockSysvarKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function validateOrderAccountsInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  market: PublicKey,
+  payerPoolAssetKey: PublicKey,
+  poolKey: PublicKey,
+  dexProgramKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  marketIndex: Numberu16,
+  payerPoolAssetIndex: Numberu64,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([11])),
+    Buffer.concat(poolSeed),
+    marketIndex.toBuffer(),
+    payerPoolAssetIndex.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: market,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: payerPoolAssetKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: dexProgramKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function recreateAssetAccountInstruction(
+  bonfidaBotProgramId: PublicKey,
+  systemProgramId: PublicKey,
+  splTokenProgramId: PublicKey,
+  rentProgramId: PublicKey,
+  fundingKey: PublicKey,
+  poolAssetKey: PublicKey,
+  poolKey: PublicKey,
+  assetMintKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  assetIndex: Numberu64,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([12])),
+    Buffer.concat(poolSeed),
+    assetIndex.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: systemProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: splTokenProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: rentProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: ASSOCIATED_TOKEN_PROGRAM_ID,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: fundingKey,
+      isSigner: true,
+      isWritable: true,
+    },
+    {
+      pubkey: poolAssetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: assetMintKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function setPoolModeInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  poolMode: PoolMode,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetPoolMode,
+    poolSeed,
+    Buffer.from(Int8Array.from([poolMode])),
+  );
+}
+
+export function emergencyWithdrawAssetInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKey: PublicKey,
+  sourcePoolTokenOwnerKey: PublicKey,
+  sourcePoolTokenKey: PublicKey,
+  targetAssetKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  assetIndex: Numberu64,
+  poolTokenAmount: Numberu64,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([14])),
+    Buffer.concat(poolSeed),
+    assetIndex.toBuffer(),
+    poolTokenAmount.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: splTokenProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: sourcePoolTokenOwnerKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: sourcePoolTokenKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: poolAssetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: targetAssetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function snapshotInstruction(
+  bonfidaBotProgramId: PublicKey,
+  systemProgramId: PublicKey,
+  clockSysvarKey: PublicKey,
+  rentProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  navSnapshotsKey: PublicKey,
+  mintKey: PublicKey,
+  poolAssetKeys: Array<PublicKey>,
+  poolSeed: Array<Buffer | Uint8Array>,
+  prices: Array<Numberu64>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([15])), Buffer.concat(poolSeed)];
+  for (var price of prices) {
+    buffers.push(price.toBuffer());
+  }
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: systemProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: clockSysvarKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: rentProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: navSnapshotsKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+  for (var poolAsset of poolAssetKeys) {
+    keys.push({
+      pubkey: poolAsset,
+      isSigner: false,
+      isWritable: false,
+    });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function addAllowedMintInstruction(
+  bonfidaBotProgramId: PublicKey,
+  systemProgramId: PublicKey,
+  rentProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  allowedMintsKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  mint: PublicKey,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([16])),
+    Buffer.concat(poolSeed),
+    mint.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: systemProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: rentProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: allowedMintsKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function removeAllowedMintInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  allowedMintsKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  mint: PublicKey,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([17])),
+    Buffer.concat(poolSeed),
+    mint.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: allowedMintsKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function toggleLockInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.ToggleLock,
+    poolSeed,
+    Buffer.alloc(0),
+  );
+}
+
+export function changeSignalProviderInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  newSignalProviderKey: PublicKey,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.ChangeSignalProvider,
+    poolSeed,
+    newSignalProviderKey.toBuffer(),
+  );
+}
+
+export function redeemSingleInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  clockSysvarKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKey: PublicKey,
+  sourcePoolTokenOwnerKey: PublicKey,
+  sourcePoolTokenKey: PublicKey,
+  targetAssetKey: PublicKey,
+  // The pool asset accounts of the other assets of the pool
+  otherPoolAssetKeys: Array<PublicKey>,
+  // Signal provider co-signing the redemption, for pools requiring it before maturity
+  signalProviderKey: PublicKey | null,
+  poolSeed: Array<Buffer | Uint8Array>,
+  poolTokenAmount: Numberu64,
+  assetIndex: Numberu64,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([20])),
+    Buffer.concat(poolSeed),
+    poolTokenAmount.toBuffer(),
+    assetIndex.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: splTokenProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: clockSysvarKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: sourcePoolTokenOwnerKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: sourcePoolTokenKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: poolAssetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: targetAssetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+  for (var otherPoolAsset of otherPoolAssetKeys) {
+    keys.push({
+      pubkey: otherPoolAsset,
+      isSigner: false,
+      isWritable: false,
+    });
+  }
+  if (!!signalProviderKey) {
+    keys.push({
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function addMarketInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  market: PublicKey,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.AddMarket,
+    poolSeed,
+    market.toBuffer(),
+  );
+}
+
+export function updateFeePeriodInstruction(
+  bonfidaBotProgramId: PublicKey,
+  clockSysvarKey: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  newPeriod: Numberu64,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([22])),
+    Buffer.concat(poolSeed),
+    newPeriod.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: clockSysvarKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function depositExactInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  sigProviderFeeReceiverKey: PublicKey,
+  bonfidaFeeReceiverKey: PublicKey,
+  bonfidaBuyAndBurnKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKeys: Array<PublicKey>,
+  targetPoolTokenKey: PublicKey,
+  sourceOwnerKey: PublicKey,
+  sourceAssetKeys: Array<PublicKey>,
+  extraFeePoolTokenKey: PublicKey | null,
+  poolSeed: Array<Buffer | Uint8Array>,
+  assetAmounts: Array<Numberu64>,
+  toleranceBps: Numberu16,
+): TransactionInstruction {
+  let instruction = depositInstruction(
+    splTokenProgramId,
+    bonfidaBotProgramId,
+    sigProviderFeeReceiverKey,
+    bonfidaFeeReceiverKey,
+    bonfidaBuyAndBurnKey,
+    mintKey,
+    poolKey,
+    poolAssetKeys,
+    targetPoolTokenKey,
+    sourceOwnerKey,
+    sourceAssetKeys,
+    null,
+    extraFeePoolTokenKey,
+    poolSeed,
+    // @ts-ignore
+    new Numberu64(0),
+  );
+  let buffers = [
+    Buffer.from(Int8Array.from([23])),
+    Buffer.concat(poolSeed),
+    toleranceBps.toBuffer(),
+  ];
+  for (var amount of assetAmounts) {
+    buffers.push(amount.toBuffer());
+  }
+  instruction.data = Buffer.concat(buffers);
+  return instruction;
+}
+
+export function cancelAllOrdersInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  market: PublicKey,
+  openOrdersKey: PublicKey,
+  serumMarketBids: PublicKey,
+  serumMarketAsks: PublicKey,
+  serumEventQueue: PublicKey,
+  poolKey: PublicKey,
+  dexProgramKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([24])), Buffer.concat(poolSeed)];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: market,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: openOrdersKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: serumMarketBids,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: serumMarketAsks,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: serumEventQueue,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: dexProgramKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function getPoolInfoInstruction(
+  bonfidaBotProgramId: PublicKey,
+  poolKey: PublicKey,
+  mintKey: PublicKey,
+  // The pool asset accounts of the assets in the page
+  poolAssetKeys: Array<PublicKey>,
+  poolSeed: Array<Buffer | Uint8Array>,
+  page: Numberu16,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([25])),
+    Buffer.concat(poolSeed),
+    page.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+  for (var poolAsset of poolAssetKeys) {
+    keys.push({
+      pubkey: poolAsset,
+      isSigner: false,
+      isWritable: false,
+    });
+  }
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function closePoolInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  mintKey: PublicKey,
+  lamportsTargetKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([26])), Buffer.concat(poolSeed)];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: lamportsTargetKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function consumeEventsInstruction(
+  bonfidaBotProgramId: PublicKey,
+  market: PublicKey,
+  openOrdersKey: PublicKey,
+  serumEventQueue: PublicKey,
+  coinFeeReceivableKey: PublicKey,
+  pcFeeReceivableKey: PublicKey,
+  poolKey: PublicKey,
+  dexProgramKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  limit: Numberu16,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([27])),
+    Buffer.concat(poolSeed),
+    limit.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: market,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: openOrdersKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: serumEventQueue,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: coinFeeReceivableKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: pcFeeReceivableKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: dexProgramKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function setDepositCapInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  // Zero for no cap
+  maxPoolTokenSupply: Numberu64,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetDepositCap,
+    poolSeed,
+    maxPoolTokenSupply.toBuffer(),
+  );
+}
+
+export function sweepUntrackedAssetInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  sourceKey: PublicKey,
+  destinationKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([29])), Buffer.concat(poolSeed)];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: splTokenProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: sourceKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: destinationKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function setExtraFeeRecipientInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  recipient: PublicKey,
+  bps: Numberu16,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetExtraFeeRecipient,
+    poolSeed,
+    Buffer.concat([recipient.toBuffer(), bps.toBuffer()]),
+  );
+}
+
+export function depositAndLockInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  sigProviderFeeReceiverKey: PublicKey,
+  bonfidaFeeReceiverKey: PublicKey,
+  bonfidaBuyAndBurnKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKeys: Array<PublicKey>,
+  targetPoolTokenKey: PublicKey,
+  sourceOwnerKey: PublicKey,
+  sourceAssetKeys: Array<PublicKey>,
+  extraFeePoolTokenKey: PublicKey | null,
+  poolSeed: Array<Buffer | Uint8Array>,
+  poolTokenAmount: Numberu64,
+): TransactionInstruction {
+  let instruction = depositInstruction(
+    splTokenProgramId,
+    bonfidaBotProgramId,
+    sigProviderFeeReceiverKey,
+    bonfidaFeeReceiverKey,
+    bonfidaBuyAndBurnKey,
+    mintKey,
+    poolKey,
+    poolAssetKeys,
+    targetPoolTokenKey,
+    sourceOwnerKey,
+    sourceAssetKeys,
+    null,
+    extraFeePoolTokenKey,
+    poolSeed,
+    poolTokenAmount,
+  );
+  instruction.data[0] = Instruction.DepositAndLock;
+  // The pool status is updated
+  instruction.keys[DEPOSIT_POOL_ACCOUNT_INDEX].isWritable = true;
+  instruction.keys.unshift({
+    pubkey: signalProviderKey,
+    isSigner: true,
+    isWritable: false,
+  });
+  return instruction;
+}
+
+export function sweepDustInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  sourceKey: PublicKey,
+  destinationKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+): TransactionInstruction {
+  let buffers = [Buffer.from(Int8Array.from([32])), Buffer.concat(poolSeed)];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: splTokenProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: mintKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: sourceKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: destinationKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function updateDexProgramInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  newDexProgramId: PublicKey,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.UpdateDexProgram,
+    poolSeed,
+    newDexProgramId.toBuffer(),
+  );
+}
+
+export function initOpenOrdersInstruction(
+  bonfidaBotProgramId: PublicKey,
+  systemProgramId: PublicKey,
+  rentProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  market: PublicKey,
+  // The OpenOrders account of the pool on the market, see findOpenOrdersKey
+  openOrdersKey: PublicKey,
+  dexProgramKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  marketIndex: Numberu16,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([34])),
+    Buffer.concat(poolSeed),
+    marketIndex.toBuffer(),
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: systemProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: rentProgramId,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: true,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: market,
+      isSigner: false,
+      isWritable: false,
+    },
+    {
+      pubkey: openOrdersKey,
+      isSigner: false,
+      isWritable: true,
+    },
+    {
+      pubkey: dexProgramKey,
+      isSigner: false,
+      isWritable: false,
+    },
+  ];
+
+  return new TransactionInstruction({
+    keys,
+    programId: bonfidaBotProgramId,
+    data,
+  });
+}
+
+export function setSingleAssetDepositsInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  enabled: boolean,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetSingleAssetDeposits,
+    poolSeed,
+    Buffer.from(Int8Array.from([enabled ? 1 : 0])),
+  );
+}
+
+export function depositSingleInstruction(
+  splTokenProgramId: PublicKey,
+  bonfidaBotProgramId: PublicKey,
+  sigProviderFeeReceiverKey: PublicKey,
+  bonfidaFeeReceiverKey: PublicKey,
+  bonfidaBuyAndBurnKey: PublicKey,
+  mintKey: PublicKey,
+  poolKey: PublicKey,
+  poolAssetKeys: Array<PublicKey>,
+  targetPoolTokenKey: PublicKey,
+  sourceOwnerKey: PublicKey,
+  sourceAssetKeys: Array<PublicKey>,
+  extraFeePoolTokenKey: PublicKey | null,
+  // The (market, bids, asks) keys valuing each of the other pool assets
+  priceMarketKeys: Array<[PublicKey, PublicKey, PublicKey]>,
+  poolSeed: Array<Buffer | Uint8Array>,
+  assetIndex: Numberu64,
+  amount: Numberu64,
+): TransactionInstruction {
+  let instruction = depositInstruction(
+    splTokenProgramId,
+    bonfidaBotProgramId,
+    sigProviderFeeReceiverKey,
+    bonfidaFeeReceiverKey,
+    bonfidaBuyAndBurnKey,
+    mintKey,
+    poolKey,
+    poolAssetKeys,
+    targetPoolTokenKey,
+    sourceOwnerKey,
+    sourceAssetKeys,
+    null,
+    extraFeePoolTokenKey,
+    poolSeed,
+    // @ts-ignore
+    new Numberu64(0),
+  );
+  let buffers = [
+    Buffer.from(Int8Array.from([36])),
+    Buffer.concat(poolSeed),
+    assetIndex.toBuffer(),
+    amount.toBuffer(),
+  ];
+  instruction.data = Buffer.concat(buffers);
+  for (var marketKeys of priceMarketKeys) {
+    for (var key of marketKeys) {
+      instruction.keys.push({
+        pubkey: key,
+        isSigner: false,
+        isWritable: false,
+      });
+    }
+  }
+  return instruction;
+}
+
+export function setMetadataInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  // At most 64 bytes once UTF-8 encoded
+  uri: string,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetMetadata,
+    poolSeed,
+    Buffer.from(uri, 'utf-8'),
+  );
+}
+
+export function setReferrerFeeInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  poolSeed: Array<Buffer | Uint8Array>,
+  referrerFeeBps: Numberu16,
+): TransactionInstruction {
+  return poolSettingInstruction(
+    bonfidaBotProgramId,
+    signalProviderKey,
+    poolKey,
+    Instruction.SetReferrerFee,
+    poolSeed,
+    referrerFeeBps.toBuffer(),
+  );
+}
+
+// Instructions by which the signal provider updates a setting of the pool, which only
+// need its signature and the pool account
+function poolSettingInstruction(
+  bonfidaBotProgramId: PublicKey,
+  signalProviderKey: PublicKey,
+  poolKey: PublicKey,
+  instruction: Instruction,
+  poolSeed: Array<Buffer | Uint8Array>,
+  args: Buffer,
+): TransactionInstruction {
+  let buffers = [
+    Buffer.from(Int8Array.from([instruction])),
+    Buffer.concat(poolSeed),
+    args,
+  ];
+
+  const data = Buffer.concat(buffers);
+  const keys = [
+    {
+      pubkey: signalProviderKey,
+      isSigner: true,
+      isWritable: false,
+    },
+    {
+      pubkey: poolKey,
+      isSigner: false,
+      isWritable: true,
+    },
+  ];
 
   return new TransactionInstruction({
     keys,
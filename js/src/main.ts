@@ -34,10 +34,12 @@ import {
   OrderType,
   PoolAsset,
   PoolHeader,
+  PoolMode,
   SelfTradeBehavior,
   unpack_assets,
   PUBKEY_LENGTH,
   unpack_markets,
+  findAllowedMintsKey,
 } from './state';
 import bs58 from 'bs58';
 import * as crypto from 'crypto';
@@ -95,6 +97,12 @@ export const PUBLIC_POOLS_SEEDS = [
  * @param payer The address of the account that should pay for the allocation fees
  * @param feeCollectionPeriod The smallest period in seconds after which the trading fees can be payed out again (minimum is 604800 s or 1 week)
  * @param feePercentage The percentage (a number from 0 to 100) of the pool assets that should be collected as fees
+ * @param depositFeePercentage The percentage (a number from 0 to 100) of the deposits that should be collected as fees, defaults to feePercentage
+ * @param rebateRetentionPercentage The percentage (a number from 0 to 100) of the serum referral rebates retained by the pool, the remainder going to the signal provider
+ * @param poolMode The operations the pool initially accepts
+ * @param redeemRequiresProvider Whether redemptions need the signal provider's signature until maturity
+ * @param maturityTimestamp The unix timestamp from which redemptions don't need the signal provider's signature anymore
+ * @param feeSplitBps The signal provider and Bonfida fee account shares of the fees in basis points, the pool gets the default 1/2, 1/4, 1/4 split when null
  */
 export async function createPool(
   connection: Connection,
@@ -107,6 +115,12 @@ export async function createPool(
   payer: PublicKey,
   feeCollectionPeriod: Numberu64,
   feePercentage: number,
+  depositFeePercentage: number = feePercentage,
+  rebateRetentionPercentage: number = 0,
+  poolMode: PoolMode = PoolMode.Normal,
+  redeemRequiresProvider: boolean = false,
+  maturityTimestamp: number = 0,
+  feeSplitBps: [number, number] | null = null,
 ): Promise<[Uint8Array, TransactionInstruction[]]> {
 
   // Find a valid pool seed
@@ -211,6 +225,15 @@ export async function createPool(
   // Create the pool
   // @ts-ignore
   let feeRatioU16 = new Numberu16(2**16 * feePercentage / 100);
+  // @ts-ignore
+  let depositFeeRatioU16 = new Numberu16(2**16 * depositFeePercentage / 100);
+  // @ts-ignore
+  let rebateRetentionRatioU16 = new Numberu16(2**16 * rebateRetentionPercentage / 100);
+  let feeSplitBpsU16: [Numberu16, Numberu16] | null = null;
+  if (!!feeSplitBps) {
+    // @ts-ignore
+    feeSplitBpsU16 = [new Numberu16(feeSplitBps[0]), new Numberu16(feeSplitBps[1])];
+  }
   let createTxInstruction = createInstruction(
     TOKEN_PROGRAM_ID,
     BONFIDABOT_PROGRAM_ID,
@@ -228,6 +251,13 @@ export async function createPool(
     markets,
     feeCollectionPeriod,
     feeRatioU16,
+    depositFeeRatioU16,
+    rebateRetentionRatioU16,
+    poolMode,
+    redeemRequiresProvider,
+    // @ts-ignore
+    new Numberu64(maturityTimestamp),
+    feeSplitBpsU16,
   );
   txInstructions = txInstructions.concat(assetTxInstructions);
   txInstructions.push(createTxInstruction);
@@ -250,6 +280,8 @@ export async function createPool(
  * @param poolTokenAmount The amount of pooltokens that should be bought (ie the amount of tokens that should be invested)
  * @param poolSeed The seed of the pool that should be invested into
  * @param payer The address of the account that should pay for the allocation fees
+ * @param referrerPoolTokenKey The pooltoken account of the referrer of the deposit (optional), which receives
+ *  a share of the deposit fee on pools with a referrer fee
  */
 export async function deposit(
   connection: Connection,
@@ -258,6 +290,7 @@ export async function deposit(
   poolTokenAmount: Numberu64,
   poolSeed: Array<Buffer | Uint8Array>,
   payer: PublicKey,
+  referrerPoolTokenKey: PublicKey | null = null,
 ): Promise<TransactionInstruction[]> {
 
   // Find the pool key and mint key
@@ -277,7 +310,7 @@ export async function deposit(
     throw 'Pool account is unavailable';
   }
   let poolData = poolInfo.data;
-  let poolHeader = PoolHeader.fromBuffer(poolData.slice(0, PoolHeader.LEN));
+  let poolHeader = PoolHeader.fromAccountData(poolData);
   let poolAssets: Array<PoolAsset> = unpack_assets(
     poolData.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -328,6 +361,19 @@ export async function deposit(
   );
   bonfidaBNBInstruction? createTargetsTxInstructions.push(bonfidaBNBInstruction) : null;
 
+  let extraFeePoolTokenKey: PublicKey | null = null;
+  if (poolHeader.hasExtraFeeRecipient()) {
+    let [extraFeeReceiverKey, extraFeeInstruction] = await findAndCreateAssociatedAccount(
+      SystemProgram.programId,
+      connection,
+      poolHeader.extraFeeRecipient,
+      poolMintKey,
+      payer
+    );
+    extraFeeInstruction? createTargetsTxInstructions.push(extraFeeInstruction) : null;
+    extraFeePoolTokenKey = extraFeeReceiverKey;
+  }
+
   let depositTxInstruction = depositInstruction(
     TOKEN_PROGRAM_ID,
     BONFIDABOT_PROGRAM_ID,
@@ -340,6 +386,8 @@ export async function deposit(
     targetPoolTokenKey,
     sourceOwnerKey,
     sourceAssetKeys,
+    referrerPoolTokenKey,
+    extraFeePoolTokenKey,
     poolSeed,
     poolTokenAmount,
   );
@@ -365,6 +413,7 @@ export async function deposit(
  * @param amountToTrade If this optional argument is given, it will overwrite the maxQuantityPercentage and fix
  *  the size of the order in base quantity. If you want to trade one and a half FIDA on FIDA/USDC for example,
  *  give 1.5 as an input here.
+ * @param minExpectedLots The minimum number of lots the order should trade, the order is rejected below it
  */
 export async function createOrder(
   connection: Connection,
@@ -379,6 +428,7 @@ export async function createOrder(
   srmDiscountKey: PublicKey | null,
   payerKey: PublicKey,
   amountToTrade?: number,
+  minExpectedLots: number = 0,
 ): Promise<[Account, TransactionInstruction[]]> {
 
   // Find the pool key
@@ -391,9 +441,7 @@ export async function createOrder(
   if (!poolInfo) {
     throw 'Pool account is unavailable';
   }
-  let poolHeader = PoolHeader.fromBuffer(
-    poolInfo.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolInfo.data);
 
   let marketData = await getMarketData(connection, market);
   let sourceMintKey: PublicKey;
@@ -482,6 +530,11 @@ export async function createOrder(
   // @ts-ignore
   : new Numberu16((2**16 * amountToTrade / (sourcePoolAssetBalance['uiAmount'])));
 
+  let [allowedMintsKey] = await findAllowedMintsKey(
+    BONFIDABOT_PROGRAM_ID,
+    poolKey,
+  );
+
   let createOrderTxInstruction = createOrderInstruction(
     BONFIDABOT_PROGRAM_ID,
     poolHeader.signalProvider,
@@ -502,6 +555,7 @@ export async function createOrder(
     SERUM_PROGRAM_ID,
     SYSVAR_RENT_PUBKEY,
     srmDiscountKey,
+    allowedMintsKey,
     [poolSeed],
     side,
     limitPrice,
@@ -515,7 +569,9 @@ export async function createOrder(
     clientId,
     selfTradeBehavior,
     // @ts-ignore
-    new Numberu16((1<<16) - 1)
+    new Numberu16((1<<16) - 1),
+    // @ts-ignore
+    new Numberu64(minExpectedLots),
   );
 
   let instructions = [
@@ -537,7 +593,8 @@ export async function createOrder(
  * @param poolSeed The seed of the pool that should be settled
  * @param market The address of the serum market on which the order is
  * @param openOrdersKey The address of the serum openOrder account to settle
- * @param srmReferrerKey The address of the referer that should receive the serum trading fees
+ * @param srmReferrerKey The address of the referrer that should receive the serum referral rebates (optional), which
+ *  has to be the pool pc wallet. The signal provider's share of the rebates is paid to its associated pc wallet.
  */
 export async function settleFunds(
   connection: Connection,
@@ -562,9 +619,7 @@ export async function settleFunds(
   }
 
   let marketData = await getMarketData(connection, market);
-  let poolHeader = PoolHeader.fromBuffer(
-    poolInfo.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolInfo.data);
   let poolAssets = unpack_assets(
     poolInfo.data.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -597,6 +652,13 @@ export async function settleFunds(
     marketData.pcMintKey,
   );
 
+  let signalProviderPcKey = !!srmReferrerKey
+    ? await findAssociatedTokenAddress(
+        poolHeader.signalProvider,
+        marketData.pcMintKey,
+      )
+    : null;
+
   let vaultSignerKey = await PublicKey.createProgramAddress(
     [market.toBuffer(), marketData.vaultSignerNonce.toBuffer()],
     SERUM_PROGRAM_ID,
@@ -616,6 +678,7 @@ export async function settleFunds(
     TOKEN_PROGRAM_ID,
     SERUM_PROGRAM_ID,
     srmReferrerKey,
+    signalProviderPcKey,
     [poolSeed],
     pcPoolAssetIndex,
     coinPoolAssetIndex,
@@ -648,9 +711,7 @@ export async function cancelOrder(
   if (!poolInfo) {
     throw 'Pool account is unavailable';
   }
-  let signalProviderKey = PoolHeader.fromBuffer(
-    poolInfo.data.slice(0, PoolHeader.LEN),
-  ).signalProvider;
+  let signalProviderKey = PoolHeader.fromAccountData(poolInfo.data).signalProvider;
   let marketData = await getMarketData(connection, market);
 
   let openOrders = await OpenOrders.load(
@@ -702,6 +763,7 @@ export async function cancelOrder(
  * @param targetAssetKeys An array of addresses to which the pool asset tokens are payed out to
  * @param poolSeed The seed of the pool that should be redeemed from
  * @param poolTokenAmount The amount of pooltokens that should be used (ie the amount of tokens that should be bought back)
+ *  Before maturity, pools requiring it need the redemption to be signed by the signal provider as well.
  */
 export async function redeem(
   connection: Connection,
@@ -729,7 +791,7 @@ export async function redeem(
     throw 'Pool account is unavailable';
   }
   let poolData = poolInfo.data;
-  let poolHeader = PoolHeader.fromBuffer(poolData.slice(0, PoolHeader.LEN));
+  let poolHeader = PoolHeader.fromAccountData(poolData);
   let poolAssets = unpack_assets(
     poolInfo.data.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -741,6 +803,12 @@ export async function redeem(
     poolAssetKeys.push(assetKey);
   }
 
+  let signalProviderKey =
+    poolHeader.redeemRequiresProvider &&
+    Date.now() / 1000 < poolHeader.maturityTimestamp.toNumber()
+      ? poolHeader.signalProvider
+      : null;
+
   let redeemTxInstruction = redeemInstruction(
     TOKEN_PROGRAM_ID,
     BONFIDABOT_PROGRAM_ID,
@@ -751,6 +819,7 @@ export async function redeem(
     sourcePoolTokenOwnerKey,
     sourcePoolTokenKey,
     targetAssetKeys,
+    signalProviderKey,
     poolSeed,
     poolTokenAmount,
   );
@@ -787,7 +856,7 @@ export async function collectFees(
     throw 'Pool account is unavailable';
   }
   let poolData = poolInfo.data;
-  let poolHeader = PoolHeader.fromBuffer(poolData.slice(0, PoolHeader.LEN));
+  let poolHeader = PoolHeader.fromAccountData(poolData);
 
   let sigProviderFeeReceiverKey = await findAssociatedTokenAddress(
     poolHeader.signalProvider,
@@ -802,6 +871,10 @@ export async function collectFees(
     poolMintKey,
   );
 
+  let extraFeePoolTokenKey = poolHeader.hasExtraFeeRecipient()
+    ? await findAssociatedTokenAddress(poolHeader.extraFeeRecipient, poolMintKey)
+    : null;
+
   let collectFeesTxInstruction = collectFeesInstruction(
     TOKEN_PROGRAM_ID,
     SYSVAR_CLOCK_PUBKEY,
@@ -811,6 +884,7 @@ export async function collectFees(
     sigProviderFeeReceiverKey,
     bonfidaFeeReceiverKey,
     bonfidaBuyAndBurnKey,
+    extraFeePoolTokenKey,
     poolSeed,
   );
   return [collectFeesTxInstruction];
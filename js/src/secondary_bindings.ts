@@ -96,9 +96,7 @@ export async function settlePool(
   if (!poolData) {
     throw 'Pool account is unavailable';
   }
-  let poolHeader = PoolHeader.fromBuffer(
-    poolData.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolData.data);
 
   let authorizedMarkets = unpack_markets(
     poolData.data.slice(
@@ -168,9 +166,7 @@ export async function fetchPoolInfo(
   if (!poolData) {
     throw 'Pool account is unavailable';
   }
-  let poolHeader = PoolHeader.fromBuffer(
-    poolData.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolData.data);
   let poolAssets = unpack_assets(
     poolData.data.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -225,9 +221,7 @@ export async function fetchPoolBalances(
   if (!poolData) {
     throw 'Pool account is unavailable';
   }
-  let poolHeader = PoolHeader.fromBuffer(
-    poolData.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolData.data);
   let poolAssets = unpack_assets(
     poolData.data.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -286,9 +280,7 @@ export async function singleTokenDeposit(
   if (!poolInfo) {
     throw 'Pool account is unavailable';
   }
-  let poolHeader = PoolHeader.fromBuffer(
-    poolInfo.data.slice(0, PoolHeader.LEN),
-  );
+  let poolHeader = PoolHeader.fromAccountData(poolInfo.data);
   let poolAssets = unpack_assets(
     poolInfo.data.slice(
       PoolHeader.LEN + Number(poolHeader.numberOfMarkets) * PUBKEY_LENGTH,
@@ -590,6 +582,22 @@ export async function singleTokenDeposit(
   );
   bonfidaBNBInstruction ? instructions.push(bonfidaBNBInstruction) : null;
 
+  let extraFeePoolTokenKey: PublicKey | null = null;
+  if (poolHeader.hasExtraFeeRecipient()) {
+    let [
+      extraFeeReceiverKey,
+      extraFeeInstruction,
+    ] = await findAndCreateAssociatedAccount(
+      SystemProgram.programId,
+      connection,
+      poolHeader.extraFeeRecipient,
+      poolMintKey,
+      payer.publicKey,
+    );
+    extraFeeInstruction ? instructions.push(extraFeeInstruction) : null;
+    extraFeePoolTokenKey = extraFeeReceiverKey;
+  }
+
   // @ts-ignore
   console.log(poolTokenAmount, new Numberu64(1000000 * poolTokenAmount));
 
@@ -607,6 +615,8 @@ export async function singleTokenDeposit(
     targetPoolTokenKey,
     sourceOwner.publicKey,
     sourceAssetKeys,
+    null,
+    extraFeePoolTokenKey,
     [poolSeed],
     // @ts-ignore
     new Numberu64(1000000 * poolTokenAmount),
@@ -652,6 +662,15 @@ export async function getPoolsSeedsBySigProvider(
     if (data.length < PoolHeader.LEN) {
       continue;
     }
+    // The allowed mints and NAV snapshots accounts of the pools are owned by the program
+    // as well, pool accounts are the ones derived from their seed
+    let poolKey = await PublicKey.createProgramAddress(
+      [data.slice(32, 64)],
+      BONFIDABOT_PROGRAM_ID,
+    ).catch(() => null);
+    if (!poolKey || poolKey.toBase58() !== account['pubkey']) {
+      continue;
+    }
     if (
       !signalProviderKey ||
       new PublicKey(data.slice(64, 96)).equals(signalProviderKey)
@@ -34,8 +34,23 @@ export enum PoolStatusID {
 
 export type PoolStatus = [PoolStatusID, number];
 
+export enum PoolMode {
+  Normal,
+  DepositOnly,
+  RedeemOnly,
+  Frozen,
+}
+
+// Layout version of the pool header, pools created before it was introduced have a shorter
+// header and can only be wound down
+export const POOL_HEADER_VERSION: number = 1;
+// Header length of each layout version, the account lengths they lead to differ modulo the
+// length of a market or asset slot
+export const POOL_HEADER_LENS: Array<number> = [117, 255];
+export const METADATA_URI_LEN: number = 64;
+
 export class PoolHeader {
-  static LEN = 117;
+  static LEN = 255;
   serumProgramId!: PublicKey;
   seed!: Uint8Array;
   signalProvider!: PublicKey;
@@ -43,7 +58,23 @@ export class PoolHeader {
   numberOfMarkets!: Numberu16;
   feeRatio!: Numberu16;
   lastFeeCollectionTimestamp!: Numberu64;
-  feeCollectionPeriod!: Numberu64
+  feeCollectionPeriod!: Numberu64;
+  depositFeeRatio!: Numberu16;
+  rebateRetentionRatio!: Numberu16;
+  pendingFeeRatio!: Numberu16;
+  pendingFeeRatioTimestamp!: Numberu64;
+  poolMode!: PoolMode;
+  redeemRequiresProvider!: boolean;
+  maturityTimestamp!: Numberu64;
+  version!: number;
+  maxPoolTokenSupply!: Numberu64;
+  extraFeeRecipient!: PublicKey;
+  extraFeeBps!: Numberu16;
+  signalProviderFeeBps!: Numberu16;
+  bonfidaFeeBps!: Numberu16;
+  singleAssetDeposits!: boolean;
+  metadataUri!: string;
+  referrerFeeBps!: Numberu16;
 
   constructor(
     serumProgramId: PublicKey,
@@ -53,7 +84,23 @@ export class PoolHeader {
     numberOfMarkets: Numberu16,
     feeRatio: Numberu16,
     lastFeeCollectionTimestamp: Numberu64,
-    feeCollectionPeriod: Numberu64
+    feeCollectionPeriod: Numberu64,
+    depositFeeRatio: Numberu16,
+    rebateRetentionRatio: Numberu16,
+    pendingFeeRatio: Numberu16,
+    pendingFeeRatioTimestamp: Numberu64,
+    poolMode: PoolMode,
+    redeemRequiresProvider: boolean,
+    maturityTimestamp: Numberu64,
+    version: number,
+    maxPoolTokenSupply: Numberu64,
+    extraFeeRecipient: PublicKey,
+    extraFeeBps: Numberu16,
+    signalProviderFeeBps: Numberu16,
+    bonfidaFeeBps: Numberu16,
+    singleAssetDeposits: boolean,
+    metadataUri: string,
+    referrerFeeBps: Numberu16,
   ) {
     this.serumProgramId = serumProgramId;
     this.seed = seed;
@@ -63,6 +110,27 @@ export class PoolHeader {
     this.feeRatio = feeRatio;
     this.lastFeeCollectionTimestamp = lastFeeCollectionTimestamp;
     this.feeCollectionPeriod = feeCollectionPeriod;
+    this.depositFeeRatio = depositFeeRatio;
+    this.rebateRetentionRatio = rebateRetentionRatio;
+    this.pendingFeeRatio = pendingFeeRatio;
+    this.pendingFeeRatioTimestamp = pendingFeeRatioTimestamp;
+    this.poolMode = poolMode;
+    this.redeemRequiresProvider = redeemRequiresProvider;
+    this.maturityTimestamp = maturityTimestamp;
+    this.version = version;
+    this.maxPoolTokenSupply = maxPoolTokenSupply;
+    this.extraFeeRecipient = extraFeeRecipient;
+    this.extraFeeBps = extraFeeBps;
+    this.signalProviderFeeBps = signalProviderFeeBps;
+    this.bonfidaFeeBps = bonfidaFeeBps;
+    this.singleAssetDeposits = singleAssetDeposits;
+    this.metadataUri = metadataUri;
+    this.referrerFeeBps = referrerFeeBps;
+  }
+
+  // Whether the deposits and collected fees mint a share to an extra fee recipient
+  public hasExtraFeeRecipient(): boolean {
+    return !this.extraFeeRecipient.equals(new PublicKey(Buffer.alloc(32)));
   }
 
   static match_status(status_byte: Buffer): PoolStatus {
@@ -90,6 +158,17 @@ export class PoolHeader {
     }
   }
 
+  // Reads the header of a whole pool account, rejecting the older layouts
+  static fromAccountData(data: Buffer): PoolHeader {
+    const version = POOL_HEADER_LENS.findIndex(
+      (len) => data.length >= len && (data.length - len) % PUBKEY_LENGTH == 0,
+    );
+    if (version != POOL_HEADER_VERSION) {
+      throw 'Unsupported pool header version, the pool can only be wound down';
+    }
+    return PoolHeader.fromBuffer(data.slice(0, PoolHeader.LEN));
+  }
+
   static fromBuffer(buf: Buffer): PoolHeader {
     const serumProgramId: PublicKey = new PublicKey(buf.slice(0, 32));
     const seed: Uint8Array = buf.slice(32, 64);
@@ -100,6 +179,22 @@ export class PoolHeader {
     const feeRatio = Numberu16.fromBuffer(buf.slice(99, 101));
     const lastFeeCollectionTimestamp = Numberu64.fromBuffer(buf.slice(101, 109));
     const feeCollectionPeriod = Numberu64.fromBuffer(buf.slice(109, 117));
+    const depositFeeRatio = Numberu16.fromBuffer(buf.slice(117, 119));
+    const rebateRetentionRatio = Numberu16.fromBuffer(buf.slice(119, 121));
+    const pendingFeeRatio = Numberu16.fromBuffer(buf.slice(121, 123));
+    const pendingFeeRatioTimestamp = Numberu64.fromBuffer(buf.slice(123, 131));
+    const poolMode: PoolMode = buf[131];
+    const redeemRequiresProvider = buf[132] != 0;
+    const maturityTimestamp = Numberu64.fromBuffer(buf.slice(133, 141));
+    const version = buf[141];
+    const maxPoolTokenSupply = Numberu64.fromBuffer(buf.slice(142, 150));
+    const extraFeeRecipient: PublicKey = new PublicKey(buf.slice(150, 182));
+    const extraFeeBps = Numberu16.fromBuffer(buf.slice(182, 184));
+    const signalProviderFeeBps = Numberu16.fromBuffer(buf.slice(184, 186));
+    const bonfidaFeeBps = Numberu16.fromBuffer(buf.slice(186, 188));
+    const singleAssetDeposits = buf[188] != 0;
+    const metadataUri = unpack_metadata_uri(buf.slice(189, 253));
+    const referrerFeeBps = Numberu16.fromBuffer(buf.slice(253, 255));
     return new PoolHeader(
       serumProgramId,
       seed,
@@ -108,11 +203,33 @@ export class PoolHeader {
       numberOfMarkets,
       feeRatio,
       lastFeeCollectionTimestamp,
-      feeCollectionPeriod
+      feeCollectionPeriod,
+      depositFeeRatio,
+      rebateRetentionRatio,
+      pendingFeeRatio,
+      pendingFeeRatioTimestamp,
+      poolMode,
+      redeemRequiresProvider,
+      maturityTimestamp,
+      version,
+      maxPoolTokenSupply,
+      extraFeeRecipient,
+      extraFeeBps,
+      signalProviderFeeBps,
+      bonfidaFeeBps,
+      singleAssetDeposits,
+      metadataUri,
+      referrerFeeBps,
     );
   }
 }
 
+// Returns the zero padded metadata URI of a pool header, empty when none was set
+export function unpack_metadata_uri(input: Buffer): string {
+  let end = input.indexOf(0);
+  return input.slice(0, end == -1 ? METADATA_URI_LEN : end).toString('utf-8');
+}
+
 export class PoolAsset {
   static LEN = 32;
   mintAddress!: PublicKey;
@@ -164,3 +281,37 @@ export function unpack_markets(
   }
   return markets;
 }
+
+// Derives the address of the account holding the allow-list of target mints of a pool
+export async function findAllowedMintsKey(
+  programId: PublicKey,
+  poolKey: PublicKey,
+): Promise<[PublicKey, number]> {
+  return await PublicKey.findProgramAddress(
+    [poolKey.toBuffer(), Buffer.from('allowed_mints')],
+    programId,
+  );
+}
+
+// Derives the address of the account holding the NAV snapshots of a pool
+export async function findNavSnapshotsKey(
+  programId: PublicKey,
+  poolKey: PublicKey,
+): Promise<[PublicKey, number]> {
+  return await PublicKey.findProgramAddress(
+    [poolKey.toBuffer(), Buffer.from('nav_snapshots')],
+    programId,
+  );
+}
+
+// Derives the address of the OpenOrders account created by the program for a pool on a market
+export async function findOpenOrdersKey(
+  programId: PublicKey,
+  poolKey: PublicKey,
+  market: PublicKey,
+): Promise<[PublicKey, number]> {
+  return await PublicKey.findProgramAddress(
+    [poolKey.toBuffer(), market.toBuffer(), Buffer.from('open_orders')],
+    programId,
+  );
+}
@@ -0,0 +1,96 @@
+//! Checks the `PoolHeader` pack/unpack round trip over arbitrary headers, across all the pool
+//! statuses. Run with `HFUZZ_BUILD_ARGS="--features fuzz" cargo hfuzz run fuzz_pool_header`.
+use std::num::NonZeroU8;
+
+use arbitrary::Arbitrary;
+use honggfuzz::fuzz;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solindex_bot::state::{PoolHeader, PoolMode, PoolStatus, POOL_HEADER_VERSION};
+
+#[derive(Arbitrary, Debug)]
+struct FuzzHeader {
+    serum_program_id: [u8; 32],
+    seed: [u8; 32],
+    signal_provider: [u8; 32],
+    status: u8,
+    pending_orders: u8,
+    number_of_markets: u16,
+    fee_ratio: u16,
+    last_fee_collection_timestamp: u64,
+    fee_collection_period: u64,
+    deposit_fee_ratio: u16,
+    rebate_retention_ratio: u16,
+    pending_fee_ratio: u16,
+    pending_fee_ratio_timestamp: u64,
+    pool_mode: u8,
+    redeem_requires_provider: bool,
+    maturity_timestamp: u64,
+    version: u8,
+    max_pooltoken_supply: u64,
+    extra_fee_recipient: [u8; 32],
+    extra_fee_bps: u16,
+    signal_provider_fee_bps: u16,
+    bonfida_fee_bps: u16,
+    single_asset_deposits: bool,
+    metadata_uri: ([u8; 32], [u8; 32]),
+    referrer_fee_bps: u16,
+}
+
+impl FuzzHeader {
+    fn to_header(&self) -> PoolHeader {
+        // Pending order counts range from 1 to 64
+        let pending_orders = NonZeroU8::new(self.pending_orders % 64 + 1).unwrap();
+        let status = match self.status % 5 {
+            0 => PoolStatus::Uninitialized,
+            1 => PoolStatus::Unlocked,
+            2 => PoolStatus::Locked,
+            3 => PoolStatus::PendingOrder(pending_orders),
+            _ => PoolStatus::LockedPendingOrder(pending_orders),
+        };
+        // Only uninitialized headers can have another version
+        let version = match status {
+            PoolStatus::Uninitialized => self.version,
+            _ => POOL_HEADER_VERSION,
+        };
+        let mut metadata_uri = [0u8; 64];
+        metadata_uri[..32].copy_from_slice(&self.metadata_uri.0);
+        metadata_uri[32..].copy_from_slice(&self.metadata_uri.1);
+        PoolHeader {
+            serum_program_id: Pubkey::new(&self.serum_program_id),
+            seed: self.seed,
+            signal_provider: Pubkey::new(&self.signal_provider),
+            status,
+            number_of_markets: self.number_of_markets,
+            fee_ratio: self.fee_ratio,
+            last_fee_collection_timestamp: self.last_fee_collection_timestamp,
+            fee_collection_period: self.fee_collection_period,
+            deposit_fee_ratio: self.deposit_fee_ratio,
+            rebate_retention_ratio: self.rebate_retention_ratio,
+            pending_fee_ratio: self.pending_fee_ratio,
+            pending_fee_ratio_timestamp: self.pending_fee_ratio_timestamp,
+            pool_mode: PoolMode::from_u8(self.pool_mode % 4).unwrap(),
+            redeem_requires_provider: self.redeem_requires_provider,
+            maturity_timestamp: self.maturity_timestamp,
+            version,
+            max_pooltoken_supply: self.max_pooltoken_supply,
+            extra_fee_recipient: Pubkey::new(&self.extra_fee_recipient),
+            extra_fee_bps: self.extra_fee_bps,
+            signal_provider_fee_bps: self.signal_provider_fee_bps,
+            bonfida_fee_bps: self.bonfida_fee_bps,
+            single_asset_deposits: self.single_asset_deposits,
+            metadata_uri,
+            referrer_fee_bps: self.referrer_fee_bps,
+        }
+    }
+}
+
+fn main() {
+    loop {
+        fuzz!(|fuzz_header: FuzzHeader| {
+            let header = fuzz_header.to_header();
+            let mut packed = [0u8; PoolHeader::LEN];
+            header.pack_into_slice(&mut packed);
+            assert_eq!(PoolHeader::unpack_unchecked(&packed).unwrap(), header);
+        });
+    }
+}
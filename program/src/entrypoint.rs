@@ -32,7 +32,14 @@ impl PrintProgramError for BonfidaBotError {
             BonfidaBotError::Overflow => msg!("Error: Arithmetic operation overflow!"),
             BonfidaBotError::LockedOperation => msg!("Error: Operation is locked in the current pool state!"),
             BonfidaBotError::NotEnoughFIDA => msg!("Error: Pool must contain a minimum amount of FIDA tokens"),
-            BonfidaBotError::OperationTooSmall => msg!("Error: Operation was too small")
+            BonfidaBotError::OperationTooSmall => msg!("Error: Operation was too small"),
+            BonfidaBotError::ClosedAssetAccount => msg!("Error: A pool asset account is closed, it can be recreated with RecreateAssetAccount"),
+            BonfidaBotError::ModeRestricted => msg!("Error: Operation is not allowed in the current pool mode!"),
+            BonfidaBotError::InsufficientAssetBalance => msg!("Error: The pool does not hold enough of the requested asset!"),
+            BonfidaBotError::MintNotAllowed => msg!("Error: The target mint is not in the pool's allow-list!"),
+            BonfidaBotError::TooManyPendingOrders => msg!("Error: The maximum number of pending orders has been reached!"),
+            BonfidaBotError::DepositCapReached => msg!("Error: The deposit would exceed the pool's pooltoken supply cap!"),
+            BonfidaBotError::InvalidMintAuthority => msg!("Error: The pooltoken mint authority is not the pool!")
         }
     }
 }
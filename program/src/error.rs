@@ -16,6 +16,20 @@ pub enum BonfidaBotError {
     NotEnoughFIDA,
     #[error("Operation too small.")]
     OperationTooSmall,
+    #[error("A pool asset account is closed or uninitialized.")]
+    ClosedAssetAccount,
+    #[error("Operation is not allowed in the current pool mode.")]
+    ModeRestricted,
+    #[error("The pool does not hold enough of the requested asset.")]
+    InsufficientAssetBalance,
+    #[error("The target mint is not in the pool's allow-list.")]
+    MintNotAllowed,
+    #[error("The maximum number of pending orders has been reached.")]
+    TooManyPendingOrders,
+    #[error("The deposit would exceed the pool's pooltoken supply cap.")]
+    DepositCapReached,
+    #[error("The pooltoken mint authority is not the pool.")]
+    InvalidMintAuthority,
 }
 
 impl From<BonfidaBotError> for ProgramError {
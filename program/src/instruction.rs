@@ -1,5 +1,8 @@
 use crate::error::BonfidaBotError;
-use crate::state::{BONFIDA_BNB, BONFIDA_FEE};
+use crate::state::{
+    find_allowed_mints_key, find_nav_snapshots_key, find_open_orders_key, PoolMode, BONFIDA_BNB,
+    BONFIDA_FEE, DEFAULT_POOL_TOKEN_DECIMALS,
+};
 use serum_dex::{
     instruction::SelfTradeBehavior,
     matching::{OrderType, Side},
@@ -9,8 +12,10 @@ use solana_program::{
     msg,
     program_error::ProgramError,
     pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, rent},
 };
-use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
 use std::{
     convert::TryInto,
     mem::size_of,
@@ -18,6 +23,18 @@ use std::{
     str::FromStr,
 };
 
+/// Bits of the mask returned by `ValidateOrderAccounts`, set when the check passes
+pub const ORDER_CHECK_MARKET: u8 = 1 << 0;
+pub const ORDER_CHECK_SOURCE_ACCOUNT: u8 = 1 << 1;
+pub const ORDER_CHECK_SERUM_PROGRAM: u8 = 1 << 2;
+pub const ORDER_CHECK_SIGNAL_PROVIDER: u8 = 1 << 3;
+
+/// Index packed in `SettleFunds` for a side whose pool asset is left untouched
+pub const SETTLE_SKIPPED_INDEX: u64 = std::u64::MAX;
+
+/// Length of the fee split optionally packed at the end of `Create`
+pub const FEE_SPLIT_LEN: usize = 4;
+
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
 pub enum PoolInstruction {
@@ -37,7 +54,11 @@ pub enum PoolInstruction {
         pool_seed: [u8; 32],
         // The maximum number of token asset types the pool will ever be able to hold
         max_number_of_assets: u32,
+        // The number of markets the pool account has room for, between 1 and 256
         number_of_markets: u16,
+        // Decimals of the pooltoken mint, at most 9. Can be omitted from the instruction data,
+        // in which case the pooltoken has 6 decimals.
+        decimals: u8,
     },
     /// Creates a new pool from an empty (uninitialized) one by performing the first deposit
     /// of any number of different tokens and setting the pubkey of the signal provider.
@@ -45,6 +66,7 @@ pub enum PoolInstruction {
     /// with respect to the deposited tokens.
     /// The init and create operations need to be separated as account data
     /// allocation needs to be first processed by the network before being overwritten.
+    /// With the `fida-minimum` feature, the first deposit should include at least 1000 FIDA.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -54,16 +76,30 @@ pub enum PoolInstruction {
     ///   1. `[]` The serum program account
     ///   1. `[]` The signal provider account
     ///   2. `[writable]` The pooltoken mint account
-    ///   3. `[writable]` The target account that receives the pooltokens
+    ///   3. `[writable]` The target account that receives the pooltokens, owned by the
+    ///      source owner
     ///   4. `[writable]` The pool account
     ///   5..M+5. `[writable]` The M pool (associated) token assets accounts in the order of the
     ///      corresponding PoolAssets in the pool account data.
     ///   M+5. `[signer]` The source owner account
     ///   M+6..2M+6. `[writable]` The M source token accounts in the same order as above
+    ///   2M+6.. `[]` For Token-2022 assets, the Token-2022 program account and the asset mints
     Create {
         pool_seed: [u8; 32],
         fee_collection_period: u64,
         fee_ratio: u16,
+        // The fee ratio applied to deposits, distinct from the periodic management fee
+        deposit_fee_ratio: u16,
+        // The share of serum referral rebates retained by the pool
+        rebate_retention_ratio: u16,
+        pool_mode: PoolMode,
+        // Whether redemptions need the signal provider's signature before maturity
+        redeem_requires_provider: bool,
+        maturity_timestamp: u64,
+        // The signal provider and Bonfida fee account shares of the minted fees in basis
+        // points, the remainder going to the buy and burn account. Packed after the deposit
+        // amounts when set, the pool gets the default split otherwise.
+        fee_split_bps: Option<(u16, u16)>,
         deposit_amounts: Vec<u64>,
         markets: Vec<Pubkey>,
     },
@@ -71,7 +107,9 @@ pub enum PoolInstruction {
     /// a corresponding amount of pool-token in exchange. The program will try to
     /// maximize the deposit sum with regards to the amounts given by the source and
     /// the ratio of tokens present in the pool at that moment. Tokens can only be deposited
-    /// in the exact ratio of tokens that are present in the pool.
+    /// in the exact ratio of tokens that are present in the pool. Deposits which would bring
+    /// the pooltoken supply above the cap set with `SetDepositCap` are rejected. The minted
+    /// pooltokens and fee are returned as return data, see `DEPOSIT_RETURN_DATA_LEN`.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -87,13 +125,27 @@ pub enum PoolInstruction {
     ///      corresponding PoolAssets in the pool account data.
     ///   M+7. `[signer]` The source owner account
     ///   M+8..2M+8. `[writable]` The M source token accounts in the same order as above
+    ///   2M+8. `[writable]` The referrer pooltoken account, only when `with_referrer` is set. It
+    ///      can't belong to the source owner or be the target account.
+    ///   2M+9. `[writable]` The extra fee recipient pooltoken account, only when the pool has an
+    ///      extra fee recipient (2M+8 without a referrer)
+    ///   2M+8.. `[]` For Token-2022 assets, the Token-2022 program account and the asset mints.
+    ///      The minted pooltokens account for the transfer fees of these mints.
     Deposit {
         pool_seed: [u8; 32],
         // The amount of pool token the source wishes to buy
         pool_token_amount: u64,
+        // Whether the referrer pooltoken account follows the source accounts, which then
+        // receives the pool's `referrer_fee_bps` share of the deposit fee. Trailing byte left
+        // out when there is no referrer.
+        with_referrer: bool,
     },
     /// As a signal provider, create a new serum order for the pool.
     /// Amounts are translated into proportions of the pool between 0 and 2**16 - 1
+    /// Limit and post-only orders can rest on the book, the pool then keeps a pending order
+    /// until their funds are settled or the order is cancelled.
+    /// On self trade, orders can only decrement the taking order or cancel the resting one,
+    /// `AbortTransaction` is rejected, see `check_self_trade_behavior`.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -112,7 +164,12 @@ pub enum PoolInstruction {
     ///   11. `[]` The spl_token_program
     ///   12. `[]` The rent sysvar account
     ///   13. `[]` The dex program account
-    ///   14. `[writable]` (optional) The (M)SRM discount account
+    ///   14. `[writable]` (optional) The (M)SRM discount account, an SRM or MSRM token account
+    ///       owned by the pool which sets the Serum fee tier of the order. The referral rebates
+    ///       of the order are paid when settling, to the pool pc wallet given as referrer.
+    ///   15. `[]` (optional) The allowed mints account of the pool (14 without a discount
+    ///       account), required when the order tracks a new target asset. It can be left
+    ///       uncreated to allow any target mint.
     CreateOrder {
         pool_seed: [u8; 32],
         side: Side,
@@ -127,9 +184,13 @@ pub enum PoolInstruction {
         coin_lot_size: u64,
         pc_lot_size: u64,
         target_mint: Pubkey,
-        serum_limit: u16
+        serum_limit: u16,
+        // Minimum number of lots the order should trade, zero for no minimum. Can be omitted
+        // from the instruction data.
+        min_expected_lots: u64,
     },
-    /// As a signal provider, cancel a serum order for the pool.
+    /// As a signal provider, cancel a serum order for the pool. Once the cancellation frees
+    /// all the funds of the OpenOrders account, its pending order is released.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -140,7 +201,7 @@ pub enum PoolInstruction {
     ///    3. `[writable]` The Serum market bids
     ///    4. `[writable]` The Serum market asks
     ///    5. `[writable]` The Serum event queue
-    ///    6. `[]` The pool account
+    ///    6. `[writable]` The pool account
     ///    7. `[]` The dex program account
     CancelOrder {
         pool_seed: [u8; 32],
@@ -162,15 +223,25 @@ pub enum PoolInstruction {
     ///    8. `[]` vault signer
     ///    9. `[]` spl token program
     ///   10. `[]` Serum dex program
-    ///   12. `[writable]` (optional) referrer pc wallet
+    ///   11. `[writable]` (optional) referrer pc wallet, which has to be the pool pc wallet
+    ///   12. `[writable]` (optional) the signal provider's associated pc wallet, receiving
+    ///       its share of the referral rebates. Required when a referrer is provided.
+    ///
+    /// One of the indices can be left out when only the other side has free funds, in which
+    /// case its pool asset slot isn't touched. Both pool wallets are still required by Serum.
     SettleFunds {
         pool_seed: [u8; 32],
-        pc_index: u64,
-        coin_index: u64,
+        // Packed as `SETTLE_SKIPPED_INDEX` when left out
+        pc_index: Option<u64>,
+        coin_index: Option<u64>,
     },
     /// Buy out of the pool by redeeming pooltokens.
     /// This instruction needs to be executed after (and within the same transaction)
-    /// having settled on all possible open orders for the pool.
+    /// having settled on all possible open orders for the pool. While fees are overdue, the
+    /// payout is computed as if they had been collected, the pooltokens they would mint
+    /// diluting the redemption. This includes the redemption of the whole supply, which
+    /// leaves the assets backing these fees in the pool asset accounts once the pool is reset,
+    /// to be recovered with `SweepDust`.
     ///
     /// Accounts expected by this instruction:
     ///
@@ -180,10 +251,13 @@ pub enum PoolInstruction {
     ///   2. `[writable]` The pooltoken mint account
     ///   3. `[signer]` The pooltoken source account owner
     ///   4. `[writable]` The pooltoken source account
-    ///   5. `[]` The pool account
+    ///   5. `[writable]` The pool account
     ///   6..M+6. `[writable]` The M pool (associated) token assets accounts in the order of the
     ///      corresponding PoolAssets found in the pool account data.
     ///   M+7..2M+7. `[writable]` The M target token accounts in the same order as above
+    ///   2M+7. `[signer]` Optional signal provider account, required before maturity for
+    ///      pools where redemptions need the signal provider's approval
+    ///   2M+8.. `[]` For Token-2022 assets, the Token-2022 program account and the asset mints
     Redeem {
         pool_seed: [u8; 32],
         // The amount of pool token the source wishes to redeem
@@ -196,12 +270,418 @@ pub enum PoolInstruction {
     ///   * Single owner
     ///   0. `[]` The spl-token program account
     ///   1. `[]` The clock sysvar account
-    ///   5. `[writable]` The pool account
-    ///   2. `[writable]` The pooltoken mint account
-    ///   3. `[writable]` The signal provider account that receives the pooltoken fees
-    ///   4. `[writable]` The Bonfida fee account that receives the pooltoken fees
-    ///   5. `[writable]` The Bonfida buy and burn account that receives the pooltoken fees
+    ///   2. `[writable]` The pool account
+    ///   3. `[writable]` The pooltoken mint account
+    ///   4. `[writable]` The signal provider account that receives the pooltoken fees
+    ///   5. `[writable]` The Bonfida fee account that receives the pooltoken fees
+    ///   6. `[writable]` The Bonfida buy and burn account that receives the pooltoken fees
+    ///   7. `[writable]` The extra fee recipient pooltoken account, only when the pool has an
+    ///      extra fee recipient
     CollectFees { pool_seed: [u8; 32] },
+    /// Compute a hash of the packed pool state (header, markets and assets) and publish it
+    /// in the program logs and return data, so that light clients can verify a provided
+    /// state blob against the on-chain state.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The pool account
+    PublishStateHash { pool_seed: [u8; 32] },
+    /// Same as `Redeem`, except that the wrapped SOL share of the pool is unwrapped to the
+    /// native balance of the pooltoken source account owner. The target token account
+    /// for the wrapped SOL asset is a temporary account owned by the source account owner,
+    /// which is closed once the transfer is done.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   * Single owner
+    ///   0. `[]` The spl-token program account
+    ///   1. `[]` The clock sysvar account
+    ///   2. `[writable]` The pooltoken mint account
+    ///   3. `[writable, signer]` The pooltoken source account owner
+    ///   4. `[writable]` The pooltoken source account
+    ///   5. `[]` The pool account
+    ///   6..M+6. `[writable]` The M pool (associated) token assets accounts in the order of the
+    ///      corresponding PoolAssets found in the pool account data.
+    ///   M+7..2M+7. `[writable]` The M target token accounts in the same order as above
+    ///   2M+7. `[signer]` Optional signal provider account, required before maturity for
+    ///      pools where redemptions need the signal provider's approval
+    RedeemUnwrapSol {
+        pool_seed: [u8; 32],
+        // The amount of pool token the source wishes to redeem
+        pool_token_amount: u64,
+    },
+    /// As a signal provider, change the fee ratio of the pool. The new fee ratio only
+    /// applies from the next fee collection period onwards, never to the current one.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The clock sysvar account
+    ///   1. `[signer]` The signal provider account
+    ///   2. `[writable]` The pool account
+    UpdateFeeRatio {
+        pool_seed: [u8; 32],
+        new_fee_ratio: u16,
+    },
+    /// Dry-run the account checks of `CreateOrder` without mutating any state. A bitmask of
+    /// the passing checks (see the `ORDER_CHECK_*` constants) is written to the return data.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[]` The market account
+    ///   2. `[]` The payer pool asset account
+    ///   3. `[]` The pool account
+    ///   4. `[]` The dex program account
+    ValidateOrderAccounts {
+        pool_seed: [u8; 32],
+        market_index: u16,
+        source_index: u64,
+    },
+    /// Re-create the associated token account of a pool asset after it has been closed.
+    /// Anyone can fund the new account.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The system program account
+    ///   1. `[]` The spl-token program account
+    ///   2. `[]` The rent sysvar account
+    ///   3. `[]` The associated token account program account
+    ///   4. `[writable, signer]` The funding account
+    ///   5. `[writable]` The closed pool asset account
+    ///   6. `[]` The pool account
+    ///   7. `[]` The pool asset mint account
+    RecreateAssetAccount {
+        pool_seed: [u8; 32],
+        asset_index: u64,
+    },
+    /// As a signal provider, restrict the pool to deposits only or redemptions only, freeze
+    /// it during an incident, or restore normal operation.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetPoolMode {
+        pool_seed: [u8; 32],
+        pool_mode: PoolMode,
+    },
+    /// Safety valve for a frozen pool: burn pooltokens against the proportional share of a
+    /// single asset, without going through the fee collection gate of `Redeem`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The spl-token program account
+    ///   1. `[writable]` The pooltoken mint account
+    ///   2. `[signer]` The pooltoken source account owner
+    ///   3. `[writable]` The pooltoken source account
+    ///   4. `[]` The pool account
+    ///   5. `[writable]` The pool (associated) token account of the withdrawn asset
+    ///   6. `[writable]` The target token account
+    EmergencyWithdrawAsset {
+        pool_seed: [u8; 32],
+        asset_index: u64,
+        pool_token_amount: u64,
+    },
+    /// As a signal provider, record the current value of a pooltoken in the pool's NAV
+    /// snapshots account, which is created on first use. One price per pool asset is given,
+    /// in a common quote unit per asset base unit.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The system program account
+    ///   1. `[]` The clock sysvar account
+    ///   2. `[]` The rent sysvar account
+    ///   3. `[writable, signer]` The signal provider account, paying for the snapshots account
+    ///   4. `[]` The pool account
+    ///   5. `[writable]` The NAV snapshots account
+    ///   6. `[]` The pooltoken mint account
+    ///   7..M+7. `[]` The M pool (associated) token assets accounts in the order of the
+    ///      corresponding PoolAssets found in the pool account data.
+    Snapshot {
+        pool_seed: [u8; 32],
+        prices: Vec<u64>,
+    },
+    /// As a signal provider, add a mint to the allow-list of target mints that the pool can
+    /// trade into. The allowed mints account is created on first use, after which new
+    /// target assets must be in the list.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The system program account
+    ///   1. `[]` The rent sysvar account
+    ///   2. `[writable, signer]` The signal provider account, paying for the allowed mints account
+    ///   3. `[]` The pool account
+    ///   4. `[writable]` The allowed mints account
+    AddAllowedMint { pool_seed: [u8; 32], mint: Pubkey },
+    /// As a signal provider, remove a mint from the allow-list of target mints.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[]` The pool account
+    ///   2. `[writable]` The allowed mints account
+    RemoveAllowedMint { pool_seed: [u8; 32], mint: Pubkey },
+    /// As a signal provider, lock the pool to halt deposits without placing an order, or
+    /// unlock it again. Pending orders are kept track of.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    ToggleLock { pool_seed: [u8; 32] },
+    /// As a signal provider, hand off the management of the pool to a new signal provider.
+    /// This is refused while orders are pending so that their fees are not misrouted.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The current signal provider account
+    ///   1. `[writable]` The pool account
+    ChangeSignalProvider {
+        pool_seed: [u8; 32],
+        new_signal_provider: Pubkey,
+    },
+    /// Buy out of the pool into a single asset: the pooltokens are burned against their
+    /// proportional share of the indexed asset. As that share is only their whole value when
+    /// the pool holds nothing else, every other asset tracked by the pool has to be empty. The
+    /// same checks as `Redeem` apply.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The spl-token program account
+    ///   1. `[]` The clock sysvar account
+    ///   2. `[writable]` The pooltoken mint account
+    ///   3. `[signer]` The pooltoken source account owner
+    ///   4. `[writable]` The pooltoken source account
+    ///   5. `[]` The pool account
+    ///   6. `[writable]` The pool (associated) token account of the redeemed asset
+    ///   7. `[writable]` The target token account
+    ///   8..8+N. `[]` The pool (associated) token accounts of the N other assets tracked by the
+    ///      pool, in the order of the pool assets
+    ///   8+N. `[signer]` (optional) The signal provider account, required to approve
+    ///      redemptions before the pool's maturity timestamp when the pool is configured so
+    RedeemSingle {
+        pool_seed: [u8; 32],
+        pool_token_amount: u64,
+        asset_index: u64,
+    },
+    /// As a signal provider, authorize a new market for the pool. The market takes the room
+    /// of one asset slot, so the last asset slot of the pool account must be free.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    AddMarket { pool_seed: [u8; 32], market: Pubkey },
+    /// As a signal provider, change the fee collection period of the pool. Fees owed for the
+    /// elapsed periods must have been collected. The period in progress keeps its start and
+    /// ends after the new period.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The clock sysvar account
+    ///   1. `[signer]` The signal provider account
+    ///   2. `[writable]` The pool account
+    UpdateFeePeriod {
+        pool_seed: [u8; 32],
+        new_period: u64,
+    },
+    /// Buy into the pool by depositing exactly the given asset amounts. The target receives
+    /// the pooltokens backed by the scarcest asset relative to the pool ratios, and the
+    /// deposit is rejected when the amounts deviate from these ratios by more than the given
+    /// tolerance.
+    ///
+    /// Accounts expected by this instruction are the same as for `Deposit`, with a read-only
+    /// pool account.
+    DepositExact {
+        pool_seed: [u8; 32],
+        // Maximum deviation of the amounts from the pool ratios, in basis points
+        tolerance_bps: u16,
+        asset_amounts: Vec<u64>,
+    },
+    /// As a signal provider, cancel all the live serum orders of one of the pool's OpenOrders
+    /// accounts. At most `MAX_CANCELLED_ORDERS_PER_INSTRUCTION` orders are cancelled at once,
+    /// in slot order, to stay within the compute budget. The number of orders left open is
+    /// logged, and the same instruction is sent again to resume while some remain. As with
+    /// `CancelOrder`, the pending order of the OpenOrders account is released by the
+    /// instruction cancelling its last order.
+    ///
+    /// Accounts expected by this instruction are the same as for `CancelOrder`.
+    CancelAllOrders { pool_seed: [u8; 32] },
+    /// Write a summary of the pool to the return data, see `pack_pool_info` for its layout.
+    /// The assets are summarized by pages of `POOL_INFO_ASSETS_PER_PAGE`, so that this
+    /// instruction can be simulated once per page.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The pool account
+    ///   1. `[]` The pooltoken mint account
+    ///   2.. `[]` The pool asset accounts of the assets in the page, in the order of the
+    ///      corresponding PoolAssets in the pool account data.
+    GetPoolInfo { pool_seed: [u8; 32], page: u16 },
+    /// As the signal provider of a pool which has been entirely redeemed, close the pool
+    /// account and transfer its rent lamports to a recipient.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    ///   2. `[]` The pooltoken mint account
+    ///   3. `[writable]` The account receiving the pool account lamports
+    ClosePool { pool_seed: [u8; 32] },
+    /// A permissionless crank consuming the serum event queue events of one of the pool's
+    /// OpenOrders accounts, so that their funds can then be settled. The pending order count
+    /// of the pool is left untouched, it is only updated by `SettleFunds`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The market account
+    ///   1. `[writable]` The pool's OpenOrders account
+    ///   2. `[writable]` The Serum event queue
+    ///   3. `[writable]` The coin fee receivable account
+    ///   4. `[writable]` The pc fee receivable account
+    ///   5. `[]` The pool account
+    ///   6. `[]` The dex program account
+    ConsumeEvents { pool_seed: [u8; 32], limit: u16 },
+    /// As a signal provider, set the pooltoken supply above which deposits are rejected.
+    /// Zero removes the cap.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetDepositCap {
+        pool_seed: [u8; 32],
+        max_pooltoken_supply: u64,
+    },
+    /// As a signal provider, transfer out the whole balance of a token account owned by the
+    /// pool whose mint isn't a pool asset, such as tokens sent to the pool by mistake.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The spl-token program account
+    ///   1. `[signer]` The signal provider account
+    ///   2. `[]` The pool account
+    ///   3. `[writable]` The token account owned by the pool to sweep
+    ///   4. `[writable]` The destination token account
+    SweepUntrackedAsset { pool_seed: [u8; 32] },
+    /// As a signal provider, set the recipient of an extra share of the deposit and collected
+    /// fees, given in basis points and carved out before the fee split. The fees are minted to
+    /// the pooltoken associated token account of the recipient. A zero share removes the
+    /// extra fee recipient.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetExtraFeeRecipient {
+        pool_seed: [u8; 32],
+        recipient: Pubkey,
+        bps: u16,
+    },
+    /// As a signal provider, deposit into the pool and lock it in the same instruction, so
+    /// that no other deposit can come in before the pool is locked. Fees are minted as for
+    /// `Deposit`, and the pool ends up `Locked`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1.. The accounts expected by `Deposit`, with a writable pool account
+    DepositAndLock {
+        pool_seed: [u8; 32],
+        pool_token_amount: u64,
+    },
+    /// As a signal provider, transfer out the whole balance of a token account owned by a pool
+    /// which has been entirely redeemed, such as rounding dust left in a former asset account
+    /// or the assets backing the fees still owed by the last redemption. The pooltoken supply
+    /// must be zero.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The spl-token program account
+    ///   1. `[signer]` The signal provider account
+    ///   2. `[]` The pooltoken mint account
+    ///   3. `[]` The pool account
+    ///   4. `[writable]` The token account owned by the pool to sweep
+    ///   5. `[writable]` The destination token account
+    SweepDust { pool_seed: [u8; 32] },
+    /// As a signal provider, move the pool to a new serum program id, such as after a DEX
+    /// program migration. The pool can't have any pending order, and the new program has to be
+    /// one of the known Serum DEX deployments.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    UpdateDexProgram {
+        pool_seed: [u8; 32],
+        new_program_id: Pubkey,
+    },
+    /// As a signal provider, create the OpenOrders account of the pool on one of its markets
+    /// and initialize it with serum, the pool being its owner. The account address is derived
+    /// from the pool and market keys, see `find_open_orders_key`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` The system program account
+    ///   1. `[]` The rent sysvar account
+    ///   2. `[writable, signer]` The signal provider account, paying for the OpenOrders account
+    ///   3. `[]` The pool account
+    ///   4. `[]` The market account
+    ///   5. `[writable]` The OpenOrders account
+    ///   6. `[]` The dex program account
+    InitOpenOrders {
+        pool_seed: [u8; 32],
+        market_index: u16,
+    },
+    /// As a signal provider, allow or forbid deposits of a single asset with `DepositSingle`.
+    /// These deposits move the pool ratios, so they are forbidden unless enabled.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetSingleAssetDeposits { pool_seed: [u8; 32], enabled: bool },
+    /// Buy into the pool by depositing an amount of a single asset, on pools which allow it.
+    /// The target receives the pooltokens backed by the deposit as a fraction of the value of
+    /// the pool in the asset. The other assets held by the pool are valued at the best orders
+    /// of one of the pool's markets trading them for the deposited asset, on the side which
+    /// values them highest. Both sides of the book should have orders, the opposite side
+    /// bounding the price. The deposit fee applies as for `Deposit`.
+    ///
+    /// Accounts expected by this instruction are the same as for `Deposit`. Only the source
+    /// token account of the deposited asset is used, the others can be any account. They are
+    /// followed by a market triple for each of the other pool assets in order, ignored for the
+    /// assets the pool doesn't hold:
+    ///
+    ///   0. `[]` A market of the pool trading the asset for the deposited asset
+    ///   1. `[]` The bids account of the market
+    ///   2. `[]` The asks account of the market
+    DepositSingle {
+        pool_seed: [u8; 32],
+        asset_index: u64,
+        amount: u64,
+    },
+    /// As a signal provider, set the URI of the off-chain metadata of the pool, such as its
+    /// name and description for front-ends. The URI is opaque to the program, it should be
+    /// UTF-8 and at most `METADATA_URI_LEN` bytes long. An empty URI clears it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetMetadata { pool_seed: [u8; 32], uri: Vec<u8> },
+    /// As a signal provider, set the share of the deposit fee minted to the referrer of a
+    /// deposit in basis points, at most `MAX_REFERRER_FEE_BPS`. Zero stops rewarding
+    /// referrals.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[signer]` The signal provider account
+    ///   1. `[writable]` The pool account
+    SetReferrerFee {
+        pool_seed: [u8; 32],
+        referrer_fee_bps: u16,
+    },
 }
 
 impl PoolInstruction {
@@ -224,10 +704,12 @@ impl PoolInstruction {
                     .and_then(|slice| slice.try_into().ok())
                     .map(u16::from_le_bytes)
                     .ok_or(InvalidInstruction)?;
+                let decimals = rest.get(38).copied().unwrap_or(DEFAULT_POOL_TOKEN_DECIMALS);
                 Self::Init {
                     pool_seed,
                     max_number_of_assets,
                     number_of_markets,
+                    decimals,
                 }
             }
             1 => {
@@ -250,8 +732,28 @@ impl PoolInstruction {
                     .and_then(|slice| slice.try_into().ok())
                     .map(u16::from_le_bytes)
                     .ok_or(InvalidInstruction)?;
+                let deposit_fee_ratio = rest
+                    .get(44..46)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let rebate_retention_ratio = rest
+                    .get(46..48)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let pool_mode = rest
+                    .get(48)
+                    .and_then(|mode| PoolMode::from_u8(*mode))
+                    .ok_or(InvalidInstruction)?;
+                let redeem_requires_provider = *rest.get(49).ok_or(InvalidInstruction)? != 0;
+                let maturity_timestamp = rest
+                    .get(50..58)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
                 let mut markets = Vec::with_capacity(number_of_markets as usize);
-                let mut offset = 44;
+                let mut offset = 58;
                 for _ in 0..number_of_markets {
                     markets.push(
                         rest.get(offset..offset + 32)
@@ -261,6 +763,20 @@ impl PoolInstruction {
                     );
                     offset = offset + 32;
                 }
+                // The fee split isn't a multiple of 8 bytes long, which tells it apart from the
+                // deposit amounts
+                let (rest, fee_split_bps) = match rest.len().saturating_sub(offset) % 8 {
+                    0 => (rest, None),
+                    FEE_SPLIT_LEN => {
+                        let (rest, fee_split) = rest.split_at(rest.len() - FEE_SPLIT_LEN);
+                        let signal_provider_fee_bps =
+                            u16::from_le_bytes(fee_split[..2].try_into().unwrap());
+                        let bonfida_fee_bps =
+                            u16::from_le_bytes(fee_split[2..].try_into().unwrap());
+                        (rest, Some((signal_provider_fee_bps, bonfida_fee_bps)))
+                    }
+                    _ => return Err(InvalidInstruction.into()),
+                };
                 let mut k = offset;
                 let mut deposit_amounts = vec![];
                 while k != 0 {
@@ -278,6 +794,12 @@ impl PoolInstruction {
                     deposit_amounts,
                     fee_collection_period,
                     fee_ratio,
+                    deposit_fee_ratio,
+                    rebate_retention_ratio,
+                    pool_mode,
+                    redeem_requires_provider,
+                    maturity_timestamp,
+                    fee_split_bps,
                 }
             }
             2 => {
@@ -290,9 +812,11 @@ impl PoolInstruction {
                     .and_then(|slice| slice.try_into().ok())
                     .map(u64::from_le_bytes)
                     .ok_or(InvalidInstruction)?;
+                let with_referrer = rest.get(40).map_or(false, |byte| *byte != 0);
                 Self::Deposit {
                     pool_seed,
                     pool_token_amount,
+                    with_referrer,
                 }
             }
             3 => {
@@ -372,6 +896,14 @@ impl PoolInstruction {
                     .and_then(|slice| slice.try_into().ok())
                     .map(u16::from_le_bytes)
                     .ok_or(InvalidInstruction)?;
+                let min_expected_lots = match rest.get(121..) {
+                    None | Some([]) => 0,
+                    Some(bytes) => bytes
+                        .try_into()
+                        .ok()
+                        .map(u64::from_le_bytes)
+                        .ok_or(InvalidInstruction)?,
+                };
                 Self::CreateOrder {
                     pool_seed,
                     side,
@@ -386,7 +918,8 @@ impl PoolInstruction {
                     coin_lot_size,
                     pc_lot_size,
                     target_mint,
-                    serum_limit
+                    serum_limit,
+                    min_expected_lots,
                 }
             }
             4 => {
@@ -428,8 +961,8 @@ impl PoolInstruction {
                     .ok_or(InvalidInstruction)?;
                 Self::SettleFunds {
                     pool_seed,
-                    pc_index,
-                    coin_index,
+                    pc_index: Some(pc_index).filter(|i| *i != SETTLE_SKIPPED_INDEX),
+                    coin_index: Some(coin_index).filter(|i| *i != SETTLE_SKIPPED_INDEX),
                 }
             }
             6 => {
@@ -454,174 +987,2026 @@ impl PoolInstruction {
                     .unwrap();
                 Self::CollectFees { pool_seed }
             }
-            _ => {
-                msg!("Unsupported tag");
-                return Err(InvalidInstruction.into());
-            }
-        })
-    }
-
-    pub fn pack(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(size_of::<Self>());
-        match self {
-            Self::Init {
-                pool_seed,
-                max_number_of_assets,
-                number_of_markets,
-            } => {
-                buf.push(0);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(&max_number_of_assets.to_le_bytes());
-                buf.extend_from_slice(&number_of_markets.to_le_bytes());
+            8 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::PublishStateHash { pool_seed }
             }
-            Self::Create {
-                pool_seed,
-                fee_collection_period,
-                fee_ratio,
-                deposit_amounts,
-                markets,
-            } => {
-                buf.push(1);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(&(markets.len() as u16).to_le_bytes());
-                buf.extend_from_slice(&fee_collection_period.to_le_bytes());
-                buf.extend_from_slice(&fee_ratio.to_le_bytes());
-                for market in markets {
-                    buf.extend_from_slice(&market.to_bytes())
-                }
-                for amount in deposit_amounts.iter() {
-                    buf.extend_from_slice(&amount.to_le_bytes());
+            9 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let pool_token_amount = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::RedeemUnwrapSol {
+                    pool_seed,
+                    pool_token_amount,
                 }
             }
-            Self::Deposit {
-                pool_seed,
-                pool_token_amount,
-            } => {
-                buf.push(2);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            10 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let new_fee_ratio = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::UpdateFeeRatio {
+                    pool_seed,
+                    new_fee_ratio,
+                }
             }
-            Self::CreateOrder {
-                pool_seed,
-                side,
-                limit_price,
-                ratio_of_pool_assets_to_trade,
-                order_type,
-                client_id,
-                self_trade_behavior,
-                source_index,
-                target_index,
-                market_index,
-                coin_lot_size,
-                pc_lot_size,
-                target_mint,
-                serum_limit
-            } => {
-                buf.push(3);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(
-                    &match side {
-                        Side::Bid => 0u8,
-                        Side::Ask => 1,
-                    }
-                    .to_le_bytes(),
-                );
-                buf.extend_from_slice(&limit_price.get().to_le_bytes());
-                buf.extend_from_slice(&ratio_of_pool_assets_to_trade.get().to_le_bytes());
-                buf.extend_from_slice(
-                    &match order_type {
-                        OrderType::Limit => 0u8,
-                        OrderType::ImmediateOrCancel => 1,
-                        OrderType::PostOnly => 2,
-                    }
-                    .to_le_bytes(),
-                );
-                buf.extend_from_slice(&client_id.to_le_bytes());
-                buf.extend_from_slice(
-                    &match self_trade_behavior {
-                        SelfTradeBehavior::DecrementTake => 0u8,
-                        SelfTradeBehavior::CancelProvide => 1,
-                        SelfTradeBehavior::AbortTransaction => 2,
-                    }
-                    .to_le_bytes(),
-                );
-                buf.extend_from_slice(&source_index.to_le_bytes());
-                buf.extend_from_slice(&target_index.to_le_bytes());
-                buf.extend_from_slice(&market_index.to_le_bytes());
-                buf.extend_from_slice(&coin_lot_size.to_le_bytes());
-                buf.extend_from_slice(&pc_lot_size.to_le_bytes());
-                buf.extend_from_slice(&target_mint.to_bytes());
-                buf.extend_from_slice(&serum_limit.to_le_bytes())
+            11 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let market_index = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let source_index = rest
+                    .get(34..42)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::ValidateOrderAccounts {
+                    pool_seed,
+                    market_index,
+                    source_index,
+                }
             }
-            Self::CancelOrder {
-                pool_seed,
-                side,
-                order_id,
-            } => {
-                buf.push(4);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(
-                    &match side {
-                        Side::Bid => 0u8,
-                        Side::Ask => 1,
-                    }
-                    .to_le_bytes(),
-                );
-                buf.extend_from_slice(&order_id.to_le_bytes());
+            12 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let asset_index = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::RecreateAssetAccount {
+                    pool_seed,
+                    asset_index,
+                }
             }
-            Self::SettleFunds {
-                pool_seed,
-                pc_index,
-                coin_index,
-            } => {
-                buf.push(5);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(&pc_index.to_le_bytes());
-                buf.extend_from_slice(&coin_index.to_le_bytes());
+            13 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let pool_mode = rest
+                    .get(32)
+                    .and_then(|mode| PoolMode::from_u8(*mode))
+                    .ok_or(InvalidInstruction)?;
+                Self::SetPoolMode {
+                    pool_seed,
+                    pool_mode,
+                }
             }
-            Self::Redeem {
-                pool_seed,
-                pool_token_amount,
-            } => {
-                buf.push(6);
-                buf.extend_from_slice(pool_seed);
-                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            14 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let asset_index = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let pool_token_amount = rest
+                    .get(40..48)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::EmergencyWithdrawAsset {
+                    pool_seed,
+                    asset_index,
+                    pool_token_amount,
+                }
             }
-            Self::CollectFees { pool_seed } => {
-                buf.push(7);
-                buf.extend_from_slice(pool_seed);
+            15 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let prices = rest[32..]
+                    .chunks_exact(8)
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                Self::Snapshot { pool_seed, prices }
             }
-        };
-        buf
-    }
+            16 | 17 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let mint = rest
+                    .get(32..64)
+                    .map(Pubkey::new)
+                    .ok_or(InvalidInstruction)?;
+                match tag {
+                    16 => Self::AddAllowedMint { pool_seed, mint },
+                    _ => Self::RemoveAllowedMint { pool_seed, mint },
+                }
+            }
+            18 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::ToggleLock { pool_seed }
+            }
+            19 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let new_signal_provider = rest
+                    .get(32..64)
+                    .map(Pubkey::new)
+                    .ok_or(InvalidInstruction)?;
+                Self::ChangeSignalProvider {
+                    pool_seed,
+                    new_signal_provider,
+                }
+            }
+            20 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let pool_token_amount = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let asset_index = rest
+                    .get(40..48)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::RedeemSingle {
+                    pool_seed,
+                    pool_token_amount,
+                    asset_index,
+                }
+            }
+            21 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let market = rest
+                    .get(32..64)
+                    .map(Pubkey::new)
+                    .ok_or(InvalidInstruction)?;
+                Self::AddMarket { pool_seed, market }
+            }
+            22 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let new_period = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::UpdateFeePeriod {
+                    pool_seed,
+                    new_period,
+                }
+            }
+            23 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let tolerance_bps = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let amounts_bytes = rest.get(34..).ok_or(InvalidInstruction)?;
+                if amounts_bytes.len() % 8 != 0 {
+                    return Err(InvalidInstruction.into());
+                }
+                let asset_amounts = amounts_bytes
+                    .chunks_exact(8)
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                Self::DepositExact {
+                    pool_seed,
+                    tolerance_bps,
+                    asset_amounts,
+                }
+            }
+            24 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::CancelAllOrders { pool_seed }
+            }
+            25 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let page = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::GetPoolInfo { pool_seed, page }
+            }
+            26 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::ClosePool { pool_seed }
+            }
+            27 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let limit = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::ConsumeEvents { pool_seed, limit }
+            }
+            28 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let max_pooltoken_supply = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::SetDepositCap {
+                    pool_seed,
+                    max_pooltoken_supply,
+                }
+            }
+            29 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::SweepUntrackedAsset { pool_seed }
+            }
+            30 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let recipient = rest
+                    .get(32..64)
+                    .map(Pubkey::new)
+                    .ok_or(InvalidInstruction)?;
+                let bps = rest
+                    .get(64..66)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::SetExtraFeeRecipient {
+                    pool_seed,
+                    recipient,
+                    bps,
+                }
+            }
+            31 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let pool_token_amount = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::DepositAndLock {
+                    pool_seed,
+                    pool_token_amount,
+                }
+            }
+            32 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                Self::SweepDust { pool_seed }
+            }
+            33 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let new_program_id = rest
+                    .get(32..64)
+                    .map(Pubkey::new)
+                    .ok_or(InvalidInstruction)?;
+                Self::UpdateDexProgram {
+                    pool_seed,
+                    new_program_id,
+                }
+            }
+            34 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let market_index = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::InitOpenOrders {
+                    pool_seed,
+                    market_index,
+                }
+            }
+            35 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let enabled = *rest.get(32).ok_or(InvalidInstruction)? != 0;
+                Self::SetSingleAssetDeposits { pool_seed, enabled }
+            }
+            36 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let asset_index = rest
+                    .get(32..40)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                let amount = rest
+                    .get(40..48)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::DepositSingle {
+                    pool_seed,
+                    asset_index,
+                    amount,
+                }
+            }
+            37 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let uri = rest[32..].to_vec();
+                Self::SetMetadata { pool_seed, uri }
+            }
+            38 => {
+                let pool_seed: [u8; 32] = rest
+                    .get(..32)
+                    .and_then(|slice| slice.try_into().ok())
+                    .ok_or(InvalidInstruction)?;
+                let referrer_fee_bps = rest
+                    .get(32..34)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u16::from_le_bytes)
+                    .ok_or(InvalidInstruction)?;
+                Self::SetReferrerFee {
+                    pool_seed,
+                    referrer_fee_bps,
+                }
+            }
+            _ => {
+                msg!("Unsupported tag");
+                return Err(InvalidInstruction.into());
+            }
+        })
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(size_of::<Self>());
+        match self {
+            Self::Init {
+                pool_seed,
+                max_number_of_assets,
+                number_of_markets,
+                decimals,
+            } => {
+                buf.push(0);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&max_number_of_assets.to_le_bytes());
+                buf.extend_from_slice(&number_of_markets.to_le_bytes());
+                buf.push(*decimals);
+            }
+            Self::Create {
+                pool_seed,
+                fee_collection_period,
+                fee_ratio,
+                deposit_fee_ratio,
+                rebate_retention_ratio,
+                pool_mode,
+                redeem_requires_provider,
+                maturity_timestamp,
+                fee_split_bps,
+                deposit_amounts,
+                markets,
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&(markets.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&fee_collection_period.to_le_bytes());
+                buf.extend_from_slice(&fee_ratio.to_le_bytes());
+                buf.extend_from_slice(&deposit_fee_ratio.to_le_bytes());
+                buf.extend_from_slice(&rebate_retention_ratio.to_le_bytes());
+                buf.push(*pool_mode as u8);
+                buf.push(*redeem_requires_provider as u8);
+                buf.extend_from_slice(&maturity_timestamp.to_le_bytes());
+                for market in markets {
+                    buf.extend_from_slice(&market.to_bytes())
+                }
+                for amount in deposit_amounts.iter() {
+                    buf.extend_from_slice(&amount.to_le_bytes());
+                }
+                if let Some((signal_provider_fee_bps, bonfida_fee_bps)) = fee_split_bps {
+                    buf.extend_from_slice(&signal_provider_fee_bps.to_le_bytes());
+                    buf.extend_from_slice(&bonfida_fee_bps.to_le_bytes());
+                }
+            }
+            Self::Deposit {
+                pool_seed,
+                pool_token_amount,
+                with_referrer,
+            } => {
+                buf.push(2);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                if *with_referrer {
+                    buf.push(1);
+                }
+            }
+            Self::CreateOrder {
+                pool_seed,
+                side,
+                limit_price,
+                ratio_of_pool_assets_to_trade,
+                order_type,
+                client_id,
+                self_trade_behavior,
+                source_index,
+                target_index,
+                market_index,
+                coin_lot_size,
+                pc_lot_size,
+                target_mint,
+                serum_limit,
+                min_expected_lots,
+            } => {
+                buf.push(3);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(
+                    &match side {
+                        Side::Bid => 0u8,
+                        Side::Ask => 1,
+                    }
+                    .to_le_bytes(),
+                );
+                buf.extend_from_slice(&limit_price.get().to_le_bytes());
+                buf.extend_from_slice(&ratio_of_pool_assets_to_trade.get().to_le_bytes());
+                buf.extend_from_slice(
+                    &match order_type {
+                        OrderType::Limit => 0u8,
+                        OrderType::ImmediateOrCancel => 1,
+                        OrderType::PostOnly => 2,
+                    }
+                    .to_le_bytes(),
+                );
+                buf.extend_from_slice(&client_id.to_le_bytes());
+                buf.extend_from_slice(
+                    &match self_trade_behavior {
+                        SelfTradeBehavior::DecrementTake => 0u8,
+                        SelfTradeBehavior::CancelProvide => 1,
+                        SelfTradeBehavior::AbortTransaction => 2,
+                    }
+                    .to_le_bytes(),
+                );
+                buf.extend_from_slice(&source_index.to_le_bytes());
+                buf.extend_from_slice(&target_index.to_le_bytes());
+                buf.extend_from_slice(&market_index.to_le_bytes());
+                buf.extend_from_slice(&coin_lot_size.to_le_bytes());
+                buf.extend_from_slice(&pc_lot_size.to_le_bytes());
+                buf.extend_from_slice(&target_mint.to_bytes());
+                buf.extend_from_slice(&serum_limit.to_le_bytes());
+                buf.extend_from_slice(&min_expected_lots.to_le_bytes());
+            }
+            Self::CancelOrder {
+                pool_seed,
+                side,
+                order_id,
+            } => {
+                buf.push(4);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(
+                    &match side {
+                        Side::Bid => 0u8,
+                        Side::Ask => 1,
+                    }
+                    .to_le_bytes(),
+                );
+                buf.extend_from_slice(&order_id.to_le_bytes());
+            }
+            Self::SettleFunds {
+                pool_seed,
+                pc_index,
+                coin_index,
+            } => {
+                buf.push(5);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pc_index.unwrap_or(SETTLE_SKIPPED_INDEX).to_le_bytes());
+                buf.extend_from_slice(&coin_index.unwrap_or(SETTLE_SKIPPED_INDEX).to_le_bytes());
+            }
+            Self::Redeem {
+                pool_seed,
+                pool_token_amount,
+            } => {
+                buf.push(6);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            }
+            Self::CollectFees { pool_seed } => {
+                buf.push(7);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::PublishStateHash { pool_seed } => {
+                buf.push(8);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::RedeemUnwrapSol {
+                pool_seed,
+                pool_token_amount,
+            } => {
+                buf.push(9);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            }
+            Self::UpdateFeeRatio {
+                pool_seed,
+                new_fee_ratio,
+            } => {
+                buf.push(10);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&new_fee_ratio.to_le_bytes());
+            }
+            Self::ValidateOrderAccounts {
+                pool_seed,
+                market_index,
+                source_index,
+            } => {
+                buf.push(11);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&market_index.to_le_bytes());
+                buf.extend_from_slice(&source_index.to_le_bytes());
+            }
+            Self::RecreateAssetAccount {
+                pool_seed,
+                asset_index,
+            } => {
+                buf.push(12);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&asset_index.to_le_bytes());
+            }
+            Self::SetPoolMode {
+                pool_seed,
+                pool_mode,
+            } => {
+                buf.push(13);
+                buf.extend_from_slice(pool_seed);
+                buf.push(*pool_mode as u8);
+            }
+            Self::EmergencyWithdrawAsset {
+                pool_seed,
+                asset_index,
+                pool_token_amount,
+            } => {
+                buf.push(14);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&asset_index.to_le_bytes());
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            }
+            Self::Snapshot { pool_seed, prices } => {
+                buf.push(15);
+                buf.extend_from_slice(pool_seed);
+                for price in prices.iter() {
+                    buf.extend_from_slice(&price.to_le_bytes());
+                }
+            }
+            Self::AddAllowedMint { pool_seed, mint } => {
+                buf.push(16);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&mint.to_bytes());
+            }
+            Self::RemoveAllowedMint { pool_seed, mint } => {
+                buf.push(17);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&mint.to_bytes());
+            }
+            Self::ToggleLock { pool_seed } => {
+                buf.push(18);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::ChangeSignalProvider {
+                pool_seed,
+                new_signal_provider,
+            } => {
+                buf.push(19);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&new_signal_provider.to_bytes());
+            }
+            Self::RedeemSingle {
+                pool_seed,
+                pool_token_amount,
+                asset_index,
+            } => {
+                buf.push(20);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+                buf.extend_from_slice(&asset_index.to_le_bytes());
+            }
+            Self::AddMarket { pool_seed, market } => {
+                buf.push(21);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&market.to_bytes());
+            }
+            Self::UpdateFeePeriod {
+                pool_seed,
+                new_period,
+            } => {
+                buf.push(22);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&new_period.to_le_bytes());
+            }
+            Self::DepositExact {
+                pool_seed,
+                tolerance_bps,
+                asset_amounts,
+            } => {
+                buf.push(23);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&tolerance_bps.to_le_bytes());
+                for amount in asset_amounts.iter() {
+                    buf.extend_from_slice(&amount.to_le_bytes());
+                }
+            }
+            Self::CancelAllOrders { pool_seed } => {
+                buf.push(24);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::GetPoolInfo { pool_seed, page } => {
+                buf.push(25);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&page.to_le_bytes());
+            }
+            Self::ClosePool { pool_seed } => {
+                buf.push(26);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::ConsumeEvents { pool_seed, limit } => {
+                buf.push(27);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&limit.to_le_bytes());
+            }
+            Self::SetDepositCap {
+                pool_seed,
+                max_pooltoken_supply,
+            } => {
+                buf.push(28);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&max_pooltoken_supply.to_le_bytes());
+            }
+            Self::SweepUntrackedAsset { pool_seed } => {
+                buf.push(29);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::SetExtraFeeRecipient {
+                pool_seed,
+                recipient,
+                bps,
+            } => {
+                buf.push(30);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&recipient.to_bytes());
+                buf.extend_from_slice(&bps.to_le_bytes());
+            }
+            Self::DepositAndLock {
+                pool_seed,
+                pool_token_amount,
+            } => {
+                buf.push(31);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&pool_token_amount.to_le_bytes());
+            }
+            Self::SweepDust { pool_seed } => {
+                buf.push(32);
+                buf.extend_from_slice(pool_seed);
+            }
+            Self::UpdateDexProgram {
+                pool_seed,
+                new_program_id,
+            } => {
+                buf.push(33);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&new_program_id.to_bytes());
+            }
+            Self::InitOpenOrders {
+                pool_seed,
+                market_index,
+            } => {
+                buf.push(34);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&market_index.to_le_bytes());
+            }
+            Self::SetSingleAssetDeposits { pool_seed, enabled } => {
+                buf.push(35);
+                buf.extend_from_slice(pool_seed);
+                buf.push(*enabled as u8);
+            }
+            Self::DepositSingle {
+                pool_seed,
+                asset_index,
+                amount,
+            } => {
+                buf.push(36);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&asset_index.to_le_bytes());
+                buf.extend_from_slice(&amount.to_le_bytes());
+            }
+            Self::SetMetadata { pool_seed, uri } => {
+                buf.push(37);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(uri);
+            }
+            Self::SetReferrerFee {
+                pool_seed,
+                referrer_fee_bps,
+            } => {
+                buf.push(38);
+                buf.extend_from_slice(pool_seed);
+                buf.extend_from_slice(&referrer_fee_bps.to_le_bytes());
+            }
+        };
+        buf
+    }
+}
+
+/// Number of accounts expected by `Create`: 7 fixed accounts, the source owner account and
+/// a pool asset account and a source account for each asset
+pub fn create_accounts_len(number_of_assets: usize) -> usize {
+    8 + 2 * number_of_assets
+}
+
+/// Number of accounts expected by `Deposit`: 7 fixed accounts, the source owner account and
+/// a pool asset account and a source account for each asset
+pub fn deposit_accounts_len(number_of_assets: usize) -> usize {
+    8 + 2 * number_of_assets
+}
+
+/// Index of the pool account among the accounts expected by `Deposit`
+pub const DEPOSIT_POOL_ACCOUNT_INDEX: usize = 6;
+
+/// Number of accounts expected by `Redeem` and `RedeemUnwrapSol`: 6 fixed accounts and a
+/// pool asset account and a target account for each asset. The optional signal provider
+/// account is not included.
+pub fn redeem_accounts_len(number_of_assets: usize) -> usize {
+    6 + 2 * number_of_assets
+}
+
+/// Checks that the parameters of a `Create` instruction are consistent with the allocation
+/// made by `Init`, so that clients can catch mistakes before submitting the transaction.
+pub fn validate_create_params(
+    max_number_of_assets: u32,
+    number_of_markets: u16,
+    deposit_amounts: &[u64],
+    markets: &[Pubkey],
+) -> Result<(), String> {
+    if deposit_amounts.is_empty() {
+        return Err("At least one asset should be deposited".to_string());
+    }
+    if deposit_amounts.len() > max_number_of_assets as usize {
+        return Err(format!(
+            "{} deposit amounts were given but the pool was initialized for at most {} assets",
+            deposit_amounts.len(),
+            max_number_of_assets
+        ));
+    }
+    if deposit_amounts.iter().all(|amount| *amount == 0) {
+        return Err("All deposit amounts are zero".to_string());
+    }
+    if markets.len() > number_of_markets as usize {
+        return Err(format!(
+            "{} markets were given but the pool was initialized for at most {} markets",
+            markets.len(),
+            number_of_markets
+        ));
+    }
+    Ok(())
+}
+
+// Creates a `Init` instruction
+pub fn init(
+    spl_token_program_id: &Pubkey,
+    system_program_id: &Pubkey,
+    rent_program_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    payer_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    max_number_of_assets: u32,
+    number_of_markets: u16,
+    decimals: u8,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::Init {
+        pool_seed,
+        max_number_of_assets,
+        number_of_markets,
+        decimals,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*rent_program_id, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new(*pool_key, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new(*payer_key, true),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `CreatePool` instruction
+pub fn create(
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    pool_asset_keys: &Vec<Pubkey>,
+    target_pool_token_key: &Pubkey,
+    source_owner_key: &Pubkey,
+    source_asset_keys: &Vec<Pubkey>,
+    serum_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    fee_collection_period: u64,
+    fee_ratio: u16,
+    // Defaults to `fee_ratio` when not provided
+    deposit_fee_ratio: Option<u16>,
+    rebate_retention_ratio: u16,
+    pool_mode: PoolMode,
+    redeem_requires_provider: bool,
+    maturity_timestamp: u64,
+    // The (signal provider, Bonfida fee account) shares of the fees in basis points, defaults
+    // to the 1/2, 1/4, 1/4 split when not provided
+    fee_split_bps: Option<(u16, u16)>,
+    deposit_amounts: Vec<u64>,
+    markets: Vec<Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::Create {
+        pool_seed,
+        deposit_amounts,
+        markets,
+        fee_collection_period,
+        fee_ratio,
+        deposit_fee_ratio: deposit_fee_ratio.unwrap_or(fee_ratio),
+        rebate_retention_ratio,
+        pool_mode,
+        redeem_requires_provider,
+        maturity_timestamp,
+        fee_split_bps,
+    }
+    .pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*serum_program_id, false),
+        AccountMeta::new_readonly(*signal_provider_key, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new(*target_pool_token_key, false),
+        AccountMeta::new(*pool_key, false),
+    ];
+    for pool_asset_key in pool_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*pool_asset_key, false))
+    }
+    accounts.push(AccountMeta::new_readonly(*source_owner_key, true));
+    for source_asset_key in source_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*source_asset_key, false))
+    }
+
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `Deposit` instruction
+pub fn deposit(
+    spl_token_program_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    target_pool_token_key: &Pubkey,
+    signal_provider_pool_token_key: &Pubkey,
+    source_owner: &Pubkey,
+    source_asset_keys: &Vec<Pubkey>,
+    pool_seed: [u8; 32],
+    pool_token_amount: u64,
+    // The referrer pooltoken account, rewarded on pools with a referrer fee
+    referrer_pool_token_key: Option<&Pubkey>,
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::Deposit {
+        pool_seed,
+        pool_token_amount,
+        with_referrer: referrer_pool_token_key.is_some(),
+    }
+    .pack();
+    let bonfida_fee_pt_account =
+        get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), mint_key);
+    let bonfida_bnb_pt_account =
+        get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), mint_key);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new(*target_pool_token_key, false),
+        AccountMeta::new(*signal_provider_pool_token_key, false),
+        AccountMeta::new(bonfida_fee_pt_account, false),
+        AccountMeta::new(bonfida_bnb_pt_account, false),
+        AccountMeta::new_readonly(*pool_key, false),
+    ];
+    for pool_asset_key in pool_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*pool_asset_key, false))
+    }
+    accounts.push(AccountMeta::new_readonly(*source_owner, true));
+    for source_asset_key in source_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*source_asset_key, false))
+    }
+    if let Some(referrer_pool_token_key) = referrer_pool_token_key {
+        accounts.push(AccountMeta::new(*referrer_pool_token_key, false))
+    }
+    if let Some(extra_fee_recipient) = extra_fee_recipient {
+        accounts.push(AccountMeta::new(
+            get_associated_token_address(extra_fee_recipient, mint_key),
+            false,
+        ))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `Redeem` instruction
+pub fn redeem(
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    source_pool_token_owner_key: &Pubkey,
+    source_pool_token_key: &Pubkey,
+    target_asset_keys: &Vec<Pubkey>,
+    pool_seed: [u8; 32],
+    pool_token_amount: u64,
+    // Signal provider co-signing the redemption, for pools requiring it before maturity
+    signal_provider_key: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::Redeem {
+        pool_seed,
+        pool_token_amount,
+    }
+    .pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new_readonly(*source_pool_token_owner_key, true),
+        AccountMeta::new(*source_pool_token_key, false),
+        AccountMeta::new(*pool_key, false),
+    ];
+    for pool_asset_key in pool_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*pool_asset_key, false))
+    }
+    for source_asset_key in target_asset_keys.iter() {
+        accounts.push(AccountMeta::new(*source_asset_key, false))
+    }
+    if let Some(signal_provider_key) = signal_provider_key {
+        accounts.push(AccountMeta::new_readonly(*signal_provider_key, true))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `RedeemUnwrapSol` instruction
+pub fn redeem_unwrap_sol(
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    source_pool_token_owner_key: &Pubkey,
+    source_pool_token_key: &Pubkey,
+    target_asset_keys: &Vec<Pubkey>,
+    pool_seed: [u8; 32],
+    pool_token_amount: u64,
+    // Signal provider co-signing the redemption, for pools requiring it before maturity
+    signal_provider_key: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = redeem(
+        spl_token_program_id,
+        clock_sysvar_id,
+        bonfidabot_program_id,
+        mint_key,
+        pool_key,
+        pool_asset_keys,
+        source_pool_token_owner_key,
+        source_pool_token_key,
+        target_asset_keys,
+        pool_seed,
+        pool_token_amount,
+        signal_provider_key,
+    )?;
+    instruction.data = PoolInstruction::RedeemUnwrapSol {
+        pool_seed,
+        pool_token_amount,
+    }
+    .pack();
+    // The owner receives the unwrapped lamports
+    instruction.accounts[3] = AccountMeta::new(*source_pool_token_owner_key, true);
+    Ok(instruction)
+}
+
+// Creates a `CreateOrder` instruction
+pub fn create_order(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    market: &Pubkey,
+    payer_pool_asset_account: &Pubkey,
+    payer_pool_asset_index: u64,
+    target_pool_asset_index: u64,
+    openorders_account: &Pubkey,
+    serum_event_queue: &Pubkey,
+    serum_request_queue: &Pubkey,
+    serum_market_bids: &Pubkey,
+    serum_market_asks: &Pubkey,
+    pool_account: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program: &Pubkey,
+    dex_program: &Pubkey,
+    rent_sysvar: &Pubkey,
+    srm_discount_account: Option<&Pubkey>,
+    pool_seed: [u8; 32],
+    side: Side,
+    limit_price: NonZeroU64,
+    market_index: u16,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+    target_mint: &Pubkey,
+    ratio_of_pool_assets_to_trade: NonZeroU16,
+    order_type: OrderType,
+    client_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+    serum_limit: u16,
+    min_expected_lots: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::CreateOrder {
+        pool_seed,
+        side,
+        limit_price,
+        ratio_of_pool_assets_to_trade,
+        order_type,
+        client_id,
+        self_trade_behavior,
+        source_index: payer_pool_asset_index,
+        target_index: target_pool_asset_index,
+        market_index,
+        coin_lot_size,
+        pc_lot_size,
+        target_mint: *target_mint,
+        serum_limit,
+        min_expected_lots,
+    }
+    .pack();
+    let (allowed_mints_key, _) = find_allowed_mints_key(bonfidabot_program_id, pool_account);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*payer_pool_asset_account, false),
+        AccountMeta::new(*openorders_account, false),
+        AccountMeta::new(*serum_event_queue, false),
+        AccountMeta::new(*serum_request_queue, false),
+        AccountMeta::new(*serum_market_bids, false),
+        AccountMeta::new(*serum_market_asks, false),
+        AccountMeta::new(*pool_account, false),
+        AccountMeta::new(*coin_vault, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new_readonly(*spl_token_program, false),
+        AccountMeta::new_readonly(*rent_sysvar, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    if let Some(key) = srm_discount_account {
+        accounts.push(AccountMeta::new(*key, false));
+    }
+    accounts.push(AccountMeta::new_readonly(allowed_mints_key, false));
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `CancelOrder` instruction
+pub fn cancel_order(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    market: &Pubkey,
+    openorders_account: &Pubkey,
+    serum_market_bids: &Pubkey,
+    serum_market_asks: &Pubkey,
+    serum_event_queue: &Pubkey,
+    pool_account: &Pubkey,
+    dex_program: &Pubkey,
+    pool_seed: [u8; 32],
+    side: Side,
+    order_id: u128,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::CancelOrder {
+        pool_seed,
+        side,
+        order_id,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*openorders_account, false),
+        AccountMeta::new(*serum_market_bids, false),
+        AccountMeta::new(*serum_market_asks, false),
+        AccountMeta::new(*serum_event_queue, false),
+        AccountMeta::new(*pool_account, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `SettleFunds` instruction
+pub fn settle_funds(
+    bonfidabot_program_id: &Pubkey,
+    market: &Pubkey,
+    openorders_account: &Pubkey,
+    pool_account: &Pubkey,
+    pool_token_mint: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    pool_coin_wallet: &Pubkey,
+    pool_pc_wallet: &Pubkey,
+    vault_signer: &Pubkey,
+    spl_token_program: &Pubkey,
+    dex_program: &Pubkey,
+    referrer_pc_account: Option<&Pubkey>,
+    signal_provider_pc_account: Option<&Pubkey>,
+    pool_seed: [u8; 32],
+    pc_index: Option<u64>,
+    coin_index: Option<u64>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::SettleFunds {
+        pool_seed,
+        pc_index,
+        coin_index,
+    }
+    .pack();
+
+    let mut accounts = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*openorders_account, false),
+        AccountMeta::new(*pool_account, false),
+        AccountMeta::new_readonly(*pool_token_mint, false),
+        AccountMeta::new(*coin_vault, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new(*pool_coin_wallet, false),
+        AccountMeta::new(*pool_pc_wallet, false),
+        AccountMeta::new_readonly(*vault_signer, false),
+        AccountMeta::new_readonly(*spl_token_program, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    if let Some(key) = referrer_pc_account {
+        accounts.push(AccountMeta::new(*key, false))
+    }
+    if let Some(key) = signal_provider_pc_account {
+        accounts.push(AccountMeta::new(*key, false))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `CollectFees` instruction
+pub fn collect_fees(
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    pool_key: &Pubkey,
+    pool_token_mint: &Pubkey,
+    signal_provider_pool_token_key: &Pubkey,
+    pool_seed: [u8; 32],
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::CollectFees { pool_seed }.pack();
+
+    let bonfida_fee_pt_account =
+        get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), pool_token_mint);
+    let bonfida_bnb_pt_account =
+        get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), pool_token_mint);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new(*pool_key, false),
+        AccountMeta::new(*pool_token_mint, false),
+        AccountMeta::new(*signal_provider_pool_token_key, false),
+        AccountMeta::new(bonfida_fee_pt_account, false),
+        AccountMeta::new(bonfida_bnb_pt_account, false),
+    ];
+    if let Some(extra_fee_recipient) = extra_fee_recipient {
+        accounts.push(AccountMeta::new(
+            get_associated_token_address(extra_fee_recipient, pool_token_mint),
+            false,
+        ))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates an `UpdateFeeRatio` instruction
+pub fn update_fee_ratio(
+    bonfidabot_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    new_fee_ratio: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::UpdateFeeRatio {
+        pool_seed,
+        new_fee_ratio,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Returns the ordered instructions setting up a pool from scratch: `Init`, the creation of the
+/// pool asset accounts, of the creator's pooltoken account and of the pooltoken fee accounts,
+/// then `Create`. They can be split over several transactions as long as the order is kept.
+pub fn bootstrap_pool_instructions(
+    bonfidabot_program_id: &Pubkey,
+    payer_key: &Pubkey,
+    pool_seed: [u8; 32],
+    max_number_of_assets: u32,
+    asset_mints: &[Pubkey],
+    source_owner_key: &Pubkey,
+    source_asset_keys: &Vec<Pubkey>,
+    serum_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    fee_collection_period: u64,
+    fee_ratio: u16,
+    deposit_fee_ratio: Option<u16>,
+    rebate_retention_ratio: u16,
+    pool_mode: PoolMode,
+    redeem_requires_provider: bool,
+    maturity_timestamp: u64,
+    fee_split_bps: Option<(u16, u16)>,
+    deposit_amounts: Vec<u64>,
+    markets: Vec<Pubkey>,
+) -> Result<Vec<Instruction>, ProgramError> {
+    let PoolAddresses {
+        pool_key, mint_key, ..
+    } = derive_pool_addresses(bonfidabot_program_id, pool_seed, signal_provider_key)?;
+
+    let mut instructions = vec![init(
+        &spl_token::id(),
+        &system_program::id(),
+        &rent::id(),
+        bonfidabot_program_id,
+        &mint_key,
+        payer_key,
+        &pool_key,
+        pool_seed,
+        max_number_of_assets,
+        markets.len() as u16,
+        DEFAULT_POOL_TOKEN_DECIMALS,
+    )?];
+
+    let mut pool_asset_keys = Vec::with_capacity(asset_mints.len());
+    for asset_mint in asset_mints.iter() {
+        instructions.push(create_associated_token_account(
+            payer_key, &pool_key, asset_mint,
+        ));
+        pool_asset_keys.push(get_associated_token_address(&pool_key, asset_mint));
+    }
+
+    let target_pool_token_key = get_associated_token_address(source_owner_key, &mint_key);
+    instructions.push(create_associated_token_account(
+        payer_key,
+        source_owner_key,
+        &mint_key,
+    ));
+    for fee_owner in [
+        *signal_provider_key,
+        Pubkey::from_str(BONFIDA_FEE).unwrap(),
+        Pubkey::from_str(BONFIDA_BNB).unwrap(),
+    ]
+    .iter()
+    {
+        instructions.push(create_associated_token_account(
+            payer_key, fee_owner, &mint_key,
+        ));
+    }
+
+    instructions.push(create(
+        &spl_token::id(),
+        &clock::id(),
+        bonfidabot_program_id,
+        &mint_key,
+        &pool_key,
+        pool_seed,
+        &pool_asset_keys,
+        &target_pool_token_key,
+        source_owner_key,
+        source_asset_keys,
+        serum_program_id,
+        signal_provider_key,
+        fee_collection_period,
+        fee_ratio,
+        deposit_fee_ratio,
+        rebate_retention_ratio,
+        pool_mode,
+        redeem_requires_provider,
+        maturity_timestamp,
+        fee_split_bps,
+        deposit_amounts,
+        markets,
+    )?);
+
+    Ok(instructions)
+}
+
+// Creates a `ValidateOrderAccounts` instruction
+pub fn validate_order_accounts(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    market: &Pubkey,
+    payer_pool_asset_account: &Pubkey,
+    pool_account: &Pubkey,
+    dex_program: &Pubkey,
+    pool_seed: [u8; 32],
+    market_index: u16,
+    payer_pool_asset_index: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::ValidateOrderAccounts {
+        pool_seed,
+        market_index,
+        source_index: payer_pool_asset_index,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new_readonly(*payer_pool_asset_account, false),
+        AccountMeta::new_readonly(*pool_account, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `RecreateAssetAccount` instruction
+pub fn recreate_asset_account(
+    bonfidabot_program_id: &Pubkey,
+    system_program_id: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_program_id: &Pubkey,
+    funding_account: &Pubkey,
+    pool_key: &Pubkey,
+    asset_mint: &Pubkey,
+    pool_seed: [u8; 32],
+    asset_index: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::RecreateAssetAccount {
+        pool_seed,
+        asset_index,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*rent_program_id, false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new(*funding_account, true),
+        AccountMeta::new(get_associated_token_address(pool_key, asset_mint), false),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `SetPoolMode` instruction
+pub fn set_pool_mode(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    pool_mode: PoolMode,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::SetPoolMode {
+        pool_seed,
+        pool_mode,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates an `EmergencyWithdrawAsset` instruction
+pub fn emergency_withdraw_asset(
+    spl_token_program_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_key: &Pubkey,
+    source_pool_token_owner_key: &Pubkey,
+    source_pool_token_key: &Pubkey,
+    target_asset_key: &Pubkey,
+    pool_seed: [u8; 32],
+    asset_index: u64,
+    pool_token_amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::EmergencyWithdrawAsset {
+        pool_seed,
+        asset_index,
+        pool_token_amount,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new_readonly(*source_pool_token_owner_key, true),
+        AccountMeta::new(*source_pool_token_key, false),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(*pool_asset_key, false),
+        AccountMeta::new(*target_asset_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `Snapshot` instruction
+pub fn snapshot(
+    bonfidabot_program_id: &Pubkey,
+    system_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    rent_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    pool_key: &Pubkey,
+    mint_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    pool_seed: [u8; 32],
+    prices: Vec<u64>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::Snapshot { pool_seed, prices }.pack();
+    let (nav_snapshots_key, _) = find_nav_snapshots_key(bonfidabot_program_id, pool_key);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*rent_program_id, false),
+        AccountMeta::new(*signal_provider_key, true),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(nav_snapshots_key, false),
+        AccountMeta::new_readonly(*mint_key, false),
+    ];
+    for pool_asset_key in pool_asset_keys.iter() {
+        accounts.push(AccountMeta::new_readonly(*pool_asset_key, false))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates an `AddAllowedMint` instruction
+pub fn add_allowed_mint(
+    bonfidabot_program_id: &Pubkey,
+    system_program_id: &Pubkey,
+    rent_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::AddAllowedMint {
+        pool_seed,
+        mint: *mint,
+    }
+    .pack();
+    let (allowed_mints_key, _) = find_allowed_mints_key(bonfidabot_program_id, pool_key);
+    let accounts = vec![
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*rent_program_id, false),
+        AccountMeta::new(*signal_provider_key, true),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(allowed_mints_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `RemoveAllowedMint` instruction
+pub fn remove_allowed_mint(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::RemoveAllowedMint {
+        pool_seed,
+        mint: *mint,
+    }
+    .pack();
+    let (allowed_mints_key, _) = find_allowed_mints_key(bonfidabot_program_id, pool_key);
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider_key, true),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(allowed_mints_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Addresses of the accounts derived from a pool seed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolAddresses {
+    pub pool_key: Pubkey,
+    pub mint_key: Pubkey,
+    // Pooltoken associated token accounts of the fee recipients
+    pub signal_provider_pt_key: Pubkey,
+    pub bonfida_fee_pt_key: Pubkey,
+    pub bonfida_bnb_pt_key: Pubkey,
+}
+
+/// Derives the pool and pooltoken mint addresses of a pool seed the way the program does,
+/// along with the pooltoken accounts receiving the fees of the pool.
+pub fn derive_pool_addresses(
+    bonfidabot_program_id: &Pubkey,
+    pool_seed: [u8; 32],
+    signal_provider: &Pubkey,
+) -> Result<PoolAddresses, ProgramError> {
+    let pool_key = Pubkey::create_program_address(&[&pool_seed], bonfidabot_program_id)?;
+    let mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], bonfidabot_program_id)?;
+    Ok(PoolAddresses {
+        pool_key,
+        mint_key,
+        signal_provider_pt_key: get_associated_token_address(signal_provider, &mint_key),
+        bonfida_fee_pt_key: get_associated_token_address(
+            &Pubkey::from_str(BONFIDA_FEE).unwrap(),
+            &mint_key,
+        ),
+        bonfida_bnb_pt_key: get_associated_token_address(
+            &Pubkey::from_str(BONFIDA_BNB).unwrap(),
+            &mint_key,
+        ),
+    })
+}
+
+// Returns the accounts expected by `CollectFees`, in the order they are read by the processor
+pub fn collect_fees_accounts(
+    bonfidabot_program_id: &Pubkey,
+    pool_seed: [u8; 32],
+    signal_provider: &Pubkey,
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
+) -> Result<Vec<AccountMeta>, ProgramError> {
+    let addresses = derive_pool_addresses(bonfidabot_program_id, pool_seed, signal_provider)?;
+    let mut accounts = vec![
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(clock::id(), false),
+        AccountMeta::new(addresses.pool_key, false),
+        AccountMeta::new(addresses.mint_key, false),
+        AccountMeta::new(addresses.signal_provider_pt_key, false),
+        AccountMeta::new(addresses.bonfida_fee_pt_key, false),
+        AccountMeta::new(addresses.bonfida_bnb_pt_key, false),
+    ];
+    if let Some(extra_fee_recipient) = extra_fee_recipient {
+        accounts.push(AccountMeta::new(
+            get_associated_token_address(extra_fee_recipient, &addresses.mint_key),
+            false,
+        ))
+    }
+    Ok(accounts)
+}
+
+// Creates a `PublishStateHash` instruction
+pub fn publish_state_hash(
+    bonfidabot_program_id: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::PublishStateHash { pool_seed }.pack();
+    let accounts = vec![AccountMeta::new_readonly(*pool_key, false)];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `ToggleLock` instruction
+pub fn toggle_lock(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::ToggleLock { pool_seed }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `ChangeSignalProvider` instruction
+pub fn change_signal_provider(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    new_signal_provider: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::ChangeSignalProvider {
+        pool_seed,
+        new_signal_provider: *new_signal_provider,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `RedeemSingle` instruction
+pub fn redeem_single(
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_key: &Pubkey,
+    source_pool_token_owner_key: &Pubkey,
+    source_pool_token_key: &Pubkey,
+    target_asset_key: &Pubkey,
+    pool_seed: [u8; 32],
+    pool_token_amount: u64,
+    asset_index: u64,
+    other_pool_asset_keys: &[Pubkey],
+    signal_provider_key: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::RedeemSingle {
+        pool_seed,
+        pool_token_amount,
+        asset_index,
+    }
+    .pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new(*mint_key, false),
+        AccountMeta::new_readonly(*source_pool_token_owner_key, true),
+        AccountMeta::new(*source_pool_token_key, false),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(*pool_asset_key, false),
+        AccountMeta::new(*target_asset_key, false),
+    ];
+    for key in other_pool_asset_keys {
+        accounts.push(AccountMeta::new_readonly(*key, false));
+    }
+    if let Some(key) = signal_provider_key {
+        accounts.push(AccountMeta::new_readonly(*key, true));
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates an `AddMarket` instruction
+pub fn add_market(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    market: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::AddMarket {
+        pool_seed,
+        market: *market,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates an `UpdateFeePeriod` instruction
+pub fn update_fee_period(
+    bonfidabot_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    new_period: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::UpdateFeePeriod {
+        pool_seed,
+        new_period,
+    }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `DepositExact` instruction
+pub fn deposit_exact(
+    spl_token_program_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    target_pool_token_key: &Pubkey,
+    signal_provider_pool_token_key: &Pubkey,
+    source_owner: &Pubkey,
+    source_asset_keys: &Vec<Pubkey>,
+    pool_seed: [u8; 32],
+    asset_amounts: Vec<u64>,
+    tolerance_bps: u16,
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = deposit(
+        spl_token_program_id,
+        bonfidabot_program_id,
+        mint_key,
+        pool_key,
+        pool_asset_keys,
+        target_pool_token_key,
+        signal_provider_pool_token_key,
+        source_owner,
+        source_asset_keys,
+        pool_seed,
+        0,
+        None,
+        extra_fee_recipient,
+    )?;
+    instruction.data = PoolInstruction::DepositExact {
+        pool_seed,
+        tolerance_bps,
+        asset_amounts,
+    }
+    .pack();
+    Ok(instruction)
+}
+
+// Creates a `CancelAllOrders` instruction
+pub fn cancel_all_orders(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    market: &Pubkey,
+    openorders_account: &Pubkey,
+    serum_market_bids: &Pubkey,
+    serum_market_asks: &Pubkey,
+    serum_event_queue: &Pubkey,
+    pool_account: &Pubkey,
+    dex_program: &Pubkey,
+    pool_seed: [u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::CancelAllOrders { pool_seed }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*openorders_account, false),
+        AccountMeta::new(*serum_market_bids, false),
+        AccountMeta::new(*serum_market_asks, false),
+        AccountMeta::new(*serum_event_queue, false),
+        AccountMeta::new(*pool_account, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `GetPoolInfo` instruction
+pub fn get_pool_info(
+    bonfidabot_program_id: &Pubkey,
+    pool_key: &Pubkey,
+    mint_key: &Pubkey,
+    // The pool asset accounts of the assets in the page
+    pool_asset_keys: &[Pubkey],
+    pool_seed: [u8; 32],
+    page: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::GetPoolInfo { pool_seed, page }.pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new_readonly(*mint_key, false),
+    ];
+    for pool_asset_key in pool_asset_keys.iter() {
+        accounts.push(AccountMeta::new_readonly(*pool_asset_key, false))
+    }
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `ClosePool` instruction
+pub fn close_pool(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    mint_key: &Pubkey,
+    lamports_target: &Pubkey,
+    pool_seed: [u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::ClosePool { pool_seed }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+        AccountMeta::new_readonly(*mint_key, false),
+        AccountMeta::new(*lamports_target, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
 }
 
-// Creates a `Init` instruction
-pub fn init(
-    spl_token_program_id: &Pubkey,
-    system_program_id: &Pubkey,
-    rent_program_id: &Pubkey,
+// Creates a `ConsumeEvents` instruction
+pub fn consume_events(
     bonfidabot_program_id: &Pubkey,
-    mint_key: &Pubkey,
-    payer_key: &Pubkey,
+    market: &Pubkey,
+    openorders_account: &Pubkey,
+    serum_event_queue: &Pubkey,
+    coin_fee_receivable_account: &Pubkey,
+    pc_fee_receivable_account: &Pubkey,
+    pool_account: &Pubkey,
+    dex_program: &Pubkey,
+    pool_seed: [u8; 32],
+    limit: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::ConsumeEvents { pool_seed, limit }.pack();
+    let accounts = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*openorders_account, false),
+        AccountMeta::new(*serum_event_queue, false),
+        AccountMeta::new(*coin_fee_receivable_account, false),
+        AccountMeta::new(*pc_fee_receivable_account, false),
+        AccountMeta::new_readonly(*pool_account, false),
+        AccountMeta::new_readonly(*dex_program, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `SetDepositCap` instruction
+pub fn set_deposit_cap(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
     pool_key: &Pubkey,
     pool_seed: [u8; 32],
-    max_number_of_assets: u32,
-    number_of_markets: u16,
+    max_pooltoken_supply: u64,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::Init {
+    let data = PoolInstruction::SetDepositCap {
         pool_seed,
-        max_number_of_assets,
-        number_of_markets,
+        max_pooltoken_supply,
     }
     .pack();
     let accounts = vec![
-        AccountMeta::new_readonly(*system_program_id, false),
-        AccountMeta::new_readonly(*rent_program_id, false),
-        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*signal_provider, true),
         AccountMeta::new(*pool_key, false),
-        AccountMeta::new(*mint_key, false),
-        AccountMeta::new(*payer_key, true),
     ];
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
@@ -630,50 +3015,50 @@ pub fn init(
     })
 }
 
-// Creates a `CreatePool` instruction
-pub fn create(
+// Creates a `SweepUntrackedAsset` instruction
+pub fn sweep_untracked_asset(
     spl_token_program_id: &Pubkey,
-    clock_sysvar_id: &Pubkey,
     bonfidabot_program_id: &Pubkey,
-    mint_key: &Pubkey,
+    signal_provider: &Pubkey,
     pool_key: &Pubkey,
+    source_key: &Pubkey,
+    destination_key: &Pubkey,
     pool_seed: [u8; 32],
-    pool_asset_keys: &Vec<Pubkey>,
-    target_pool_token_key: &Pubkey,
-    source_owner_key: &Pubkey,
-    source_asset_keys: &Vec<Pubkey>,
-    serum_program_id: &Pubkey,
-    signal_provider_key: &Pubkey,
-    fee_collection_period: u64,
-    fee_ratio: u16,
-    deposit_amounts: Vec<u64>,
-    markets: Vec<Pubkey>,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::Create {
+    let data = PoolInstruction::SweepUntrackedAsset { pool_seed }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(*source_key, false),
+        AccountMeta::new(*destination_key, false),
+    ];
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `SetExtraFeeRecipient` instruction
+pub fn set_extra_fee_recipient(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    recipient: &Pubkey,
+    bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::SetExtraFeeRecipient {
         pool_seed,
-        deposit_amounts,
-        markets,
-        fee_collection_period,
-        fee_ratio,
+        recipient: *recipient,
+        bps,
     }
     .pack();
-    let mut accounts = vec![
-        AccountMeta::new_readonly(*spl_token_program_id, false),
-        AccountMeta::new_readonly(*clock_sysvar_id, false),
-        AccountMeta::new_readonly(*serum_program_id, false),
-        AccountMeta::new_readonly(*signal_provider_key, false),
-        AccountMeta::new(*mint_key, false),
-        AccountMeta::new(*target_pool_token_key, false),
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
         AccountMeta::new(*pool_key, false),
     ];
-    for pool_asset_key in pool_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*pool_asset_key, false))
-    }
-    accounts.push(AccountMeta::new_readonly(*source_owner_key, true));
-    for source_asset_key in source_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*source_asset_key, false))
-    }
-
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
         accounts,
@@ -681,10 +3066,11 @@ pub fn create(
     })
 }
 
-// Creates a `Deposit` instruction
-pub fn deposit(
+// Creates a `DepositAndLock` instruction
+pub fn deposit_and_lock(
     spl_token_program_id: &Pubkey,
     bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
     mint_key: &Pubkey,
     pool_key: &Pubkey,
     pool_asset_keys: &Vec<Pubkey>,
@@ -694,32 +3080,60 @@ pub fn deposit(
     source_asset_keys: &Vec<Pubkey>,
     pool_seed: [u8; 32],
     pool_token_amount: u64,
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::Deposit {
+    let deposit_instruction = deposit(
+        spl_token_program_id,
+        bonfidabot_program_id,
+        mint_key,
+        pool_key,
+        pool_asset_keys,
+        target_pool_token_key,
+        signal_provider_pool_token_key,
+        source_owner,
+        source_asset_keys,
+        pool_seed,
+        pool_token_amount,
+        None,
+        extra_fee_recipient,
+    )?;
+    let data = PoolInstruction::DepositAndLock {
         pool_seed,
         pool_token_amount,
     }
     .pack();
-    let bonfida_fee_pt_account =
-        get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), mint_key);
-    let bonfida_bnb_pt_account =
-        get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), mint_key);
-    let mut accounts = vec![
+    let mut accounts = vec![AccountMeta::new_readonly(*signal_provider, true)];
+    accounts.extend(deposit_instruction.accounts);
+    // The pool status is updated
+    accounts[1 + DEPOSIT_POOL_ACCOUNT_INDEX].is_writable = true;
+    Ok(Instruction {
+        program_id: *bonfidabot_program_id,
+        accounts,
+        data,
+    })
+}
+
+// Creates a `SweepDust` instruction
+pub fn sweep_dust(
+    spl_token_program_id: &Pubkey,
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    source_key: &Pubkey,
+    destination_key: &Pubkey,
+    pool_seed: [u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::SweepDust { pool_seed }.pack();
+    let accounts = vec![
         AccountMeta::new_readonly(*spl_token_program_id, false),
-        AccountMeta::new(*mint_key, false),
-        AccountMeta::new(*target_pool_token_key, false),
-        AccountMeta::new(*signal_provider_pool_token_key, false),
-        AccountMeta::new(bonfida_fee_pt_account, false),
-        AccountMeta::new(bonfida_bnb_pt_account, false),
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new_readonly(*mint_key, false),
         AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new(*source_key, false),
+        AccountMeta::new(*destination_key, false),
     ];
-    for pool_asset_key in pool_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*pool_asset_key, false))
-    }
-    accounts.push(AccountMeta::new_readonly(*source_owner, true));
-    for source_asset_key in source_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*source_asset_key, false))
-    }
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
         accounts,
@@ -727,39 +3141,23 @@ pub fn deposit(
     })
 }
 
-// Creates a `Redeem` instruction
-pub fn redeem(
-    spl_token_program_id: &Pubkey,
-    clock_sysvar_id: &Pubkey,
+// Creates an `UpdateDexProgram` instruction
+pub fn update_dex_program(
     bonfidabot_program_id: &Pubkey,
-    mint_key: &Pubkey,
+    signal_provider: &Pubkey,
     pool_key: &Pubkey,
-    pool_asset_keys: &Vec<Pubkey>,
-    source_pool_token_owner_key: &Pubkey,
-    source_pool_token_key: &Pubkey,
-    target_asset_keys: &Vec<Pubkey>,
     pool_seed: [u8; 32],
-    pool_token_amount: u64,
+    new_program_id: &Pubkey,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::Redeem {
+    let data = PoolInstruction::UpdateDexProgram {
         pool_seed,
-        pool_token_amount,
+        new_program_id: *new_program_id,
     }
     .pack();
-    let mut accounts = vec![
-        AccountMeta::new_readonly(*spl_token_program_id, false),
-        AccountMeta::new_readonly(*clock_sysvar_id, false),
-        AccountMeta::new(*mint_key, false),
-        AccountMeta::new_readonly(*source_pool_token_owner_key, true),
-        AccountMeta::new(*source_pool_token_key, false),
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
         AccountMeta::new(*pool_key, false),
     ];
-    for pool_asset_key in pool_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*pool_asset_key, false))
-    }
-    for source_asset_key in target_asset_keys.iter() {
-        accounts.push(AccountMeta::new(*source_asset_key, false))
-    }
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
         accounts,
@@ -767,75 +3165,33 @@ pub fn redeem(
     })
 }
 
-// Creates a `CreateOrder` instruction
-pub fn create_order(
+// Creates an `InitOpenOrders` instruction
+pub fn init_open_orders(
     bonfidabot_program_id: &Pubkey,
-    signal_provider: &Pubkey,
-    market: &Pubkey,
-    payer_pool_asset_account: &Pubkey,
-    payer_pool_asset_index: u64,
-    target_pool_asset_index: u64,
-    openorders_account: &Pubkey,
-    serum_event_queue: &Pubkey,
-    serum_request_queue: &Pubkey,
-    serum_market_bids: &Pubkey,
-    serum_market_asks: &Pubkey,
-    pool_account: &Pubkey,
-    coin_vault: &Pubkey,
-    pc_vault: &Pubkey,
-    spl_token_program: &Pubkey,
-    dex_program: &Pubkey,
-    rent_sysvar: &Pubkey,
-    srm_discount_account: Option<&Pubkey>,
+    system_program_id: &Pubkey,
+    rent_program_id: &Pubkey,
+    signal_provider_key: &Pubkey,
+    pool_key: &Pubkey,
+    market_key: &Pubkey,
+    dex_program_id: &Pubkey,
     pool_seed: [u8; 32],
-    side: Side,
-    limit_price: NonZeroU64,
     market_index: u16,
-    coin_lot_size: u64,
-    pc_lot_size: u64,
-    target_mint: &Pubkey,
-    ratio_of_pool_assets_to_trade: NonZeroU16,
-    order_type: OrderType,
-    client_id: u64,
-    self_trade_behavior: SelfTradeBehavior,
-    serum_limit: u16
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::CreateOrder {
+    let data = PoolInstruction::InitOpenOrders {
         pool_seed,
-        side,
-        limit_price,
-        ratio_of_pool_assets_to_trade,
-        order_type,
-        client_id,
-        self_trade_behavior,
-        source_index: payer_pool_asset_index,
-        target_index: target_pool_asset_index,
         market_index,
-        coin_lot_size,
-        pc_lot_size,
-        target_mint: *target_mint,
-        serum_limit
     }
     .pack();
-    let mut accounts = vec![
-        AccountMeta::new_readonly(*signal_provider, true),
-        AccountMeta::new(*market, false),
-        AccountMeta::new(*payer_pool_asset_account, false),
-        AccountMeta::new(*openorders_account, false),
-        AccountMeta::new(*serum_event_queue, false),
-        AccountMeta::new(*serum_request_queue, false),
-        AccountMeta::new(*serum_market_bids, false),
-        AccountMeta::new(*serum_market_asks, false),
-        AccountMeta::new(*pool_account, false),
-        AccountMeta::new(*coin_vault, false),
-        AccountMeta::new(*pc_vault, false),
-        AccountMeta::new_readonly(*spl_token_program, false),
-        AccountMeta::new_readonly(*rent_sysvar, false),
-        AccountMeta::new_readonly(*dex_program, false),
+    let (open_orders_key, _) = find_open_orders_key(bonfidabot_program_id, pool_key, market_key);
+    let accounts = vec![
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*rent_program_id, false),
+        AccountMeta::new(*signal_provider_key, true),
+        AccountMeta::new_readonly(*pool_key, false),
+        AccountMeta::new_readonly(*market_key, false),
+        AccountMeta::new(open_orders_key, false),
+        AccountMeta::new_readonly(*dex_program_id, false),
     ];
-    if let Some(key) = srm_discount_account {
-        accounts.push(AccountMeta::new(*key, false));
-    }
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
         accounts,
@@ -843,36 +3199,18 @@ pub fn create_order(
     })
 }
 
-// Creates a `CancelOrder` instruction
-pub fn cancel_order(
+// Creates a `SetSingleAssetDeposits` instruction
+pub fn set_single_asset_deposits(
     bonfidabot_program_id: &Pubkey,
     signal_provider: &Pubkey,
-    market: &Pubkey,
-    openorders_account: &Pubkey,
-    serum_market_bids: &Pubkey,
-    serum_market_asks: &Pubkey,
-    serum_event_queue: &Pubkey,
-    pool_account: &Pubkey,
-    dex_program: &Pubkey,
+    pool_key: &Pubkey,
     pool_seed: [u8; 32],
-    side: Side,
-    order_id: u128,
+    enabled: bool,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::CancelOrder {
-        pool_seed,
-        side,
-        order_id,
-    }
-    .pack();
-    let accounts = vec![
-        AccountMeta::new_readonly(*signal_provider, true),
-        AccountMeta::new_readonly(*market, false),
-        AccountMeta::new(*openorders_account, false),
-        AccountMeta::new(*serum_market_bids, false),
-        AccountMeta::new(*serum_market_asks, false),
-        AccountMeta::new(*serum_event_queue, false),
-        AccountMeta::new_readonly(*pool_account, false),
-        AccountMeta::new_readonly(*dex_program, false),
+    let data = PoolInstruction::SetSingleAssetDeposits { pool_seed, enabled }.pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
     ];
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
@@ -881,48 +3219,77 @@ pub fn cancel_order(
     })
 }
 
-// Creates a settle funds
-pub fn settle_funds(
+// Creates a `DepositSingle` instruction
+pub fn deposit_single(
+    spl_token_program_id: &Pubkey,
     bonfidabot_program_id: &Pubkey,
-    market: &Pubkey,
-    openorders_account: &Pubkey,
-    pool_account: &Pubkey,
-    pool_token_mint: &Pubkey,
-    coin_vault: &Pubkey,
-    pc_vault: &Pubkey,
-    pool_coin_wallet: &Pubkey,
-    pool_pc_wallet: &Pubkey,
-    vault_signer: &Pubkey,
-    spl_token_program: &Pubkey,
-    dex_program: &Pubkey,
-    referrer_pc_account: Option<&Pubkey>,
+    mint_key: &Pubkey,
+    pool_key: &Pubkey,
+    pool_asset_keys: &Vec<Pubkey>,
+    target_pool_token_key: &Pubkey,
+    signal_provider_pool_token_key: &Pubkey,
+    source_owner: &Pubkey,
+    source_asset_keys: &Vec<Pubkey>,
     pool_seed: [u8; 32],
-    pc_index: u64,
-    coin_index: u64,
+    asset_index: u64,
+    amount: u64,
+    // The extra fee recipient of the pool, if any
+    extra_fee_recipient: Option<&Pubkey>,
+    // The (market, bids, asks) keys valuing each of the other pool assets
+    price_market_keys: &[(Pubkey, Pubkey, Pubkey)],
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::SettleFunds {
+    let mut instruction = deposit(
+        spl_token_program_id,
+        bonfidabot_program_id,
+        mint_key,
+        pool_key,
+        pool_asset_keys,
+        target_pool_token_key,
+        signal_provider_pool_token_key,
+        source_owner,
+        source_asset_keys,
         pool_seed,
-        pc_index,
-        coin_index,
+        0,
+        None,
+        extra_fee_recipient,
+    )?;
+    instruction.data = PoolInstruction::DepositSingle {
+        pool_seed,
+        asset_index,
+        amount,
     }
     .pack();
+    for (market_key, bids_key, asks_key) in price_market_keys.iter() {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(*market_key, false));
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(*bids_key, false));
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(*asks_key, false));
+    }
+    Ok(instruction)
+}
 
-    let mut accounts = vec![
-        AccountMeta::new(*market, false),
-        AccountMeta::new(*openorders_account, false),
-        AccountMeta::new(*pool_account, false),
-        AccountMeta::new_readonly(*pool_token_mint, false),
-        AccountMeta::new(*coin_vault, false),
-        AccountMeta::new(*pc_vault, false),
-        AccountMeta::new(*pool_coin_wallet, false),
-        AccountMeta::new(*pool_pc_wallet, false),
-        AccountMeta::new_readonly(*vault_signer, false),
-        AccountMeta::new_readonly(*spl_token_program, false),
-        AccountMeta::new_readonly(*dex_program, false),
-    ];
-    if let Some(key) = referrer_pc_account {
-        accounts.push(AccountMeta::new(*key, false))
+// Creates a `SetMetadata` instruction
+pub fn set_metadata(
+    bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
+    pool_key: &Pubkey,
+    pool_seed: [u8; 32],
+    uri: &str,
+) -> Result<Instruction, ProgramError> {
+    let data = PoolInstruction::SetMetadata {
+        pool_seed,
+        uri: uri.as_bytes().to_vec(),
     }
+    .pack();
+    let accounts = vec![
+        AccountMeta::new_readonly(*signal_provider, true),
+        AccountMeta::new(*pool_key, false),
+    ];
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
         accounts,
@@ -930,29 +3297,22 @@ pub fn settle_funds(
     })
 }
 
-pub fn collect_fees(
-    spl_token_program_id: &Pubkey,
-    clock_sysvar_id: &Pubkey,
+// Creates a `SetReferrerFee` instruction
+pub fn set_referrer_fee(
     bonfidabot_program_id: &Pubkey,
+    signal_provider: &Pubkey,
     pool_key: &Pubkey,
-    pool_token_mint: &Pubkey,
-    signal_provider_pool_token_key: &Pubkey,
     pool_seed: [u8; 32],
+    referrer_fee_bps: u16,
 ) -> Result<Instruction, ProgramError> {
-    let data = PoolInstruction::CollectFees { pool_seed }.pack();
-
-    let bonfida_fee_pt_account =
-        get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), pool_token_mint);
-    let bonfida_bnb_pt_account =
-        get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), pool_token_mint);
+    let data = PoolInstruction::SetReferrerFee {
+        pool_seed,
+        referrer_fee_bps,
+    }
+    .pack();
     let accounts = vec![
-        AccountMeta::new_readonly(*spl_token_program_id, false),
-        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*signal_provider, true),
         AccountMeta::new(*pool_key, false),
-        AccountMeta::new(*pool_token_mint, false),
-        AccountMeta::new(*signal_provider_pool_token_key, false),
-        AccountMeta::new(bonfida_fee_pt_account, false),
-        AccountMeta::new(bonfida_bnb_pt_account, false),
     ];
     Ok(Instruction {
         program_id: *bonfidabot_program_id,
@@ -963,15 +3323,37 @@ pub fn collect_fees(
 
 #[cfg(test)]
 mod test {
-    use std::num::{NonZeroU16, NonZeroU64};
+    use std::{
+        num::{NonZeroU16, NonZeroU64},
+        str::FromStr,
+    };
 
     use serum_dex::{
         instruction::SelfTradeBehavior,
         matching::{OrderType, Side},
     };
-    use solana_program::pubkey::Pubkey;
+    use solana_program::{pubkey::Pubkey, sysvar::clock};
+    use spl_associated_token_account::get_associated_token_address;
+
+    use super::{
+        bootstrap_pool_instructions, collect_fees, collect_fees_accounts, create,
+        create_accounts_len, create_order, deposit, deposit_accounts_len, derive_pool_addresses,
+        redeem, redeem_accounts_len, validate_create_params, PoolInstruction,
+    };
+    use crate::state::{find_allowed_mints_key, PoolMode, BONFIDA_BNB, BONFIDA_FEE};
 
-    use super::PoolInstruction;
+    // Returns a seed for which both the pool and pool mint addresses can be derived
+    fn get_valid_seed(program_id: &Pubkey) -> [u8; 32] {
+        let mut pool_seed = [0u8; 32];
+        loop {
+            if Pubkey::create_program_address(&[&pool_seed], program_id).is_ok()
+                && Pubkey::create_program_address(&[&pool_seed, &[1]], program_id).is_ok()
+            {
+                return pool_seed;
+            }
+            pool_seed[0] += 1;
+        }
+    }
 
     #[test]
     fn test_instruction_packing() {
@@ -979,11 +3361,23 @@ mod test {
             pool_seed: [50u8; 32],
             max_number_of_assets: 43,
             number_of_markets: 50,
+            decimals: 9,
         };
         assert_eq!(
             original_init,
             PoolInstruction::unpack(&original_init.pack()).unwrap()
         );
+        // Instructions packed before the decimals were added default to 6 decimals
+        let packed_init = original_init.pack();
+        assert_eq!(
+            PoolInstruction::unpack(&packed_init[..packed_init.len() - 1]).unwrap(),
+            PoolInstruction::Init {
+                pool_seed: [50u8; 32],
+                max_number_of_assets: 43,
+                number_of_markets: 50,
+                decimals: 6,
+            }
+        );
 
         let original_create = PoolInstruction::Create {
             pool_seed: [50u8; 32],
@@ -996,14 +3390,42 @@ mod test {
             ],
             fee_collection_period: 10_000,
             fee_ratio: 15,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pool_mode: PoolMode::DepositOnly,
+            redeem_requires_provider: true,
+            maturity_timestamp: 1_700_000_000,
+            fee_split_bps: Some((6_000, 2_000)),
+        };
+        let packed_create = original_create.pack();
+        let unpacked_create = PoolInstruction::unpack(&packed_create).unwrap();
+        assert_eq!(original_create, unpacked_create);
+
+        // Without a fee split, the fee split is left out of the data
+        let original_create = PoolInstruction::Create {
+            fee_split_bps: None,
+            ..original_create
         };
         let packed_create = original_create.pack();
+        assert_eq!(packed_create.len() % 8, (1 + 58) % 8);
         let unpacked_create = PoolInstruction::unpack(&packed_create).unwrap();
         assert_eq!(original_create, unpacked_create);
 
         let original_deposit = PoolInstruction::Deposit {
             pool_seed: [50u8; 32],
             pool_token_amount: 24 as u64,
+            with_referrer: false,
+        };
+        let packed_deposit = original_deposit.pack();
+        // Deposits without a referrer keep the original layout
+        assert_eq!(packed_deposit.len(), 41);
+        let unpacked_deposit = PoolInstruction::unpack(&packed_deposit).unwrap();
+        assert_eq!(original_deposit, unpacked_deposit);
+
+        let original_deposit = PoolInstruction::Deposit {
+            pool_seed: [50u8; 32],
+            pool_token_amount: 24 as u64,
+            with_referrer: true,
         };
         let packed_deposit = original_deposit.pack();
         let unpacked_deposit = PoolInstruction::unpack(&packed_deposit).unwrap();
@@ -1023,22 +3445,42 @@ mod test {
             coin_lot_size: 41,
             pc_lot_size: 41,
             target_mint: Pubkey::new_unique(),
-            serum_limit: 5000
+            serum_limit: 5000,
+            min_expected_lots: 12,
         };
         let packed_create_order = original_create_order.pack();
         let unpacked_create_order = PoolInstruction::unpack(&packed_create_order).unwrap();
         assert_eq!(original_create_order, unpacked_create_order);
+        // The minimum expected lots can be omitted, but not truncated
+        let packed_len = packed_create_order.len();
+        match PoolInstruction::unpack(&packed_create_order[..packed_len - 8]).unwrap() {
+            PoolInstruction::CreateOrder {
+                min_expected_lots, ..
+            } => assert_eq!(min_expected_lots, 0),
+            _ => panic!(),
+        }
+        assert!(PoolInstruction::unpack(&packed_create_order[..packed_len - 1]).is_err());
         assert_eq!(original_deposit, unpacked_deposit);
 
         let original_settle_order = PoolInstruction::SettleFunds {
             pool_seed: [50u8; 32],
-            pc_index: 42,
-            coin_index: 52,
+            pc_index: Some(42),
+            coin_index: Some(52),
         };
         let packed_settle_order = original_settle_order.pack();
         let unpacked_settle_order = PoolInstruction::unpack(&packed_settle_order).unwrap();
         assert_eq!(original_settle_order, unpacked_settle_order);
 
+        let original_partial_settle = PoolInstruction::SettleFunds {
+            pool_seed: [50u8; 32],
+            pc_index: None,
+            coin_index: Some(52),
+        };
+        let packed_partial_settle = original_partial_settle.pack();
+        assert_eq!(packed_partial_settle.len(), 49);
+        let unpacked_partial_settle = PoolInstruction::unpack(&packed_partial_settle).unwrap();
+        assert_eq!(original_partial_settle, unpacked_partial_settle);
+
         let original_redeem = PoolInstruction::Redeem {
             pool_seed: [50u8; 32],
             pool_token_amount: 24 as u64,
@@ -1047,6 +3489,15 @@ mod test {
         let unpacked_redeem = PoolInstruction::unpack(&packed_redeem).unwrap();
         assert_eq!(original_redeem, unpacked_redeem);
 
+        let original_redeem_unwrap_sol = PoolInstruction::RedeemUnwrapSol {
+            pool_seed: [50u8; 32],
+            pool_token_amount: 24 as u64,
+        };
+        let packed_redeem_unwrap_sol = original_redeem_unwrap_sol.pack();
+        let unpacked_redeem_unwrap_sol =
+            PoolInstruction::unpack(&packed_redeem_unwrap_sol).unwrap();
+        assert_eq!(original_redeem_unwrap_sol, unpacked_redeem_unwrap_sol);
+
         let original_cancel_order = PoolInstruction::CancelOrder {
             pool_seed: [50u8; 32],
             side: Side::Ask,
@@ -1062,5 +3513,559 @@ mod test {
         let packed_collect_fees = original_collect_fees.pack();
         let unpacked_collect_fees = PoolInstruction::unpack(&packed_collect_fees).unwrap();
         assert_eq!(original_collect_fees, unpacked_collect_fees);
+
+        let original_publish_state_hash = PoolInstruction::PublishStateHash {
+            pool_seed: [50u8; 32],
+        };
+        let packed_publish_state_hash = original_publish_state_hash.pack();
+        let unpacked_publish_state_hash =
+            PoolInstruction::unpack(&packed_publish_state_hash).unwrap();
+        assert_eq!(original_publish_state_hash, unpacked_publish_state_hash);
+
+        let original_update_fee_ratio = PoolInstruction::UpdateFeeRatio {
+            pool_seed: [50u8; 32],
+            new_fee_ratio: 20,
+        };
+        let packed_update_fee_ratio = original_update_fee_ratio.pack();
+        let unpacked_update_fee_ratio = PoolInstruction::unpack(&packed_update_fee_ratio).unwrap();
+        assert_eq!(original_update_fee_ratio, unpacked_update_fee_ratio);
+
+        let original_validate_order_accounts = PoolInstruction::ValidateOrderAccounts {
+            pool_seed: [50u8; 32],
+            market_index: 3,
+            source_index: 42,
+        };
+        let packed_validate_order_accounts = original_validate_order_accounts.pack();
+        let unpacked_validate_order_accounts =
+            PoolInstruction::unpack(&packed_validate_order_accounts).unwrap();
+        assert_eq!(
+            original_validate_order_accounts,
+            unpacked_validate_order_accounts
+        );
+
+        let original_recreate_asset_account = PoolInstruction::RecreateAssetAccount {
+            pool_seed: [50u8; 32],
+            asset_index: 4,
+        };
+        let packed_recreate_asset_account = original_recreate_asset_account.pack();
+        let unpacked_recreate_asset_account =
+            PoolInstruction::unpack(&packed_recreate_asset_account).unwrap();
+        assert_eq!(
+            original_recreate_asset_account,
+            unpacked_recreate_asset_account
+        );
+
+        let original_set_pool_mode = PoolInstruction::SetPoolMode {
+            pool_seed: [50u8; 32],
+            pool_mode: PoolMode::RedeemOnly,
+        };
+        let packed_set_pool_mode = original_set_pool_mode.pack();
+        let unpacked_set_pool_mode = PoolInstruction::unpack(&packed_set_pool_mode).unwrap();
+        assert_eq!(original_set_pool_mode, unpacked_set_pool_mode);
+
+        let original_emergency_withdraw_asset = PoolInstruction::EmergencyWithdrawAsset {
+            pool_seed: [50u8; 32],
+            asset_index: 1,
+            pool_token_amount: 500,
+        };
+        let packed_emergency_withdraw_asset = original_emergency_withdraw_asset.pack();
+        let unpacked_emergency_withdraw_asset =
+            PoolInstruction::unpack(&packed_emergency_withdraw_asset).unwrap();
+        assert_eq!(
+            original_emergency_withdraw_asset,
+            unpacked_emergency_withdraw_asset
+        );
+
+        let original_snapshot = PoolInstruction::Snapshot {
+            pool_seed: [50u8; 32],
+            prices: vec![1_000, 25, 3],
+        };
+        let packed_snapshot = original_snapshot.pack();
+        let unpacked_snapshot = PoolInstruction::unpack(&packed_snapshot).unwrap();
+        assert_eq!(original_snapshot, unpacked_snapshot);
+
+        let original_add_allowed_mint = PoolInstruction::AddAllowedMint {
+            pool_seed: [50u8; 32],
+            mint: Pubkey::new_unique(),
+        };
+        let packed_add_allowed_mint = original_add_allowed_mint.pack();
+        let unpacked_add_allowed_mint = PoolInstruction::unpack(&packed_add_allowed_mint).unwrap();
+        assert_eq!(original_add_allowed_mint, unpacked_add_allowed_mint);
+
+        let original_remove_allowed_mint = PoolInstruction::RemoveAllowedMint {
+            pool_seed: [50u8; 32],
+            mint: Pubkey::new_unique(),
+        };
+        let packed_remove_allowed_mint = original_remove_allowed_mint.pack();
+        let unpacked_remove_allowed_mint =
+            PoolInstruction::unpack(&packed_remove_allowed_mint).unwrap();
+        assert_eq!(original_remove_allowed_mint, unpacked_remove_allowed_mint);
+
+        let original_toggle_lock = PoolInstruction::ToggleLock {
+            pool_seed: [50u8; 32],
+        };
+        let packed_toggle_lock = original_toggle_lock.pack();
+        let unpacked_toggle_lock = PoolInstruction::unpack(&packed_toggle_lock).unwrap();
+        assert_eq!(original_toggle_lock, unpacked_toggle_lock);
+
+        let original_change_signal_provider = PoolInstruction::ChangeSignalProvider {
+            pool_seed: [50u8; 32],
+            new_signal_provider: Pubkey::new_unique(),
+        };
+        let packed_change_signal_provider = original_change_signal_provider.pack();
+        let unpacked_change_signal_provider =
+            PoolInstruction::unpack(&packed_change_signal_provider).unwrap();
+        assert_eq!(
+            original_change_signal_provider,
+            unpacked_change_signal_provider
+        );
+
+        let original_redeem_single = PoolInstruction::RedeemSingle {
+            pool_seed: [50u8; 32],
+            pool_token_amount: 500,
+            asset_index: 2,
+        };
+        let packed_redeem_single = original_redeem_single.pack();
+        let unpacked_redeem_single = PoolInstruction::unpack(&packed_redeem_single).unwrap();
+        assert_eq!(original_redeem_single, unpacked_redeem_single);
+
+        let original_add_market = PoolInstruction::AddMarket {
+            pool_seed: [50u8; 32],
+            market: Pubkey::new_unique(),
+        };
+        let packed_add_market = original_add_market.pack();
+        let unpacked_add_market = PoolInstruction::unpack(&packed_add_market).unwrap();
+        assert_eq!(original_add_market, unpacked_add_market);
+
+        let original_update_fee_period = PoolInstruction::UpdateFeePeriod {
+            pool_seed: [50u8; 32],
+            new_period: 1_209_600,
+        };
+        let packed_update_fee_period = original_update_fee_period.pack();
+        let unpacked_update_fee_period =
+            PoolInstruction::unpack(&packed_update_fee_period).unwrap();
+        assert_eq!(original_update_fee_period, unpacked_update_fee_period);
+
+        let original_deposit_exact = PoolInstruction::DepositExact {
+            pool_seed: [50u8; 32],
+            tolerance_bps: 50,
+            asset_amounts: vec![1_000, 0, 42],
+        };
+        let packed_deposit_exact = original_deposit_exact.pack();
+        let unpacked_deposit_exact = PoolInstruction::unpack(&packed_deposit_exact).unwrap();
+        assert_eq!(original_deposit_exact, unpacked_deposit_exact);
+        assert!(
+            PoolInstruction::unpack(&packed_deposit_exact[..packed_deposit_exact.len() - 1])
+                .is_err()
+        );
+
+        let original_cancel_all_orders = PoolInstruction::CancelAllOrders {
+            pool_seed: [50u8; 32],
+        };
+        let packed_cancel_all_orders = original_cancel_all_orders.pack();
+        let unpacked_cancel_all_orders =
+            PoolInstruction::unpack(&packed_cancel_all_orders).unwrap();
+        assert_eq!(original_cancel_all_orders, unpacked_cancel_all_orders);
+
+        let original_get_pool_info = PoolInstruction::GetPoolInfo {
+            pool_seed: [50u8; 32],
+            page: 3,
+        };
+        let packed_get_pool_info = original_get_pool_info.pack();
+        let unpacked_get_pool_info = PoolInstruction::unpack(&packed_get_pool_info).unwrap();
+        assert_eq!(original_get_pool_info, unpacked_get_pool_info);
+
+        let original_close_pool = PoolInstruction::ClosePool {
+            pool_seed: [50u8; 32],
+        };
+        let packed_close_pool = original_close_pool.pack();
+        let unpacked_close_pool = PoolInstruction::unpack(&packed_close_pool).unwrap();
+        assert_eq!(original_close_pool, unpacked_close_pool);
+
+        let original_consume_events = PoolInstruction::ConsumeEvents {
+            pool_seed: [50u8; 32],
+            limit: 10,
+        };
+        let packed_consume_events = original_consume_events.pack();
+        let unpacked_consume_events = PoolInstruction::unpack(&packed_consume_events).unwrap();
+        assert_eq!(original_consume_events, unpacked_consume_events);
+
+        let original_set_deposit_cap = PoolInstruction::SetDepositCap {
+            pool_seed: [50u8; 32],
+            max_pooltoken_supply: 1_000_000_000,
+        };
+        let packed_set_deposit_cap = original_set_deposit_cap.pack();
+        let unpacked_set_deposit_cap = PoolInstruction::unpack(&packed_set_deposit_cap).unwrap();
+        assert_eq!(original_set_deposit_cap, unpacked_set_deposit_cap);
+
+        let original_sweep = PoolInstruction::SweepUntrackedAsset {
+            pool_seed: [50u8; 32],
+        };
+        let packed_sweep = original_sweep.pack();
+        let unpacked_sweep = PoolInstruction::unpack(&packed_sweep).unwrap();
+        assert_eq!(original_sweep, unpacked_sweep);
+
+        let original_set_extra_fee_recipient = PoolInstruction::SetExtraFeeRecipient {
+            pool_seed: [50u8; 32],
+            recipient: Pubkey::new_unique(),
+            bps: 1_000,
+        };
+        let packed_set_extra_fee_recipient = original_set_extra_fee_recipient.pack();
+        let unpacked_set_extra_fee_recipient =
+            PoolInstruction::unpack(&packed_set_extra_fee_recipient).unwrap();
+        assert_eq!(
+            original_set_extra_fee_recipient,
+            unpacked_set_extra_fee_recipient
+        );
+
+        let original_deposit_and_lock = PoolInstruction::DepositAndLock {
+            pool_seed: [50u8; 32],
+            pool_token_amount: 1_000_000,
+        };
+        let packed_deposit_and_lock = original_deposit_and_lock.pack();
+        let unpacked_deposit_and_lock = PoolInstruction::unpack(&packed_deposit_and_lock).unwrap();
+        assert_eq!(original_deposit_and_lock, unpacked_deposit_and_lock);
+
+        let original_sweep_dust = PoolInstruction::SweepDust {
+            pool_seed: [50u8; 32],
+        };
+        let packed_sweep_dust = original_sweep_dust.pack();
+        let unpacked_sweep_dust = PoolInstruction::unpack(&packed_sweep_dust).unwrap();
+        assert_eq!(original_sweep_dust, unpacked_sweep_dust);
+
+        let original_update_dex_program = PoolInstruction::UpdateDexProgram {
+            pool_seed: [50u8; 32],
+            new_program_id: Pubkey::new_unique(),
+        };
+        let packed_update_dex_program = original_update_dex_program.pack();
+        let unpacked_update_dex_program =
+            PoolInstruction::unpack(&packed_update_dex_program).unwrap();
+        assert_eq!(original_update_dex_program, unpacked_update_dex_program);
+
+        let original_init_open_orders = PoolInstruction::InitOpenOrders {
+            pool_seed: [50u8; 32],
+            market_index: 3,
+        };
+        let packed_init_open_orders = original_init_open_orders.pack();
+        let unpacked_init_open_orders = PoolInstruction::unpack(&packed_init_open_orders).unwrap();
+        assert_eq!(original_init_open_orders, unpacked_init_open_orders);
+
+        let original_set_single_asset_deposits = PoolInstruction::SetSingleAssetDeposits {
+            pool_seed: [50u8; 32],
+            enabled: true,
+        };
+        let packed_set_single_asset_deposits = original_set_single_asset_deposits.pack();
+        let unpacked_set_single_asset_deposits =
+            PoolInstruction::unpack(&packed_set_single_asset_deposits).unwrap();
+        assert_eq!(
+            original_set_single_asset_deposits,
+            unpacked_set_single_asset_deposits
+        );
+
+        let original_deposit_single = PoolInstruction::DepositSingle {
+            pool_seed: [50u8; 32],
+            asset_index: 2,
+            amount: 1_000_000,
+        };
+        let packed_deposit_single = original_deposit_single.pack();
+        let unpacked_deposit_single = PoolInstruction::unpack(&packed_deposit_single).unwrap();
+        assert_eq!(original_deposit_single, unpacked_deposit_single);
+
+        let original_set_metadata = PoolInstruction::SetMetadata {
+            pool_seed: [50u8; 32],
+            uri: b"https://example.com/pool.json".to_vec(),
+        };
+        let packed_set_metadata = original_set_metadata.pack();
+        let unpacked_set_metadata = PoolInstruction::unpack(&packed_set_metadata).unwrap();
+        assert_eq!(original_set_metadata, unpacked_set_metadata);
+
+        let original_set_referrer_fee = PoolInstruction::SetReferrerFee {
+            pool_seed: [50u8; 32],
+            referrer_fee_bps: 2_500,
+        };
+        let packed_set_referrer_fee = original_set_referrer_fee.pack();
+        let unpacked_set_referrer_fee = PoolInstruction::unpack(&packed_set_referrer_fee).unwrap();
+        assert_eq!(original_set_referrer_fee, unpacked_set_referrer_fee);
+    }
+
+    #[test]
+    fn test_create_order_account_flags() {
+        let program_id = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..15).map(|_| Pubkey::new_unique()).collect();
+        let srm_discount_account = Pubkey::new_unique();
+        let build = |srm_discount_account| {
+            create_order(
+                &program_id,
+                &keys[0],
+                &keys[1],
+                &keys[2],
+                0,
+                1,
+                &keys[3],
+                &keys[4],
+                &keys[5],
+                &keys[6],
+                &keys[7],
+                &keys[8],
+                &keys[9],
+                &keys[10],
+                &keys[11],
+                &keys[13],
+                &keys[12],
+                srm_discount_account,
+                [0u8; 32],
+                Side::Bid,
+                NonZeroU64::new(1).unwrap(),
+                0,
+                1,
+                1,
+                &Pubkey::new_unique(),
+                NonZeroU16::new(1).unwrap(),
+                OrderType::Limit,
+                0,
+                SelfTradeBehavior::DecrementTake,
+                65535,
+                0,
+            )
+            .unwrap()
+        };
+
+        let instruction = build(None);
+        assert_eq!(instruction.accounts.len(), 15);
+        for (i, account) in instruction.accounts.iter().enumerate() {
+            // Only the signal provider signs
+            assert_eq!(account.is_signer, i == 0);
+            // The signal provider, the programs, the sysvar and the allowed mints are read-only
+            assert_eq!(account.is_writable, !matches!(i, 0 | 11..=14));
+            if i < 14 {
+                assert_eq!(account.pubkey, keys[i]);
+            }
+        }
+
+        // The discount account keeps its position, the allowed mints account comes last
+        let instruction = build(Some(&srm_discount_account));
+        assert_eq!(instruction.accounts.len(), 16);
+        assert_eq!(instruction.accounts[14].pubkey, srm_discount_account);
+        assert!(instruction.accounts[14].is_writable);
+        assert_eq!(
+            instruction.accounts[15].pubkey,
+            find_allowed_mints_key(&program_id, &keys[8]).0
+        );
+        assert!(!instruction.accounts[15].is_writable);
+    }
+
+    #[test]
+    fn test_derive_pool_addresses() {
+        let program_id = Pubkey::new_unique();
+        let signal_provider = Pubkey::new_unique();
+        let pool_seed = get_valid_seed(&program_id);
+        let addresses = derive_pool_addresses(&program_id, pool_seed, &signal_provider).unwrap();
+
+        // The derivations of the processor
+        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id).unwrap();
+        let mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        assert_eq!(addresses.pool_key, pool_key);
+        assert_eq!(addresses.mint_key, mint_key);
+        assert_eq!(
+            addresses.signal_provider_pt_key,
+            get_associated_token_address(&signal_provider, &mint_key)
+        );
+        assert_eq!(
+            addresses.bonfida_fee_pt_key,
+            get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), &mint_key)
+        );
+        assert_eq!(
+            addresses.bonfida_bnb_pt_key,
+            get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), &mint_key)
+        );
+    }
+
+    #[test]
+    fn test_collect_fees_accounts() {
+        let program_id = Pubkey::new_unique();
+        let signal_provider = Pubkey::new_unique();
+        let pool_seed = get_valid_seed(&program_id);
+        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id).unwrap();
+        let pool_token_mint =
+            Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+
+        let instruction = collect_fees(
+            &spl_token::id(),
+            &clock::id(),
+            &program_id,
+            &pool_key,
+            &pool_token_mint,
+            &get_associated_token_address(&signal_provider, &pool_token_mint),
+            pool_seed,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            collect_fees_accounts(&program_id, pool_seed, &signal_provider, None).unwrap(),
+            instruction.accounts
+        );
+
+        // The extra fee recipient's pooltoken account comes last
+        let extra_fee_recipient = Pubkey::new_unique();
+        let instruction = collect_fees(
+            &spl_token::id(),
+            &clock::id(),
+            &program_id,
+            &pool_key,
+            &pool_token_mint,
+            &get_associated_token_address(&signal_provider, &pool_token_mint),
+            pool_seed,
+            Some(&extra_fee_recipient),
+        )
+        .unwrap();
+        assert_eq!(
+            collect_fees_accounts(
+                &program_id,
+                pool_seed,
+                &signal_provider,
+                Some(&extra_fee_recipient)
+            )
+            .unwrap(),
+            instruction.accounts
+        );
+        assert_eq!(
+            instruction.accounts[7].pubkey,
+            get_associated_token_address(&extra_fee_recipient, &pool_token_mint)
+        );
+    }
+
+    #[test]
+    fn test_validate_create_params() {
+        let markets = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        assert!(validate_create_params(3, 2, &[10, 0, 5], &markets).is_ok());
+
+        // No assets
+        assert!(validate_create_params(3, 2, &[], &markets).is_err());
+        // More assets than allocated
+        assert!(validate_create_params(2, 2, &[10, 0, 5], &markets).is_err());
+        // Nothing deposited
+        assert!(validate_create_params(3, 2, &[0, 0], &markets).is_err());
+        // More markets than allocated
+        assert!(validate_create_params(3, 1, &[10, 0, 5], &markets).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_accounts_len() {
+        let program_id = Pubkey::new_unique();
+        let pool_seed = get_valid_seed(&program_id);
+        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id).unwrap();
+        let mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        let pool_asset_keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let user_asset_keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let owner = Pubkey::new_unique();
+        let pool_token_key = Pubkey::new_unique();
+
+        let create_instruction = create(
+            &spl_token::id(),
+            &clock::id(),
+            &program_id,
+            &mint_key,
+            &pool_key,
+            pool_seed,
+            &pool_asset_keys,
+            &pool_token_key,
+            &owner,
+            &user_asset_keys,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            604_800,
+            15,
+            None,
+            0,
+            PoolMode::Normal,
+            false,
+            0,
+            None,
+            vec![10, 20],
+            vec![Pubkey::new_unique()],
+        )
+        .unwrap();
+        assert_eq!(create_instruction.accounts.len(), create_accounts_len(2));
+
+        let deposit_instruction = deposit(
+            &spl_token::id(),
+            &program_id,
+            &mint_key,
+            &pool_key,
+            &pool_asset_keys,
+            &pool_token_key,
+            &Pubkey::new_unique(),
+            &owner,
+            &user_asset_keys,
+            pool_seed,
+            1_000,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(deposit_instruction.accounts.len(), deposit_accounts_len(2));
+
+        let redeem_instruction = redeem(
+            &spl_token::id(),
+            &clock::id(),
+            &program_id,
+            &mint_key,
+            &pool_key,
+            &pool_asset_keys,
+            &owner,
+            &pool_token_key,
+            &user_asset_keys,
+            pool_seed,
+            1_000,
+            None,
+        )
+        .unwrap();
+        assert_eq!(redeem_instruction.accounts.len(), redeem_accounts_len(2));
+    }
+
+    #[test]
+    fn test_bootstrap_pool_instructions() {
+        let program_id = Pubkey::new_unique();
+        let pool_seed = get_valid_seed(&program_id);
+        let asset_mints = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let source_asset_keys = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let instructions = bootstrap_pool_instructions(
+            &program_id,
+            &Pubkey::new_unique(),
+            pool_seed,
+            10,
+            &asset_mints,
+            &Pubkey::new_unique(),
+            &source_asset_keys,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            604_800,
+            15,
+            None,
+            0,
+            PoolMode::Normal,
+            false,
+            0,
+            None,
+            vec![10, 20],
+            vec![Pubkey::new_unique()],
+        )
+        .unwrap();
+
+        // Init, 2 pool asset accounts, the creator's pooltoken account, 3 fee accounts, Create
+        assert_eq!(instructions.len(), 8);
+        assert_eq!(instructions[0].program_id, program_id);
+        assert!(matches!(
+            PoolInstruction::unpack(&instructions[0].data).unwrap(),
+            PoolInstruction::Init { .. }
+        ));
+        for instruction in instructions[1..7].iter() {
+            assert_eq!(instruction.program_id, spl_associated_token_account::id());
+        }
+        assert_eq!(instructions[7].program_id, program_id);
+        assert!(matches!(
+            PoolInstruction::unpack(&instructions[7].data).unwrap(),
+            PoolInstruction::Create { .. }
+        ));
     }
 }
@@ -1,49 +1,273 @@
-use std::{cmp::min, convert::TryInto, mem::zeroed, num::{NonZeroU16, NonZeroU64, NonZeroU8}, str::FromStr};
+use std::{
+    cmp::min,
+    convert::TryInto,
+    mem::zeroed,
+    num::{NonZeroU16, NonZeroU64},
+    str::FromStr,
+};
 
 use crate::{
     error::BonfidaBotError,
-    instruction::PoolInstruction,
+    instruction::{
+        create_accounts_len, deposit_accounts_len, redeem_accounts_len, PoolInstruction,
+        DEPOSIT_POOL_ACCOUNT_INDEX,
+    },
     state::{
-        get_asset_slice, pack_markets, unpack_assets, unpack_market, unpack_unchecked_asset,
-        PoolAsset, PoolHeader, PoolStatus, BONFIDA_BNB, BONFIDA_FEE, PUBKEY_LENGTH,
+        add_allowed_mint, append_market, compute_deposit_amounts, compute_exact_deposit,
+        compute_nav_per_token, compute_received_pooltokens, compute_single_asset_deposit,
+        find_allowed_mints_key, find_nav_snapshots_key, find_open_orders_key,
+        get_allowed_mints_seeds, get_asset_slice, get_nav_snapshots_seeds, get_open_orders_seeds,
+        hash_pool_state, pack_assets, pack_markets, pack_metadata_uri, pack_pool_info,
+        pack_versioned_pool_header, preview_deposit, push_nav_snapshot, remove_allowed_mint,
+        unpack_assets, unpack_market, unpack_markets, unpack_metadata_uri, unpack_pool_assets,
+        unpack_pool_header, unpack_pool_header_unchecked, unpack_unchecked_asset,
+        unpack_versioned_pool_header, unpack_versioned_pool_header_unchecked, NavSnapshot,
+        PoolAsset, PoolHeader, PoolMode, PoolStatus, ALLOWED_MINTS_ACCOUNT_LEN, FIDA_MINT,
+        MAX_NUMBER_OF_MARKETS, METADATA_URI_LEN, NAV_SNAPSHOTS_ACCOUNT_LEN,
+        OPEN_ORDERS_ACCOUNT_LEN, POOL_HEADER_VERSION, POOL_INFO_ASSETS_PER_PAGE, PUBKEY_LENGTH,
     },
-    utils::{check_pool_key, check_signal_provider, fill_slice, pow_fixedpoint_u16},
+    utils::{
+        check_account_writable, check_accounts_len, check_asset_untracked, check_ata_program,
+        check_create_deposit_amounts, check_deposit_allowed, check_deposit_cap,
+        check_deposit_status, check_dex_program_update, check_discount_account,
+        check_emergency_withdraw_allowed, check_extra_fee_pt_account, check_extra_fee_recipient,
+        check_fee_collection_period, check_fee_pt_accounts, check_fee_ratio, check_fee_split_bps,
+        check_fida_minimum, check_market_order_books, check_min_deposit, check_min_expected_lots,
+        check_mint_authority, check_new_pool_asset, check_number_of_markets,
+        check_open_orders_owner, check_order_asset_indices, check_order_covered,
+        check_pool_asset_empty, check_pool_closable, check_pool_has_assets, check_pool_key,
+        check_pool_layout_fits, check_pool_token_decimals, check_redeem_allowed,
+        check_redeem_authorized, check_referrer_fee_bps, check_referrer_pt_account,
+        check_self_trade_behavior, check_serum_result, check_settle_indices, check_signal_provider,
+        check_single_asset_deposits_allowed, check_skipped_settle_side, check_sysvar_account,
+        check_target_mint_allowed, check_token_account, check_untracked_mint, check_vault_signer,
+        collect_fees_at, compute_deposit_fee, fill_slice, format_deposit_log, format_redeem_log,
+        get_asset_share, get_market_value, get_next_fee_period_timestamp, get_open_orders,
+        get_order_accounts_checks, get_order_amounts, get_order_required_amount,
+        get_owed_fee_pooltokens, get_pool_asset_address, get_resting_bid_lots,
+        get_toggled_lock_status, get_token_program, is_order_fully_freed, is_token_program,
+        pack_deposit_return_data, read_open_orders_balances, split_extra_fee, split_fee_bps,
+        split_rebate, split_referrer_fee, transfer_checked_instruction, unpack_mint_decimals,
+        unpack_pool_asset_account, unpack_token_account_data, update_fee_collection_period,
+        DEFAULT_BONFIDA_FEE_BPS, DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+        MAX_CANCELLED_ORDERS_PER_INSTRUCTION, MIN_FEE_COLLECTION_PERIOD,
+    },
+};
+use serum_dex::{
+    instruction::{self, cancel_order, new_order, settle_funds, SelfTradeBehavior},
+    matching::{OrderType, Side},
 };
-use serum_dex::{instruction::{self, SelfTradeBehavior, cancel_order, new_order, settle_funds}, matching::{OrderType, Side}};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     clock::Clock,
     entrypoint::ProgramResult,
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
     rent::Rent,
-    system_instruction::create_account,
-    sysvar::Sysvar,
+    system_instruction::{self, create_account},
+    sysvar::{clock, rent, Sysvar},
 };
-use spl_associated_token_account::get_associated_token_address;
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
 use spl_token::{
-    instruction::{burn, initialize_mint, mint_to, transfer},
+    instruction::{burn, close_account, initialize_mint, mint_to, transfer},
+    native_mint,
     state::Account,
     state::Mint,
 };
 
+/// Amounts of a deposit executed by `execute_deposit`
+enum DepositAmounts<'a> {
+    /// A wished amount of pooltokens, bound by the source balances
+    Pooltokens(u64),
+    /// Exact asset amounts along with the tolerated deviation from the pool ratios in basis
+    /// points
+    Exact(&'a [u64], u16),
+    /// An amount of the asset at the given index alone
+    SingleAsset(usize, u64),
+}
+
 pub struct Processor {}
 
 impl Processor {
+    /// Returns the pooltoken supply against which a redemption is paid out. Overdue fees are
+    /// not collected by redemptions, since the fee recipient accounts might not exist. The
+    /// pooltokens their collection would mint dilute the redemption instead, the redemption
+    /// of the whole supply included, and the assets backing them stay in the pool.
+    fn get_diluted_pooltokens(
+        pool_header: &PoolHeader,
+        total_pooltokens: u64,
+        current_timestamp: u64,
+    ) -> Result<u64, ProgramError> {
+        let owed_fee_pooltokens =
+            get_owed_fee_pooltokens(pool_header, total_pooltokens, current_timestamp)?;
+        if owed_fee_pooltokens != 0 {
+            msg!(
+                "Fees are overdue, the redemption is diluted by {} pooltokens",
+                owed_fee_pooltokens
+            );
+        }
+        total_pooltokens
+            .checked_add(owed_fee_pooltokens)
+            .ok_or_else(|| BonfidaBotError::Overflow.into())
+    }
+
+    /// Returns whether all the funds of a serum OpenOrders account are free, meaning that it
+    /// has no order left on the book.
+    fn is_open_orders_fully_freed(openorders_account: &AccountInfo) -> Result<bool, ProgramError> {
+        let openorders_balances = read_open_orders_balances(&openorders_account.data.borrow())?;
+        Ok(is_order_fully_freed(
+            openorders_balances.free_coin,
+            openorders_balances.total_coin,
+            openorders_balances.free_pc,
+            openorders_balances.total_pc,
+        ))
+    }
+
+    /// Releases the pending order of an OpenOrders account once cancelling its orders freed
+    /// all of its funds, which were not all free before. The freed funds are then settled
+    /// back into the pool as any others.
+    fn release_cancelled_order(
+        pool_account: &AccountInfo,
+        openorders_account: &AccountInfo,
+        was_fully_freed: bool,
+    ) -> ProgramResult {
+        if was_fully_freed || !Self::is_open_orders_fully_freed(openorders_account)? {
+            return Ok(());
+        }
+        let (mut pool_header, header_len) =
+            unpack_versioned_pool_header(&pool_account.data.borrow())?;
+        if !pool_header.status.has_pending_orders() {
+            return Ok(());
+        }
+        pool_header.status = pool_header.status.settle_one()?;
+        pack_versioned_pool_header(
+            &pool_header,
+            &mut pool_account.data.borrow_mut()[..header_len],
+        );
+        Ok(())
+    }
+
+    /// Creates a program derived account, which `create_account` would refuse to do once the
+    /// address holds lamports. Anyone can fund the address beforehand, so only the lamports
+    /// missing for rent exemption are transferred before allocating and assigning it.
+    fn create_pda_account<'a>(
+        system_program_account: &AccountInfo<'a>,
+        payer_account: &AccountInfo<'a>,
+        new_account: &AccountInfo<'a>,
+        rent: &Rent,
+        space: usize,
+        owner: &Pubkey,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        let missing_lamports = rent
+            .minimum_balance(space)
+            .saturating_sub(new_account.lamports());
+        if missing_lamports != 0 {
+            invoke(
+                &system_instruction::transfer(payer_account.key, new_account.key, missing_lamports),
+                &[
+                    system_program_account.clone(),
+                    payer_account.clone(),
+                    new_account.clone(),
+                ],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(new_account.key, space as u64),
+            &[system_program_account.clone(), new_account.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(new_account.key, owner),
+            &[system_program_account.clone(), new_account.clone()],
+            &[signer_seeds],
+        )
+    }
+
+    /// Transfers an asset under the token program owning the pool asset account. Token-2022
+    /// assets are transferred with `TransferChecked`, which requires the Token-2022 program
+    /// and asset mint accounts to be appended to the instruction accounts.
+    fn transfer_asset<'a>(
+        accounts: &[AccountInfo<'a>],
+        spl_token_account: &AccountInfo<'a>,
+        token_program_id: &Pubkey,
+        mint_key: &Pubkey,
+        source: &AccountInfo<'a>,
+        destination: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        amount: u64,
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        if token_program_id == &spl_token::id() {
+            let instruction = transfer(
+                spl_token_account.key,
+                source.key,
+                destination.key,
+                authority.key,
+                &[],
+                amount,
+            )?;
+            return invoke_signed(
+                &instruction,
+                &[
+                    source.clone(),
+                    destination.clone(),
+                    spl_token_account.clone(),
+                    authority.clone(),
+                ],
+                signers_seeds,
+            );
+        }
+        let token_program_account = get_token_program(accounts, token_program_id)?;
+        let mint_account = accounts
+            .iter()
+            .find(|account| account.key == mint_key)
+            .ok_or_else(|| {
+                msg!(
+                    "The mint account of the Token-2022 asset {} should be provided",
+                    mint_key
+                );
+                ProgramError::NotEnoughAccountKeys
+            })?;
+        let decimals = unpack_mint_decimals(&mint_account.data.borrow())?;
+        let instruction = transfer_checked_instruction(
+            token_program_id,
+            source.key,
+            mint_key,
+            destination.key,
+            authority.key,
+            amount,
+            decimals,
+        );
+        invoke_signed(
+            &instruction,
+            &[
+                source.clone(),
+                mint_account.clone(),
+                destination.clone(),
+                token_program_account.clone(),
+                authority.clone(),
+            ],
+            signers_seeds,
+        )
+    }
+
     pub fn process_init(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         pool_seed: [u8; 32],
         max_number_of_assets: u32,
         number_of_markets: u16,
+        decimals: u8,
     ) -> ProgramResult {
         let accounts_iter = &mut accounts.iter();
 
         let system_program_account = next_account_info(accounts_iter)?;
         let rent_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
         let spl_token_program_account = next_account_info(accounts_iter)?;
         let pool_account = next_account_info(accounts_iter)?;
         let mint_account = next_account_info(accounts_iter)?;
@@ -53,7 +277,7 @@ impl Processor {
 
         if spl_token_program_account.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
 
         // Find the non reversible public key for the pool account via the seed
@@ -74,6 +298,8 @@ impl Processor {
             msg!("Provided pool account is invalid");
             return Err(ProgramError::InvalidArgument);
         }
+        check_pool_token_decimals(decimals)?;
+        check_number_of_markets(number_of_markets as usize)?;
 
         let state_size = PoolHeader::LEN
             + PUBKEY_LENGTH * (number_of_markets as usize)
@@ -100,7 +326,7 @@ impl Processor {
             &mint_key,
             &pool_key,
             None,
-            6,
+            decimals,
         )?;
 
         invoke_signed(
@@ -139,17 +365,30 @@ impl Processor {
         markets: Vec<Pubkey>,
         fee_collection_period: u64,
         fee_ratio: u16,
+        deposit_fee_ratio: u16,
+        rebate_retention_ratio: u16,
+        pool_mode: PoolMode,
+        redeem_requires_provider: bool,
+        maturity_timestamp: u64,
+        fee_split_bps: Option<(u16, u16)>,
     ) -> ProgramResult {
         let number_of_assets = deposit_amounts.len();
+        check_accounts_len(
+            accounts.len(),
+            create_accounts_len(number_of_assets),
+            "Create",
+            number_of_assets,
+        )?;
         let accounts_iter = &mut accounts.iter();
 
         let spl_token_account = next_account_info(accounts_iter)?;
         if spl_token_account.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
 
         let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
         let serum_program_account = next_account_info(accounts_iter)?;
         let signal_provider_account = next_account_info(accounts_iter)?;
 
@@ -157,6 +396,9 @@ impl Processor {
         let target_pool_token_account = next_account_info(accounts_iter)?;
 
         let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+        check_account_writable(mint_account, "pooltoken mint")?;
+        check_account_writable(target_pool_token_account, "target pooltoken")?;
         let mut pool_assets_accounts: Vec<&AccountInfo> = vec![];
         for _ in 0..number_of_assets {
             pool_assets_accounts.push(next_account_info(accounts_iter)?)
@@ -170,8 +412,8 @@ impl Processor {
         let current_timestamp =
             Clock::from_account_info(&clock_sysvar_account)?.unix_timestamp as u64;
 
-        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id).unwrap();
-        let mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id)?;
+        let mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
 
         if pool_key != *pool_account.key {
             msg!("Provided pool account is invalid");
@@ -181,11 +423,9 @@ impl Processor {
             msg!("Provided mint account is invalid");
             return Err(ProgramError::InvalidArgument);
         }
+        check_mint_authority(&Mint::unpack(&mint_account.data.borrow())?, &pool_key)?;
         // Verifying that no pool was already created with this seed
-        let pool_status =
-            PoolHeader::unpack_from_slice(&pool_account.try_borrow_data()?[..PoolHeader::LEN])
-                .unwrap()
-                .status;
+        let pool_status = unpack_pool_header_unchecked(&pool_account.try_borrow_data()?)?.status;
         if pool_status != PoolStatus::Uninitialized {
             msg!("Cannot overwrite an existing pool.");
             return Err(ProgramError::InvalidArgument);
@@ -198,61 +438,88 @@ impl Processor {
             msg!("Source token account owner should be a signer.");
             return Err(ProgramError::InvalidArgument);
         }
-        if markets.len() >> 16 != 0 {
+        // The founding pooltokens can only be minted to an account controlled by the creator
+        let target_pool_token_data = Account::unpack(&target_pool_token_account.data.borrow())
+            .or_else(|e| {
+                msg!("Invalid target pooltoken account provided");
+                Err(e)
+            })?;
+        check_token_account(&target_pool_token_data, &mint_key, source_owner_account.key)?;
+        if markets.len() > MAX_NUMBER_OF_MARKETS {
             msg!("Number of given markets is too high.");
             return Err(ProgramError::InvalidArgument);
         }
-        if fee_collection_period < 604800 {
+        if fee_collection_period < MIN_FEE_COLLECTION_PERIOD {
             msg!("Fee collection period should be longer than a week.");
             return Err(ProgramError::InvalidArgument);
         }
+        check_fee_ratio(fee_ratio)?;
+        check_fee_ratio(deposit_fee_ratio)?;
+        let (signal_provider_fee_bps, bonfida_fee_bps) = match fee_split_bps {
+            Some((signal_provider_fee_bps, bonfida_fee_bps)) => {
+                check_fee_split_bps(signal_provider_fee_bps, bonfida_fee_bps)?;
+                (signal_provider_fee_bps, bonfida_fee_bps)
+            }
+            None => (DEFAULT_SIGNAL_PROVIDER_FEE_BPS, DEFAULT_BONFIDA_FEE_BPS),
+        };
+        check_create_deposit_amounts(&deposit_amounts)?;
 
         let mut pool_assets: Vec<PoolAsset> = vec![];
+        let mut fida_amount = 0;
         for i in 0..number_of_assets {
-
             if deposit_amounts[i as usize] == 0 {
                 continue;
             }
 
-            let pool_asset_data = Account::unpack(&pool_assets_accounts[i as usize].data.borrow())?;
+            let token_program_id = pool_assets_accounts[i as usize].owner;
+            if !is_token_program(token_program_id) {
+                msg!("Pool asset accounts should be owned by a token program");
+                return Err(ProgramError::InvalidArgument);
+            }
+            let pool_asset_data =
+                unpack_token_account_data(&pool_assets_accounts[i as usize].data.borrow())?;
 
             if pool_asset_data.close_authority.is_some() | pool_asset_data.delegate.is_some() {
                 msg!("Invalid pool asset account");
-                return Err(ProgramError::InvalidArgument)
+                return Err(ProgramError::InvalidArgument);
             }
 
             let mint_asset_key = pool_asset_data.mint;
-            let pool_asset_key = get_associated_token_address(&pool_key, &mint_asset_key);
+            check_new_pool_asset(&pool_assets, &mint_asset_key)?;
+            if mint_asset_key == Pubkey::from_str(FIDA_MINT).unwrap() {
+                fida_amount = deposit_amounts[i as usize];
+            }
+            let pool_asset_key =
+                get_pool_asset_address(&pool_key, &mint_asset_key, token_program_id);
 
             if pool_asset_key != *pool_assets_accounts[i as usize].key {
                 msg!("Provided pool asset account is invalid");
                 return Err(ProgramError::InvalidArgument);
             }
 
-            let transfer_instruction = transfer(
-                spl_token_account.key,
-                source_assets_accounts[i as usize].key,
-                &pool_assets_accounts[i as usize].key,
-                source_owner_account.key,
-                &[],
+            Self::transfer_asset(
+                accounts,
+                spl_token_account,
+                token_program_id,
+                &mint_asset_key,
+                source_assets_accounts[i as usize],
+                pool_assets_accounts[i as usize],
+                source_owner_account,
                 deposit_amounts[i as usize],
-            )?;
-
-            invoke(
-                &transfer_instruction,
-                &[
-                    source_assets_accounts[i as usize].clone(),
-                    pool_assets_accounts[i].clone(),
-                    spl_token_account.clone(),
-                    source_owner_account.clone(),
-                ],
+                &[],
             )?;
             pool_assets.push(PoolAsset {
                 mint_address: mint_asset_key,
             });
         }
+        if cfg!(feature = "fida-minimum") {
+            check_fida_minimum(fida_amount)?;
+        }
 
-        // Mint the first pooltoken to the target
+        // Mint the first pooltoken to the target. The amount is in base units, so that the first
+        // deposit buys 10**(6 - decimals) pooltokens, which is one pooltoken with the default
+        // 6 decimals. Redemptions and deposits are proportional to the supply in base units and
+        // aren't affected by the decimals choice.
         let instruction = mint_to(
             spl_token_account.key,
             &mint_key,
@@ -283,19 +550,40 @@ impl Processor {
             last_fee_collection_timestamp: current_timestamp,
             fee_collection_period,
             fee_ratio,
+            deposit_fee_ratio,
+            rebate_retention_ratio,
+            pending_fee_ratio: 0,
+            pending_fee_ratio_timestamp: 0,
+            pool_mode,
+            redeem_requires_provider,
+            maturity_timestamp,
+            version: POOL_HEADER_VERSION,
+            max_pooltoken_supply: 0,
+            extra_fee_recipient: Pubkey::default(),
+            extra_fee_bps: 0,
+            signal_provider_fee_bps,
+            bonfida_fee_bps,
+            single_asset_deposits: false,
+            metadata_uri: [0u8; METADATA_URI_LEN],
+            referrer_fee_bps: 0,
         };
+        check_pool_layout_fits(
+            pool_account.data.borrow().len(),
+            markets.len(),
+            pool_assets.len(),
+        )?;
         let mut data = pool_account.data.borrow_mut();
         state_header.pack_into_slice(&mut data);
 
         // Write the authorized markets to the account data
         pack_markets(&mut data[PoolHeader::LEN..], &markets)?;
 
-        // Write the assets into the account data
-        let mut offset = PoolHeader::LEN + PUBKEY_LENGTH * markets.len();
-        for asset in pool_assets.iter() {
-            asset.pack_into_slice(&mut data[offset..]);
-            offset += PoolAsset::LEN;
-        }
+        // Write the assets into the account data. Assets with a zero initial deposit
+        // were skipped above and don't occupy a slot.
+        pack_assets(
+            &mut data[PoolHeader::LEN + PUBKEY_LENGTH * markets.len()..],
+            &pool_assets,
+        )?;
 
         Ok(())
     }
@@ -306,13 +594,154 @@ impl Processor {
         pool_seed: [u8; 32],
         // The amount of pooltokens wished to be bought
         pool_token_amount: u64,
+        // Whether a referrer pooltoken account follows the source accounts
+        with_referrer: bool,
+    ) -> ProgramResult {
+        Self::execute_deposit(
+            program_id,
+            accounts,
+            pool_seed,
+            DepositAmounts::Pooltokens(pool_token_amount),
+            with_referrer,
+        )
+    }
+
+    pub fn process_deposit_exact(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        asset_amounts: Vec<u64>,
+        tolerance_bps: u16,
+    ) -> ProgramResult {
+        Self::execute_deposit(
+            program_id,
+            accounts,
+            pool_seed,
+            DepositAmounts::Exact(&asset_amounts, tolerance_bps),
+            false,
+        )
+    }
+
+    pub fn process_deposit_single(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        asset_index: usize,
+        amount: u64,
+    ) -> ProgramResult {
+        Self::execute_deposit(
+            program_id,
+            accounts,
+            pool_seed,
+            DepositAmounts::SingleAsset(asset_index, amount),
+            false,
+        )
+    }
+
+    pub fn process_deposit_and_lock(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        pool_token_amount: u64,
+    ) -> ProgramResult {
+        let (signal_provider_account, deposit_accounts) = accounts
+            .split_first()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let pool_account = deposit_accounts
+            .get(DEPOSIT_POOL_ACCOUNT_INDEX)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        check_account_writable(pool_account, "pool")?;
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        Self::process_deposit(
+            program_id,
+            deposit_accounts,
+            pool_seed,
+            pool_token_amount,
+            false,
+        )?;
+
+        // The deposit went through, so the pool was unlocked
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        pool_header.status = get_toggled_lock_status(&pool_header.status)?;
+        msg!("The pool status is now {:?}", pool_header.status);
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    /// Returns the value of the pool in the asset at `asset_index`. Each of the other assets held
+    /// by the pool is valued on one of the pool's markets trading it for the asset, given in
+    /// `price_accounts` as a market, bids and asks accounts triple per other asset in order.
+    fn get_pool_value_in_asset(
+        pool_header: &PoolHeader,
+        pool_account: &AccountInfo,
+        pool_assets: &[PoolAsset],
+        pool_asset_amounts: &[u64],
+        asset_index: usize,
+        price_accounts: &[&AccountInfo],
+    ) -> Result<u128, ProgramError> {
+        let quote_mint = pool_assets
+            .get(asset_index)
+            .ok_or_else(|| {
+                msg!("The pool has no asset at index {}", asset_index);
+                ProgramError::InvalidArgument
+            })?
+            .mint_address;
+        let markets = unpack_markets(
+            &pool_account.data.borrow()[PoolHeader::LEN..],
+            pool_header.number_of_markets,
+        );
+        let mut pool_value = pool_asset_amounts[asset_index] as u128;
+        let other_assets = (0..pool_assets.len()).filter(|i| *i != asset_index);
+        for (i, accounts) in other_assets.zip(price_accounts.chunks_exact(3)) {
+            if pool_asset_amounts[i] == 0 {
+                continue;
+            }
+            let (market, bids, asks) = (accounts[0], accounts[1], accounts[2]);
+            if market.owner != &pool_header.serum_program_id || !markets.contains(market.key) {
+                msg!("The market given for asset {} is not one of the pool's", i);
+                return Err(ProgramError::InvalidArgument);
+            }
+            let market_data = market.data.borrow();
+            check_market_order_books(&market_data, bids.key, asks.key)?;
+            let value = get_market_value(
+                &market_data,
+                &bids.data.borrow(),
+                &asks.data.borrow(),
+                &pool_assets[i].mint_address,
+                &quote_mint,
+                pool_asset_amounts[i],
+            )?;
+            pool_value = pool_value
+                .checked_add(value)
+                .ok_or(BonfidaBotError::Overflow)?;
+        }
+        Ok(pool_value)
+    }
+
+    /// Executes a deposit either for a wished amount of pooltokens, bound by the source
+    /// balances, for exact asset amounts along with the tolerated deviation from the pool
+    /// ratios in basis points, or for an amount of a single asset on pools which allow it.
+    /// With `with_referrer`, the pool's share of the deposit fee for referrers is minted to the
+    /// referrer pooltoken account following the source accounts.
+    fn execute_deposit(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        amounts: DepositAmounts,
+        with_referrer: bool,
     ) -> ProgramResult {
         let accounts_iter = &mut accounts.iter();
 
         let spl_token_account = next_account_info(accounts_iter)?;
         if spl_token_account.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
         let mint_account = next_account_info(accounts_iter)?;
 
@@ -322,11 +751,26 @@ impl Processor {
         let bonfida_bnb_pt_account = next_account_info(accounts_iter)?;
 
         let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(mint_account, "pooltoken mint")?;
+        check_account_writable(target_pool_token_account, "target pooltoken")?;
 
-        let pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
-        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
-        let pool_assets = unpack_assets(&pool_account.data.borrow()[asset_offset..])?;
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        let pool_assets =
+            unpack_pool_assets(&pool_account.data.borrow(), pool_header.number_of_markets)?;
         let nb_assets = pool_assets.len();
+        check_pool_has_assets(nb_assets)?;
+        // The optional referrer account is expected when one is given, the extra fee
+        // recipient account when its share is nonzero and the price accounts for single asset
+        // deposits
+        let price_accounts_len = match amounts {
+            DepositAmounts::SingleAsset(..) => 3 * (nb_assets - 1),
+            _ => 0,
+        };
+        let expected_accounts_len = deposit_accounts_len(nb_assets)
+            + with_referrer as usize
+            + (pool_header.extra_fee_bps != 0) as usize
+            + price_accounts_len;
+        check_accounts_len(accounts.len(), expected_accounts_len, "Deposit", nb_assets)?;
 
         let mut pool_assets_accounts: Vec<&AccountInfo> = vec![];
         let mut source_assets_accounts: Vec<&AccountInfo> = vec![];
@@ -337,17 +781,29 @@ impl Processor {
         for _ in 0..nb_assets {
             source_assets_accounts.push(next_account_info(accounts_iter)?)
         }
+        let referrer_pt_account = if with_referrer {
+            let referrer_pt_account = next_account_info(accounts_iter)?;
+            check_referrer_pt_account(
+                referrer_pt_account,
+                target_pool_token_account,
+                source_owner_account.key,
+            )?;
+            Some(referrer_pt_account)
+        } else {
+            None
+        };
+        let extra_fee_pt_account = if pool_header.extra_fee_bps != 0 {
+            Some(next_account_info(accounts_iter)?)
+        } else {
+            None
+        };
+        let mut price_accounts = Vec::with_capacity(price_accounts_len);
+        for _ in 0..price_accounts_len {
+            price_accounts.push(next_account_info(accounts_iter)?);
+        }
 
-        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id).unwrap();
-        let pool_mint_key =
-            Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
-
-        let signal_provider_pt_key =
-            get_associated_token_address(&pool_header.signal_provider, &pool_mint_key);
-        let bonfida_fee_pt_key =
-            get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), &pool_mint_key);
-        let bonfida_bnb_pt_key =
-            get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), &pool_mint_key);
+        let pool_key = Pubkey::create_program_address(&[&pool_seed], &program_id)?;
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
 
         // Safety verifications
         if pool_key != *pool_account.key {
@@ -367,102 +823,194 @@ impl Processor {
             return Err(ProgramError::InvalidArgument);
         }
 
-        if signal_provider_pt_account.key != &signal_provider_pt_key {
-            msg!("The provided signal provider pool token account is invalid.");
-            return Err(ProgramError::InvalidArgument);
-        }
+        check_fee_pt_accounts(
+            &pool_header,
+            &pool_mint_key,
+            signal_provider_pt_account.key,
+            bonfida_fee_pt_account.key,
+            bonfida_bnb_pt_account.key,
+        )?;
 
-        if bonfida_fee_pt_account.key != &bonfida_fee_pt_key {
-            msg!("The provided bonfida fee pool token account is invalid.");
-            return Err(ProgramError::InvalidArgument);
+        if let Some(extra_fee_pt_account) = extra_fee_pt_account {
+            check_extra_fee_pt_account(&pool_header, &pool_mint_key, extra_fee_pt_account)?;
         }
 
-        if bonfida_bnb_pt_account.key != &bonfida_bnb_pt_key {
-            msg!("The provided bonfida buy and burn pool token account is invalid.");
-            return Err(ProgramError::InvalidArgument);
+        check_deposit_allowed(&pool_header)?;
+        check_deposit_status(&pool_header.status)?;
+        if let DepositAmounts::SingleAsset(..) = amounts {
+            check_single_asset_deposits_allowed(&pool_header)?;
         }
 
-        // Doing a match on all cases here would be more idiomatic
-        match pool_header.status {
-            PoolStatus::Unlocked => (),
-            PoolStatus::Locked | PoolStatus::LockedPendingOrder(_) => {
-                msg!("The signal provider has currently locked the pool. No buy-ins are possible for now.");
-                return Err(BonfidaBotError::LockedOperation.into())
+        let pool_mint = Mint::unpack(&mint_account.data.borrow())?;
+        check_mint_authority(&pool_mint, pool_account.key)?;
+        let total_pooltokens = pool_mint.supply;
+        let mut pool_asset_amounts = Vec::with_capacity(nb_assets);
+        for i in 0..nb_assets {
+            pool_asset_amounts.push(unpack_pool_asset_account(pool_assets_accounts[i], i)?.amount);
+        }
+        // Single asset deposits are valued against the value of the pool in the deposited asset
+        let single_asset_pool_value = match amounts {
+            DepositAmounts::SingleAsset(asset_index, _) => Self::get_pool_value_in_asset(
+                &pool_header,
+                pool_account,
+                &pool_assets,
+                &pool_asset_amounts,
+                asset_index,
+                &price_accounts,
+            )?,
+            _ => 0,
+        };
+        let (deposit_amounts, mut pool_token_effective_amount) = match amounts {
+            DepositAmounts::Pooltokens(pool_token_amount) => {
+                let mut source_asset_amounts = Vec::with_capacity(nb_assets);
+                for source_asset_account in source_assets_accounts.iter() {
+                    source_asset_amounts.push(
+                        unpack_token_account_data(&source_asset_account.data.borrow())?.amount,
+                    );
+                }
+                // Compute buy-in amount. The effective buy-in amount can be less than the
+                // input_token_amount as the source accounts need to satisfy the pool asset ratios
+                let pool_token_effective_amount = preview_deposit(
+                    total_pooltokens,
+                    pool_token_amount,
+                    &pool_asset_amounts,
+                    &source_asset_amounts,
+                );
+                if pool_token_effective_amount < pool_token_amount {
+                    msg!(
+                        "The source balances bound the deposit to {} pooltokens",
+                        pool_token_effective_amount
+                    );
+                }
+
+                // The transferred amounts are rounded down, only mint the pooltokens they
+                // actually back
+                let (deposit_amounts, backed_pooltokens) = compute_deposit_amounts(
+                    total_pooltokens,
+                    &pool_asset_amounts,
+                    pool_token_effective_amount,
+                );
+                if backed_pooltokens < pool_token_effective_amount {
+                    msg!(
+                        "Rounding reduces the deposit from {} to {} pooltokens",
+                        pool_token_effective_amount,
+                        backed_pooltokens
+                    );
+                }
+                (deposit_amounts, backed_pooltokens)
+            }
+            DepositAmounts::Exact(asset_amounts, tolerance_bps) => {
+                if asset_amounts.len() != nb_assets {
+                    msg!(
+                        "One amount should be given for each of the {} pool assets",
+                        nb_assets
+                    );
+                    return Err(ProgramError::InvalidArgument);
+                }
+                // Only the pooltokens backed by the scarcest asset are minted
+                let (backed_pooltokens, deviation_bps) =
+                    compute_exact_deposit(total_pooltokens, &pool_asset_amounts, asset_amounts)
+                        .ok_or_else(|| {
+                            msg!("Assets absent from the pool cannot be deposited");
+                            ProgramError::InvalidArgument
+                        })?;
+                if deviation_bps > tolerance_bps as u64 {
+                    msg!(
+                        "The amounts deviate from the pool ratios by {} basis points, above the tolerance of {}",
+                        deviation_bps,
+                        tolerance_bps
+                    );
+                    return Err(ProgramError::InvalidArgument);
+                }
+                (asset_amounts.to_vec(), backed_pooltokens)
             }
-            PoolStatus::PendingOrder(_) => {
-                msg!("The pool has one or more pending orders. No buy-ins are possible for now. Try again later.");
-                return Err(BonfidaBotError::LockedOperation.into())
+            DepositAmounts::SingleAsset(asset_index, amount) => {
+                let backed_pooltokens =
+                    compute_single_asset_deposit(total_pooltokens, single_asset_pool_value, amount)
+                        .ok_or_else(|| {
+                            msg!("A pool without value cannot be deposited into");
+                            ProgramError::InvalidArgument
+                        })?;
+                let mut deposit_amounts = vec![0; nb_assets];
+                deposit_amounts[asset_index] = amount;
+                (deposit_amounts, backed_pooltokens)
             }
-            PoolStatus::Uninitialized => unreachable!(),
         };
-
-        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
-        let mut pool_asset_amounts = Vec::with_capacity(nb_assets);
-        // Compute buy-in amount. The effective buy-in amount can be less than the
-        // input_token_amount as the source accounts need to satisfy the pool asset ratios
-        let mut pool_token_effective_amount = std::u64::MAX;
-        for i in 0..nb_assets {
-            let pool_asset_amount = Account::unpack(&pool_assets_accounts[i].data.borrow())?.amount;
-            pool_asset_amounts.push(pool_asset_amount);
-
-            let source_asset_amount =
-                Account::unpack(&source_assets_accounts[i].data.borrow())?.amount;
-            pool_token_effective_amount = min(
-                ((source_asset_amount as u128) * (total_pooltokens as u128))
-                    .checked_div(pool_asset_amount as u128)
-                    .unwrap_or(std::u64::MAX.into()) as u64,
-                pool_token_effective_amount,
-            );
+        if pool_token_effective_amount == 0 {
+            msg!("The deposit is too small to back any pooltokens");
+            return Err(BonfidaBotError::OperationTooSmall.into());
         }
-        pool_token_effective_amount = min(pool_token_amount, pool_token_effective_amount);
+        check_min_deposit(pool_token_effective_amount, pool_mint.decimals)?;
 
         // Execute buy in
         let mut amounts_all_zero = true;
+        let mut received_amounts = vec![0; nb_assets];
         for i in 0..nb_assets {
+            let token_program_id = pool_assets_accounts[i].owner;
             let pool_asset_key =
-                get_associated_token_address(&pool_key, &pool_assets[i].mint_address);
+                get_pool_asset_address(&pool_key, &pool_assets[i].mint_address, token_program_id);
 
             if pool_asset_key != *pool_assets_accounts[i as usize].key {
                 msg!("Provided pool asset account is invalid");
                 return Err(ProgramError::InvalidArgument);
             }
 
-            let amount = ((pool_token_effective_amount as u128) * (pool_asset_amounts[i] as u128))
-                / (total_pooltokens as u128);
+            let amount = deposit_amounts[i];
             if amount == 0 {
                 continue;
             } else {
                 amounts_all_zero = false;
             }
 
-            let instruction = transfer(
-                spl_token_account.key,
-                source_assets_accounts[i].key,
-                pool_assets_accounts[i].key,
-                source_owner_account.key,
+            Self::transfer_asset(
+                accounts,
+                spl_token_account,
+                token_program_id,
+                &pool_assets[i].mint_address,
+                source_assets_accounts[i],
+                pool_assets_accounts[i],
+                source_owner_account,
+                amount,
                 &[],
-                amount as u64,
-            )?;
-            invoke(
-                &instruction,
-                &[
-                    source_assets_accounts[i].clone(),
-                    pool_assets_accounts[i].clone(),
-                    spl_token_account.clone(),
-                    source_owner_account.clone(),
-                ],
             )?;
+            // Transfer fees of Token-2022 mints reduce the amount actually received
+            received_amounts[i] = unpack_pool_asset_account(pool_assets_accounts[i], i)?
+                .amount
+                .checked_sub(pool_asset_amounts[i])
+                .ok_or(BonfidaBotError::Overflow)?;
         }
         if amounts_all_zero {
             msg!("The provided amounts cannot be all zero.");
             return Err(ProgramError::InvalidArgument);
         }
 
-        let cast_fee_ratio = pool_header.fee_ratio as u128;
-
-        let pool_token_fee = ((cast_fee_ratio * pool_token_effective_amount as u128) >> 16) as u64;
+        let received_pooltokens = match amounts {
+            DepositAmounts::SingleAsset(asset_index, _) => compute_single_asset_deposit(
+                total_pooltokens,
+                single_asset_pool_value,
+                received_amounts[asset_index],
+            )
+            .ok_or(ProgramError::InvalidArgument)?,
+            _ => compute_received_pooltokens(
+                total_pooltokens,
+                &pool_asset_amounts,
+                &deposit_amounts,
+                &received_amounts,
+                pool_token_effective_amount,
+            ),
+        };
+        if received_pooltokens < pool_token_effective_amount {
+            msg!(
+                "Transfer fees reduce the deposit from {} to {} pooltokens",
+                pool_token_effective_amount,
+                received_pooltokens
+            );
+        }
+        pool_token_effective_amount = received_pooltokens;
 
-        let pool_token_amount_after_fee = pool_token_effective_amount - pool_token_fee;
+        let (pool_token_amount_after_fee, pool_token_fee) =
+            compute_deposit_fee(pool_header.deposit_fee_ratio, pool_token_effective_amount)?;
+        check_deposit_cap(&pool_header, total_pooltokens, pool_token_effective_amount)?;
 
         // Mint the effective amount of pooltokens to the target
         let instruction = mint_to(
@@ -485,79 +1033,150 @@ impl Processor {
             &[&[&pool_seed]],
         )?;
 
-        // Mint the effective amount of pooltokens to the target
-        let signal_provider_fee = pool_token_fee / 2;
-        let instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            signal_provider_pt_account.key,
-            &pool_key,
-            &[],
-            signal_provider_fee,
-        )?;
-
-        invoke_signed(
-            &instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                signal_provider_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+        // Nothing is minted as fees for small deposits
+        if pool_token_fee != 0 {
+            let referrer_fee_bps = if referrer_pt_account.is_some() {
+                pool_header.referrer_fee_bps
+            } else {
+                0
+            };
+            let (referrer_fee, pool_token_fee) =
+                split_referrer_fee(pool_token_fee, referrer_fee_bps);
+            if let Some(referrer_pt_account) = referrer_pt_account.filter(|_| referrer_fee != 0) {
+                // Mint the referrer's share of the fees
+                let instruction = mint_to(
+                    spl_token_account.key,
+                    &pool_mint_key,
+                    referrer_pt_account.key,
+                    &pool_key,
+                    &[],
+                    referrer_fee,
+                )?;
+
+                invoke_signed(
+                    &instruction,
+                    &[
+                        spl_token_account.clone(),
+                        mint_account.clone(),
+                        referrer_pt_account.clone(),
+                        pool_account.clone(),
+                    ],
+                    &[&[&pool_seed]],
+                )?;
+            }
 
-        // Mint the effective amount of pooltokens to the target
-        let bonfida_fee = pool_token_fee / 4;
-        let instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            bonfida_fee_pt_account.key,
-            &pool_key,
-            &[],
-            bonfida_fee,
-        )?;
+            let (extra_fee, pool_token_fee) =
+                split_extra_fee(pool_token_fee, pool_header.extra_fee_bps);
+            if let Some(extra_fee_pt_account) = extra_fee_pt_account.filter(|_| extra_fee != 0) {
+                // Mint the extra fee recipient's share of the fees
+                let instruction = mint_to(
+                    spl_token_account.key,
+                    &pool_mint_key,
+                    extra_fee_pt_account.key,
+                    &pool_key,
+                    &[],
+                    extra_fee,
+                )?;
+
+                invoke_signed(
+                    &instruction,
+                    &[
+                        spl_token_account.clone(),
+                        mint_account.clone(),
+                        extra_fee_pt_account.clone(),
+                        pool_account.clone(),
+                    ],
+                    &[&[&pool_seed]],
+                )?;
+            }
 
-        invoke_signed(
-            &instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                bonfida_fee_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+            let (signal_provider_fee, bonfida_fee, bonfida_bnb_fee) = split_fee_bps(
+                pool_token_fee,
+                pool_header.signal_provider_fee_bps,
+                pool_header.bonfida_fee_bps,
+            );
 
-        // Mint the effective amount of pooltokens to the target
-        let instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            bonfida_bnb_pt_account.key,
-            &pool_key,
-            &[],
-            pool_token_fee - bonfida_fee - signal_provider_fee,
-        )?;
+            // Mint the signal provider's share of the fees
+            let instruction = mint_to(
+                spl_token_account.key,
+                &pool_mint_key,
+                signal_provider_pt_account.key,
+                &pool_key,
+                &[],
+                signal_provider_fee,
+            )?;
 
-        invoke_signed(
-            &instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                bonfida_bnb_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_account.clone(),
+                    mint_account.clone(),
+                    signal_provider_pt_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
 
-        Ok(())
-    }
+            // Mint the Bonfida fee account's share of the fees
+            let instruction = mint_to(
+                spl_token_account.key,
+                &pool_mint_key,
+                bonfida_fee_pt_account.key,
+                &pool_key,
+                &[],
+                bonfida_fee,
+            )?;
 
-    pub fn process_create_order(
-        program_id: &Pubkey,
-        accounts: &[AccountInfo],
-        pool_seed: [u8; 32],
-        side: Side,
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_account.clone(),
+                    mint_account.clone(),
+                    bonfida_fee_pt_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+
+            // Mint the remainder of the fees to the Bonfida buy and burn account
+            let instruction = mint_to(
+                spl_token_account.key,
+                &pool_mint_key,
+                bonfida_bnb_pt_account.key,
+                &pool_key,
+                &[],
+                bonfida_bnb_fee,
+            )?;
+
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_account.clone(),
+                    mint_account.clone(),
+                    bonfida_bnb_pt_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+        }
+
+        msg!(
+            "{}",
+            format_deposit_log(pool_token_amount_after_fee, pool_token_fee)
+        );
+        set_return_data(&pack_deposit_return_data(
+            pool_token_amount_after_fee,
+            pool_token_fee,
+        ));
+
+        Ok(())
+    }
+
+    pub fn process_create_order(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        side: Side,
         limit_price: NonZeroU64,
         max_ratio_of_pool_to_sell_to_another_fellow_trader: NonZeroU16,
         order_type: OrderType,
@@ -570,6 +1189,7 @@ impl Processor {
         source_index: usize,
         target_index: usize,
         serum_limit: u16,
+        min_expected_lots: u64,
     ) -> ProgramResult {
         // TODO : Enforce one order limit on openorders accounts
 
@@ -589,13 +1209,28 @@ impl Processor {
         let spl_token_program = next_account_info(account_iter)?;
         if spl_token_program.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
         let rent_sysvar_account = next_account_info(account_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
         let dex_program = next_account_info(account_iter)?;
-        let discount_account = next_account_info(account_iter).ok();
+        check_account_writable(pool_account, "pool")?;
 
         check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        // The allowed mints account follows the optional discount account
+        let (allowed_mints_key, _) = find_allowed_mints_key(program_id, pool_account.key);
+        let mut discount_account = next_account_info(account_iter).ok();
+        let allowed_mints_account = if discount_account.map(|a| a.key) == Some(&allowed_mints_key) {
+            discount_account.take()
+        } else {
+            next_account_info(account_iter).ok()
+        };
+        if let Some(allowed_mints_account) = allowed_mints_account {
+            if allowed_mints_key != *allowed_mints_account.key {
+                msg!("Provided allowed mints account is invalid");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
 
         let source_account =
             Account::unpack(&pool_asset_token_account.data.borrow()).or_else(|e| {
@@ -609,78 +1244,38 @@ impl Processor {
             msg!("Source token account should be associated to the pool account");
             return Err(ProgramError::InvalidArgument);
         }
-        if order_type != OrderType::ImmediateOrCancel {
-            msg!("Order needs to be of type ImmediateOrCancel");
-            return Err(ProgramError::InvalidArgument);
-        }
-
-        let mut pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
         if &pool_header.serum_program_id != dex_program.key {
             msg!("The provided serum program account is invalid for this pool.");
             return Err(ProgramError::InvalidArgument);
         }
-        if !signal_provider_account.is_signer {
-            msg!("The signal provider's signature is required.");
-            return Err(ProgramError::MissingRequiredSignature);
-        }
-        if signal_provider_account.key != &pool_header.signal_provider {
-            msg!("A wrong signal provider account was provided.");
-            return Err(ProgramError::MissingRequiredSignature);
-        }
+        check_signal_provider(&pool_header, signal_provider_account)?;
         if market.key
             != &unpack_market(&pool_account.data.borrow()[PoolHeader::LEN..], market_index)
         {
             msg!("The given market account is not authorized.");
             return Err(ProgramError::MissingRequiredSignature);
         }
+        if pool_header.pool_mode == PoolMode::Frozen {
+            msg!("The pool is frozen. No new orders are possible.");
+            return Err(BonfidaBotError::ModeRestricted.into());
+        }
+        if let Some(discount_account) = discount_account {
+            let discount_data = Account::unpack(&discount_account.data.borrow())?;
+            check_discount_account(&discount_data, pool_account.key)?;
+        }
 
-        
-        let openorders_total_pc = openorders_account
-            .data
-            .borrow()
-            .get(101..109)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        let openorders_balances = read_open_orders_balances(&openorders_account.data.borrow())?;
 
-        let openorders_total_coin = openorders_account
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let max_number_of_assets = pool_account
             .data
             .borrow()
-            .get(85..93)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
-        
-        let new_open_order = (openorders_total_coin == 0) && (openorders_total_pc == 0);
-        match (&pool_header.status, new_open_order) {
-            (PoolStatus::Uninitialized, _) => return Err(ProgramError::UninitializedAccount),
-            (PoolStatus::Unlocked, _) => {
-                pool_header.status = PoolStatus::PendingOrder(NonZeroU8::new(1).unwrap())
-            }
-            (PoolStatus::Locked, _) => {
-                pool_header.status = PoolStatus::LockedPendingOrder(NonZeroU8::new(1).unwrap())
-            }
-            (PoolStatus::PendingOrder(n), true) | (PoolStatus::LockedPendingOrder(n), true) => {
-                if n.get() == 64 {
-                    msg!("Maximum number of active orders has been reached. Settle or cancel a pending order.");
-                    return Err(BonfidaBotError::Overflow.into());
-                }
-                let pending_orders = NonZeroU8::new(n.get() + 1).unwrap();
-                pool_header.status = match pool_header.status {
-                    PoolStatus::PendingOrder(_) => PoolStatus::PendingOrder(pending_orders),
-                    PoolStatus::LockedPendingOrder(_) => {
-                        PoolStatus::LockedPendingOrder(pending_orders)
-                    }
-                    _ => {
-                        unreachable!()
-                    }
-                }
-            }
-            _ => {} // This happens in the case when the openorder account is already counted in the pending orders.
-        };
-        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
-
-        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+            .len()
+            .saturating_sub(asset_offset)
+            / PoolAsset::LEN;
+        check_order_asset_indices(source_index, target_index, max_number_of_assets)?;
+        check_self_trade_behavior(self_trade_behavior)?;
         let source_asset =
             unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], source_index)?;
         let mut target_asset =
@@ -707,6 +1302,12 @@ impl Processor {
                 return Err(ProgramError::InvalidArgument);
             }
         } else {
+            let allowed_mints_account = allowed_mints_account.ok_or_else(|| {
+                msg!("The allowed mints account is required to trade into a new asset");
+                ProgramError::NotEnoughAccountKeys
+            })?;
+            check_target_mint_allowed(&allowed_mints_account.data.borrow(), &target_mint)?;
+            check_asset_untracked(&pool_account.data.borrow()[asset_offset..], &target_mint)?;
             target_asset.mint_address = target_mint;
             &target_asset.pack_into_slice(get_asset_slice(
                 &mut pool_account.data.borrow_mut()[asset_offset..],
@@ -714,22 +1315,51 @@ impl Processor {
             )?);
         }
 
+        // Re-read the balance right before placement as it may have changed since the
+        // client computed the order, and only update the pool status once it is feasible.
         let pool_asset_amount = Account::unpack(&pool_asset_token_account.data.borrow())?.amount;
+        let (amount_to_trade, lots_to_trade) = get_order_amounts(
+            pool_asset_amount,
+            max_ratio_of_pool_to_sell_to_another_fellow_trader,
+            side,
+            coin_lot_size,
+            pc_lot_size,
+        )?;
+        // Orders resting on the book only buy the lots the traded amount covers at the limit
+        // price, immediate orders are bounded by `max_native_pc_qty_including_fees` instead
+        let lots_to_trade = match (side, order_type) {
+            (Side::Bid, OrderType::Limit) | (Side::Bid, OrderType::PostOnly) => {
+                get_resting_bid_lots(amount_to_trade, limit_price, pc_lot_size)?
+            }
+            _ => lots_to_trade,
+        };
+        let required_amount =
+            get_order_required_amount(side, amount_to_trade, lots_to_trade, coin_lot_size)?;
+        check_min_expected_lots(lots_to_trade, min_expected_lots)?;
+        let lots_to_trade = NonZeroU64::new(lots_to_trade).ok_or_else(|| {
+            msg!("Operation too small");
+            BonfidaBotError::OperationTooSmall
+        })?;
+        let max_native_pc_qty_including_fees = match side {
+            Side::Bid => NonZeroU64::new(amount_to_trade).ok_or_else(|| {
+                msg!("Operation too small");
+                BonfidaBotError::OperationTooSmall
+            })?,
+            Side::Ask => NonZeroU64::new(1).unwrap(),
+        };
 
-        let amount_to_trade = (((pool_asset_amount as u128)
-            * (max_ratio_of_pool_to_sell_to_another_fellow_trader.get() as u128))
-            >> 16) as u64;
-
-        let lots_to_trade = amount_to_trade
-            .checked_div(match side {
-                Side::Bid => pc_lot_size,
-                Side::Ask => coin_lot_size,
-            })
-            .ok_or(BonfidaBotError::Overflow)?;
+        let new_open_order =
+            (openorders_balances.total_coin == 0) && (openorders_balances.total_pc == 0);
+        // An openorder account with funds, such as one with an order resting on the book, is
+        // already counted in the pending orders
+        if new_open_order || !pool_header.status.has_pending_orders() {
+            pool_header.status = pool_header.status.begin_order()?;
+        }
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
 
         if pool_asset_amount == amount_to_trade {
             // If order empties a pool asset, reset it
-            
+
             fill_slice(
                 get_asset_slice(
                     &mut pool_account.data.borrow_mut()[asset_offset..],
@@ -739,14 +1369,6 @@ impl Processor {
             );
         }
 
-        let max_native_pc_qty_including_fees = match side {
-            Side::Bid => NonZeroU64::new(amount_to_trade).ok_or_else(|| {
-                msg!("Operation too small");
-                BonfidaBotError::OperationTooSmall
-            })?,
-            Side::Ask => NonZeroU64::new(1).unwrap(),
-        };
-
         let new_order_instruction = new_order(
             market.key,
             openorders_account.key,
@@ -764,10 +1386,7 @@ impl Processor {
             dex_program.key,
             side,
             limit_price,
-            NonZeroU64::new(lots_to_trade).ok_or_else(|| {
-                msg!("Operation too small");
-                BonfidaBotError::OperationTooSmall
-            })?,
+            lots_to_trade,
             order_type,
             client_id,
             self_trade_behavior,
@@ -795,7 +1414,17 @@ impl Processor {
             account_infos.push(account.clone());
         }
 
-        invoke_signed(&new_order_instruction, &account_infos, &[&[&pool_seed]])?;
+        check_serum_result(
+            invoke_signed(&new_order_instruction, &account_infos, &[&[&pool_seed]]),
+            "new order",
+            &pool_seed,
+            market.key,
+        )?;
+        check_order_covered(
+            pool_asset_amount,
+            Account::unpack(&pool_asset_token_account.data.borrow())?.amount,
+            required_amount,
+        )?;
 
         Ok(())
     }
@@ -804,8 +1433,8 @@ impl Processor {
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         pool_seed: [u8; 32],
-        pc_index: usize,
-        coin_index: usize,
+        pc_index: Option<usize>,
+        coin_index: Option<usize>,
     ) -> ProgramResult {
         let account_iter = &mut accounts.iter();
         let market = next_account_info(account_iter)?;
@@ -820,11 +1449,14 @@ impl Processor {
         let spl_token_program = next_account_info(account_iter)?;
         if spl_token_program.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
         let dex_program = next_account_info(account_iter)?;
 
         let referrer_account = next_account_info(account_iter).ok();
+        let signal_provider_pc_account = next_account_info(account_iter).ok();
+        check_account_writable(pool_account, "pool")?;
+        check_settle_indices(coin_index, pc_index)?;
 
         check_pool_key(program_id, pool_account.key, &pool_seed)?;
 
@@ -833,8 +1465,7 @@ impl Processor {
 
         let pool_coin_account_key = get_associated_token_address(pool_account.key, &coin_mint);
         let pool_pc_account_key = get_associated_token_address(pool_account.key, &pc_mint);
-        let pool_mint_key =
-            Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
 
         if &pool_mint_key != pool_token_mint.key {
             msg!("Provided pool mint account is invalid.");
@@ -849,17 +1480,35 @@ impl Processor {
             msg!("Provided pool pc account does not match the pool pc asset");
             return Err(ProgramError::InvalidArgument);
         }
+        check_vault_signer(
+            dex_program.key,
+            market.key,
+            &market.data.borrow(),
+            vault_signer.key,
+        )?;
 
         let pool_coin_account = Account::unpack(&pool_coin_wallet.data.borrow())?;
         let pool_pc_account = Account::unpack(&pool_pc_wallet.data.borrow())?;
 
-        let mut pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
-
-        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
-        let mut pool_coin_asset =
-            unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], coin_index)?;
-        let mut pool_pc_asset =
-            unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], pc_index)?;
+        // Pools with an older layout settle their orders to be wound down
+        let (mut pool_header, header_len) =
+            unpack_versioned_pool_header(&pool_account.data.borrow())?;
+
+        let asset_offset = header_len + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let mut pool_coin_asset = match coin_index {
+            Some(i) => Some((
+                i,
+                unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], i)?,
+            )),
+            None => None,
+        };
+        let mut pool_pc_asset = match pc_index {
+            Some(i) => Some((
+                i,
+                unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], i)?,
+            )),
+            None => None,
+        };
 
         if &pool_coin_account.owner != pool_account.key {
             msg!("Pool should own the provided coin account");
@@ -871,105 +1520,102 @@ impl Processor {
             return Err(ProgramError::InvalidArgument);
         }
 
-        if pool_coin_asset.is_initialized() {
-            if pool_coin_asset.mint_address != coin_mint {
-                msg!("Coin asset does not match market coin token");
-                return Err(ProgramError::InvalidArgument);
+        if let Some((_, pool_coin_asset)) = pool_coin_asset.as_mut() {
+            if pool_coin_asset.is_initialized() {
+                if pool_coin_asset.mint_address != coin_mint {
+                    msg!("Coin asset does not match market coin token");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            } else {
+                check_asset_untracked(&pool_account.data.borrow()[asset_offset..], &coin_mint)?;
+                pool_coin_asset.mint_address = coin_mint
             }
-        } else {
-            pool_coin_asset.mint_address = coin_mint
         }
 
-        if pool_pc_asset.is_initialized() {
-            if pool_pc_asset.mint_address != pc_mint {
-                msg!("Coin asset does not match market pc token");
-                return Err(ProgramError::InvalidArgument);
+        if let Some((_, pool_pc_asset)) = pool_pc_asset.as_mut() {
+            if pool_pc_asset.is_initialized() {
+                if pool_pc_asset.mint_address != pc_mint {
+                    msg!("Coin asset does not match market pc token");
+                    return Err(ProgramError::InvalidArgument);
+                }
+            } else {
+                check_asset_untracked(&pool_account.data.borrow()[asset_offset..], &pc_mint)?;
+                pool_pc_asset.mint_address = pc_mint
             }
-        } else {
-            pool_pc_asset.mint_address = pc_mint
         }
 
+        if let (Some((_, pool_coin_asset)), Some((_, pool_pc_asset))) =
+            (&pool_coin_asset, &pool_pc_asset)
+        {
+            if pool_coin_asset.mint_address == pool_pc_asset.mint_address {
+                msg!("The coin and pc assets should be different");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
 
-        let openorders_free_pc = openorders_account
-            .data
-            .borrow()
-            .get(93..101)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
-
-        let openorders_free_coin = openorders_account
-            .data
-            .borrow()
-            .get(77..85)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
-
-        let openorders_total_pc = openorders_account
-            .data
-            .borrow()
-            .get(101..109)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        let openorders_balances = read_open_orders_balances(&openorders_account.data.borrow())?;
 
-        let openorders_total_coin = openorders_account
-            .data
-            .borrow()
-            .get(85..93)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(ProgramError::InvalidAccountData)?;
+        check_skipped_settle_side(coin_index, openorders_balances.free_coin, "coin")?;
+        check_skipped_settle_side(pc_index, openorders_balances.free_pc, "pc")?;
 
-        if (openorders_free_pc == openorders_total_pc)
-            && (openorders_free_coin == openorders_total_coin)
-        {
-            // This means the order can be entirely settled.
-            pool_header.status = match pool_header.status {
-                PoolStatus::PendingOrder(n) | PoolStatus::LockedPendingOrder(n) => {
-                    if n.get() == 1 {
-                        match pool_header.status {
-                            PoolStatus::PendingOrder(_) => PoolStatus::Unlocked,
-                            PoolStatus::LockedPendingOrder(_) => PoolStatus::Locked,
-                            _ => {
-                                unreachable!()
-                            }
-                        }
-                    } else {
-                        let pending_orders = NonZeroU8::new(n.get() - 1).unwrap();
-                        match pool_header.status {
-                            PoolStatus::PendingOrder(_) => PoolStatus::PendingOrder(pending_orders),
-                            PoolStatus::LockedPendingOrder(_) => {
-                                PoolStatus::LockedPendingOrder(pending_orders)
-                            }
-                            _ => {
-                                unreachable!()
-                            }
-                        }
-                    }
+        // Referral rebates are paid to the pool, the signal provider's share is then
+        // transferred out of the pool pc account once the funds are settled.
+        let referrer_rebate = match referrer_account {
+            Some(referrer) => {
+                if referrer.key != pool_pc_wallet.key {
+                    msg!("Referral rebates should be paid to the pool pc account");
+                    return Err(ProgramError::InvalidArgument);
                 }
-                _ => {
-                    msg!("The pool has no pending orders.");
-                    return Err(ProgramError::InvalidAccountData)
-                },
+                let signal_provider_pc_key =
+                    get_associated_token_address(&pool_header.signal_provider, &pc_mint);
+                if signal_provider_pc_account.map(|a| a.key) != Some(&signal_provider_pc_key) {
+                    msg!("The provided signal provider pc account is invalid.");
+                    return Err(ProgramError::InvalidArgument);
+                }
+                openorders_account
+                    .data
+                    .borrow()
+                    .get(3213..3221)
+                    .and_then(|slice| slice.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(ProgramError::InvalidAccountData)?
             }
+            None => 0,
+        };
+
+        // This means the order can be entirely settled. Its pending order was already
+        // released if its funds were freed by cancelling it.
+        if is_order_fully_freed(
+            openorders_balances.free_coin,
+            openorders_balances.total_coin,
+            openorders_balances.free_pc,
+            openorders_balances.total_pc,
+        ) && pool_header.status.has_pending_orders()
+        {
+            pool_header.status = pool_header.status.settle_one()?;
         }
-        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+        pack_versioned_pool_header(
+            &pool_header,
+            &mut pool_account.data.borrow_mut()[..header_len],
+        );
 
-        if (openorders_free_pc == 0) & (openorders_free_coin == 0) {
+        if (openorders_balances.free_pc == 0) & (openorders_balances.free_coin == 0) {
             msg!("No funds to settle.");
             return Err(BonfidaBotError::LockedOperation.into());
         }
 
-        &pool_coin_asset.pack_into_slice(get_asset_slice(
-            &mut pool_account.data.borrow_mut()[asset_offset..],
-            coin_index,
-        )?);
-        &pool_pc_asset.pack_into_slice(get_asset_slice(
-            &mut pool_account.data.borrow_mut()[asset_offset..],
-            pc_index,
-        )?);
+        if let Some((coin_index, pool_coin_asset)) = pool_coin_asset {
+            pool_coin_asset.pack_into_slice(get_asset_slice(
+                &mut pool_account.data.borrow_mut()[asset_offset..],
+                coin_index,
+            )?);
+        }
+        if let Some((pc_index, pool_pc_asset)) = pool_pc_asset {
+            pool_pc_asset.pack_into_slice(get_asset_slice(
+                &mut pool_account.data.borrow_mut()[asset_offset..],
+                pc_index,
+            )?);
+        }
 
         let instruction = settle_funds(
             dex_program.key,
@@ -1002,7 +1648,37 @@ impl Processor {
             accounts.push(a.clone())
         }
 
-        invoke_signed(&instruction, &accounts, &[&[&pool_seed]])?;
+        check_serum_result(
+            invoke_signed(&instruction, &accounts, &[&[&pool_seed]]),
+            "settle funds",
+            &pool_seed,
+            market.key,
+        )?;
+
+        let (_, signal_provider_rebate) =
+            split_rebate(referrer_rebate, pool_header.rebate_retention_ratio);
+        if signal_provider_rebate != 0 {
+            let signal_provider_pc_account =
+                signal_provider_pc_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let instruction = transfer(
+                spl_token_program.key,
+                pool_pc_wallet.key,
+                signal_provider_pc_account.key,
+                pool_account.key,
+                &[],
+                signal_provider_rebate,
+            )?;
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_program.clone(),
+                    pool_pc_wallet.clone(),
+                    signal_provider_pc_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+        }
 
         Ok(())
     }
@@ -1026,9 +1702,16 @@ impl Processor {
         let dex_program = next_account_info(accounts_iter)?;
 
         check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        check_account_writable(pool_account, "pool")?;
 
-        let pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
-        check_signal_provider(&pool_header, signal_provider, true)?;
+        let (pool_header, _) = unpack_versioned_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider)?;
+        if &pool_header.serum_program_id != dex_program.key {
+            msg!("The provided serum program account is invalid for this pool.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        check_open_orders_owner(&openorders_account.data.borrow(), pool_account.key)?;
+        let was_fully_freed = Self::is_open_orders_fully_freed(openorders_account)?;
 
         let instruction = cancel_order(
             &dex_program.key,
@@ -1042,18 +1725,154 @@ impl Processor {
             order_id,
         )?;
 
-        invoke_signed(
-            &instruction,
-            &vec![
-                dex_program.clone(),
-                market.clone(),
-                serum_market_bids.clone(),
-                serum_market_asks.clone(),
-                openorders_account.clone(),
-                pool_account.clone(),
-                event_queue.clone(),
-            ],
-            &[&[&pool_seed]],
+        check_serum_result(
+            invoke_signed(
+                &instruction,
+                &vec![
+                    dex_program.clone(),
+                    market.clone(),
+                    serum_market_bids.clone(),
+                    serum_market_asks.clone(),
+                    openorders_account.clone(),
+                    pool_account.clone(),
+                    event_queue.clone(),
+                ],
+                &[&[&pool_seed]],
+            ),
+            "cancel order",
+            &pool_seed,
+            market.key,
+        )?;
+
+        Self::release_cancelled_order(pool_account, openorders_account, was_fully_freed)
+    }
+
+    pub fn process_cancel_all(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let signal_provider = next_account_info(accounts_iter)?;
+        let market = next_account_info(accounts_iter)?;
+        let openorders_account = next_account_info(accounts_iter)?;
+        let serum_market_bids = next_account_info(accounts_iter)?;
+        let serum_market_asks = next_account_info(accounts_iter)?;
+        let event_queue = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let dex_program = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        check_account_writable(pool_account, "pool")?;
+
+        let (pool_header, _) = unpack_versioned_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider)?;
+        if &pool_header.serum_program_id != dex_program.key {
+            msg!("The provided serum program account is invalid for this pool.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        check_open_orders_owner(&openorders_account.data.borrow(), pool_account.key)?;
+        let was_fully_freed = Self::is_open_orders_fully_freed(openorders_account)?;
+
+        let open_orders = get_open_orders(&openorders_account.data.borrow())?;
+        let cancelled_orders = min(open_orders.len(), MAX_CANCELLED_ORDERS_PER_INSTRUCTION);
+        for (side, order_id) in open_orders.into_iter().take(cancelled_orders) {
+            let instruction = cancel_order(
+                &dex_program.key,
+                market.key,
+                serum_market_bids.key,
+                serum_market_asks.key,
+                openorders_account.key,
+                pool_account.key,
+                event_queue.key,
+                side,
+                order_id,
+            )?;
+
+            check_serum_result(
+                invoke_signed(
+                    &instruction,
+                    &vec![
+                        dex_program.clone(),
+                        market.clone(),
+                        serum_market_bids.clone(),
+                        serum_market_asks.clone(),
+                        openorders_account.clone(),
+                        pool_account.clone(),
+                        event_queue.clone(),
+                    ],
+                    &[&[&pool_seed]],
+                ),
+                "cancel order",
+                &pool_seed,
+                market.key,
+            )?;
+        }
+
+        let remaining_orders = get_open_orders(&openorders_account.data.borrow())?.len();
+        msg!(
+            "Cancelled {} orders, {} orders remain open",
+            cancelled_orders,
+            remaining_orders
+        );
+
+        // The pending order is released once the last order is cancelled, possibly by a
+        // later instruction
+        Self::release_cancelled_order(pool_account, openorders_account, was_fully_freed)
+    }
+
+    pub fn process_consume_events(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        limit: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let market = next_account_info(accounts_iter)?;
+        let openorders_account = next_account_info(accounts_iter)?;
+        let event_queue = next_account_info(accounts_iter)?;
+        let coin_fee_receivable_account = next_account_info(accounts_iter)?;
+        let pc_fee_receivable_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let dex_program = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        if &pool_header.serum_program_id != dex_program.key {
+            msg!("The provided serum program account is invalid for this pool.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        check_open_orders_owner(&openorders_account.data.borrow(), pool_account.key)?;
+
+        // Only the events are processed, the pool status is not modified
+        let instruction = instruction::consume_events(
+            dex_program.key,
+            vec![openorders_account.key],
+            market.key,
+            event_queue.key,
+            coin_fee_receivable_account.key,
+            pc_fee_receivable_account.key,
+            limit,
+        )?;
+
+        check_serum_result(
+            invoke(
+                &instruction,
+                &[
+                    dex_program.clone(),
+                    openorders_account.clone(),
+                    market.clone(),
+                    event_queue.clone(),
+                    coin_fee_receivable_account.clone(),
+                    pc_fee_receivable_account.clone(),
+                ],
+            ),
+            "consume events",
+            &pool_seed,
+            market.key,
         )?;
 
         Ok(())
@@ -1065,25 +1884,40 @@ impl Processor {
         pool_seed: [u8; 32],
         // The amount of pooltokens wished to be redeemed
         pool_token_amount: u64,
+        // Whether the wrapped SOL share should be unwrapped to the owner's native balance
+        unwrap_sol: bool,
     ) -> ProgramResult {
         let accounts_iter = &mut accounts.iter();
 
         let spl_token_account = next_account_info(accounts_iter)?;
         if spl_token_account.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
         let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
 
         let mint_account = next_account_info(accounts_iter)?;
         let source_pool_token_owner_account = next_account_info(accounts_iter)?;
         let source_pool_token_account = next_account_info(accounts_iter)?;
         let pool_account = next_account_info(accounts_iter)?;
-
-        let mut pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
-        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
-        let pool_assets = unpack_assets(&pool_account.data.borrow()[asset_offset..])?;
+        check_account_writable(pool_account, "pool")?;
+        check_account_writable(mint_account, "pooltoken mint")?;
+        check_account_writable(source_pool_token_account, "source pooltoken")?;
+
+        // Pools with an older layout are read in place, so that their pooltokens can always be
+        // redeemed
+        let (mut pool_header, header_len) =
+            unpack_versioned_pool_header(&pool_account.data.borrow())?;
+        let pool_assets =
+            unpack_pool_assets(&pool_account.data.borrow(), pool_header.number_of_markets)?;
         let nb_assets = pool_assets.len();
+        check_accounts_len(
+            accounts.len(),
+            redeem_accounts_len(nb_assets),
+            "Redeem",
+            nb_assets,
+        )?;
 
         let mut pool_assets_accounts: Vec<&AccountInfo> = vec![];
         let mut target_assets_accounts: Vec<&AccountInfo> = vec![];
@@ -1093,11 +1927,14 @@ impl Processor {
         for _ in 0..nb_assets {
             target_assets_accounts.push(next_account_info(accounts_iter)?)
         }
+        // The Token-2022 program account may be appended without a signal provider
+        let signal_provider_account = next_account_info(accounts_iter)
+            .ok()
+            .filter(|account| !is_token_program(account.key));
 
         // Safety verifications
         check_pool_key(&program_id, &pool_account.key, &pool_seed)?;
-        let pool_mint_key =
-            Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
         if pool_mint_key != *mint_account.key {
             msg!("Provided mint account is invalid");
             return Err(ProgramError::InvalidArgument);
@@ -1110,6 +1947,8 @@ impl Processor {
             msg!("Program should own pool account");
             return Err(ProgramError::InvalidArgument);
         }
+        check_redeem_allowed(&pool_header)?;
+        check_fee_collection_period(&pool_header)?;
         match pool_header.status {
             PoolStatus::PendingOrder(_) | PoolStatus::LockedPendingOrder(_) => {
                 msg!("The pool has one or more pending orders. No buy-outs are possible for now. Try again later.");
@@ -1120,59 +1959,72 @@ impl Processor {
 
         let current_timestamp =
             Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
-        if current_timestamp - pool_header.last_fee_collection_timestamp
-            > pool_header.fee_collection_period
-        {
-            msg!("Fees should be collected before redeeming.");
-            return Err(BonfidaBotError::LockedOperation.into());
-        }
+        check_redeem_authorized(&pool_header, signal_provider_account, current_timestamp)?;
 
         let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
-        let total_user_pooltokens = Account::unpack(&source_pool_token_account.data.borrow())?.amount;
+        let total_user_pooltokens =
+            Account::unpack(&source_pool_token_account.data.borrow())?.amount;
 
         if total_user_pooltokens < pool_token_amount {
             msg!("Insufficient pool token funds");
-            return Err(ProgramError::InsufficientFunds)
-        } 
+            return Err(ProgramError::InsufficientFunds);
+        }
+        let diluted_pooltokens =
+            Self::get_diluted_pooltokens(&pool_header, total_pooltokens, current_timestamp)?;
 
         // Execute buy out
+        let mut redeemed_amounts = Vec::with_capacity(nb_assets);
         for i in 0..nb_assets {
-            let pool_asset_key =
-                get_associated_token_address(&pool_account.key, &pool_assets[i].mint_address);
+            let token_program_id = pool_assets_accounts[i].owner;
+            let pool_asset_key = get_pool_asset_address(
+                &pool_account.key,
+                &pool_assets[i].mint_address,
+                token_program_id,
+            );
 
             if pool_asset_key != *pool_assets_accounts[i].key {
                 msg!("Provided pool asset account is invalid");
                 return Err(ProgramError::InvalidArgument);
             }
 
-            let pool_asset_amount = Account::unpack(&pool_assets_accounts[i].data.borrow())?.amount;
+            let pool_asset_amount = unpack_pool_asset_account(pool_assets_accounts[i], i)?.amount;
 
-            let amount: u64 = (((pool_token_amount as u128) * (pool_asset_amount as u128))
-                / (total_pooltokens as u128))
-                .try_into()
-                .map_err(|_| BonfidaBotError::Overflow)?;
+            let amount = get_asset_share(pool_token_amount, pool_asset_amount, diluted_pooltokens)?;
+            redeemed_amounts.push(amount);
 
             if amount == 0 {
                 continue;
             }
-            let instruction = transfer(
-                spl_token_account.key,
-                pool_assets_accounts[i].key,
-                target_assets_accounts[i].key,
-                pool_account.key,
-                &[],
+            Self::transfer_asset(
+                accounts,
+                spl_token_account,
+                token_program_id,
+                &pool_assets[i].mint_address,
+                pool_assets_accounts[i],
+                target_assets_accounts[i],
+                pool_account,
                 amount,
-            )?;
-            invoke_signed(
-                &instruction,
-                &[
-                    spl_token_account.clone(),
-                    pool_assets_accounts[i].clone(),
-                    target_assets_accounts[i].clone(),
-                    pool_account.clone(),
-                ],
                 &[&[&pool_seed]],
             )?;
+
+            if unwrap_sol && pool_assets[i].mint_address == native_mint::id() {
+                // Close the temporary wrapped SOL account to the owner's native balance
+                let instruction = close_account(
+                    spl_token_account.key,
+                    target_assets_accounts[i].key,
+                    source_pool_token_owner_account.key,
+                    source_pool_token_owner_account.key,
+                    &[],
+                )?;
+                invoke(
+                    &instruction,
+                    &[
+                        spl_token_account.clone(),
+                        target_assets_accounts[i].clone(),
+                        source_pool_token_owner_account.clone(),
+                    ],
+                )?;
+            }
         }
 
         // Burn the redeemed pooltokens
@@ -1195,11 +2047,19 @@ impl Processor {
             ],
         )?;
 
+        msg!(
+            "{}",
+            format_redeem_log(pool_token_amount, &redeemed_amounts)
+        );
+
         if pool_token_amount == total_pooltokens {
             // Reset the pool data, keeping the pool header mostly intact to preserve pool seeds
-            fill_slice(&mut pool_account.data.borrow_mut()[PoolHeader::LEN..], 0u8);
+            fill_slice(&mut pool_account.data.borrow_mut()[header_len..], 0u8);
             pool_header.status = PoolStatus::Uninitialized;
-            pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+            pack_versioned_pool_header(
+                &pool_header,
+                &mut pool_account.data.borrow_mut()[..header_len],
+            );
         }
 
         Ok(())
@@ -1214,149 +2074,1386 @@ impl Processor {
         let spl_token_account = next_account_info(accounts_iter)?;
         if spl_token_account.key != &spl_token::id() {
             msg!("Incorrect spl token program provided");
-            return Err(ProgramError::IncorrectProgramId)
+            return Err(ProgramError::IncorrectProgramId);
         }
         let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
         let pool_account = next_account_info(accounts_iter)?;
 
         let mint_account = next_account_info(accounts_iter)?;
         let signal_provider_pt_account = next_account_info(accounts_iter)?;
         let bonfida_fee_pt_account = next_account_info(accounts_iter)?;
         let bonfida_bnb_pt_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+        check_account_writable(mint_account, "pooltoken mint")?;
 
         check_pool_key(program_id, pool_account.key, &pool_seed)?;
 
-        let pool_mint_key =
-            Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id).unwrap();
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
         if pool_mint_key != *mint_account.key {
             msg!("Provided mint account is invalid.");
             return Err(ProgramError::InvalidArgument);
         }
 
-        let mut pool_header = PoolHeader::unpack(&pool_account.data.borrow()[..PoolHeader::LEN])?;
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_fee_collection_period(&pool_header)?;
 
-        let signal_provider_pt_key =
-            get_associated_token_address(&pool_header.signal_provider, &pool_mint_key);
-        let bonfida_fee_pt_key =
-            get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), &pool_mint_key);
-        let bonfida_bnb_pt_key =
-            get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), &pool_mint_key);
+        check_fee_pt_accounts(
+            &pool_header,
+            &pool_mint_key,
+            signal_provider_pt_account.key,
+            bonfida_fee_pt_account.key,
+            bonfida_bnb_pt_account.key,
+        )?;
+
+        let extra_fee_pt_account = if pool_header.extra_fee_bps != 0 {
+            let extra_fee_pt_account = next_account_info(accounts_iter)?;
+            check_extra_fee_pt_account(&pool_header, &pool_mint_key, extra_fee_pt_account)?;
+            Some(extra_fee_pt_account)
+        } else {
+            None
+        };
+
+        let current_timestamp =
+            Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
+        let pool_mint = Mint::unpack(&mint_account.data.borrow())?;
+        check_mint_authority(&pool_mint, pool_account.key)?;
+        let total_pooltokens = pool_mint.supply;
+        let tokens_to_mint =
+            collect_fees_at(&mut pool_header, total_pooltokens, current_timestamp)?;
+
+        let (extra_fee, tokens_to_mint) =
+            split_extra_fee(tokens_to_mint, pool_header.extra_fee_bps);
+        if let Some(extra_fee_pt_account) = extra_fee_pt_account.filter(|_| extra_fee != 0) {
+            // Mint the extra fee recipient's share of the fees
+            let mint_to_extra_instruction = mint_to(
+                spl_token_account.key,
+                &pool_mint_key,
+                extra_fee_pt_account.key,
+                &pool_account.key,
+                &[],
+                extra_fee,
+            )?;
+
+            invoke_signed(
+                &mint_to_extra_instruction,
+                &[
+                    spl_token_account.clone(),
+                    mint_account.clone(),
+                    extra_fee_pt_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+        }
+
+        let (signal_provider_fee, bonfida_fee, bonfida_bnb_fee) = split_fee_bps(
+            tokens_to_mint,
+            pool_header.signal_provider_fee_bps,
+            pool_header.bonfida_fee_bps,
+        );
+
+        // Mint the required amount of pooltokens to the signal provider
+        let mint_to_sp_instruction = mint_to(
+            spl_token_account.key,
+            &pool_mint_key,
+            signal_provider_pt_account.key,
+            &pool_account.key,
+            &[],
+            signal_provider_fee,
+        )?;
+
+        invoke_signed(
+            &mint_to_sp_instruction,
+            &[
+                spl_token_account.clone(),
+                mint_account.clone(),
+                signal_provider_pt_account.clone(),
+                pool_account.clone(),
+            ],
+            &[&[&pool_seed]],
+        )?;
+
+        // Mint the required amount of pooltokens to the bonfida fee account
+        let mint_to_bonfida_fee_instruction = mint_to(
+            spl_token_account.key,
+            &pool_mint_key,
+            bonfida_fee_pt_account.key,
+            &pool_account.key,
+            &[],
+            bonfida_fee,
+        )?;
+
+        invoke_signed(
+            &mint_to_bonfida_fee_instruction,
+            &[
+                spl_token_account.clone(),
+                mint_account.clone(),
+                bonfida_fee_pt_account.clone(),
+                pool_account.clone(),
+            ],
+            &[&[&pool_seed]],
+        )?;
+
+        // Mint the required amount of pooltokens to the bonfida buy and burn account
+        let mint_to_bonfida_bnb_instruction = mint_to(
+            spl_token_account.key,
+            &pool_mint_key,
+            bonfida_bnb_pt_account.key,
+            &pool_account.key,
+            &[],
+            bonfida_bnb_fee,
+        )?;
+
+        invoke_signed(
+            &mint_to_bonfida_bnb_instruction,
+            &[
+                spl_token_account.clone(),
+                mint_account.clone(),
+                bonfida_bnb_pt_account.clone(),
+                pool_account.clone(),
+            ],
+            &[&[&pool_seed]],
+        )?;
+
+        PoolHeader::pack(
+            pool_header,
+            &mut pool_account.data.borrow_mut()[..PoolHeader::LEN],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_publish_state_hash(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let pool_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        // Make sure the pool is initialized
+        unpack_pool_header(&pool_account.data.borrow())?;
+
+        let state_hash = hash_pool_state(&pool_account.data.borrow());
+        msg!("Pool state hash: {}", state_hash);
+        set_return_data(state_hash.as_ref());
+
+        Ok(())
+    }
+
+    pub fn process_get_pool_info(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        page: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let pool_account = next_account_info(accounts_iter)?;
+        let mint_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid.");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let pool_assets = unpack_assets(&pool_account.data.borrow()[asset_offset..])?;
+        let page_start = (page as usize) * POOL_INFO_ASSETS_PER_PAGE;
+        if page_start >= pool_assets.len() && page != 0 {
+            msg!("The pool has no assets in page {}", page);
+            return Err(ProgramError::InvalidArgument);
+        }
+        let page_end = min(page_start + POOL_INFO_ASSETS_PER_PAGE, pool_assets.len());
+
+        let mut assets = Vec::with_capacity(page_end.saturating_sub(page_start));
+        for i in page_start..page_end {
+            let pool_asset_account = next_account_info(accounts_iter)?;
+            let pool_asset_key = get_pool_asset_address(
+                pool_account.key,
+                &pool_assets[i].mint_address,
+                pool_asset_account.owner,
+            );
+            if pool_asset_key != *pool_asset_account.key {
+                msg!("Provided pool asset account is invalid");
+                return Err(ProgramError::InvalidArgument);
+            }
+            let balance = unpack_pool_asset_account(pool_asset_account, i)?.amount;
+            assets.push((pool_assets[i].mint_address, balance));
+        }
+
+        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
+        set_return_data(&pack_pool_info(
+            total_pooltokens,
+            pool_assets.len() as u16,
+            page,
+            &assets,
+        ));
+
+        Ok(())
+    }
+
+    pub fn process_close_pool(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let mint_account = next_account_info(accounts_iter)?;
+        let lamports_target_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+        check_account_writable(lamports_target_account, "lamports target")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid.");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Entirely redeemed pools keep their header with an uninitialized status
+        let (pool_header, _) = unpack_versioned_pool_header_unchecked(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
+        check_pool_closable(&pool_header, total_pooltokens)?;
+
+        let pool_lamports = pool_account.lamports();
+        **lamports_target_account.lamports.borrow_mut() = lamports_target_account
+            .lamports()
+            .checked_add(pool_lamports)
+            .ok_or(BonfidaBotError::Overflow)?;
+        **pool_account.lamports.borrow_mut() = 0;
+        fill_slice(&mut pool_account.data.borrow_mut()[..], 0u8);
+        msg!(
+            "Closed the pool account, {} lamports were reclaimed",
+            pool_lamports
+        );
+
+        Ok(())
+    }
+
+    pub fn process_set_deposit_cap(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        max_pooltoken_supply: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        pool_header.max_pooltoken_supply = max_pooltoken_supply;
+        msg!(
+            "The pooltoken supply cap was set to {}",
+            max_pooltoken_supply
+        );
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_set_single_asset_deposits(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        enabled: bool,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        pool_header.single_asset_deposits = enabled;
+        msg!("Single asset deposits are now allowed: {}", enabled);
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_set_metadata(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        uri: Vec<u8>,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        pool_header.metadata_uri = pack_metadata_uri(&uri)?;
+        msg!(
+            "The metadata URI was set to {}",
+            unpack_metadata_uri(&pool_header.metadata_uri)?
+        );
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_set_referrer_fee(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        referrer_fee_bps: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        check_referrer_fee_bps(referrer_fee_bps)?;
+
+        pool_header.referrer_fee_bps = referrer_fee_bps;
+        msg!("The referrer fee was set to {} bps", referrer_fee_bps);
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_sweep_untracked_asset(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let spl_token_account = next_account_info(accounts_iter)?;
+        if spl_token_account.key != &spl_token::id() {
+            msg!("Incorrect spl token program provided");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let source_account = next_account_info(accounts_iter)?;
+        let destination_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        let source_data = Account::unpack(&source_account.data.borrow())?;
+        if &source_data.owner != pool_account.key {
+            msg!("The swept token account should be owned by the pool");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let pool_assets =
+            unpack_pool_assets(&pool_account.data.borrow(), pool_header.number_of_markets)?;
+        check_untracked_mint(&pool_assets, &source_data.mint)?;
+
+        msg!(
+            "Sweeping {} of untracked mint {}",
+            source_data.amount,
+            source_data.mint
+        );
+        let instruction = transfer(
+            spl_token_account.key,
+            source_account.key,
+            destination_account.key,
+            pool_account.key,
+            &[],
+            source_data.amount,
+        )?;
+        invoke_signed(
+            &instruction,
+            &[
+                spl_token_account.clone(),
+                source_account.clone(),
+                destination_account.clone(),
+                pool_account.clone(),
+            ],
+            &[&[&pool_seed]],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_set_extra_fee_recipient(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        recipient: Pubkey,
+        bps: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        check_extra_fee_recipient(&recipient, bps)?;
+
+        if bps == 0 {
+            pool_header.extra_fee_recipient = Pubkey::default();
+            msg!("The extra fee recipient was removed");
+        } else {
+            pool_header.extra_fee_recipient = recipient;
+            msg!(
+                "The extra fee recipient was set to {} with {} bps",
+                recipient,
+                bps
+            );
+        }
+        pool_header.extra_fee_bps = bps;
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_sweep_dust(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let spl_token_account = next_account_info(accounts_iter)?;
+        if spl_token_account.key != &spl_token::id() {
+            msg!("Incorrect spl token program provided");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let mint_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let source_account = next_account_info(accounts_iter)?;
+        let destination_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // An entirely redeemed pool is uninitialized, whatever the layout of its account
+        let (pool_header, _) = unpack_versioned_pool_header_unchecked(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
+        check_pool_closable(&pool_header, total_pooltokens)?;
+
+        let source_data = Account::unpack(&source_account.data.borrow())?;
+        if &source_data.owner != pool_account.key {
+            msg!("The swept token account should be owned by the pool");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if source_data.amount == 0 {
+            msg!("No dust left to sweep");
+            return Ok(());
+        }
+
+        msg!(
+            "Sweeping {} of mint {}",
+            source_data.amount,
+            source_data.mint
+        );
+        let instruction = transfer(
+            spl_token_account.key,
+            source_account.key,
+            destination_account.key,
+            pool_account.key,
+            &[],
+            source_data.amount,
+        )?;
+        invoke_signed(
+            &instruction,
+            &[
+                spl_token_account.clone(),
+                source_account.clone(),
+                destination_account.clone(),
+                pool_account.clone(),
+            ],
+            &[&[&pool_seed]],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_update_dex_program(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        new_program_id: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        check_dex_program_update(&pool_header, &new_program_id)?;
+
+        msg!(
+            "The dex program was updated from {} to {}",
+            pool_header.serum_program_id,
+            new_program_id
+        );
+        pool_header.serum_program_id = new_program_id;
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_init_open_orders(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        market_index: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let system_program_account = next_account_info(accounts_iter)?;
+        let rent_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let market = next_account_info(accounts_iter)?;
+        let openorders_account = next_account_info(accounts_iter)?;
+        let dex_program = next_account_info(accounts_iter)?;
+        check_account_writable(openorders_account, "OpenOrders")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        if &pool_header.serum_program_id != dex_program.key {
+            msg!("The provided serum program account is invalid for this pool.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if market_index >= pool_header.number_of_markets
+            || market.key
+                != &unpack_market(&pool_account.data.borrow()[PoolHeader::LEN..], market_index)
+        {
+            msg!("The given market account is not authorized.");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let (openorders_key, bump) = find_open_orders_key(program_id, pool_account.key, market.key);
+        if openorders_key != *openorders_account.key {
+            msg!("Provided OpenOrders account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pool_key_bytes = pool_account.key.to_bytes();
+        let market_bytes = market.key.to_bytes();
+        let bump_bytes = [bump];
+        let openorders_seeds = get_open_orders_seeds(&pool_key_bytes, &market_bytes, &bump_bytes);
+        Self::create_pda_account(
+            system_program_account,
+            signal_provider_account,
+            openorders_account,
+            &Rent::from_account_info(rent_sysvar_account)?,
+            OPEN_ORDERS_ACCOUNT_LEN,
+            dex_program.key,
+            &openorders_seeds,
+        )?;
+
+        let instruction = instruction::init_open_orders(
+            dex_program.key,
+            openorders_account.key,
+            pool_account.key,
+            market.key,
+            None,
+        )?;
+        check_serum_result(
+            invoke_signed(
+                &instruction,
+                &[
+                    dex_program.clone(),
+                    openorders_account.clone(),
+                    pool_account.clone(),
+                    market.clone(),
+                    rent_sysvar_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            ),
+            "init open orders",
+            &pool_seed,
+            market.key,
+        )?;
+        msg!(
+            "The OpenOrders account {} was initialized",
+            openorders_account.key
+        );
+
+        Ok(())
+    }
+
+    pub fn process_update_fee_ratio(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        new_fee_ratio: u16,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+        check_fee_ratio(new_fee_ratio)?;
+
+        let current_timestamp =
+            Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
+        pool_header.pending_fee_ratio = new_fee_ratio;
+        pool_header.pending_fee_ratio_timestamp =
+            get_next_fee_period_timestamp(&pool_header, current_timestamp);
+        msg!(
+            "The fee ratio will be updated to {} from timestamp {}",
+            new_fee_ratio,
+            pool_header.pending_fee_ratio_timestamp
+        );
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_update_fee_period(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        new_period: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        let current_timestamp =
+            Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
+        let old_period = pool_header.fee_collection_period;
+        update_fee_collection_period(&mut pool_header, new_period, current_timestamp)?;
+        msg!(
+            "The fee collection period was updated from {} to {}",
+            old_period,
+            new_period
+        );
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_validate_order_accounts(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        market_index: u16,
+        source_index: usize,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let market = next_account_info(accounts_iter)?;
+        let pool_asset_token_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let dex_program = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let checks = get_order_accounts_checks(
+            &pool_account.data.borrow(),
+            pool_account.key,
+            market_index,
+            source_index,
+            signal_provider_account,
+            market,
+            pool_asset_token_account,
+            dex_program,
+        )?;
+        msg!("Order accounts checks: {:#06b}", checks);
+        set_return_data(&[checks]);
+
+        Ok(())
+    }
+
+    pub fn process_recreate_asset_account(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        asset_index: usize,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let system_program_account = next_account_info(accounts_iter)?;
+        let spl_token_program_account = next_account_info(accounts_iter)?;
+        let rent_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
+        let associated_token_program_account = next_account_info(accounts_iter)?;
+        let funding_account = next_account_info(accounts_iter)?;
+        let pool_asset_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let mint_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        check_ata_program(associated_token_program_account)?;
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let pool_asset =
+            unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], asset_index)?;
+        if pool_asset.mint_address != *mint_account.key {
+            msg!(
+                "Provided mint does not match the pool asset {}",
+                asset_index
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        if get_associated_token_address(pool_account.key, mint_account.key)
+            != *pool_asset_account.key
+        {
+            msg!("Provided pool asset account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !pool_asset_account.data_is_empty() {
+            msg!(
+                "The pool asset account for asset {} is not closed",
+                asset_index
+            );
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let instruction = create_associated_token_account(
+            funding_account.key,
+            pool_account.key,
+            mint_account.key,
+        );
+        invoke(
+            &instruction,
+            &[
+                funding_account.clone(),
+                pool_asset_account.clone(),
+                pool_account.clone(),
+                mint_account.clone(),
+                system_program_account.clone(),
+                spl_token_program_account.clone(),
+                rent_sysvar_account.clone(),
+                associated_token_program_account.clone(),
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_set_pool_mode(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        pool_mode: PoolMode,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        pool_header.pool_mode = pool_mode;
+        msg!("The pool mode is now {:?}", pool_mode);
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_emergency_withdraw_asset(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        asset_index: usize,
+        pool_token_amount: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let spl_token_account = next_account_info(accounts_iter)?;
+        if spl_token_account.key != &spl_token::id() {
+            msg!("Incorrect spl token program provided");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let mint_account = next_account_info(accounts_iter)?;
+        let source_pool_token_owner_account = next_account_info(accounts_iter)?;
+        let source_pool_token_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let pool_asset_account = next_account_info(accounts_iter)?;
+        let target_asset_account = next_account_info(accounts_iter)?;
+        check_account_writable(mint_account, "pooltoken mint")?;
+        check_account_writable(source_pool_token_account, "source pooltoken")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !source_pool_token_owner_account.is_signer {
+            msg!("Source pooltoken account owner should be a signer.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_emergency_withdraw_allowed(&pool_header)?;
+
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let pool_asset =
+            unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], asset_index)?;
+        if get_associated_token_address(pool_account.key, &pool_asset.mint_address)
+            != *pool_asset_account.key
+        {
+            msg!("Provided pool asset account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
+        let total_user_pooltokens =
+            Account::unpack(&source_pool_token_account.data.borrow())?.amount;
+        if total_user_pooltokens < pool_token_amount {
+            msg!("Insufficient pool token funds");
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        let pool_asset_amount = unpack_pool_asset_account(pool_asset_account, asset_index)?.amount;
+        let amount = get_asset_share(pool_token_amount, pool_asset_amount, total_pooltokens)?;
+        msg!(
+            "Withdrawing {} of asset {} for {} pooltokens",
+            amount,
+            asset_index,
+            pool_token_amount
+        );
+
+        // Burn the pooltokens before paying out
+        let instruction = burn(
+            spl_token_account.key,
+            source_pool_token_account.key,
+            mint_account.key,
+            source_pool_token_owner_account.key,
+            &[],
+            pool_token_amount,
+        )?;
+        invoke(
+            &instruction,
+            &[
+                spl_token_account.clone(),
+                source_pool_token_account.clone(),
+                mint_account.clone(),
+                source_pool_token_owner_account.clone(),
+            ],
+        )?;
+
+        if amount != 0 {
+            let instruction = transfer(
+                spl_token_account.key,
+                pool_asset_account.key,
+                target_asset_account.key,
+                pool_account.key,
+                &[],
+                amount,
+            )?;
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_account.clone(),
+                    pool_asset_account.clone(),
+                    target_asset_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn process_redeem_single(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        pool_token_amount: u64,
+        asset_index: usize,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let spl_token_account = next_account_info(accounts_iter)?;
+        if spl_token_account.key != &spl_token::id() {
+            msg!("Incorrect spl token program provided");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
+        let mint_account = next_account_info(accounts_iter)?;
+        let source_pool_token_owner_account = next_account_info(accounts_iter)?;
+        let source_pool_token_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let pool_asset_account = next_account_info(accounts_iter)?;
+        let target_asset_account = next_account_info(accounts_iter)?;
+        check_account_writable(mint_account, "pooltoken mint")?;
+        check_account_writable(source_pool_token_account, "source pooltoken")?;
+
+        // Safety verifications
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !source_pool_token_owner_account.is_signer {
+            msg!("Source pooltoken account owner should be a signer.");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_redeem_allowed(&pool_header)?;
+        check_fee_collection_period(&pool_header)?;
+        match pool_header.status {
+            PoolStatus::PendingOrder(_) | PoolStatus::LockedPendingOrder(_) => {
+                msg!("The pool has one or more pending orders. No buy-outs are possible for now. Try again later.");
+                return Err(BonfidaBotError::LockedOperation.into());
+            }
+            _ => (),
+        };
+
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let pool_asset =
+            unpack_unchecked_asset(&pool_account.data.borrow()[asset_offset..], asset_index)?;
+        if !pool_asset.is_initialized() {
+            msg!("The pool has no asset at the specified index");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if get_associated_token_address(pool_account.key, &pool_asset.mint_address)
+            != *pool_asset_account.key
+        {
+            msg!("Provided pool asset account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // The redeemed asset only pays out the whole value of the pooltokens when the pool
+        // holds none of the other assets it tracks.
+        let pool_assets =
+            unpack_pool_assets(&pool_account.data.borrow(), pool_header.number_of_markets)?;
+        for other_asset in pool_assets
+            .iter()
+            .filter(|asset| asset.mint_address != pool_asset.mint_address)
+        {
+            let other_asset_account = next_account_info(accounts_iter)?;
+            check_pool_asset_empty(pool_account.key, other_asset, other_asset_account)?;
+        }
+
+        let signal_provider_account = next_account_info(accounts_iter).ok();
+        let current_timestamp =
+            Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
+        check_redeem_authorized(&pool_header, signal_provider_account, current_timestamp)?;
+
+        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply;
+        let total_user_pooltokens =
+            Account::unpack(&source_pool_token_account.data.borrow())?.amount;
+        if total_user_pooltokens < pool_token_amount {
+            msg!("Insufficient pool token funds");
+            return Err(ProgramError::InsufficientFunds);
+        }
+        let diluted_pooltokens =
+            Self::get_diluted_pooltokens(&pool_header, total_pooltokens, current_timestamp)?;
+
+        let pool_asset_amount = unpack_pool_asset_account(pool_asset_account, asset_index)?.amount;
+        let amount = get_asset_share(pool_token_amount, pool_asset_amount, diluted_pooltokens)?;
+        msg!(
+            "Redeeming {} of asset {} for {} pooltokens",
+            amount,
+            asset_index,
+            pool_token_amount
+        );
+
+        // Burn the pooltokens before paying out
+        let instruction = burn(
+            spl_token_account.key,
+            source_pool_token_account.key,
+            mint_account.key,
+            source_pool_token_owner_account.key,
+            &[],
+            pool_token_amount,
+        )?;
+        invoke(
+            &instruction,
+            &[
+                spl_token_account.clone(),
+                source_pool_token_account.clone(),
+                mint_account.clone(),
+                source_pool_token_owner_account.clone(),
+            ],
+        )?;
+
+        if amount != 0 {
+            let instruction = transfer(
+                spl_token_account.key,
+                pool_asset_account.key,
+                target_asset_account.key,
+                pool_account.key,
+                &[],
+                amount,
+            )?;
+            invoke_signed(
+                &instruction,
+                &[
+                    spl_token_account.clone(),
+                    pool_asset_account.clone(),
+                    target_asset_account.clone(),
+                    pool_account.clone(),
+                ],
+                &[&[&pool_seed]],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn process_snapshot(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        prices: Vec<u64>,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let system_program_account = next_account_info(accounts_iter)?;
+        let clock_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(clock_sysvar_account, &clock::id(), "clock")?;
+        let rent_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let nav_snapshots_account = next_account_info(accounts_iter)?;
+        check_account_writable(nav_snapshots_account, "NAV snapshots")?;
+        let mint_account = next_account_info(accounts_iter)?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        let pool_mint_key = Pubkey::create_program_address(&[&pool_seed, &[1]], &program_id)?;
+        if pool_mint_key != *mint_account.key {
+            msg!("Provided mint account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (nav_snapshots_key, bump) = find_nav_snapshots_key(program_id, pool_account.key);
+        if nav_snapshots_key != *nav_snapshots_account.key {
+            msg!("Provided NAV snapshots account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+        let pool_assets = unpack_assets(&pool_account.data.borrow()[asset_offset..])?;
+        if prices.len() != pool_assets.len() {
+            msg!(
+                "One price should be given for each of the {} pool assets",
+                pool_assets.len()
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut balances = Vec::with_capacity(pool_assets.len());
+        for (i, pool_asset) in pool_assets.iter().enumerate() {
+            let pool_asset_account = next_account_info(accounts_iter)?;
+            if get_associated_token_address(pool_account.key, &pool_asset.mint_address)
+                != *pool_asset_account.key
+            {
+                msg!("Provided pool asset account is invalid");
+                return Err(ProgramError::InvalidArgument);
+            }
+            balances.push(unpack_pool_asset_account(pool_asset_account, i)?.amount);
+        }
+
+        let pool_mint = Mint::unpack(&mint_account.data.borrow())?;
+        let nav_per_token =
+            compute_nav_per_token(&balances, &prices, pool_mint.supply, pool_mint.decimals)
+                .ok_or(BonfidaBotError::Overflow)?;
+        let timestamp = Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
+
+        let pool_key_bytes = pool_account.key.to_bytes();
+        let bump_bytes = [bump];
+        let nav_snapshots_seeds = get_nav_snapshots_seeds(&pool_key_bytes, &bump_bytes);
+        if nav_snapshots_account.data_is_empty() {
+            Self::create_pda_account(
+                system_program_account,
+                signal_provider_account,
+                nav_snapshots_account,
+                &Rent::from_account_info(rent_sysvar_account)?,
+                NAV_SNAPSHOTS_ACCOUNT_LEN,
+                program_id,
+                &nav_snapshots_seeds,
+            )?;
+        }
+
+        msg!("NAV per pooltoken at {}: {}", timestamp, nav_per_token);
+        push_nav_snapshot(
+            &mut nav_snapshots_account.data.borrow_mut(),
+            &NavSnapshot {
+                timestamp,
+                nav_per_token,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn process_add_allowed_mint(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        mint: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let system_program_account = next_account_info(accounts_iter)?;
+        let rent_sysvar_account = next_account_info(accounts_iter)?;
+        check_sysvar_account(rent_sysvar_account, &rent::id(), "rent")?;
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let allowed_mints_account = next_account_info(accounts_iter)?;
+        check_account_writable(allowed_mints_account, "allowed mints")?;
 
-        if signal_provider_pt_account.key != &signal_provider_pt_key {
-            msg!("The provided signal provider pool token account is invalid.");
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
             return Err(ProgramError::InvalidArgument);
         }
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
 
-        if bonfida_fee_pt_account.key != &bonfida_fee_pt_key {
-            msg!("The provided bonfida fee pool token account is invalid.");
+        let (allowed_mints_key, bump) = find_allowed_mints_key(program_id, pool_account.key);
+        if allowed_mints_key != *allowed_mints_account.key {
+            msg!("Provided allowed mints account is invalid");
             return Err(ProgramError::InvalidArgument);
         }
 
-        if bonfida_bnb_pt_account.key != &bonfida_bnb_pt_key {
-            msg!("The provided bonfida buy and burn pool token account is invalid.");
-            return Err(ProgramError::InvalidArgument);
+        if allowed_mints_account.data_is_empty() {
+            let pool_key_bytes = pool_account.key.to_bytes();
+            let bump_bytes = [bump];
+            let allowed_mints_seeds = get_allowed_mints_seeds(&pool_key_bytes, &bump_bytes);
+            Self::create_pda_account(
+                system_program_account,
+                signal_provider_account,
+                allowed_mints_account,
+                &Rent::from_account_info(rent_sysvar_account)?,
+                ALLOWED_MINTS_ACCOUNT_LEN,
+                program_id,
+                &allowed_mints_seeds,
+            )?;
         }
 
-        let current_timestamp =
-            Clock::from_account_info(clock_sysvar_account)?.unix_timestamp as u64;
-        let fee_cycles_to_collect = (current_timestamp - pool_header.last_fee_collection_timestamp)
-            / pool_header.fee_collection_period;
+        add_allowed_mint(&mut allowed_mints_account.data.borrow_mut(), &mint)?;
+        msg!("The pool can now trade into {}", mint);
 
-        if fee_cycles_to_collect == 0 {
-            msg!("There are currently no fees to collect");
-            return Err(BonfidaBotError::LockedOperation.into());
+        Ok(())
+    }
+
+    pub fn process_remove_allowed_mint(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        mint: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        let allowed_mints_account = next_account_info(accounts_iter)?;
+        check_account_writable(allowed_mints_account, "allowed mints")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
         }
+        let pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
 
-        // 2**-16 = 1.52587890625e-5_f32
-        // let feeless_ratio_u16 = (((!pool_header.fee_ratio) as f32 * 1.52587890625e-5_f32).powi(
-        //     fee_cycles_to_collect
-        //         .try_into()
-        //         .map_err(|_| BonfidaBotError::Overflow)?,
-        // ) * 65536.) as u16;
-        let feeless_ratio_u16 =
-            pow_fixedpoint_u16(!pool_header.fee_ratio as u32, fee_cycles_to_collect) as u16;
-        let collect_ratio = (!feeless_ratio_u16) as u128;
-        let feeless_ratio = feeless_ratio_u16 as u128;
-        pool_header.last_fee_collection_timestamp +=
-            fee_cycles_to_collect * pool_header.fee_collection_period;
+        let (allowed_mints_key, _) = find_allowed_mints_key(program_id, pool_account.key);
+        if allowed_mints_key != *allowed_mints_account.key {
+            msg!("Provided allowed mints account is invalid");
+            return Err(ProgramError::InvalidArgument);
+        }
 
-        let total_pooltokens = Mint::unpack(&mint_account.data.borrow())?.supply as u128;
+        remove_allowed_mint(&mut allowed_mints_account.data.borrow_mut(), &mint).or_else(|e| {
+            msg!("The mint {} is not in the allow-list", mint);
+            Err(e)
+        })?;
+        msg!("The pool can no longer trade into {}", mint);
 
-        let tokens_to_mint = (collect_ratio * total_pooltokens / feeless_ratio) as u64;
+        Ok(())
+    }
 
+    pub fn process_toggle_lock(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
 
-        // Mint the required amount of pooltokens to the signal provider
-        //
-        // Like with deposit, these will often not be minted in the quantity
-        // expected, unless it's always divisible by 4
-        let signal_provider_fee = tokens_to_mint / 2;
-        let mint_to_sp_instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            signal_provider_pt_account.key,
-            &pool_account.key,
-            &[],
-            signal_provider_fee,
-        )?;
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
 
-        invoke_signed(
-            &mint_to_sp_instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                signal_provider_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
 
-        // Mint the required amount of pooltokens to the bonfida fee account
-        let bonfida_fee = tokens_to_mint / 4;
-        let mint_to_bonfida_fee_instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            &bonfida_fee_pt_key,
-            &pool_account.key,
-            &[],
-            bonfida_fee,
-        )?;
+        pool_header.status = get_toggled_lock_status(&pool_header.status).or_else(|e| {
+            msg!("The pool is not initialized.");
+            Err(e)
+        })?;
+        msg!("The pool status is now {:?}", pool_header.status);
 
-        invoke_signed(
-            &mint_to_bonfida_fee_instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                bonfida_fee_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
 
-        // Mint the required amount of pooltokens to the bonfida fee account
-        let mint_to_bonfida_bnb_instruction = mint_to(
-            spl_token_account.key,
-            &pool_mint_key,
-            &bonfida_bnb_pt_key,
-            &pool_account.key,
-            &[],
-            tokens_to_mint - bonfida_fee - signal_provider_fee,
-        )?;
+        Ok(())
+    }
 
-        invoke_signed(
-            &mint_to_bonfida_bnb_instruction,
-            &[
-                spl_token_account.clone(),
-                mint_account.clone(),
-                bonfida_bnb_pt_account.clone(),
-                pool_account.clone(),
-            ],
-            &[&[&pool_seed]],
-        )?;
+    pub fn process_change_signal_provider(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        new_signal_provider: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
 
-        PoolHeader::pack(
-            pool_header,
-            &mut pool_account.data.borrow_mut()[..PoolHeader::LEN],
-        )?;
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        match pool_header.status {
+            PoolStatus::PendingOrder(_) | PoolStatus::LockedPendingOrder(_) => {
+                msg!("The signal provider cannot be changed while orders are pending. Settle or cancel them first.");
+                return Err(BonfidaBotError::LockedOperation.into());
+            }
+            _ => {}
+        };
+
+        msg!(
+            "Signal provider changed from {} to {}",
+            pool_header.signal_provider,
+            new_signal_provider
+        );
+        pool_header.signal_provider = new_signal_provider;
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
+
+        Ok(())
+    }
+
+    pub fn process_add_market(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pool_seed: [u8; 32],
+        market: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+        let signal_provider_account = next_account_info(accounts_iter)?;
+        let pool_account = next_account_info(accounts_iter)?;
+        check_account_writable(pool_account, "pool")?;
+
+        check_pool_key(program_id, pool_account.key, &pool_seed)?;
+        if *pool_account.owner != *program_id {
+            msg!("Program should own pool account");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut pool_header = unpack_pool_header(&pool_account.data.borrow())?;
+        check_signal_provider(&pool_header, signal_provider_account)?;
+
+        pool_header.number_of_markets = append_market(
+            &mut pool_account.data.borrow_mut()[PoolHeader::LEN..],
+            pool_header.number_of_markets,
+            &market,
+        )
+        .or_else(|e| {
+            msg!("The market is already authorized or the pool has no free asset slot left.");
+            Err(e)
+        })?;
+        msg!(
+            "Market {} authorized, the pool now has {} markets",
+            market,
+            pool_header.number_of_markets
+        );
+
+        pool_header.pack_into_slice(&mut pool_account.data.borrow_mut()[..PoolHeader::LEN]);
 
         Ok(())
     }
@@ -1375,6 +3472,7 @@ impl Processor {
                 pool_seed,
                 max_number_of_assets,
                 number_of_markets,
+                decimals,
             } => {
                 msg!("Instruction: Init");
                 Self::process_init(
@@ -1383,12 +3481,19 @@ impl Processor {
                     pool_seed,
                     max_number_of_assets,
                     number_of_markets,
+                    decimals,
                 )
             }
             PoolInstruction::Create {
                 pool_seed,
                 fee_collection_period,
                 fee_ratio,
+                deposit_fee_ratio,
+                rebate_retention_ratio,
+                pool_mode,
+                redeem_requires_provider,
+                maturity_timestamp,
+                fee_split_bps,
                 deposit_amounts,
                 markets,
             } => {
@@ -1401,14 +3506,41 @@ impl Processor {
                     markets,
                     fee_collection_period,
                     fee_ratio,
+                    deposit_fee_ratio,
+                    rebate_retention_ratio,
+                    pool_mode,
+                    redeem_requires_provider,
+                    maturity_timestamp,
+                    fee_split_bps,
                 )
             }
             PoolInstruction::Deposit {
                 pool_seed,
                 pool_token_amount,
+                with_referrer,
             } => {
                 msg!("Instruction: Deposit into Pool");
-                Self::process_deposit(program_id, accounts, pool_seed, pool_token_amount)
+                Self::process_deposit(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    pool_token_amount,
+                    with_referrer,
+                )
+            }
+            PoolInstruction::DepositExact {
+                pool_seed,
+                asset_amounts,
+                tolerance_bps,
+            } => {
+                msg!("Instruction: Deposit Exact Amounts into Pool");
+                Self::process_deposit_exact(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    asset_amounts,
+                    tolerance_bps,
+                )
             }
             PoolInstruction::CreateOrder {
                 pool_seed,
@@ -1425,6 +3557,7 @@ impl Processor {
                 pc_lot_size,
                 target_mint,
                 serum_limit,
+                min_expected_lots,
             } => {
                 msg!("Instruction: Create Order for Pool");
                 Self::process_create_order(
@@ -1444,6 +3577,7 @@ impl Processor {
                     source_index as usize,
                     target_index as usize,
                     serum_limit,
+                    min_expected_lots,
                 )
             }
             PoolInstruction::SettleFunds {
@@ -1456,8 +3590,8 @@ impl Processor {
                     program_id,
                     accounts,
                     pool_seed,
-                    pc_index as usize,
-                    coin_index as usize,
+                    pc_index.map(|i| i as usize),
+                    coin_index.map(|i| i as usize),
                 )
             }
             PoolInstruction::CancelOrder {
@@ -1468,17 +3602,226 @@ impl Processor {
                 msg!("Instruction: Cancel Order for Pool");
                 Self::process_cancel(program_id, accounts, pool_seed, side, order_id)
             }
+            PoolInstruction::CancelAllOrders { pool_seed } => {
+                msg!("Instruction: Cancel All Orders for Pool");
+                Self::process_cancel_all(program_id, accounts, pool_seed)
+            }
+            PoolInstruction::GetPoolInfo { pool_seed, page } => {
+                msg!("Instruction: Get Pool Info");
+                Self::process_get_pool_info(program_id, accounts, pool_seed, page)
+            }
+            PoolInstruction::ClosePool { pool_seed } => {
+                msg!("Instruction: Close Pool");
+                Self::process_close_pool(program_id, accounts, pool_seed)
+            }
+            PoolInstruction::ConsumeEvents { pool_seed, limit } => {
+                msg!("Instruction: Consume Events for Pool");
+                Self::process_consume_events(program_id, accounts, pool_seed, limit)
+            }
+            PoolInstruction::SetDepositCap {
+                pool_seed,
+                max_pooltoken_supply,
+            } => {
+                msg!("Instruction: Set Deposit Cap");
+                Self::process_set_deposit_cap(program_id, accounts, pool_seed, max_pooltoken_supply)
+            }
+            PoolInstruction::SweepUntrackedAsset { pool_seed } => {
+                msg!("Instruction: Sweep Untracked Asset");
+                Self::process_sweep_untracked_asset(program_id, accounts, pool_seed)
+            }
+            PoolInstruction::SetExtraFeeRecipient {
+                pool_seed,
+                recipient,
+                bps,
+            } => {
+                msg!("Instruction: Set Extra Fee Recipient");
+                Self::process_set_extra_fee_recipient(
+                    program_id, accounts, pool_seed, recipient, bps,
+                )
+            }
+            PoolInstruction::DepositAndLock {
+                pool_seed,
+                pool_token_amount,
+            } => {
+                msg!("Instruction: Deposit and Lock");
+                Self::process_deposit_and_lock(program_id, accounts, pool_seed, pool_token_amount)
+            }
+            PoolInstruction::SweepDust { pool_seed } => {
+                msg!("Instruction: Sweep Dust");
+                Self::process_sweep_dust(program_id, accounts, pool_seed)
+            }
+            PoolInstruction::UpdateDexProgram {
+                pool_seed,
+                new_program_id,
+            } => {
+                msg!("Instruction: Update Dex Program");
+                Self::process_update_dex_program(program_id, accounts, pool_seed, new_program_id)
+            }
+            PoolInstruction::InitOpenOrders {
+                pool_seed,
+                market_index,
+            } => {
+                msg!("Instruction: Init Open Orders");
+                Self::process_init_open_orders(program_id, accounts, pool_seed, market_index)
+            }
+            PoolInstruction::SetSingleAssetDeposits { pool_seed, enabled } => {
+                msg!("Instruction: Set Single Asset Deposits");
+                Self::process_set_single_asset_deposits(program_id, accounts, pool_seed, enabled)
+            }
+            PoolInstruction::DepositSingle {
+                pool_seed,
+                asset_index,
+                amount,
+            } => {
+                msg!("Instruction: Deposit Single Asset into Pool");
+                Self::process_deposit_single(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    asset_index as usize,
+                    amount,
+                )
+            }
+            PoolInstruction::SetMetadata { pool_seed, uri } => {
+                msg!("Instruction: Set Metadata");
+                Self::process_set_metadata(program_id, accounts, pool_seed, uri)
+            }
+            PoolInstruction::SetReferrerFee {
+                pool_seed,
+                referrer_fee_bps,
+            } => {
+                msg!("Instruction: Set Referrer Fee");
+                Self::process_set_referrer_fee(program_id, accounts, pool_seed, referrer_fee_bps)
+            }
             PoolInstruction::Redeem {
                 pool_seed,
                 pool_token_amount,
             } => {
                 msg!("Instruction: Redeem out of Pool");
-                Self::process_redeem(program_id, accounts, pool_seed, pool_token_amount)
+                Self::process_redeem(program_id, accounts, pool_seed, pool_token_amount, false)
+            }
+            PoolInstruction::RedeemUnwrapSol {
+                pool_seed,
+                pool_token_amount,
+            } => {
+                msg!("Instruction: Redeem out of Pool unwrapping SOL");
+                Self::process_redeem(program_id, accounts, pool_seed, pool_token_amount, true)
+            }
+            PoolInstruction::UpdateFeeRatio {
+                pool_seed,
+                new_fee_ratio,
+            } => {
+                msg!("Instruction: Update Fee Ratio for Pool");
+                Self::process_update_fee_ratio(program_id, accounts, pool_seed, new_fee_ratio)
+            }
+            PoolInstruction::ValidateOrderAccounts {
+                pool_seed,
+                market_index,
+                source_index,
+            } => {
+                msg!("Instruction: Validate Order Accounts for Pool");
+                Self::process_validate_order_accounts(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    market_index,
+                    source_index as usize,
+                )
+            }
+            PoolInstruction::RecreateAssetAccount {
+                pool_seed,
+                asset_index,
+            } => {
+                msg!("Instruction: Recreate Asset Account for Pool");
+                Self::process_recreate_asset_account(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    asset_index as usize,
+                )
+            }
+            PoolInstruction::SetPoolMode {
+                pool_seed,
+                pool_mode,
+            } => {
+                msg!("Instruction: Set Pool Mode");
+                Self::process_set_pool_mode(program_id, accounts, pool_seed, pool_mode)
+            }
+            PoolInstruction::EmergencyWithdrawAsset {
+                pool_seed,
+                asset_index,
+                pool_token_amount,
+            } => {
+                msg!("Instruction: Emergency Withdraw Asset from Pool");
+                Self::process_emergency_withdraw_asset(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    asset_index as usize,
+                    pool_token_amount,
+                )
+            }
+            PoolInstruction::Snapshot { pool_seed, prices } => {
+                msg!("Instruction: Snapshot Pool NAV");
+                Self::process_snapshot(program_id, accounts, pool_seed, prices)
+            }
+            PoolInstruction::AddAllowedMint { pool_seed, mint } => {
+                msg!("Instruction: Add Allowed Target Mint");
+                Self::process_add_allowed_mint(program_id, accounts, pool_seed, mint)
+            }
+            PoolInstruction::RemoveAllowedMint { pool_seed, mint } => {
+                msg!("Instruction: Remove Allowed Target Mint");
+                Self::process_remove_allowed_mint(program_id, accounts, pool_seed, mint)
+            }
+            PoolInstruction::ToggleLock { pool_seed } => {
+                msg!("Instruction: Toggle Pool Lock");
+                Self::process_toggle_lock(program_id, accounts, pool_seed)
+            }
+            PoolInstruction::ChangeSignalProvider {
+                pool_seed,
+                new_signal_provider,
+            } => {
+                msg!("Instruction: Change Signal Provider");
+                Self::process_change_signal_provider(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    new_signal_provider,
+                )
+            }
+            PoolInstruction::RedeemSingle {
+                pool_seed,
+                pool_token_amount,
+                asset_index,
+            } => {
+                msg!("Instruction: Redeem Single Asset from Pool");
+                Self::process_redeem_single(
+                    program_id,
+                    accounts,
+                    pool_seed,
+                    pool_token_amount,
+                    asset_index as usize,
+                )
+            }
+            PoolInstruction::AddMarket { pool_seed, market } => {
+                msg!("Instruction: Add Market to Pool");
+                Self::process_add_market(program_id, accounts, pool_seed, market)
+            }
+            PoolInstruction::UpdateFeePeriod {
+                pool_seed,
+                new_period,
+            } => {
+                msg!("Instruction: Update Pool Fee Collection Period");
+                Self::process_update_fee_period(program_id, accounts, pool_seed, new_period)
             }
             PoolInstruction::CollectFees { pool_seed } => {
                 msg!("Instruction: Collect Fees for Pool");
                 Self::process_collect_fees(program_id, accounts, pool_seed)
             }
+            PoolInstruction::PublishStateHash { pool_seed } => {
+                msg!("Instruction: Publish Pool State Hash");
+                Self::process_publish_state_hash(program_id, accounts, pool_seed)
+            }
         }
     }
 }
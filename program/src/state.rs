@@ -1,14 +1,37 @@
 use solana_program::{
+    hash::{hash, Hash},
+    msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use spl_token::state::{Account, Mint};
 use std::{convert::TryInto, num::NonZeroU8};
 
+use crate::{
+    error::BonfidaBotError,
+    utils::{DEFAULT_BONFIDA_FEE_BPS, DEFAULT_SIGNAL_PROVIDER_FEE_BPS},
+};
+
 pub const PUBKEY_LENGTH: usize = 32;
 
 pub const BONFIDA_FEE: &str = "31LVSggbVz4VcwBSPdtK8HJ3Lt1cKTJUVQTRNNYMfqBq";
 pub const BONFIDA_BNB: &str = "3oQzjfjzUkJ5qHsERk2JPEpAKo34dxAQjUriBqursfxU";
+pub const FIDA_MINT: &str = "EchesyfXePKdLtoiZSL8pBe8Myagyy8ZRqsACNCFGnvp";
+/// Mints of the token accounts accepted by Serum as fee discount accounts
+pub const SRM_MINT: &str = "SRMuApVNdxXokk5GT7XD5cUUgXMBCoAz2LHeuAoKWRt";
+pub const MSRM_MINT: &str = "MSRMcoVyrFxnSgo5uXwone5SKcGhT1KEJMFEkMEWf9L";
+/// Serum DEX deployments a pool can be moved to by `UpdateDexProgram`
+pub const SERUM_DEX_PROGRAM_IDS: [&str; 4] = [
+    "4ckmDgGdxQoPDLUkDT3vHgSAkzA3QRdNq5ywwY4sUSJn",
+    "EUqojwWA2rd19FZrzeBncJsm38Jm1hEhE3zsmX3bRc2o",
+    "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+    "srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX",
+];
+
+/// Decimals of the pooltoken mint when left out of `Init`, and the maximum allowed
+pub const DEFAULT_POOL_TOKEN_DECIMALS: u8 = 6;
+pub const MAX_POOL_TOKEN_DECIMALS: u8 = 9;
 
 // Pool state is composed of PoolHeader, Array of markets (pubkeys) and array of poolassets
 
@@ -16,7 +39,7 @@ pub const BONFIDA_BNB: &str = "3oQzjfjzUkJ5qHsERk2JPEpAKo34dxAQjUriBqursfxU";
 pub struct PoolAsset {
     pub mint_address: Pubkey,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PoolStatus {
     Uninitialized,
     Unlocked,
@@ -26,6 +49,85 @@ pub enum PoolStatus {
     LockedPendingOrder(NonZeroU8),
 }
 
+/// Maximum number of orders a pool can have pending at once
+pub const MAX_PENDING_ORDERS: u8 = 64;
+
+impl PoolStatus {
+    pub fn has_pending_orders(&self) -> bool {
+        matches!(
+            self,
+            PoolStatus::PendingOrder(_) | PoolStatus::LockedPendingOrder(_)
+        )
+    }
+
+    /// Returns the status once a new order is pending, keeping the lock.
+    pub fn begin_order(self) -> Result<Self, ProgramError> {
+        let one = NonZeroU8::new(1).unwrap();
+        match self {
+            PoolStatus::Uninitialized => Err(ProgramError::UninitializedAccount),
+            PoolStatus::Unlocked => Ok(PoolStatus::PendingOrder(one)),
+            PoolStatus::Locked => Ok(PoolStatus::LockedPendingOrder(one)),
+            PoolStatus::PendingOrder(n) | PoolStatus::LockedPendingOrder(n) => {
+                if n.get() >= MAX_PENDING_ORDERS {
+                    msg!("Maximum number of active orders has been reached. Settle or cancel a pending order.");
+                    return Err(BonfidaBotError::TooManyPendingOrders.into());
+                }
+                let pending_orders = NonZeroU8::new(n.get() + 1).unwrap();
+                Ok(match self {
+                    PoolStatus::LockedPendingOrder(_) => {
+                        PoolStatus::LockedPendingOrder(pending_orders)
+                    }
+                    _ => PoolStatus::PendingOrder(pending_orders),
+                })
+            }
+        }
+    }
+
+    /// Returns the status once a pending order is entirely settled, keeping the lock.
+    pub fn settle_one(self) -> Result<Self, ProgramError> {
+        match self {
+            PoolStatus::PendingOrder(n) | PoolStatus::LockedPendingOrder(n) => {
+                let locked = matches!(self, PoolStatus::LockedPendingOrder(_));
+                Ok(match (NonZeroU8::new(n.get() - 1), locked) {
+                    (None, false) => PoolStatus::Unlocked,
+                    (None, true) => PoolStatus::Locked,
+                    (Some(pending_orders), false) => PoolStatus::PendingOrder(pending_orders),
+                    (Some(pending_orders), true) => PoolStatus::LockedPendingOrder(pending_orders),
+                })
+            }
+            _ => {
+                msg!("The pool has no pending orders.");
+                Err(ProgramError::InvalidAccountData)
+            }
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PoolMode {
+    Normal = 0,
+    /// Redemptions are disabled
+    DepositOnly = 1,
+    /// Deposits are disabled
+    RedeemOnly = 2,
+    /// Incident state: deposits, redemptions and trading are disabled, only
+    /// `EmergencyWithdrawAsset` can move funds out of the pool
+    Frozen = 3,
+}
+
+impl PoolMode {
+    pub fn from_u8(mode: u8) -> Option<Self> {
+        match mode {
+            0 => Some(PoolMode::Normal),
+            1 => Some(PoolMode::DepositOnly),
+            2 => Some(PoolMode::RedeemOnly),
+            3 => Some(PoolMode::Frozen),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct PoolHeader {
     pub serum_program_id: Pubkey,
@@ -36,8 +138,64 @@ pub struct PoolHeader {
     pub fee_ratio: u16,
     pub last_fee_collection_timestamp: u64,
     pub fee_collection_period: u64,
+    pub deposit_fee_ratio: u16,
+    /// Share of the settled serum referral rebates retained by the pool, the remainder
+    /// goes to the signal provider.
+    pub rebate_retention_ratio: u16,
+    /// Fee ratio which will replace `fee_ratio` from `pending_fee_ratio_timestamp` onwards
+    pub pending_fee_ratio: u16,
+    /// Start of the fee collection period from which the pending fee ratio applies.
+    /// Zero when no fee ratio change is pending.
+    pub pending_fee_ratio_timestamp: u64,
+    pub pool_mode: PoolMode,
+    /// When set, redemptions need the signal provider's signature until maturity
+    pub redeem_requires_provider: bool,
+    pub maturity_timestamp: u64,
+    /// Layout version of the pool account, see `POOL_HEADER_VERSION`
+    pub version: u8,
+    /// Pooltoken supply above which deposits are rejected, zero for no cap
+    pub max_pooltoken_supply: u64,
+    /// Owner of the pooltoken account receiving the extra share of the fees, see
+    /// `extra_fee_bps`
+    pub extra_fee_recipient: Pubkey,
+    /// Share of the deposit and collected fees minted to the extra fee recipient in basis
+    /// points, carved out before the fee split. Zero when there is no extra fee recipient.
+    pub extra_fee_bps: u16,
+    /// Shares of the fees minted to the signal provider and to the Bonfida fee account in
+    /// basis points, the remainder going to the Bonfida buy and burn account. Set to the
+    /// default 1/2, 1/4, 1/4 split when the pool is created without one.
+    pub signal_provider_fee_bps: u16,
+    pub bonfida_fee_bps: u16,
+    /// When set, deposits of a single asset are accepted with `DepositSingle`, which moves the
+    /// pool ratios. Off unless the signal provider opts in with `SetSingleAssetDeposits`.
+    pub single_asset_deposits: bool,
+    /// URI of the off-chain metadata of the pool, such as its name and description, set by
+    /// the signal provider with `SetMetadata`. UTF-8, zero padded and empty by default, see
+    /// `unpack_metadata_uri`.
+    pub metadata_uri: [u8; METADATA_URI_LEN],
+    /// Share of the deposit fee minted to the referrer of a deposit in basis points, carved
+    /// out before the fee split. Set by the signal provider with `SetReferrerFee`, zero when
+    /// referrals aren't rewarded.
+    pub referrer_fee_bps: u16,
 }
 
+/// Current layout version of the pool header. Pool accounts created before the version byte
+/// was introduced have the 117 bytes header of version 0, see `get_pool_header_version`.
+/// They can't hold the current header, so they are read in place by the instructions winding
+/// them down, see `unpack_versioned_pool_header`.
+pub const POOL_HEADER_VERSION: u8 = 1;
+
+/// Length of the pool header of each layout version, indexed by version. Each layout extends
+/// the previous one, the fields it shares with it keeping their offsets. The lengths differ
+/// modulo the length of a market or an asset slot.
+pub const POOL_HEADER_LENS: [usize; POOL_HEADER_VERSION as usize + 1] = [117, PoolHeader::LEN];
+
+/// Offset of the version byte in the pool header
+pub const POOL_HEADER_VERSION_OFFSET: usize = 141;
+
+/// Maximum length in bytes of the metadata URI of a pool
+pub const METADATA_URI_LEN: usize = 64;
+
 const STATUS_PENDING_ORDER_FLAG: u8 = 1 << 6;
 const STATUS_PENDING_ORDER_MASK: u8 = 0x3f;
 const STATUS_LOCKED_FLAG: u8 = 2 << 6;
@@ -46,7 +204,7 @@ const STATUS_UNLOCKED_FLAG: u8 = STATUS_PENDING_ORDER_MASK;
 impl Sealed for PoolHeader {}
 
 impl Pack for PoolHeader {
-    const LEN: usize = 117;
+    const LEN: usize = 255;
 
     fn pack_into_slice(&self, target: &mut [u8]) {
         let serum_program_id_bytes = self.serum_program_id.to_bytes();
@@ -72,6 +230,22 @@ impl Pack for PoolHeader {
         target[99..101].copy_from_slice(&self.fee_ratio.to_le_bytes());
         target[101..109].copy_from_slice(&self.last_fee_collection_timestamp.to_le_bytes());
         target[109..117].copy_from_slice(&self.fee_collection_period.to_le_bytes());
+        target[117..119].copy_from_slice(&self.deposit_fee_ratio.to_le_bytes());
+        target[119..121].copy_from_slice(&self.rebate_retention_ratio.to_le_bytes());
+        target[121..123].copy_from_slice(&self.pending_fee_ratio.to_le_bytes());
+        target[123..131].copy_from_slice(&self.pending_fee_ratio_timestamp.to_le_bytes());
+        target[131] = self.pool_mode as u8;
+        target[132] = self.redeem_requires_provider as u8;
+        target[133..141].copy_from_slice(&self.maturity_timestamp.to_le_bytes());
+        target[141] = self.version;
+        target[142..150].copy_from_slice(&self.max_pooltoken_supply.to_le_bytes());
+        target[150..182].copy_from_slice(&self.extra_fee_recipient.to_bytes());
+        target[182..184].copy_from_slice(&self.extra_fee_bps.to_le_bytes());
+        target[184..186].copy_from_slice(&self.signal_provider_fee_bps.to_le_bytes());
+        target[186..188].copy_from_slice(&self.bonfida_fee_bps.to_le_bytes());
+        target[188] = self.single_asset_deposits as u8;
+        target[189..253].copy_from_slice(&self.metadata_uri);
+        target[253..255].copy_from_slice(&self.referrer_fee_bps.to_le_bytes());
     }
 
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
@@ -99,6 +273,27 @@ impl Pack for PoolHeader {
         let fee_ratio = u16::from_le_bytes(src[99..101].try_into().unwrap());
         let last_fee_collection_timestamp = u64::from_le_bytes(src[101..109].try_into().unwrap());
         let fee_collection_period = u64::from_le_bytes(src[109..117].try_into().unwrap());
+        let deposit_fee_ratio = u16::from_le_bytes(src[117..119].try_into().unwrap());
+        let rebate_retention_ratio = u16::from_le_bytes(src[119..121].try_into().unwrap());
+        let pending_fee_ratio = u16::from_le_bytes(src[121..123].try_into().unwrap());
+        let pending_fee_ratio_timestamp = u64::from_le_bytes(src[123..131].try_into().unwrap());
+        let pool_mode = PoolMode::from_u8(src[131]).ok_or(ProgramError::InvalidAccountData)?;
+        let redeem_requires_provider = src[132] != 0;
+        let maturity_timestamp = u64::from_le_bytes(src[133..141].try_into().unwrap());
+        let version = src[141];
+        let max_pooltoken_supply = u64::from_le_bytes(src[142..150].try_into().unwrap());
+        let extra_fee_recipient = Pubkey::new(&src[150..182]);
+        let extra_fee_bps = u16::from_le_bytes(src[182..184].try_into().unwrap());
+        let signal_provider_fee_bps = u16::from_le_bytes(src[184..186].try_into().unwrap());
+        let bonfida_fee_bps = u16::from_le_bytes(src[186..188].try_into().unwrap());
+        let single_asset_deposits = src[188] != 0;
+        let metadata_uri: [u8; METADATA_URI_LEN] = src[189..253].try_into().unwrap();
+        let referrer_fee_bps = u16::from_le_bytes(src[253..255].try_into().unwrap());
+        // Uninitialized headers have no layout yet
+        if status != PoolStatus::Uninitialized && version != POOL_HEADER_VERSION {
+            msg!("Unsupported pool header version {}", version);
+            return Err(ProgramError::InvalidAccountData);
+        }
         Ok(Self {
             serum_program_id,
             seed,
@@ -108,6 +303,22 @@ impl Pack for PoolHeader {
             fee_ratio,
             last_fee_collection_timestamp,
             fee_collection_period,
+            deposit_fee_ratio,
+            rebate_retention_ratio,
+            pending_fee_ratio,
+            pending_fee_ratio_timestamp,
+            pool_mode,
+            redeem_requires_provider,
+            maturity_timestamp,
+            version,
+            max_pooltoken_supply,
+            extra_fee_recipient,
+            extra_fee_bps,
+            signal_provider_fee_bps,
+            bonfida_fee_bps,
+            single_asset_deposits,
+            metadata_uri,
+            referrer_fee_bps,
         })
     }
 
@@ -148,6 +359,38 @@ impl IsInitialized for PoolHeader {
     }
 }
 
+/// An unlocked pool header on one market with small fees, which tests override with the
+/// struct update syntax
+#[cfg(test)]
+pub(crate) fn test_pool_header() -> PoolHeader {
+    PoolHeader {
+        serum_program_id: Pubkey::new_unique(),
+        seed: [0u8; 32],
+        signal_provider: Pubkey::new_unique(),
+        status: PoolStatus::Unlocked,
+        number_of_markets: 1,
+        fee_ratio: 10,
+        last_fee_collection_timestamp: 1_000,
+        fee_collection_period: 604_800,
+        deposit_fee_ratio: 10,
+        rebate_retention_ratio: 0,
+        pending_fee_ratio: 0,
+        pending_fee_ratio_timestamp: 0,
+        pool_mode: PoolMode::Normal,
+        redeem_requires_provider: false,
+        maturity_timestamp: 0,
+        version: POOL_HEADER_VERSION,
+        max_pooltoken_supply: 0,
+        extra_fee_recipient: Pubkey::default(),
+        extra_fee_bps: 0,
+        signal_provider_fee_bps: 0,
+        bonfida_fee_bps: 0,
+        single_asset_deposits: false,
+        metadata_uri: [0u8; METADATA_URI_LEN],
+        referrer_fee_bps: 0,
+    }
+}
+
 impl Sealed for PoolAsset {}
 
 impl IsInitialized for PoolAsset {
@@ -170,17 +413,75 @@ impl Pack for PoolAsset {
     }
 }
 
+/// Unpacks the tracked pool assets, skipping empty (all-zero) slots. Slots which cannot be
+/// genuine assets, such as a mint tracked twice or a truncated trailing slot, are reported
+/// as invalid data instead of being dropped.
 pub fn unpack_assets(input: &[u8]) -> Result<Vec<PoolAsset>, ProgramError> {
-    let number_of_assets = input.len() / PoolAsset::LEN;
-    let mut output: Vec<PoolAsset> = Vec::with_capacity(number_of_assets);
+    let slots = input.chunks_exact(PoolAsset::LEN);
+    if slots.remainder().iter().any(|byte| *byte != 0) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let mut output: Vec<PoolAsset> = Vec::with_capacity(input.len() / PoolAsset::LEN);
+    for slot in slots {
+        if slot.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+        let asset = PoolAsset::unpack_from_slice(slot)?;
+        if output.contains(&asset) {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        output.push(asset);
+    }
+    Ok(output)
+}
+
+/// Maximum number of assets a pool can track. Deposits and redemptions take two accounts per
+/// asset, so larger pools couldn't be operated within a transaction anyway.
+pub const MAX_POOL_ASSETS: usize = 64;
+
+/// Maximum number of markets a pool can be initialized for, which bounds the rent paid for its
+/// markets region.
+pub const MAX_NUMBER_OF_MARKETS: usize = 256;
+
+/// Unpacks the assets of a pool from the whole pool account data of any layout version,
+/// checking that the asset region following the markets fits the account and holds at most
+/// `MAX_POOL_ASSETS` assets.
+pub fn unpack_pool_assets(
+    data: &[u8],
+    number_of_markets: u16,
+) -> Result<Vec<PoolAsset>, ProgramError> {
+    let header_len = POOL_HEADER_LENS[get_pool_header_version(data.len())? as usize];
+    let asset_offset = header_len + PUBKEY_LENGTH * number_of_markets as usize;
+    let assets_data = data.get(asset_offset..).ok_or_else(|| {
+        msg!(
+            "The pool account data is too short for its {} markets",
+            number_of_markets
+        );
+        ProgramError::InvalidAccountData
+    })?;
+    let pool_assets = unpack_assets(assets_data)?;
+    if pool_assets.len() > MAX_POOL_ASSETS {
+        msg!(
+            "The pool tracks {} assets, more than the maximum of {}",
+            pool_assets.len(),
+            MAX_POOL_ASSETS
+        );
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(pool_assets)
+}
+
+pub fn pack_assets(target: &mut [u8], assets: &[PoolAsset]) -> Result<(), ProgramError> {
     let mut offset = 0;
-    for _ in 0..number_of_assets {
-        PoolAsset::unpack(&input[offset..offset + PoolAsset::LEN])
-            .and_then(|asset| Ok(output.push(asset)))
-            .unwrap_or(());
+    for asset in assets.iter() {
+        asset.pack_into_slice(
+            target
+                .get_mut(offset..offset + PoolAsset::LEN)
+                .ok_or(ProgramError::InvalidAccountData)?,
+        );
         offset += PoolAsset::LEN;
     }
-    Ok(output)
+    Ok(())
 }
 
 pub fn unpack_unchecked_asset(input: &[u8], index: usize) -> Result<PoolAsset, ProgramError> {
@@ -203,34 +504,649 @@ pub fn unpack_market(input: &[u8], market_index: u16) -> Pubkey {
     return Pubkey::new(&input[offset..offset + 32]);
 }
 
+/// Unpacks the `count` markets the pool is authorized to trade on, as given by the
+/// `number_of_markets` of its header. Markets past the end of the input are left out.
+pub fn unpack_markets(input: &[u8], count: u16) -> Vec<Pubkey> {
+    input
+        .chunks_exact(PUBKEY_LENGTH)
+        .take(count as usize)
+        .map(Pubkey::new)
+        .collect()
+}
+
 pub fn pack_markets(target: &mut [u8], markets: &Vec<Pubkey>) -> Result<(), ProgramError> {
+    if target.len() < PUBKEY_LENGTH * markets.len() {
+        msg!("The pool account has no room for {} markets", markets.len());
+        return Err(ProgramError::InvalidAccountData);
+    }
     for i in 0..markets.len() {
         target[32 * i..32 * (i + 1)].copy_from_slice(&markets[i].to_bytes());
     }
     Ok(())
 }
 
+/// Appends a market to the pool data following the header. The assets region is shifted to
+/// make room for it, which requires its last slot to be free.
+pub fn append_market(
+    input: &mut [u8],
+    number_of_markets: u16,
+    market: &Pubkey,
+) -> Result<u16, ProgramError> {
+    let markets_len = PUBKEY_LENGTH * number_of_markets as usize;
+    if input.len() < markets_len + PUBKEY_LENGTH {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if input[..markets_len]
+        .chunks_exact(PUBKEY_LENGTH)
+        .any(|authorized_market| authorized_market == market.as_ref())
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let last_slot_offset = input.len() - PUBKEY_LENGTH;
+    if input[last_slot_offset..].iter().any(|byte| *byte != 0) {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    let new_number_of_markets = number_of_markets
+        .checked_add(1)
+        .ok_or(BonfidaBotError::Overflow)?;
+    input.copy_within(markets_len..last_slot_offset, markets_len + PUBKEY_LENGTH);
+    input[markets_len..markets_len + PUBKEY_LENGTH].copy_from_slice(&market.to_bytes());
+    Ok(new_number_of_markets)
+}
+
+/// Decodes the headers of many pool accounts at once. Each entry is the full pool account data.
+pub fn unpack_headers<'a>(
+    datas: impl Iterator<Item = &'a [u8]>,
+) -> Vec<Result<PoolHeader, ProgramError>> {
+    datas.map(unpack_pool_header).collect()
+}
+
+/// Zero pads a metadata URI into the header field, checking that it is UTF-8 and fits.
+/// Zero bytes are rejected since they mark the end of the URI.
+pub fn pack_metadata_uri(uri: &[u8]) -> Result<[u8; METADATA_URI_LEN], ProgramError> {
+    if uri.len() > METADATA_URI_LEN {
+        msg!(
+            "The metadata URI is {} bytes long, above the maximum of {}",
+            uri.len(),
+            METADATA_URI_LEN
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    if std::str::from_utf8(uri).is_err() || uri.contains(&0) {
+        msg!("The metadata URI should be a valid UTF-8 string");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut metadata_uri = [0u8; METADATA_URI_LEN];
+    metadata_uri[..uri.len()].copy_from_slice(uri);
+    Ok(metadata_uri)
+}
+
+/// Returns the metadata URI stored in a pool header, empty when none was set
+pub fn unpack_metadata_uri(metadata_uri: &[u8; METADATA_URI_LEN]) -> Result<&str, ProgramError> {
+    let len = metadata_uri
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(METADATA_URI_LEN);
+    std::str::from_utf8(&metadata_uri[..len]).map_err(|_| ProgramError::InvalidAccountData)
+}
+
+/// Returns the layout version of a pool account from the length of its data. Pool accounts
+/// are allocated with a header followed by whole markets and asset slots, so that the header
+/// length of the account is known. The version byte of the header can't tell the layouts
+/// apart, since it lies within the markets of a version 0 account.
+pub fn get_pool_header_version(data_len: usize) -> Result<u8, ProgramError> {
+    POOL_HEADER_LENS
+        .iter()
+        .position(|header_len| {
+            data_len >= *header_len && (data_len - header_len) % PUBKEY_LENGTH == 0
+        })
+        .map(|version| version as u8)
+        .ok_or_else(|| {
+            msg!(
+                "The pool account has an invalid length of {} bytes",
+                data_len
+            );
+            ProgramError::InvalidAccountData
+        })
+}
+
+/// Unpacks the header of a pool account with the current layout, without checking that it
+/// is initialized. Pools with an older layout can only be wound down, see
+/// `unpack_versioned_pool_header`.
+pub fn unpack_pool_header_unchecked(data: &[u8]) -> Result<PoolHeader, ProgramError> {
+    let version = get_pool_header_version(data.len())?;
+    if version != POOL_HEADER_VERSION {
+        msg!(
+            "The pool account has a version {} layout, its orders can only be cancelled and settled, and its pooltokens redeemed",
+            version
+        );
+        return Err(ProgramError::InvalidAccountData);
+    }
+    PoolHeader::unpack_unchecked(&data[..PoolHeader::LEN])
+}
+
+/// Unpacks the header of an initialized pool account with the current layout
+pub fn unpack_pool_header(data: &[u8]) -> Result<PoolHeader, ProgramError> {
+    let pool_header = unpack_pool_header_unchecked(data)?;
+    if !pool_header.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok(pool_header)
+}
+
+/// Unpacks the header of a pool account of any layout version without checking that it is
+/// initialized, along with the length of the header. The fields missing from a version 0
+/// header take their defaults: its deposit fee ratio is its fee ratio, and its fees are split
+/// the default way.
+pub fn unpack_versioned_pool_header_unchecked(
+    data: &[u8],
+) -> Result<(PoolHeader, usize), ProgramError> {
+    let version = get_pool_header_version(data.len())?;
+    if version == POOL_HEADER_VERSION {
+        return Ok((
+            PoolHeader::unpack_unchecked(&data[..PoolHeader::LEN])?,
+            PoolHeader::LEN,
+        ));
+    }
+    let header_len = POOL_HEADER_LENS[version as usize];
+    let mut header = [0u8; PoolHeader::LEN];
+    header[..header_len].copy_from_slice(&data[..header_len]);
+    header[POOL_HEADER_VERSION_OFFSET] = POOL_HEADER_VERSION;
+    let pool_header = PoolHeader::unpack_unchecked(&header)?;
+    Ok((
+        PoolHeader {
+            version,
+            deposit_fee_ratio: pool_header.fee_ratio,
+            signal_provider_fee_bps: DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+            bonfida_fee_bps: DEFAULT_BONFIDA_FEE_BPS,
+            ..pool_header
+        },
+        header_len,
+    ))
+}
+
+/// Unpacks the header of an initialized pool account of any layout version, along with the
+/// length of the header
+pub fn unpack_versioned_pool_header(data: &[u8]) -> Result<(PoolHeader, usize), ProgramError> {
+    let (pool_header, header_len) = unpack_versioned_pool_header_unchecked(data)?;
+    if !pool_header.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    Ok((pool_header, header_len))
+}
+
+/// Packs a pool header into the header of a pool account of any layout version, leaving out
+/// the fields which the layout of `target` doesn't have.
+pub fn pack_versioned_pool_header(pool_header: &PoolHeader, target: &mut [u8]) {
+    let mut header = [0u8; PoolHeader::LEN];
+    pool_header.pack_into_slice(&mut header);
+    target.copy_from_slice(&header[..target.len()]);
+}
+
+/// Number of assets summarized by each page of `GetPoolInfo`, which keeps the return data
+/// under its 1024 bytes limit
+pub const POOL_INFO_ASSETS_PER_PAGE: usize = 25;
+
+/// Length of the summary of the pool returned by `GetPoolInfo` before the asset summaries
+pub const POOL_INFO_HEADER_LEN: usize = 12;
+
+/// Serializes a page of the pool summary returned by `GetPoolInfo`: the total pooltoken
+/// supply (u64), the number of pool assets (u16) and the page index (u16), followed by the
+/// mint (32 bytes) and the pool balance (u64) of each asset of the page.
+pub fn pack_pool_info(
+    total_pooltokens: u64,
+    number_of_assets: u16,
+    page: u16,
+    assets: &[(Pubkey, u64)],
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(POOL_INFO_HEADER_LEN + assets.len() * 40);
+    buf.extend_from_slice(&total_pooltokens.to_le_bytes());
+    buf.extend_from_slice(&number_of_assets.to_le_bytes());
+    buf.extend_from_slice(&page.to_le_bytes());
+    for (mint, balance) in assets {
+        buf.extend_from_slice(&mint.to_bytes());
+        buf.extend_from_slice(&balance.to_le_bytes());
+    }
+    buf
+}
+
+/// For each asset, returns the amount of pooltokens that the source balance allows to buy
+/// with respect to the pool balance. The deposit is bound by the smallest of these ratios,
+/// which can be dominated by low precision (low decimals) assets.
+pub fn compute_deposit_ratios(
+    total_pooltokens: u64,
+    pool_balances: &[u64],
+    source_balances: &[u64],
+) -> Vec<u64> {
+    pool_balances
+        .iter()
+        .zip(source_balances.iter())
+        .map(|(pool_balance, source_balance)| {
+            ((*source_balance as u128) * (total_pooltokens as u128))
+                .checked_div(*pool_balance as u128)
+                .unwrap_or(std::u64::MAX.into()) as u64
+        })
+        .collect()
+}
+
+/// Returns the amount of pooltokens a deposit of `requested` pooltokens can actually buy given
+/// the source balances, before the transferred amounts are rounded down. An empty pool does
+/// not bound the deposit.
+pub fn preview_deposit(
+    total_pooltokens: u64,
+    requested: u64,
+    pool_balances: &[u64],
+    source_balances: &[u64],
+) -> u64 {
+    compute_deposit_ratios(total_pooltokens, pool_balances, source_balances)
+        .into_iter()
+        .fold(requested, std::cmp::min)
+}
+
+/// Returns the amount of pooltokens backed by depositing exactly `deposit_amounts`, which is
+/// the lowest amount backed by a single asset, along with the deviation in basis points of
+/// the highest amount backed by a single asset from it. Returns `None` when an amount is
+/// given for an asset the pool doesn't hold.
+pub fn compute_exact_deposit(
+    total_pooltokens: u64,
+    pool_balances: &[u64],
+    deposit_amounts: &[u64],
+) -> Option<(u64, u64)> {
+    let mut min_backed = std::u64::MAX;
+    let mut max_backed = 0;
+    for ((pool_balance, deposit_amount), backed) in pool_balances
+        .iter()
+        .zip(deposit_amounts.iter())
+        .zip(compute_deposit_ratios(
+            total_pooltokens,
+            pool_balances,
+            deposit_amounts,
+        ))
+    {
+        if *pool_balance == 0 {
+            if *deposit_amount != 0 {
+                return None;
+            }
+            continue;
+        }
+        min_backed = std::cmp::min(min_backed, backed);
+        max_backed = std::cmp::max(max_backed, backed);
+    }
+    if max_backed == 0 {
+        return Some((0, 0));
+    }
+    let deviation_bps =
+        ((max_backed - min_backed) as u128 * 10_000 + max_backed as u128 - 1) / max_backed as u128;
+    Some((min_backed, deviation_bps as u64))
+}
+
+/// Returns the amount of pooltokens backed by depositing `amount` of an asset alone, given the
+/// value of the whole pool in that asset. Returns `None` for a pool without value.
+pub fn compute_single_asset_deposit(
+    total_pooltokens: u64,
+    pool_value: u128,
+    amount: u64,
+) -> Option<u64> {
+    if pool_value == 0 {
+        return None;
+    }
+    ((total_pooltokens as u128) * (amount as u128) / pool_value)
+        .try_into()
+        .ok()
+}
+
+/// Returns the amount of each asset paid out for redeeming `pool_token_amount` pooltokens,
+/// with the same rounding down as `Redeem`.
+pub fn compute_redemption_amounts(
+    total_pooltokens: u64,
+    pool_token_amount: u64,
+    asset_balances: &[u64],
+) -> Vec<u64> {
+    asset_balances
+        .iter()
+        .map(|asset_balance| {
+            ((pool_token_amount as u128) * (*asset_balance as u128))
+                .checked_div(total_pooltokens as u128)
+                .unwrap_or(0)
+                .try_into()
+                .unwrap_or(std::u64::MAX)
+        })
+        .collect()
+}
+
+/// Returns the amount of pooltokens backed by the amounts actually received by the pool, which
+/// can be lower than the transferred amounts for mints charging transfer fees. Assets which
+/// were not transferred are not taken into account.
+pub fn compute_received_pooltokens(
+    total_pooltokens: u64,
+    pool_balances: &[u64],
+    deposit_amounts: &[u64],
+    received_amounts: &[u64],
+    backed_pooltokens: u64,
+) -> u64 {
+    compute_deposit_ratios(total_pooltokens, pool_balances, received_amounts)
+        .iter()
+        .zip(deposit_amounts.iter())
+        .filter(|(_, deposit_amount)| **deposit_amount != 0)
+        .fold(backed_pooltokens, |backed, (ratio, _)| {
+            std::cmp::min(backed, *ratio)
+        })
+}
+
+/// Returns the amount of each asset to transfer for a deposit of `pool_token_effective_amount`
+/// pooltokens, along with the amount of pooltokens actually backed by these transfers. Since
+/// the transferred amounts are rounded down, the backed amount can be slightly lower than the
+/// requested one and is what should be minted. Assets rounding down to a zero transfer are
+/// not taken into account.
+pub fn compute_deposit_amounts(
+    total_pooltokens: u64,
+    pool_balances: &[u64],
+    pool_token_effective_amount: u64,
+) -> (Vec<u64>, u64) {
+    let mut backed_pooltokens = pool_token_effective_amount;
+    let amounts = pool_balances
+        .iter()
+        .map(|pool_balance| {
+            let amount = ((pool_token_effective_amount as u128) * (*pool_balance as u128))
+                .checked_div(total_pooltokens as u128)
+                .unwrap_or(0) as u64;
+            if amount != 0 {
+                let backed = ((amount as u128) * (total_pooltokens as u128)
+                    / (*pool_balance as u128)) as u64;
+                backed_pooltokens = std::cmp::min(backed_pooltokens, backed);
+            }
+            amount
+        })
+        .collect();
+    (amounts, backed_pooltokens)
+}
+
+// The pool state hash covers the whole packed pool account data (header, markets and assets)
+pub fn hash_pool_state(pool_data: &[u8]) -> Hash {
+    hash(pool_data)
+}
+
+pub fn verify_pool_state(pool_data: &[u8], expected_hash: &Hash) -> bool {
+    &hash_pool_state(pool_data) == expected_hash
+}
+
+/// Maximum number of NAV snapshots kept for a pool, older ones are overwritten
+pub const MAX_NAV_SNAPSHOTS: usize = 64;
+
+// The snapshot account is composed of the index of the next slot to write (u32), the number
+// of stored snapshots (u32) and a ring buffer of MAX_NAV_SNAPSHOTS snapshots
+pub const NAV_SNAPSHOTS_ACCOUNT_LEN: usize = 8 + MAX_NAV_SNAPSHOTS * NavSnapshot::LEN;
+
+const NAV_SNAPSHOTS_SEED: &[u8] = b"nav_snapshots";
+
+#[derive(Debug, PartialEq)]
+pub struct NavSnapshot {
+    pub timestamp: u64,
+    /// Value of one pooltoken (10^decimals base units) in the quote unit of the provided prices
+    pub nav_per_token: u64,
+}
+
+impl Sealed for NavSnapshot {}
+
+impl Pack for NavSnapshot {
+    const LEN: usize = 16;
+
+    fn pack_into_slice(&self, target: &mut [u8]) {
+        target[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        target[8..16].copy_from_slice(&self.nav_per_token.to_le_bytes());
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let timestamp = u64::from_le_bytes(src[0..8].try_into().unwrap());
+        let nav_per_token = u64::from_le_bytes(src[8..16].try_into().unwrap());
+        Ok(Self {
+            timestamp,
+            nav_per_token,
+        })
+    }
+}
+
+/// Derives the address of the account holding the NAV snapshots of a pool
+pub fn find_nav_snapshots_key(program_id: &Pubkey, pool_key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&pool_key.to_bytes(), NAV_SNAPSHOTS_SEED], program_id)
+}
+
+pub fn get_nav_snapshots_seeds<'a>(pool_key: &'a [u8], bump: &'a [u8]) -> [&'a [u8]; 3] {
+    [pool_key, NAV_SNAPSHOTS_SEED, bump]
+}
+
+/// Length of a serum OpenOrders account, its padding included
+pub const OPEN_ORDERS_ACCOUNT_LEN: usize = 3228;
+
+const OPEN_ORDERS_SEED: &[u8] = b"open_orders";
+
+/// Derives the address of the OpenOrders account created by the program for a pool on a market
+pub fn find_open_orders_key(
+    program_id: &Pubkey,
+    pool_key: &Pubkey,
+    market: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[&pool_key.to_bytes(), &market.to_bytes(), OPEN_ORDERS_SEED],
+        program_id,
+    )
+}
+
+pub fn get_open_orders_seeds<'a>(
+    pool_key: &'a [u8],
+    market: &'a [u8],
+    bump: &'a [u8],
+) -> [&'a [u8]; 4] {
+    [pool_key, market, OPEN_ORDERS_SEED, bump]
+}
+
+/// Maximum number of target mints in the allow-list of a pool
+pub const MAX_ALLOWED_MINTS: usize = 32;
+
+// The allowed mints account is composed of the number of allowed mints (u32) followed by
+// MAX_ALLOWED_MINTS mint slots
+pub const ALLOWED_MINTS_ACCOUNT_LEN: usize = 4 + MAX_ALLOWED_MINTS * PUBKEY_LENGTH;
+
+const ALLOWED_MINTS_SEED: &[u8] = b"allowed_mints";
+
+/// Derives the address of the account holding the allow-list of target mints of a pool
+pub fn find_allowed_mints_key(program_id: &Pubkey, pool_key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&pool_key.to_bytes(), ALLOWED_MINTS_SEED], program_id)
+}
+
+pub fn get_allowed_mints_seeds<'a>(pool_key: &'a [u8], bump: &'a [u8]) -> [&'a [u8]; 3] {
+    [pool_key, ALLOWED_MINTS_SEED, bump]
+}
+
+/// Returns the mints of the allow-list
+pub fn unpack_allowed_mints(data: &[u8]) -> Result<Vec<Pubkey>, ProgramError> {
+    if data.len() != ALLOWED_MINTS_ACCOUNT_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let count = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    if count > MAX_ALLOWED_MINTS {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok((0..count)
+        .map(|i| {
+            let offset = 4 + i * PUBKEY_LENGTH;
+            Pubkey::new(&data[offset..offset + PUBKEY_LENGTH])
+        })
+        .collect())
+}
+
+/// Adds a mint to the allow-list, doing nothing if it is already allowed
+pub fn add_allowed_mint(data: &mut [u8], mint: &Pubkey) -> Result<(), ProgramError> {
+    let allowed_mints = unpack_allowed_mints(data)?;
+    if allowed_mints.contains(mint) {
+        return Ok(());
+    }
+    let count = allowed_mints.len();
+    if count == MAX_ALLOWED_MINTS {
+        return Err(BonfidaBotError::Overflow.into());
+    }
+    let offset = 4 + count * PUBKEY_LENGTH;
+    data[offset..offset + PUBKEY_LENGTH].copy_from_slice(&mint.to_bytes());
+    data[0..4].copy_from_slice(&((count + 1) as u32).to_le_bytes());
+    Ok(())
+}
+
+/// Removes a mint from the allow-list, moving the last allowed mint into its slot
+pub fn remove_allowed_mint(data: &mut [u8], mint: &Pubkey) -> Result<(), ProgramError> {
+    let allowed_mints = unpack_allowed_mints(data)?;
+    let index = allowed_mints
+        .iter()
+        .position(|allowed_mint| allowed_mint == mint)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let last_index = allowed_mints.len() - 1;
+    let offset = 4 + index * PUBKEY_LENGTH;
+    data[offset..offset + PUBKEY_LENGTH].copy_from_slice(&allowed_mints[last_index].to_bytes());
+    let last_offset = 4 + last_index * PUBKEY_LENGTH;
+    data[last_offset..last_offset + PUBKEY_LENGTH].copy_from_slice(&[0u8; PUBKEY_LENGTH]);
+    data[0..4].copy_from_slice(&(last_index as u32).to_le_bytes());
+    Ok(())
+}
+
+/// Computes the value of one pooltoken, that is 10^decimals base units of the pooltoken mint,
+/// from the pool balances and a price for each asset
+pub fn compute_nav_per_token(
+    balances: &[u64],
+    prices: &[u64],
+    total_pooltokens: u64,
+    decimals: u8,
+) -> Option<u64> {
+    let mut total_value: u128 = 0;
+    for (balance, price) in balances.iter().zip(prices.iter()) {
+        total_value = total_value.checked_add((*balance as u128).checked_mul(*price as u128)?)?;
+    }
+    total_value
+        .checked_mul(10u128.checked_pow(decimals as u32)?)?
+        .checked_div(total_pooltokens as u128)?
+        .try_into()
+        .ok()
+}
+
+/// Balance of each asset of a pool along with its pooltoken supply, which is all that is needed
+/// to value the pool and its pooltoken given a price for each asset.
+#[derive(Debug, PartialEq)]
+pub struct PoolComposition {
+    pub assets: Vec<(Pubkey, u64)>,
+    pub pooltoken_supply: u64,
+}
+
+/// Builds the composition of a pool from its assets and the parsed pool asset token accounts
+/// and pooltoken mint, for clients. The asset accounts are expected in the order of the assets.
+pub fn pool_composition(
+    pool_assets: &[PoolAsset],
+    asset_accounts: &[Account],
+    pool_mint: &Mint,
+) -> Result<PoolComposition, ProgramError> {
+    if pool_assets.len() != asset_accounts.len() {
+        msg!(
+            "{} asset accounts were given for {} pool assets",
+            asset_accounts.len(),
+            pool_assets.len()
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    let mut assets = Vec::with_capacity(pool_assets.len());
+    for (pool_asset, asset_account) in pool_assets.iter().zip(asset_accounts.iter()) {
+        if asset_account.mint != pool_asset.mint_address {
+            msg!(
+                "The asset account of mint {} is out of order",
+                asset_account.mint
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        assets.push((pool_asset.mint_address, asset_account.amount));
+    }
+    Ok(PoolComposition {
+        assets,
+        pooltoken_supply: pool_mint.supply,
+    })
+}
+
+/// Appends a snapshot to the ring buffer, overwriting the oldest one when full
+pub fn push_nav_snapshot(data: &mut [u8], snapshot: &NavSnapshot) -> Result<(), ProgramError> {
+    if data.len() != NAV_SNAPSHOTS_ACCOUNT_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let next_index = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    if next_index >= MAX_NAV_SNAPSHOTS {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let offset = 8 + next_index * NavSnapshot::LEN;
+    snapshot.pack_into_slice(&mut data[offset..offset + NavSnapshot::LEN]);
+    let next_index = (next_index + 1) % MAX_NAV_SNAPSHOTS;
+    let count = std::cmp::min(count + 1, MAX_NAV_SNAPSHOTS);
+    data[0..4].copy_from_slice(&(next_index as u32).to_le_bytes());
+    data[4..8].copy_from_slice(&(count as u32).to_le_bytes());
+    Ok(())
+}
+
+/// Returns the stored snapshots from oldest to newest
+pub fn unpack_nav_snapshots(data: &[u8]) -> Result<Vec<NavSnapshot>, ProgramError> {
+    if data.len() != NAV_SNAPSHOTS_ACCOUNT_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let next_index = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    if next_index >= MAX_NAV_SNAPSHOTS || count > MAX_NAV_SNAPSHOTS {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let first_index = (next_index + MAX_NAV_SNAPSHOTS - count) % MAX_NAV_SNAPSHOTS;
+    let mut snapshots = Vec::with_capacity(count);
+    for i in 0..count {
+        let offset = 8 + ((first_index + i) % MAX_NAV_SNAPSHOTS) * NavSnapshot::LEN;
+        snapshots.push(NavSnapshot::unpack_from_slice(
+            &data[offset..offset + NavSnapshot::LEN],
+        )?);
+    }
+    Ok(snapshots)
+}
+
 #[cfg(test)]
 mod tests {
     use std::num::NonZeroU8;
 
-    use super::{pack_markets, unpack_assets, unpack_market, PoolAsset, PoolHeader, PoolStatus};
+    use crate::error::BonfidaBotError;
+
+    use super::{
+        add_allowed_mint, append_market, compute_deposit_amounts, compute_deposit_ratios,
+        compute_exact_deposit, compute_nav_per_token, compute_received_pooltokens,
+        compute_redemption_amounts, compute_single_asset_deposit, get_pool_header_version,
+        hash_pool_state, pack_assets, pack_markets, pack_metadata_uri, pack_pool_info,
+        pack_versioned_pool_header, pool_composition, preview_deposit, push_nav_snapshot,
+        remove_allowed_mint, test_pool_header, unpack_allowed_mints, unpack_assets, unpack_headers,
+        unpack_market, unpack_markets, unpack_metadata_uri, unpack_nav_snapshots,
+        unpack_pool_assets, unpack_pool_header, unpack_versioned_pool_header, verify_pool_state,
+        NavSnapshot, PoolAsset, PoolHeader, PoolMode, PoolStatus, ALLOWED_MINTS_ACCOUNT_LEN,
+        DEFAULT_BONFIDA_FEE_BPS, DEFAULT_SIGNAL_PROVIDER_FEE_BPS, MAX_ALLOWED_MINTS,
+        MAX_NAV_SNAPSHOTS, MAX_PENDING_ORDERS, MAX_POOL_ASSETS, METADATA_URI_LEN,
+        NAV_SNAPSHOTS_ACCOUNT_LEN, POOL_HEADER_LENS, POOL_HEADER_VERSION,
+        POOL_HEADER_VERSION_OFFSET, POOL_INFO_ASSETS_PER_PAGE, PUBKEY_LENGTH,
+    };
     use solana_program::{
+        program_error::ProgramError,
         program_pack::{IsInitialized, Pack},
         pubkey::Pubkey,
     };
+    use spl_token::state::{Account, Mint};
 
     #[test]
     fn test_state_packing() {
         let header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
             status: PoolStatus::PendingOrder(NonZeroU8::new(39).unwrap()),
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
         };
 
         let header_size = PoolHeader::LEN;
@@ -257,14 +1173,16 @@ mod tests {
     #[test]
     fn test_header_packing() {
         let mut header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
             status: PoolStatus::PendingOrder(NonZeroU8::new(39).unwrap()),
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
         };
         assert_eq!(
             header_state,
@@ -272,14 +1190,20 @@ mod tests {
         );
 
         header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
             status: PoolStatus::LockedPendingOrder(NonZeroU8::new(64).unwrap()),
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            pool_mode: PoolMode::RedeemOnly,
+            redeem_requires_provider: true,
+            maturity_timestamp: 1_100_000_000,
+            referrer_fee_bps: 2_500,
+            ..test_pool_header()
         };
         assert_eq!(
             header_state,
@@ -287,14 +1211,16 @@ mod tests {
         );
 
         header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
             status: PoolStatus::Locked,
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
         };
         assert_eq!(
             header_state,
@@ -302,14 +1228,15 @@ mod tests {
         );
 
         header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
-            status: PoolStatus::Unlocked,
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
         };
         assert_eq!(
             header_state,
@@ -317,14 +1244,16 @@ mod tests {
         );
 
         header_state = PoolHeader {
-            serum_program_id: Pubkey::new_unique(),
-            seed: [0u8; 32],
-            signal_provider: Pubkey::new_unique(),
             status: PoolStatus::Uninitialized,
             number_of_markets: 234,
             fee_ratio: 15,
             last_fee_collection_timestamp: 1_000_000_000,
             fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
         };
         assert!(PoolHeader::unpack(&get_packed(&header_state)).is_err());
     }
@@ -354,5 +1283,888 @@ mod tests {
         for i in 0..4 {
             assert_eq!(markets[i], unpack_market(&output_array, i as u16));
         }
+        assert_eq!(unpack_markets(&output_array, 4), markets);
+        assert_eq!(unpack_markets(&output_array, 2), markets[..2].to_vec());
+        assert!(unpack_markets(&output_array, 0).is_empty());
+        // Markets past the end of the data are left out
+        assert_eq!(
+            unpack_markets(&output_array[..3 * 32 + 1], 4),
+            markets[..3].to_vec()
+        );
+
+        // An undersized target is reported instead of panicking, and left untouched
+        let mut undersized_array = [0u8; 4 * 32 - 1];
+        assert_eq!(
+            pack_markets(&mut undersized_array, &markets),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert!(undersized_array.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_append_market() {
+        let markets = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let pool_assets = vec![
+            PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            },
+            PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            },
+        ];
+        // Two markets and three asset slots, the last of which is free
+        let mut data = [0u8; 5 * 32];
+        pack_markets(&mut data, &markets).unwrap();
+        pack_assets(&mut data[2 * 32..], &pool_assets).unwrap();
+
+        let new_market = Pubkey::new_unique();
+        assert_eq!(
+            append_market(&mut data, 2, &markets[1]),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(append_market(&mut data, 2, &new_market), Ok(3));
+        assert_eq!(unpack_market(&data, 0), markets[0]);
+        assert_eq!(unpack_market(&data, 1), markets[1]);
+        assert_eq!(unpack_market(&data, 2), new_market);
+        assert_eq!(unpack_assets(&data[3 * 32..]).unwrap(), pool_assets);
+
+        // No asset slot is left to make room for another market
+        assert_eq!(
+            append_market(&mut data, 3, &Pubkey::new_unique()),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_assets_packing_skips_zero_deposits() {
+        // Mirrors process_create, which only tracks assets with a nonzero initial deposit
+        let deposit_amounts = [100u64, 0, 42];
+        let mints = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let pool_assets: Vec<PoolAsset> = deposit_amounts
+            .iter()
+            .zip(mints.iter())
+            .filter(|(amount, _)| **amount != 0)
+            .map(|(_, mint)| PoolAsset {
+                mint_address: *mint,
+            })
+            .collect();
+
+        let mut assets_array = [0u8; 3 * PoolAsset::LEN];
+        pack_assets(&mut assets_array, &pool_assets).unwrap();
+
+        let unpacked_pool_assets = unpack_assets(&assets_array).unwrap();
+        assert_eq!(unpacked_pool_assets, pool_assets);
+        assert_eq!(
+            &assets_array[2 * PoolAsset::LEN..],
+            &[0u8; PoolAsset::LEN][..]
+        );
+
+        assert!(pack_assets(&mut assets_array[..PoolAsset::LEN], &pool_assets).is_err());
+    }
+
+    #[test]
+    fn test_unpack_assets_corrupted_slot() {
+        let pool_assets: Vec<PoolAsset> = (0..3)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        let mut assets_array = [0u8; 4 * PoolAsset::LEN];
+        pack_assets(&mut assets_array, &pool_assets).unwrap();
+
+        // An emptied middle slot is skipped
+        let mut emptied_array = assets_array;
+        emptied_array[PoolAsset::LEN..2 * PoolAsset::LEN].copy_from_slice(&[0u8; PoolAsset::LEN]);
+        assert_eq!(
+            unpack_assets(&emptied_array).unwrap(),
+            vec![
+                PoolAsset {
+                    mint_address: pool_assets[0].mint_address
+                },
+                PoolAsset {
+                    mint_address: pool_assets[2].mint_address
+                },
+            ]
+        );
+
+        // A middle slot overwritten with another asset is reported
+        let mut corrupted_array = assets_array;
+        corrupted_array[PoolAsset::LEN..2 * PoolAsset::LEN]
+            .copy_from_slice(&assets_array[..PoolAsset::LEN]);
+        assert_eq!(
+            unpack_assets(&corrupted_array),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // So is a truncated trailing slot
+        assert_eq!(
+            unpack_assets(&assets_array[..3 * PoolAsset::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            unpack_assets(&assets_array[..3 * PoolAsset::LEN + 1]).unwrap(),
+            pool_assets
+        );
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let header_state = PoolHeader {
+            fee_ratio: 15,
+            last_fee_collection_timestamp: 1_000_000_000,
+            fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
+        };
+        let mut state_array = [0u8; PoolHeader::LEN + 32 + PoolAsset::LEN];
+        header_state.pack_into_slice(&mut state_array[..PoolHeader::LEN]);
+        pack_markets(
+            &mut state_array[PoolHeader::LEN..],
+            &vec![Pubkey::new_unique()],
+        )
+        .unwrap();
+        pack_assets(
+            &mut state_array[PoolHeader::LEN + 32..],
+            &[PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            }],
+        )
+        .unwrap();
+
+        let state_hash = hash_pool_state(&state_array);
+        assert!(verify_pool_state(&state_array, &state_hash));
+
+        let mut tampered_array = state_array;
+        tampered_array[PoolHeader::LEN + 32] ^= 1;
+        assert!(!verify_pool_state(&tampered_array, &state_hash));
+    }
+
+    #[test]
+    fn test_deposit_ratios_binding_asset() {
+        let total_pooltokens = 1_000_000;
+        // A 0 decimals asset and a 9 decimals asset, 10 units of each in the pool
+        let pool_balances = [10, 10_000_000_000];
+        // 3.9 units can't be deposited for the 0 decimals asset, it is bound to 3 units
+        let source_balances = [3, 3_900_000_000];
+        let ratios = compute_deposit_ratios(total_pooltokens, &pool_balances, &source_balances);
+        assert_eq!(ratios, vec![300_000, 390_000]);
+        let binding_index = (0..ratios.len()).min_by_key(|i| ratios[*i]).unwrap();
+        assert_eq!(binding_index, 0);
+
+        // An empty pool balance does not constrain the deposit
+        let ratios = compute_deposit_ratios(total_pooltokens, &[0, 10], &[5, 5]);
+        assert_eq!(ratios, vec![std::u64::MAX, 500_000]);
+    }
+
+    #[test]
+    fn test_status_unpacking_never_panics() {
+        let header_state = PoolHeader {
+            number_of_markets: 234,
+            fee_ratio: 15,
+            last_fee_collection_timestamp: 1_000_000_000,
+            fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
+        };
+        let mut packed = get_packed(&header_state);
+        for status_byte in 0..=255u8 {
+            packed[96] = status_byte;
+            let unpacked = PoolHeader::unpack_unchecked(&packed);
+            if status_byte == 0 {
+                assert_eq!(unpacked.unwrap().status, PoolStatus::Uninitialized);
+            } else {
+                assert_ne!(unpacked.unwrap().status, PoolStatus::Uninitialized);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_headers() {
+        let header_state = PoolHeader {
+            status: PoolStatus::Locked,
+            number_of_markets: 2,
+            fee_ratio: 15,
+            last_fee_collection_timestamp: 1_000_000_000,
+            fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            rebate_retention_ratio: 1 << 15,
+            pending_fee_ratio: 20,
+            pending_fee_ratio_timestamp: 1_000_010_000,
+            ..test_pool_header()
+        };
+        let mut pool_data = get_packed(&header_state);
+        pool_data.extend_from_slice(&[0u8; 2 * 32 + PoolAsset::LEN]);
+        let truncated_data = &pool_data[..PoolHeader::LEN - 1];
+        let uninitialized_data = [0u8; PoolHeader::LEN];
+
+        let headers = unpack_headers(
+            vec![
+                &pool_data[..],
+                truncated_data,
+                &uninitialized_data[..],
+                &pool_data[..PoolHeader::LEN],
+            ]
+            .into_iter(),
+        );
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers[0], Ok(header_state));
+        assert_eq!(headers[1], Err(ProgramError::InvalidAccountData));
+        assert_eq!(headers[2], Err(ProgramError::UninitializedAccount));
+        assert!(headers[3].is_ok());
+    }
+
+    #[test]
+    fn test_redemption_amounts() {
+        let asset_balances = [1_000_000u64, 3, 0, std::u64::MAX];
+        let total_pooltokens = 1_000;
+        assert_eq!(
+            compute_redemption_amounts(total_pooltokens, 10, &asset_balances),
+            vec![10_000, 0, 0, std::u64::MAX / 100]
+        );
+        // Small redemptions round down to zero
+        assert_eq!(
+            compute_redemption_amounts(total_pooltokens, 333, &asset_balances[1..3]),
+            vec![0, 0]
+        );
+        assert_eq!(
+            compute_redemption_amounts(total_pooltokens, 334, &asset_balances[1..3]),
+            vec![1, 0]
+        );
+        assert_eq!(
+            compute_redemption_amounts(total_pooltokens, total_pooltokens, &asset_balances),
+            asset_balances.to_vec()
+        );
+        assert_eq!(
+            compute_redemption_amounts(0, 10, &asset_balances),
+            vec![0; 4]
+        );
+
+        // Matches the per-asset share paid out by Redeem
+        for pool_token_amount in [1, 7, 333, 999, 1_000].iter() {
+            let amounts =
+                compute_redemption_amounts(total_pooltokens, *pool_token_amount, &asset_balances);
+            for (amount, asset_balance) in amounts.iter().zip(asset_balances.iter()) {
+                assert_eq!(
+                    *amount,
+                    crate::utils::get_asset_share(
+                        *pool_token_amount,
+                        *asset_balance,
+                        total_pooltokens
+                    )
+                    .unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_received_pooltokens_with_transfer_fees() {
+        let pool_balances = [1_000u64, 2_000, 500];
+        let (deposit_amounts, backed) = compute_deposit_amounts(1_000, &pool_balances, 100);
+        assert_eq!((deposit_amounts.clone(), backed), (vec![100, 200, 50], 100));
+
+        let received_pooltokens = |received_amounts: &[u64]| {
+            compute_received_pooltokens(
+                1_000,
+                &pool_balances,
+                &deposit_amounts,
+                received_amounts,
+                backed,
+            )
+        };
+
+        // Without transfer fees, the backed amount is unchanged
+        assert_eq!(received_pooltokens(&deposit_amounts), 100);
+        // A 5% transfer fee on the second asset binds the deposit
+        assert_eq!(received_pooltokens(&[100, 190, 50]), 95);
+        // Assets which were not transferred are ignored
+        assert_eq!(
+            compute_received_pooltokens(1_000, &pool_balances, &[100, 200, 0], &[100, 200, 0], 100),
+            100
+        );
+    }
+
+    #[test]
+    fn test_deposit_never_mints_more_than_deposited() {
+        // Simple deterministic pseudo-random generator to explore many pool configurations
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % bound + 1
+        };
+        for _ in 0..10_000 {
+            let total_pooltokens = next(1 << 40);
+            let nb_assets = next(5) as usize;
+            let pool_balances: Vec<u64> = (0..nb_assets).map(|_| next(1 << 50)).collect();
+            let source_balances: Vec<u64> = (0..nb_assets).map(|_| next(1 << 50)).collect();
+            let requested = next(1 << 40);
+
+            let effective =
+                compute_deposit_ratios(total_pooltokens, &pool_balances, &source_balances)
+                    .into_iter()
+                    .fold(requested, std::cmp::min);
+            let (amounts, minted) =
+                compute_deposit_amounts(total_pooltokens, &pool_balances, effective);
+
+            assert!(minted <= effective);
+            for (amount, pool_balance) in amounts.iter().zip(pool_balances.iter()) {
+                if *amount == 0 {
+                    continue;
+                }
+                // minted / supply <= amount / pool_balance
+                assert!(
+                    (minted as u128) * (*pool_balance as u128)
+                        <= (*amount as u128) * (total_pooltokens as u128)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_nav_snapshots() {
+        let mut data = vec![0u8; NAV_SNAPSHOTS_ACCOUNT_LEN];
+        assert_eq!(unpack_nav_snapshots(&data).unwrap(), vec![]);
+
+        // Two assets worth 2 and 3 quote units per base unit for 2 whole pooltokens
+        let nav_per_token = compute_nav_per_token(&[1_000, 500], &[2, 3], 2_000_000, 6).unwrap();
+        assert_eq!(nav_per_token, 1_750);
+        assert_eq!(compute_nav_per_token(&[1_000], &[2], 0, 6), None);
+        // The same pool with 9 decimals pooltokens, a whole pooltoken being 10^9 base units
+        assert_eq!(
+            compute_nav_per_token(&[1_000, 500], &[2, 3], 2_000_000_000, 9),
+            Some(1_750)
+        );
+        assert_eq!(
+            compute_nav_per_token(&[1_000, 500], &[2, 3], 20, 0),
+            Some(175)
+        );
+
+        for i in 0..3 {
+            push_nav_snapshot(
+                &mut data,
+                &NavSnapshot {
+                    timestamp: 1_000 + i,
+                    nav_per_token: nav_per_token + i,
+                },
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            unpack_nav_snapshots(&data).unwrap(),
+            (0..3)
+                .map(|i| NavSnapshot {
+                    timestamp: 1_000 + i,
+                    nav_per_token: nav_per_token + i,
+                })
+                .collect::<Vec<_>>()
+        );
+
+        // The buffer is bounded, the oldest snapshots get overwritten
+        for i in 3..(MAX_NAV_SNAPSHOTS as u64 + 10) {
+            push_nav_snapshot(
+                &mut data,
+                &NavSnapshot {
+                    timestamp: 1_000 + i,
+                    nav_per_token: nav_per_token + i,
+                },
+            )
+            .unwrap();
+        }
+        let snapshots = unpack_nav_snapshots(&data).unwrap();
+        assert_eq!(snapshots.len(), MAX_NAV_SNAPSHOTS);
+        assert_eq!(snapshots[0].timestamp, 1_010);
+        assert_eq!(
+            snapshots[MAX_NAV_SNAPSHOTS - 1].timestamp,
+            1_000 + MAX_NAV_SNAPSHOTS as u64 + 9
+        );
+    }
+
+    #[test]
+    fn test_allowed_mints() {
+        let mut data = vec![0u8; ALLOWED_MINTS_ACCOUNT_LEN];
+        assert_eq!(unpack_allowed_mints(&data).unwrap(), vec![]);
+
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        for mint in mints.iter() {
+            add_allowed_mint(&mut data, mint).unwrap();
+        }
+        // Adding an allowed mint twice keeps a single entry
+        add_allowed_mint(&mut data, &mints[1]).unwrap();
+        assert_eq!(unpack_allowed_mints(&data).unwrap(), mints);
+
+        remove_allowed_mint(&mut data, &mints[0]).unwrap();
+        assert_eq!(
+            unpack_allowed_mints(&data).unwrap(),
+            vec![mints[2], mints[1]]
+        );
+        assert_eq!(
+            remove_allowed_mint(&mut data, &mints[0]),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        for _ in 2..MAX_ALLOWED_MINTS {
+            add_allowed_mint(&mut data, &Pubkey::new_unique()).unwrap();
+        }
+        assert!(add_allowed_mint(&mut data, &Pubkey::new_unique()).is_err());
+        assert_eq!(
+            unpack_allowed_mints(&data).unwrap().len(),
+            MAX_ALLOWED_MINTS
+        );
+
+        assert_eq!(
+            unpack_allowed_mints(&data[..ALLOWED_MINTS_ACCOUNT_LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_exact_deposit() {
+        let pool_balances = [1_000, 4_000, 0];
+
+        // Amounts in the exact pool ratios
+        assert_eq!(
+            compute_exact_deposit(100, &pool_balances, &[100, 400, 0]),
+            Some((10, 0))
+        );
+
+        // Asset 1 is 1% short, only the pooltokens it backs are minted
+        assert_eq!(
+            compute_exact_deposit(100_000, &pool_balances, &[100, 396, 0]),
+            Some((9_900, 100))
+        );
+
+        // The deviation is rounded up
+        assert_eq!(
+            compute_exact_deposit(100_000, &pool_balances, &[1_000, 3_999, 0]),
+            Some((99_975, 3))
+        );
+
+        // A missing asset is a full deviation
+        assert_eq!(
+            compute_exact_deposit(100, &pool_balances, &[100, 0, 0]),
+            Some((0, 10_000))
+        );
+
+        // Assets which are not held by the pool cannot be deposited
+        assert_eq!(
+            compute_exact_deposit(100, &pool_balances, &[100, 400, 1]),
+            None
+        );
+
+        assert_eq!(
+            compute_exact_deposit(100, &pool_balances, &[0, 0, 0]),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_header_version() {
+        let header_state = PoolHeader {
+            fee_ratio: 15,
+            last_fee_collection_timestamp: 1_000_000_000,
+            fee_collection_period: 10_000,
+            deposit_fee_ratio: 30,
+            ..test_pool_header()
+        };
+        let mut packed = get_packed(&header_state);
+        assert_eq!(packed[POOL_HEADER_VERSION_OFFSET], POOL_HEADER_VERSION);
+
+        packed[POOL_HEADER_VERSION_OFFSET] = POOL_HEADER_VERSION + 1;
+        assert_eq!(
+            PoolHeader::unpack(&packed),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // Uninitialized headers are not versioned
+        assert_eq!(
+            PoolHeader::unpack(&[0u8; PoolHeader::LEN]),
+            Err(ProgramError::UninitializedAccount)
+        );
+
+        // The layout of a pool account is given by its length
+        let legacy_len = POOL_HEADER_LENS[0];
+        assert_eq!(
+            get_pool_header_version(legacy_len + 3 * PUBKEY_LENGTH),
+            Ok(0)
+        );
+        assert_eq!(
+            get_pool_header_version(PoolHeader::LEN + 3 * PUBKEY_LENGTH),
+            Ok(POOL_HEADER_VERSION)
+        );
+        assert_eq!(
+            get_pool_header_version(PoolHeader::LEN + 3 * PUBKEY_LENGTH - 1),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            get_pool_header_version(legacy_len - PUBKEY_LENGTH),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // A version 0 pool account on one market with every asset slot in use, whose market
+        // has a byte of 1 where the current header has its version byte
+        let mut market_bytes = Pubkey::new_unique().to_bytes();
+        market_bytes[POOL_HEADER_VERSION_OFFSET - legacy_len] = POOL_HEADER_VERSION;
+        let market = Pubkey::new(&market_bytes);
+        let pool_assets: Vec<PoolAsset> = (0..6)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        let assets_offset = legacy_len + PUBKEY_LENGTH;
+        let mut data = vec![0u8; assets_offset + pool_assets.len() * PoolAsset::LEN];
+        data[..legacy_len].copy_from_slice(&packed[..legacy_len]);
+        data[legacy_len..assets_offset].copy_from_slice(&market.to_bytes());
+        pack_assets(&mut data[assets_offset..], &pool_assets).unwrap();
+
+        // It isn't mistaken for a current header, it is only read in place
+        assert_eq!(
+            unpack_pool_header(&data),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            unpack_headers(vec![&data[..]].into_iter()),
+            vec![Err(ProgramError::InvalidAccountData)]
+        );
+        let (legacy_header, header_len) = unpack_versioned_pool_header(&data).unwrap();
+        assert_eq!(header_len, legacy_len);
+        // Deposits of version 0 pools were charged the fee ratio
+        assert_eq!(
+            legacy_header,
+            PoolHeader {
+                version: 0,
+                deposit_fee_ratio: header_state.fee_ratio,
+                signal_provider_fee_bps: DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+                bonfida_fee_bps: DEFAULT_BONFIDA_FEE_BPS,
+                ..header_state
+            }
+        );
+        assert_eq!(unpack_market(&data[header_len..], 0), market);
+        assert_eq!(unpack_pool_assets(&data, 1).unwrap(), pool_assets);
+
+        // Packing it back only writes the version 0 fields
+        let before = data.clone();
+        pack_versioned_pool_header(
+            &PoolHeader {
+                status: PoolStatus::Uninitialized,
+                ..legacy_header
+            },
+            &mut data[..header_len],
+        );
+        assert_eq!(data[96], 0);
+        assert_eq!(data[..96], before[..96]);
+        assert_eq!(data[97..], before[97..]);
+
+        // Current pool accounts are read as such
+        let mut data = get_packed(&header_state);
+        data.extend_from_slice(&market.to_bytes());
+        assert_eq!(
+            unpack_versioned_pool_header(&data),
+            Ok((header_state, PoolHeader::LEN))
+        );
+    }
+
+    #[test]
+    fn test_pool_info_packing() {
+        let mint = Pubkey::new_unique();
+        let packed = pack_pool_info(1_000, 30, 1, &[(mint, 42)]);
+        assert_eq!(packed.len(), 52);
+        assert_eq!(packed[..8], 1_000u64.to_le_bytes());
+        assert_eq!(packed[8..10], 30u16.to_le_bytes());
+        assert_eq!(packed[10..12], 1u16.to_le_bytes());
+        assert_eq!(Pubkey::new(&packed[12..44]), mint);
+        assert_eq!(packed[44..52], 42u64.to_le_bytes());
+
+        // A full page fits in the return data
+        let assets = vec![(mint, u64::MAX); POOL_INFO_ASSETS_PER_PAGE];
+        assert!(pack_pool_info(u64::MAX, u16::MAX, 0, &assets).len() <= 1024);
+    }
+
+    #[test]
+    fn test_unpack_pool_assets() {
+        let pool_assets: Vec<PoolAsset> = (0..2)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH;
+        let mut data = vec![0u8; asset_offset + 3 * PoolAsset::LEN];
+        pack_assets(&mut data[asset_offset..], &pool_assets).unwrap();
+        assert_eq!(unpack_pool_assets(&data, 1).unwrap(), pool_assets);
+
+        // A corrupted number of markets pointing past the account data is caught
+        assert_eq!(
+            unpack_pool_assets(&data, 100),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // So is an asset region holding more than the maximum number of assets
+        let too_many_assets: Vec<PoolAsset> = (0..MAX_POOL_ASSETS + 1)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        let mut data = vec![0u8; asset_offset + too_many_assets.len() * PoolAsset::LEN];
+        pack_assets(&mut data[asset_offset..], &too_many_assets).unwrap();
+        assert_eq!(
+            unpack_pool_assets(&data, 1),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            unpack_pool_assets(&data[..data.len() - PoolAsset::LEN], 1)
+                .unwrap()
+                .len(),
+            MAX_POOL_ASSETS
+        );
+    }
+
+    #[test]
+    fn test_preview_deposit() {
+        // The inline computation previously done by the deposit processor
+        fn inline_preview(total: u64, requested: u64, pool: &[u64], source: &[u64]) -> u64 {
+            let mut effective = std::u64::MAX;
+            for ratio in compute_deposit_ratios(total, pool, source) {
+                if ratio < effective {
+                    effective = ratio;
+                }
+            }
+            std::cmp::min(requested, effective)
+        }
+        let scenarios: [(u64, u64, &[u64], &[u64]); 6] = [
+            // The request is fully covered by the sources
+            (
+                1_000_000,
+                100_000,
+                &[10, 10_000_000_000],
+                &[5, 5_000_000_000],
+            ),
+            // The low precision asset binds the deposit
+            (
+                1_000_000,
+                500_000,
+                &[10, 10_000_000_000],
+                &[3, 3_900_000_000],
+            ),
+            // The high precision asset binds the deposit
+            (
+                1_000_000,
+                500_000,
+                &[10, 10_000_000_000],
+                &[5, 1_000_000_000],
+            ),
+            // An empty source prevents any deposit
+            (1_000_000, 500_000, &[10, 20], &[0, 20]),
+            // An empty pool balance does not bound the deposit
+            (1_000_000, 500_000, &[0, 10], &[5, 10]),
+            // No asset
+            (1_000_000, 500_000, &[], &[]),
+        ];
+        for (total, requested, pool, source) in scenarios.iter() {
+            assert_eq!(
+                preview_deposit(*total, *requested, pool, source),
+                inline_preview(*total, *requested, pool, source)
+            );
+        }
+        assert_eq!(
+            preview_deposit(
+                1_000_000,
+                500_000,
+                &[10, 10_000_000_000],
+                &[3, 3_900_000_000]
+            ),
+            300_000
+        );
+        assert_eq!(
+            preview_deposit(1_000_000, 500_000, &[0, 10], &[5, 10]),
+            500_000
+        );
+        assert_eq!(
+            preview_deposit(1_000_000, std::u64::MAX, &[0], &[5]),
+            std::u64::MAX
+        );
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        let n = |n| NonZeroU8::new(n).unwrap();
+
+        assert_eq!(
+            PoolStatus::Unlocked.begin_order(),
+            Ok(PoolStatus::PendingOrder(n(1)))
+        );
+        assert_eq!(
+            PoolStatus::Locked.begin_order(),
+            Ok(PoolStatus::LockedPendingOrder(n(1)))
+        );
+        assert_eq!(
+            PoolStatus::PendingOrder(n(3)).begin_order(),
+            Ok(PoolStatus::PendingOrder(n(4)))
+        );
+        assert_eq!(
+            PoolStatus::LockedPendingOrder(n(3)).begin_order(),
+            Ok(PoolStatus::LockedPendingOrder(n(4)))
+        );
+        assert_eq!(
+            PoolStatus::PendingOrder(n(MAX_PENDING_ORDERS)).begin_order(),
+            Err(BonfidaBotError::TooManyPendingOrders.into())
+        );
+        assert_eq!(
+            PoolStatus::LockedPendingOrder(n(MAX_PENDING_ORDERS)).begin_order(),
+            Err(BonfidaBotError::TooManyPendingOrders.into())
+        );
+        assert_eq!(
+            PoolStatus::Uninitialized.begin_order(),
+            Err(ProgramError::UninitializedAccount)
+        );
+
+        assert_eq!(
+            PoolStatus::PendingOrder(n(1)).settle_one(),
+            Ok(PoolStatus::Unlocked)
+        );
+        assert_eq!(
+            PoolStatus::LockedPendingOrder(n(1)).settle_one(),
+            Ok(PoolStatus::Locked)
+        );
+        assert_eq!(
+            PoolStatus::PendingOrder(n(MAX_PENDING_ORDERS)).settle_one(),
+            Ok(PoolStatus::PendingOrder(n(MAX_PENDING_ORDERS - 1)))
+        );
+        assert_eq!(
+            PoolStatus::LockedPendingOrder(n(2)).settle_one(),
+            Ok(PoolStatus::LockedPendingOrder(n(1)))
+        );
+        for status in [
+            PoolStatus::Uninitialized,
+            PoolStatus::Unlocked,
+            PoolStatus::Locked,
+        ]
+        .iter()
+        {
+            assert_eq!(status.settle_one(), Err(ProgramError::InvalidAccountData));
+            assert!(!status.has_pending_orders());
+        }
+        assert!(PoolStatus::PendingOrder(n(1)).has_pending_orders());
+        assert!(PoolStatus::LockedPendingOrder(n(1)).has_pending_orders());
+
+        // Every pending order begun can be settled back
+        let mut status = PoolStatus::Locked;
+        for _ in 0..MAX_PENDING_ORDERS {
+            status = status.begin_order().unwrap();
+        }
+        for _ in 0..MAX_PENDING_ORDERS {
+            status = status.settle_one().unwrap();
+        }
+        assert_eq!(status, PoolStatus::Locked);
+    }
+
+    #[test]
+    fn test_pool_composition() {
+        let pool_assets: Vec<PoolAsset> = (0..3)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        let asset_accounts: Vec<Account> = pool_assets
+            .iter()
+            .zip([1_000u64, 0, 42].iter())
+            .map(|(pool_asset, amount)| Account {
+                mint: pool_asset.mint_address,
+                amount: *amount,
+                ..Account::default()
+            })
+            .collect();
+        let pool_mint = Mint {
+            supply: 1_000_000,
+            is_initialized: true,
+            ..Mint::default()
+        };
+
+        let composition = pool_composition(&pool_assets, &asset_accounts, &pool_mint).unwrap();
+        assert_eq!(composition.pooltoken_supply, 1_000_000);
+        assert_eq!(
+            composition.assets,
+            vec![
+                (pool_assets[0].mint_address, 1_000),
+                (pool_assets[1].mint_address, 0),
+                (pool_assets[2].mint_address, 42),
+            ]
+        );
+
+        // Asset accounts missing or out of order
+        assert_eq!(
+            pool_composition(&pool_assets, &asset_accounts[..2], &pool_mint),
+            Err(ProgramError::InvalidArgument)
+        );
+        let mut swapped_accounts = asset_accounts.clone();
+        swapped_accounts.swap(0, 1);
+        assert_eq!(
+            pool_composition(&pool_assets, &swapped_accounts, &pool_mint),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_single_asset_deposit() {
+        // A tenth of the value of the pool backs a tenth of the supply
+        assert_eq!(
+            compute_single_asset_deposit(3_000_000, 1_000_000, 100_000),
+            Some(300_000)
+        );
+        // The pooltokens are rounded down
+        assert_eq!(
+            compute_single_asset_deposit(3_000_000, 1_000_000, 1),
+            Some(3)
+        );
+        assert_eq!(compute_single_asset_deposit(3_000_000, 0, 1), None);
+        assert_eq!(compute_single_asset_deposit(u64::MAX, 1, u64::MAX), None);
+
+        // A single asset deposit is valued as the proportional deposit of the same value: with
+        // the second asset worth 250 of the first, the pool is worth 2_000_000 of the first
+        let pool_balances = [1_000_000, 4_000];
+        let total_pooltokens = 2_000_000;
+        let (proportional_amounts, proportional_pooltokens) =
+            compute_deposit_amounts(total_pooltokens, &pool_balances, 20_000);
+        assert_eq!(proportional_amounts, vec![10_000, 40]);
+        assert_eq!(
+            compute_single_asset_deposit(total_pooltokens, 2_000_000, 10_000 + 40 * 250),
+            Some(proportional_pooltokens)
+        );
+    }
+
+    #[test]
+    fn test_metadata_uri() {
+        let uri = "https://example.com/pools/sol-usdc.json";
+        let metadata_uri = pack_metadata_uri(uri.as_bytes()).unwrap();
+        assert_eq!(unpack_metadata_uri(&metadata_uri), Ok(uri));
+        assert_eq!(unpack_metadata_uri(&[0u8; METADATA_URI_LEN]), Ok(""));
+
+        // URIs filling the whole field, including multi-byte characters
+        let uri = "é".repeat(METADATA_URI_LEN / 2);
+        let metadata_uri = pack_metadata_uri(uri.as_bytes()).unwrap();
+        assert_eq!(unpack_metadata_uri(&metadata_uri), Ok(uri.as_str()));
+
+        assert_eq!(
+            pack_metadata_uri(&[b'a'; METADATA_URI_LEN + 1]),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            pack_metadata_uri(&[b'a', 0xc3]),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            pack_metadata_uri(&[b'a', 0, b'b']),
+            Err(ProgramError::InvalidArgument)
+        );
     }
 }
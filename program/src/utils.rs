@@ -1,9 +1,109 @@
 use solana_program::{
-    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
     pubkey::Pubkey,
 };
 
-use crate::state::PoolHeader;
+use crate::{
+    error::BonfidaBotError,
+    instruction::{
+        ORDER_CHECK_MARKET, ORDER_CHECK_SERUM_PROGRAM, ORDER_CHECK_SIGNAL_PROVIDER,
+        ORDER_CHECK_SOURCE_ACCOUNT,
+    },
+    state::{
+        unpack_allowed_mints, unpack_market, unpack_pool_header, unpack_unchecked_asset, PoolAsset,
+        PoolHeader, PoolMode, PoolStatus, BONFIDA_BNB, BONFIDA_FEE, MAX_NUMBER_OF_MARKETS,
+        MAX_POOL_TOKEN_DECIMALS, MSRM_MINT, PUBKEY_LENGTH, SERUM_DEX_PROGRAM_IDS, SRM_MINT,
+    },
+};
+use serum_dex::{instruction::SelfTradeBehavior, matching::Side};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account, Mint};
+use std::{
+    cmp::{max, min},
+    convert::TryInto,
+    num::{NonZeroU16, NonZeroU64},
+    str::FromStr,
+};
+
+/// Maximum number of fee cycles collected by a single fee collection. Overdue cycles
+/// beyond this cap are left for subsequent collections.
+pub const MAX_FEE_CYCLES_PER_COLLECTION: u64 = 52;
+
+/// Maximum fee ratio of a pool as a 16-bit fixed point fraction, which is 20%.
+pub const MAX_FEE_RATIO: u16 = 13_107;
+
+/// Minimum fee collection period of a pool, which is one week.
+pub const MIN_FEE_COLLECTION_PERIOD: u64 = 604_800;
+
+/// Maximum share of a deposit fee which can be minted to a referrer, in basis points (50%).
+pub const MAX_REFERRER_FEE_BPS: u16 = 5_000;
+
+/// Maximum share of the fees which can be minted to a pool's extra fee recipient, in basis
+/// points (25%), so that the signal provider and Bonfida keep most of the fee split.
+pub const MAX_EXTRA_FEE_BPS: u16 = 2_500;
+
+/// Default shares of the fees minted to the signal provider and to the Bonfida fee account,
+/// used when a pool is created without a fee split.
+pub const DEFAULT_SIGNAL_PROVIDER_FEE_BPS: u16 = 5_000;
+pub const DEFAULT_BONFIDA_FEE_BPS: u16 = 2_500;
+
+/// Minimum share of the fees minted to the Bonfida fee account, in basis points (10%).
+pub const MIN_BONFIDA_FEE_BPS: u16 = 1_000;
+
+/// Minimum amount of pooltokens a deposit should buy, as a fraction of a whole pooltoken: 0.1
+/// pooltoken, which is 100_000 base units at the default 6 decimals. Smaller deposits would
+/// only add dust to the pooltoken supply.
+pub const MIN_DEPOSIT_POOLTOKEN_FRACTION: u64 = 10;
+
+/// Minimum amount of FIDA a pool should be created with when the `fida-minimum` feature is
+/// enabled, which is 1000 FIDA.
+pub const MIN_POOL_FIDA_AMOUNT: u64 = 1_000_000_000;
+
+/// Id of the SPL Token-2022 program, whose mints can be held as pool assets
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+/// Prefix of the log line emitted by a successful deposit, which reads
+/// `bonfida:deposit pool_tokens=<minted to the target> fee=<minted as fees>`
+pub const DEPOSIT_LOG_PREFIX: &str = "bonfida:deposit";
+
+/// Length of the return data of a successful deposit: the pooltokens minted to the target
+/// (u64) followed by the pooltokens minted as fees (u64), both little-endian
+pub const DEPOSIT_RETURN_DATA_LEN: usize = 16;
+
+/// Prefix of the log line emitted by a successful redemption, which reads
+/// `bonfida:redeem pool_tokens=<burned> amounts=<paid out asset amounts, comma separated>`
+/// with the amounts in the order of the pool assets
+pub const REDEEM_LOG_PREFIX: &str = "bonfida:redeem";
+
+/// Maximum number of orders cancelled by a single `CancelAllOrders` instruction. Each
+/// cancellation is a serum CPI, and a serum OpenOrders account holds up to 128 orders, more
+/// than one instruction can cancel within the compute budget. The orders left open are
+/// cancelled by sending the same instruction again, until it logs that none remain.
+pub const MAX_CANCELLED_ORDERS_PER_INSTRUCTION: usize = 12;
+
+// Layout of the serum OpenOrders account, after the 5 bytes of padding
+const OPEN_ORDERS_FREE_SLOT_BITS_OFFSET: usize = 109;
+const OPEN_ORDERS_IS_BID_BITS_OFFSET: usize = 125;
+const OPEN_ORDERS_ORDERS_OFFSET: usize = 141;
+const OPEN_ORDERS_SLOTS: usize = 128;
+const OPEN_ORDERS_OWNER_OFFSET: usize = 45;
+const OPEN_ORDERS_BALANCES_OFFSET: usize = 77;
+
+/// Native coin and pc amounts of a serum OpenOrders account. The free amounts can be settled,
+/// the totals also include the funds locked by the orders on the book.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpenOrdersBalances {
+    pub free_coin: u64,
+    pub total_coin: u64,
+    pub free_pc: u64,
+    pub total_pc: u64,
+}
 
 pub fn check_pool_key(program_id: &Pubkey, key: &Pubkey, pool_seed: &[u8; 32]) -> ProgramResult {
     let expected_key = Pubkey::create_program_address(&[pool_seed], program_id)?;
@@ -16,55 +116,3463 @@ pub fn check_pool_key(program_id: &Pubkey, key: &Pubkey, pool_seed: &[u8; 32]) -
     Ok(())
 }
 
-pub fn check_signal_provider(
+pub fn check_ata_program(account: &AccountInfo) -> ProgramResult {
+    if account.key != &spl_associated_token_account::id() {
+        msg!("Incorrect associated token account program provided");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+pub fn check_account_writable(account: &AccountInfo, name: &str) -> ProgramResult {
+    if !account.is_writable {
+        msg!("The {} account should be writable", name);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks upfront that an instruction whose accounts depend on the number of pool assets was
+/// given enough accounts, rather than failing once the account iterator runs dry.
+pub fn check_accounts_len(
+    accounts_len: usize,
+    expected_accounts_len: usize,
+    instruction_name: &str,
+    number_of_assets: usize,
+) -> ProgramResult {
+    if accounts_len < expected_accounts_len {
+        msg!(
+            "{} expects at least {} accounts for a pool with {} assets, {} were given",
+            instruction_name,
+            expected_accounts_len,
+            number_of_assets,
+            accounts_len
+        );
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    Ok(())
+}
+
+/// Checks that a sysvar account is the actual sysvar, so that spoofed timestamps or rent
+/// parameters are not trusted.
+pub fn check_sysvar_account(
+    account: &AccountInfo,
+    sysvar_id: &Pubkey,
+    name: &str,
+) -> ProgramResult {
+    if account.key != sysvar_id {
+        msg!("Incorrect {} sysvar account provided", name);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+pub fn check_deposit_allowed(pool_header: &PoolHeader) -> ProgramResult {
+    match pool_header.pool_mode {
+        PoolMode::RedeemOnly => {
+            msg!("The pool is in redeem-only mode. No buy-ins are possible.");
+            Err(BonfidaBotError::ModeRestricted.into())
+        }
+        PoolMode::Frozen => {
+            msg!("The pool is frozen. No buy-ins are possible.");
+            Err(BonfidaBotError::ModeRestricted.into())
+        }
+        PoolMode::Normal | PoolMode::DepositOnly => Ok(()),
+    }
+}
+
+/// Checks that the pool accepts deposits of a single asset, which move the pool ratios and are
+/// only allowed once the signal provider opted in.
+pub fn check_single_asset_deposits_allowed(pool_header: &PoolHeader) -> ProgramResult {
+    if !pool_header.single_asset_deposits {
+        msg!("The pool doesn't accept deposits of a single asset.");
+        return Err(BonfidaBotError::ModeRestricted.into());
+    }
+    Ok(())
+}
+
+/// Checks that the pool status allows for deposits, which need an unlocked pool without
+/// pending orders.
+pub fn check_deposit_status(status: &PoolStatus) -> ProgramResult {
+    match status {
+        PoolStatus::Unlocked => Ok(()),
+        PoolStatus::Locked | PoolStatus::LockedPendingOrder(_) => {
+            msg!("The signal provider has currently locked the pool. No buy-ins are possible for now.");
+            Err(BonfidaBotError::LockedOperation.into())
+        }
+        PoolStatus::PendingOrder(_) => {
+            msg!("The pool has one or more pending orders. No buy-ins are possible for now. Try again later.");
+            Err(BonfidaBotError::LockedOperation.into())
+        }
+        PoolStatus::Uninitialized => {
+            msg!("The pool has an invalid status.");
+            Err(ProgramError::InvalidAccountData)
+        }
+    }
+}
+
+pub fn check_redeem_allowed(pool_header: &PoolHeader) -> ProgramResult {
+    match pool_header.pool_mode {
+        PoolMode::DepositOnly => {
+            msg!("The pool is in deposit-only mode. No buy-outs are possible.");
+            Err(BonfidaBotError::ModeRestricted.into())
+        }
+        PoolMode::Frozen => {
+            msg!("The pool is frozen. Use EmergencyWithdrawAsset instead.");
+            Err(BonfidaBotError::ModeRestricted.into())
+        }
+        PoolMode::Normal | PoolMode::RedeemOnly => Ok(()),
+    }
+}
+
+/// Redemptions from a pool requiring the signal provider's approval need its signature
+/// until the pool reaches maturity.
+pub fn check_redeem_authorized(
     pool_header: &PoolHeader,
-    signal_provider_account: &AccountInfo,
-    is_signer: bool,
+    signal_provider_account: Option<&AccountInfo>,
+    current_timestamp: u64,
 ) -> ProgramResult {
-    if &pool_header.signal_provider != signal_provider_account.key {
+    if !pool_header.redeem_requires_provider || current_timestamp >= pool_header.maturity_timestamp
+    {
+        return Ok(());
+    }
+    match signal_provider_account {
+        Some(account) => check_signal_provider(pool_header, account),
+        None => {
+            msg!("The signal provider's signature is required to redeem before maturity.");
+            Err(ProgramError::MissingRequiredSignature)
+        }
+    }
+}
+
+pub fn check_emergency_withdraw_allowed(pool_header: &PoolHeader) -> ProgramResult {
+    if pool_header.pool_mode != PoolMode::Frozen {
+        msg!("Emergency withdrawals are only possible from a frozen pool.");
+        return Err(BonfidaBotError::ModeRestricted.into());
+    }
+    Ok(())
+}
+
+/// Returns the share of a pool asset owed for `pool_token_amount` pooltokens
+pub fn get_asset_share(
+    pool_token_amount: u64,
+    pool_asset_amount: u64,
+    total_pooltokens: u64,
+) -> Result<u64, ProgramError> {
+    if total_pooltokens == 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    (((pool_token_amount as u128) * (pool_asset_amount as u128)) / (total_pooltokens as u128))
+        .try_into()
+        .map_err(|_| BonfidaBotError::Overflow.into())
+}
+
+/// Checks that the key is the one of the pool's signal provider, without requiring a signature
+pub fn verify_signal_provider_key(pool_header: &PoolHeader, key: &Pubkey) -> ProgramResult {
+    if &pool_header.signal_provider != key {
         msg!("A wrong signal provider account was provided.");
         return Err(ProgramError::MissingRequiredSignature);
     }
-    if is_signer & !signal_provider_account.is_signer {
+    Ok(())
+}
+
+/// Checks that the account is the pool's signal provider and has signed the transaction
+pub fn check_signal_provider(
+    pool_header: &PoolHeader,
+    signal_provider_account: &AccountInfo,
+) -> ProgramResult {
+    verify_signal_provider_key(pool_header, signal_provider_account.key)?;
+    if !signal_provider_account.is_signer {
         msg!("The signal provider's signature is required.");
         return Err(ProgramError::MissingRequiredSignature);
     }
     Ok(())
 }
 
-pub fn fill_slice(target: &mut [u8], val: u8) {
-    for i in 0..target.len() {
-        target[i] = val;
+/// Checks that a pool can be closed: it should have been entirely redeemed, which resets its
+/// status, and have no pooltokens left in circulation.
+pub fn check_pool_closable(pool_header: &PoolHeader, total_pooltokens: u64) -> ProgramResult {
+    if pool_header.status != PoolStatus::Uninitialized {
+        msg!("Only pools which have been entirely redeemed can be closed.");
+        return Err(BonfidaBotError::LockedOperation.into());
+    }
+    if total_pooltokens != 0 {
+        msg!("The pool can't be closed while pooltokens are in circulation.");
+        return Err(BonfidaBotError::LockedOperation.into());
     }
+    Ok(())
 }
 
-pub fn pow_fixedpoint_u16(x: u32, n: u64) -> u32 {
-    if n == 1{
-        x
+/// Rejects orders trading fewer lots than the signal provider expected
+pub fn check_min_expected_lots(lots_to_trade: u64, min_expected_lots: u64) -> ProgramResult {
+    if lots_to_trade < min_expected_lots {
+        msg!(
+            "The order would trade {} lots, less than the expected minimum of {}",
+            lots_to_trade,
+            min_expected_lots
+        );
+        return Err(BonfidaBotError::OperationTooSmall.into());
+    }
+    Ok(())
+}
+
+/// Checks that a pool is created with at least one asset
+pub fn check_create_deposit_amounts(deposit_amounts: &[u64]) -> ProgramResult {
+    if deposit_amounts.iter().all(|amount| *amount == 0) {
+        msg!("At least one asset should be deposited to create the pool.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that a pool tracks at least one asset before depositing into it, as the pooltokens
+/// minted for a deposit into a pool without assets wouldn't be backed by anything.
+pub fn check_pool_has_assets(nb_assets: usize) -> ProgramResult {
+    if nb_assets == 0 {
+        msg!("The pool has no assets to deposit into.");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(())
+}
+
+/// Checks that a mint doesn't already occupy one of the pool asset slots
+pub fn check_new_pool_asset(pool_assets: &[PoolAsset], mint: &Pubkey) -> ProgramResult {
+    if pool_assets.iter().any(|asset| &asset.mint_address == mint) {
+        msg!("The mint {} is given for more than one pool asset.", mint);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that the source and target asset slots of an order are distinct and within the
+/// `max_number_of_assets` slots allocated for the pool.
+pub fn check_order_asset_indices(
+    source_index: usize,
+    target_index: usize,
+    max_number_of_assets: usize,
+) -> ProgramResult {
+    if source_index == target_index {
+        msg!("The source and target indices should be different");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if source_index >= max_number_of_assets || target_index >= max_number_of_assets {
+        msg!(
+            "The source and target indices should be lower than the {} pool asset slots",
+            max_number_of_assets
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that a mint isn't one of the pool assets, which can only leave the pool through
+/// redemptions.
+pub fn check_untracked_mint(pool_assets: &[PoolAsset], mint: &Pubkey) -> ProgramResult {
+    if pool_assets.iter().any(|asset| &asset.mint_address == mint) {
+        msg!("The mint {} is a pool asset and cannot be swept", mint);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Guards against settling both sides of a market into the same pool asset slot
+pub fn check_settle_indices(coin_index: Option<usize>, pc_index: Option<usize>) -> ProgramResult {
+    match (coin_index, pc_index) {
+        (None, None) => {
+            msg!("At least one of the coin and pc indices should be provided");
+            Err(ProgramError::InvalidArgument)
+        }
+        (Some(coin_index), Some(pc_index)) if coin_index == pc_index => {
+            msg!("The coin and pc indices should be different");
+            Err(ProgramError::InvalidArgument)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a side left out of a settlement has no free funds, which would otherwise be
+/// settled into the pool without being tracked as a pool asset.
+pub fn check_skipped_settle_side(
+    index: Option<usize>,
+    free_amount: u64,
+    side: &str,
+) -> ProgramResult {
+    if index.is_none() && free_amount != 0 {
+        msg!(
+            "The {} side has free funds, its index should be provided",
+            side
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+pub fn check_vault_signer(
+    dex_program_id: &Pubkey,
+    market_key: &Pubkey,
+    market_data: &[u8],
+    vault_signer_key: &Pubkey,
+) -> ProgramResult {
+    let vault_signer_nonce = market_data
+        .get(45..53)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let expected_key = Pubkey::create_program_address(
+        &[&market_key.to_bytes(), &vault_signer_nonce.to_le_bytes()],
+        dex_program_id,
+    )?;
+
+    if &expected_key != vault_signer_key {
+        msg!("Provided vault signer does not match the market's vault signer nonce");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+pub fn check_token_account(
+    token_account: &Account,
+    expected_mint: &Pubkey,
+    expected_owner: &Pubkey,
+) -> ProgramResult {
+    if &token_account.mint != expected_mint {
+        msg!("The provided token account has the wrong mint.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if &token_account.owner != expected_owner {
+        msg!("The provided token account has the wrong owner.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that the pool is still the mint authority of the pooltoken mint before minting,
+/// which guards against a substituted mint.
+pub fn check_mint_authority(pool_mint: &Mint, pool_key: &Pubkey) -> ProgramResult {
+    if pool_mint.mint_authority != COption::Some(*pool_key) {
+        msg!("The pooltoken mint authority is not the pool.");
+        return Err(BonfidaBotError::InvalidMintAuthority.into());
+    }
+    Ok(())
+}
+
+/// Checks that the discount account given to Serum on order placement is an SRM or MSRM
+/// token account of the pool, so that Serum doesn't reject the order over it.
+pub fn check_discount_account(discount_account: &Account, pool_key: &Pubkey) -> ProgramResult {
+    if discount_account.mint != Pubkey::from_str(SRM_MINT).unwrap()
+        && discount_account.mint != Pubkey::from_str(MSRM_MINT).unwrap()
+    {
+        msg!("The discount account should be an SRM or MSRM token account.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if &discount_account.owner != pool_key {
+        msg!("The discount account should be owned by the pool.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Runs the account checks performed by `process_create_order` and returns a bitmask
+/// of the passing checks.
+pub fn get_order_accounts_checks(
+    pool_data: &[u8],
+    pool_key: &Pubkey,
+    market_index: u16,
+    source_index: usize,
+    signal_provider_account: &AccountInfo,
+    market: &AccountInfo,
+    pool_asset_token_account: &AccountInfo,
+    dex_program: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let pool_header = unpack_pool_header(pool_data)?;
+    let mut checks = 0;
+
+    if market_index < pool_header.number_of_markets
+        && market.key == &unpack_market(&pool_data[PoolHeader::LEN..], market_index)
+    {
+        checks |= ORDER_CHECK_MARKET;
+    }
+
+    let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH * pool_header.number_of_markets as usize;
+    let source_asset = pool_data
+        .get(asset_offset..)
+        .ok_or(ProgramError::InvalidAccountData)
+        .and_then(|assets_data| unpack_unchecked_asset(assets_data, source_index));
+    if let (Ok(source_account), Ok(source_asset)) = (
+        Account::unpack(&pool_asset_token_account.data.borrow()),
+        source_asset,
+    ) {
+        if pool_asset_token_account.key
+            == &get_associated_token_address(pool_key, &source_account.mint)
+            && source_asset.mint_address == source_account.mint
+            && &source_account.owner == pool_key
+        {
+            checks |= ORDER_CHECK_SOURCE_ACCOUNT;
+        }
+    }
+
+    if &pool_header.serum_program_id == dex_program.key {
+        checks |= ORDER_CHECK_SERUM_PROGRAM;
+    }
+
+    if check_signal_provider(&pool_header, signal_provider_account).is_ok() {
+        checks |= ORDER_CHECK_SIGNAL_PROVIDER;
+    }
+
+    Ok(checks)
+}
+
+/// Computes the amount of source asset to trade and the matching number of lots from the
+/// current balance of the pool asset account.
+pub fn get_order_amounts(
+    pool_asset_amount: u64,
+    max_ratio_of_pool_to_sell: NonZeroU16,
+    side: Side,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+) -> Result<(u64, u64), ProgramError> {
+    let amount_to_trade: u64 =
+        (((pool_asset_amount as u128) * (max_ratio_of_pool_to_sell.get() as u128)) >> 16)
+            .try_into()
+            .map_err(|_| BonfidaBotError::Overflow)?;
+
+    let lots_to_trade = amount_to_trade
+        .checked_div(match side {
+            Side::Bid => pc_lot_size,
+            Side::Ask => coin_lot_size,
+        })
+        .ok_or(BonfidaBotError::Overflow)?;
+
+    Ok((amount_to_trade, lots_to_trade))
+}
+
+/// Returns the number of coin lots a bid resting on the book can be placed for. A resting
+/// bid locks `limit_price * pc_lot_size` of the price currency per coin lot, so the lots
+/// are bounded by the traded amount at the limit price rather than by the price lot size.
+pub fn get_resting_bid_lots(
+    amount_to_trade: u64,
+    limit_price: NonZeroU64,
+    pc_lot_size: u64,
+) -> Result<u64, ProgramError> {
+    let pc_per_coin_lot = limit_price
+        .get()
+        .checked_mul(pc_lot_size)
+        .ok_or(BonfidaBotError::Overflow)?;
+    Ok(amount_to_trade
+        .checked_div(pc_per_coin_lot)
+        .ok_or(BonfidaBotError::Overflow)?)
+}
+
+/// Offsets of the fields of a serum market account read by the program, past its 5 bytes of
+/// padding
+const MARKET_COIN_MINT_OFFSET: usize = 53;
+const MARKET_PC_MINT_OFFSET: usize = 85;
+const MARKET_BIDS_OFFSET: usize = 285;
+const MARKET_ASKS_OFFSET: usize = 317;
+const MARKET_COIN_LOT_SIZE_OFFSET: usize = 349;
+const MARKET_PC_LOT_SIZE_OFFSET: usize = 357;
+
+/// Offsets of the root node, of the number of orders and of the 72 bytes long nodes of the
+/// critbit tree of a serum order book account
+const SLAB_ROOT_NODE_OFFSET: usize = 33;
+const SLAB_LEAF_COUNT_OFFSET: usize = 37;
+const SLAB_NODES_OFFSET: usize = 45;
+const SLAB_NODE_LEN: usize = 72;
+const SLAB_INNER_NODE_TAG: u32 = 1;
+const SLAB_LEAF_NODE_TAG: u32 = 2;
+
+fn read_market_pubkey(market_data: &[u8], offset: usize) -> Result<Pubkey, ProgramError> {
+    market_data
+        .get(offset..offset + 32)
+        .map(Pubkey::new)
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ProgramError> {
+    data.get(offset..offset + 8)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ProgramError> {
+    data.get(offset..offset + 4)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+/// Checks that the order book accounts are the bids and asks of a serum market
+pub fn check_market_order_books(
+    market_data: &[u8],
+    bids_key: &Pubkey,
+    asks_key: &Pubkey,
+) -> ProgramResult {
+    if read_market_pubkey(market_data, MARKET_BIDS_OFFSET)? != *bids_key
+        || read_market_pubkey(market_data, MARKET_ASKS_OFFSET)? != *asks_key
+    {
+        msg!("The order book accounts don't belong to the market");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Returns the price of the best order of a serum order book account in price lots per coin
+/// lot: the highest bid on the bids side and the lowest ask on the asks side. Returns `None`
+/// for an empty order book.
+pub fn get_best_order_price(slab_data: &[u8], side: Side) -> Result<Option<u64>, ProgramError> {
+    if read_u64(slab_data, SLAB_LEAF_COUNT_OFFSET)? == 0 {
+        return Ok(None);
+    }
+    let mut node = read_u32(slab_data, SLAB_ROOT_NODE_OFFSET)? as usize;
+    // The tree is at most as deep as its number of nodes
+    for _ in 0..slab_data.len() / SLAB_NODE_LEN {
+        let node_offset = SLAB_NODES_OFFSET + node * SLAB_NODE_LEN;
+        match read_u32(slab_data, node_offset)? {
+            SLAB_INNER_NODE_TAG => {
+                let child_offset = match side {
+                    Side::Bid => node_offset + 28,
+                    Side::Ask => node_offset + 24,
+                };
+                node = read_u32(slab_data, child_offset)? as usize;
+            }
+            // The price makes up the upper half of the 128 bits key of an order
+            SLAB_LEAF_NODE_TAG => return read_u64(slab_data, node_offset + 16).map(Some),
+            _ => break,
+        }
+    }
+    msg!("The order book account is corrupted");
+    Err(ProgramError::InvalidAccountData)
+}
+
+/// Returns the value of `amount` of the asset of `mint` in the asset of `quote_mint`, at the
+/// best orders of a serum market trading one for the other. The value errs on the high side: the
+/// asset is valued at the higher of the best bid and ask when it is the coin of the market, and
+/// at the lower of them when the quote asset is the coin. Both sides of the book need orders,
+/// so that a single order can't set the price: an order placed across the book would have
+/// matched the best order of the opposite side.
+pub fn get_market_value(
+    market_data: &[u8],
+    bids_data: &[u8],
+    asks_data: &[u8],
+    mint: &Pubkey,
+    quote_mint: &Pubkey,
+    amount: u64,
+) -> Result<u128, ProgramError> {
+    let coin_mint = read_market_pubkey(market_data, MARKET_COIN_MINT_OFFSET)?;
+    let pc_mint = read_market_pubkey(market_data, MARKET_PC_MINT_OFFSET)?;
+    let coin_lot_size = read_u64(market_data, MARKET_COIN_LOT_SIZE_OFFSET)? as u128;
+    let pc_lot_size = read_u64(market_data, MARKET_PC_LOT_SIZE_OFFSET)? as u128;
+    let side = if (coin_mint, pc_mint) == (*mint, *quote_mint) {
+        Side::Ask
+    } else if (coin_mint, pc_mint) == (*quote_mint, *mint) {
+        Side::Bid
     } else {
-        let q = n >> 1;
-        if q == 0 {
-            return x
+        msg!("The market doesn't trade {} for {}", mint, quote_mint);
+        return Err(ProgramError::InvalidArgument);
+    };
+    let (best_bid, best_ask) = match (
+        get_best_order_price(bids_data, Side::Bid)?,
+        get_best_order_price(asks_data, Side::Ask)?,
+    ) {
+        (Some(best_bid), Some(best_ask)) => (best_bid, best_ask),
+        _ => {
+            msg!(
+                "The market should have orders on both sides to value {} in {}",
+                mint,
+                quote_mint
+            );
+            return Err(ProgramError::InvalidArgument);
         }
-        let p = pow_fixedpoint_u16(x, n >> 1);
-        let sq = (p * p) >> 16;
-        if n & 1 == 1 {
-            (sq * x) >> 16
-        } else {
-            sq
+    };
+    let price = match side {
+        Side::Ask => max(best_bid, best_ask),
+        Side::Bid => min(best_bid, best_ask),
+    } as u128;
+    // Prices are in price lots per coin lot
+    let (numerator, denominator) = match side {
+        Side::Ask => (price * pc_lot_size, coin_lot_size),
+        Side::Bid => (coin_lot_size, price * pc_lot_size),
+    };
+    if denominator == 0 {
+        msg!("The market has a zero lot size");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let numerator = (amount as u128)
+        .checked_mul(numerator)
+        .ok_or(BonfidaBotError::Overflow)?;
+    Ok(numerator / denominator + (numerator % denominator != 0) as u128)
+}
+
+/// Checks the self trade behavior of a new order. Orders matching a resting order of the
+/// pool can either decrement the taking order or cancel the resting one, both of which let
+/// the order go through. `AbortTransaction` is rejected as it fails every crossing order
+/// while the pool has orders resting on the book, leaving the pool pending until they are
+/// cancelled.
+pub fn check_self_trade_behavior(self_trade_behavior: SelfTradeBehavior) -> ProgramResult {
+    match self_trade_behavior {
+        SelfTradeBehavior::DecrementTake | SelfTradeBehavior::CancelProvide => Ok(()),
+        SelfTradeBehavior::AbortTransaction => {
+            msg!("Orders aborting on self trade are not allowed, use DecrementTake or CancelProvide.");
+            Err(ProgramError::InvalidArgument)
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::pow_fixedpoint_u16;
+/// Returns the amount of the pool asset an order computed by `get_order_amounts` can lock: the
+/// maximum amount of the price currency including fees for a bid, and the coin lots for an ask.
+pub fn get_order_required_amount(
+    side: Side,
+    amount_to_trade: u64,
+    lots_to_trade: u64,
+    coin_lot_size: u64,
+) -> Result<u64, ProgramError> {
+    match side {
+        Side::Bid => Ok(amount_to_trade),
+        Side::Ask => Ok(lots_to_trade
+            .checked_mul(coin_lot_size)
+            .ok_or(BonfidaBotError::Overflow)?),
+    }
+}
 
-    #[test]
-    fn test_exp(){
-        let half:u16 = 1<<15;
-        for i in 1..16 {
-            assert_eq!(pow_fixedpoint_u16(half as u32, i), 1<<(16 - i));
+/// Checks the pool asset balance read back once the dex placed an order against the balance
+/// the order was computed from: the order covers what the dex debited only if no more than
+/// its required amount left the pool asset account.
+pub fn check_order_covered(
+    pool_asset_amount_before: u64,
+    pool_asset_amount_after: u64,
+    required_amount: u64,
+) -> ProgramResult {
+    let debited_amount = pool_asset_amount_before.saturating_sub(pool_asset_amount_after);
+    if debited_amount > required_amount {
+        msg!(
+            "The order debited {} from the pool asset account, more than the {} it required.",
+            debited_amount,
+            required_amount
+        );
+        return Err(BonfidaBotError::InsufficientAssetBalance.into());
+    }
+    Ok(())
+}
+
+/// Returns whether all the funds of an open orders account are free, meaning that it has no
+/// order left on the book and that settling it releases its pending order.
+pub fn is_order_fully_freed(free_coin: u64, total_coin: u64, free_pc: u64, total_pc: u64) -> bool {
+    free_coin == total_coin && free_pc == total_pc
+}
+
+/// Returns the status of a pool once its lock is toggled, keeping the number of pending orders.
+pub fn get_toggled_lock_status(status: &PoolStatus) -> Result<PoolStatus, ProgramError> {
+    match status {
+        PoolStatus::Uninitialized => Err(ProgramError::UninitializedAccount),
+        PoolStatus::Unlocked => Ok(PoolStatus::Locked),
+        PoolStatus::Locked => Ok(PoolStatus::Unlocked),
+        PoolStatus::PendingOrder(n) => Ok(PoolStatus::LockedPendingOrder(*n)),
+        PoolStatus::LockedPendingOrder(n) => Ok(PoolStatus::PendingOrder(*n)),
+    }
+}
+
+/// Checks a newly tracked target mint against the allow-list of a pool. Pools which never
+/// created their allowed mints account can trade into any mint.
+pub fn check_target_mint_allowed(allowed_mints_data: &[u8], target_mint: &Pubkey) -> ProgramResult {
+    if allowed_mints_data.is_empty() {
+        return Ok(());
+    }
+    if !unpack_allowed_mints(allowed_mints_data)?.contains(target_mint) {
+        msg!(
+            "The target mint {} is not allowed for this pool.",
+            target_mint
+        );
+        return Err(BonfidaBotError::MintNotAllowed.into());
+    }
+    Ok(())
+}
+
+/// Checks that the pool holds none of an asset. A closed pool asset account holds none either.
+pub fn check_pool_asset_empty(
+    pool_key: &Pubkey,
+    pool_asset: &PoolAsset,
+    pool_asset_account: &AccountInfo,
+) -> ProgramResult {
+    if get_associated_token_address(pool_key, &pool_asset.mint_address) != *pool_asset_account.key {
+        msg!("Provided pool asset account is invalid");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if pool_asset_account.data_is_empty() {
+        return Ok(());
+    }
+    let amount = unpack_token_account_data(&pool_asset_account.data.borrow())?.amount;
+    if amount != 0 {
+        msg!(
+            "The pool still holds {} of the asset {}. Redeem into all the assets of the pool instead.",
+            amount,
+            pool_asset.mint_address
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that a mint about to be written into an empty asset slot isn't tracked by another slot
+/// already, as a pool holding the same asset twice could no longer be unpacked.
+pub fn check_asset_untracked(assets_data: &[u8], mint: &Pubkey) -> ProgramResult {
+    let tracked = assets_data
+        .chunks_exact(PoolAsset::LEN)
+        .any(|slot| slot == mint.as_ref());
+    if tracked {
+        msg!(
+            "The pool already tracks the mint {}, use its asset index instead.",
+            mint
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+pub fn format_deposit_log(pool_token_amount: u64, pool_token_fee: u64) -> String {
+    format!(
+        "{} pool_tokens={} fee={}",
+        DEPOSIT_LOG_PREFIX, pool_token_amount, pool_token_fee
+    )
+}
+
+pub fn pack_deposit_return_data(
+    pool_token_amount: u64,
+    pool_token_fee: u64,
+) -> [u8; DEPOSIT_RETURN_DATA_LEN] {
+    let mut data = [0u8; DEPOSIT_RETURN_DATA_LEN];
+    data[..8].copy_from_slice(&pool_token_amount.to_le_bytes());
+    data[8..].copy_from_slice(&pool_token_fee.to_le_bytes());
+    data
+}
+
+pub fn format_redeem_log(pool_token_amount: u64, amounts: &[u64]) -> String {
+    let amounts: Vec<String> = amounts.iter().map(|amount| amount.to_string()).collect();
+    format!(
+        "{} pool_tokens={} amounts={}",
+        REDEEM_LOG_PREFIX,
+        pool_token_amount,
+        amounts.join(",")
+    )
+}
+
+fn read_u128(data: &[u8], offset: usize) -> Result<u128, ProgramError> {
+    data.get(offset..offset + 16)
+        .and_then(|slice| slice.try_into().ok())
+        .map(u128::from_le_bytes)
+        .ok_or(ProgramError::InvalidAccountData)
+}
+
+/// Reads the free and total amounts of a serum OpenOrders account
+pub fn read_open_orders_balances(
+    openorders_data: &[u8],
+) -> Result<OpenOrdersBalances, ProgramError> {
+    let balances = openorders_data
+        .get(OPEN_ORDERS_BALANCES_OFFSET..OPEN_ORDERS_BALANCES_OFFSET + 32)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let read_u64 = |i: usize| u64::from_le_bytes(balances[8 * i..8 * (i + 1)].try_into().unwrap());
+    Ok(OpenOrdersBalances {
+        free_coin: read_u64(0),
+        total_coin: read_u64(1),
+        free_pc: read_u64(2),
+        total_pc: read_u64(3),
+    })
+}
+
+/// Checks that a serum OpenOrders account is owned by the pool
+pub fn check_open_orders_owner(openorders_data: &[u8], pool_key: &Pubkey) -> ProgramResult {
+    let owner = openorders_data
+        .get(OPEN_ORDERS_OWNER_OFFSET..OPEN_ORDERS_OWNER_OFFSET + 32)
+        .map(Pubkey::new)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    if &owner != pool_key {
+        msg!("The OpenOrders account should be owned by the pool");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Returns the side and id of the live orders of a serum OpenOrders account, in slot order
+pub fn get_open_orders(openorders_data: &[u8]) -> Result<Vec<(Side, u128)>, ProgramError> {
+    let free_slot_bits = read_u128(openorders_data, OPEN_ORDERS_FREE_SLOT_BITS_OFFSET)?;
+    let is_bid_bits = read_u128(openorders_data, OPEN_ORDERS_IS_BID_BITS_OFFSET)?;
+    let mut orders = vec![];
+    for slot in 0..OPEN_ORDERS_SLOTS {
+        if free_slot_bits & (1 << slot) != 0 {
+            continue;
         }
+        let side = if is_bid_bits & (1 << slot) != 0 {
+            Side::Bid
+        } else {
+            Side::Ask
+        };
+        let order_id = read_u128(openorders_data, OPEN_ORDERS_ORDERS_OFFSET + 16 * slot)?;
+        orders.push((side, order_id));
+    }
+    Ok(orders)
+}
+
+/// Returns the context logged when a serum instruction fails for a pool.
+pub fn serum_error_context(operation: &str, pool_seed: &[u8; 32], market: &Pubkey) -> String {
+    format!(
+        "Serum {} failed for pool seed {} on market {}",
+        operation,
+        Pubkey::new(pool_seed),
+        market
+    )
+}
+
+/// Logs the pool and market concerned by a failed serum CPI. The original error is
+/// returned untouched so that clients still see the serum error code.
+pub fn check_serum_result(
+    result: ProgramResult,
+    operation: &str,
+    pool_seed: &[u8; 32],
+    market: &Pubkey,
+) -> ProgramResult {
+    result.map_err(|e| {
+        msg!("{}", serum_error_context(operation, pool_seed, market));
+        e
+    })
+}
+
+/// Unpacks a pool asset token account, reporting which asset slot is broken if the
+/// account has been closed or was never initialized.
+pub fn unpack_pool_asset_account(
+    pool_asset_account: &AccountInfo,
+    asset_index: usize,
+) -> Result<Account, ProgramError> {
+    if pool_asset_account.data_is_empty() || !is_token_program(pool_asset_account.owner) {
+        msg!(
+            "The pool asset account for asset {} is closed. Recreate it with RecreateAssetAccount.",
+            asset_index
+        );
+        return Err(BonfidaBotError::ClosedAssetAccount.into());
+    }
+    unpack_token_account_data(&pool_asset_account.data.borrow()).map_err(|_| {
+        msg!(
+            "The pool asset account for asset {} is uninitialized. Recreate it with RecreateAssetAccount.",
+            asset_index
+        );
+        BonfidaBotError::ClosedAssetAccount.into()
+    })
+}
+
+/// Returns whether the key is the one of the legacy SPL Token program or of Token-2022
+pub fn is_token_program(key: &Pubkey) -> bool {
+    key == &spl_token::id() || key == &Pubkey::from_str(TOKEN_2022_PROGRAM_ID).unwrap()
+}
+
+/// Finds the account of a token program among the instruction accounts. Clients append the
+/// Token-2022 program account when a transfer involves Token-2022 assets.
+pub fn get_token_program<'a, 'b>(
+    accounts: &'a [AccountInfo<'b>],
+    token_program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'b>, ProgramError> {
+    accounts
+        .iter()
+        .find(|account| account.key == token_program_id)
+        .ok_or_else(|| {
+            msg!(
+                "The token program {} account should be provided",
+                token_program_id
+            );
+            ProgramError::NotEnoughAccountKeys
+        })
+}
+
+/// Derives the associated token account of the pool for an asset held under the given token
+/// program
+pub fn get_pool_asset_address(
+    pool_key: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Pubkey {
+    if token_program_id == &spl_token::id() {
+        return get_associated_token_address(pool_key, mint);
+    }
+    Pubkey::find_program_address(
+        &[
+            &pool_key.to_bytes(),
+            &token_program_id.to_bytes(),
+            &mint.to_bytes(),
+        ],
+        &spl_associated_token_account::id(),
+    )
+    .0
+}
+
+/// Unpacks the base state of a token account, ignoring the extensions of Token-2022 accounts
+pub fn unpack_token_account_data(data: &[u8]) -> Result<Account, ProgramError> {
+    Account::unpack(
+        data.get(..Account::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?,
+    )
+}
+
+/// Returns the decimals of a mint, ignoring the extensions of Token-2022 mints
+pub fn unpack_mint_decimals(data: &[u8]) -> Result<u8, ProgramError> {
+    Ok(Mint::unpack(
+        data.get(..Mint::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?,
+    )?
+    .decimals)
+}
+
+/// Creates a Token-2022 `TransferChecked` instruction. Unlike `Transfer`, it is accepted for
+/// mints charging transfer fees.
+pub fn transfer_checked_instruction(
+    token_program_id: &Pubkey,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Instruction {
+    let mut data = Vec::with_capacity(10);
+    data.push(12);
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+    Instruction {
+        program_id: *token_program_id,
+        accounts: vec![
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data,
+    }
+}
+
+pub fn fill_slice(target: &mut [u8], val: u8) {
+    for i in 0..target.len() {
+        target[i] = val;
+    }
+}
+
+pub fn pow_fixedpoint_u16(x: u32, n: u64) -> u32 {
+    if n == 1 {
+        x
+    } else {
+        let q = n >> 1;
+        if q == 0 {
+            return x;
+        }
+        let p = pow_fixedpoint_u16(x, n >> 1) as u64;
+        // The products are computed on 64 bits as they can exceed 2^32 for x close to 1
+        let sq = (p * p) >> 16;
+        if n & 1 == 1 {
+            ((sq * x as u64) >> 16) as u32
+        } else {
+            sq as u32
+        }
+    }
+}
+
+/// Returns the number of fee cycles to collect at once along with the corresponding
+/// compounded feeless ratio (as a 16-bit fixed point fraction). The number of cycles is
+/// capped by `MAX_FEE_CYCLES_PER_COLLECTION` and further reduced until the feeless ratio
+/// stays representable (nonzero), so that the fee computation never divides by zero.
+pub fn get_feeless_ratio(
+    fee_ratio: u16,
+    fee_cycles_to_collect: u64,
+) -> Result<(u64, u16), ProgramError> {
+    let mut fee_cycles = min(fee_cycles_to_collect, MAX_FEE_CYCLES_PER_COLLECTION);
+    loop {
+        let feeless_ratio = pow_fixedpoint_u16(!fee_ratio as u32, fee_cycles) as u16;
+        if feeless_ratio != 0 {
+            return Ok((fee_cycles, feeless_ratio));
+        }
+        if fee_cycles <= 1 {
+            msg!("The fee ratio is too high for fees to be collected");
+            return Err(BonfidaBotError::Overflow.into());
+        }
+        fee_cycles /= 2;
+    }
+}
+
+/// Returns the amount of pooltokens to mint so that the fees make up the share
+/// `1 - feeless_ratio` of the new supply. The amount is not saturated: an amount which does
+/// not fit in a u64, after many overdue cycles at a high fee ratio, is an overflow error,
+/// as is a feeless ratio which collapsed to zero.
+pub fn get_fee_pooltokens(total_pooltokens: u64, feeless_ratio: u16) -> Result<u64, ProgramError> {
+    if feeless_ratio == 0 {
+        msg!("The feeless ratio collapsed to zero");
+        return Err(BonfidaBotError::Overflow.into());
+    }
+    let collect_ratio = (!feeless_ratio) as u128;
+    (collect_ratio * total_pooltokens as u128 / feeless_ratio as u128)
+        .try_into()
+        .map_err(|_| BonfidaBotError::Overflow.into())
+}
+
+/// Returns the number of full fee periods elapsed since the last fee collection. A last
+/// collection timestamp ahead of the clock, after a clock rewind, counts as no cycle.
+/// The fee collection period should have been checked to be nonzero.
+pub fn get_elapsed_fee_cycles(pool_header: &PoolHeader, current_timestamp: u64) -> u64 {
+    current_timestamp.saturating_sub(pool_header.last_fee_collection_timestamp)
+        / pool_header.fee_collection_period
+}
+
+/// Returns the amount of pooltokens a fee collection would currently mint, at most
+/// `MAX_FEE_CYCLES_PER_COLLECTION` cycles for each fee ratio. Cycles following a pending fee
+/// ratio change are counted at the new fee ratio.
+pub fn get_owed_fee_pooltokens(
+    pool_header: &PoolHeader,
+    total_pooltokens: u64,
+    current_timestamp: u64,
+) -> Result<u64, ProgramError> {
+    check_fee_collection_period(pool_header)?;
+    let fee_cycles = get_elapsed_fee_cycles(pool_header, current_timestamp);
+    let cycles_at_fee_ratio = if pool_header.pending_fee_ratio_timestamp == 0 {
+        fee_cycles
+    } else {
+        min(
+            fee_cycles,
+            pool_header
+                .pending_fee_ratio_timestamp
+                .saturating_sub(pool_header.last_fee_collection_timestamp)
+                / pool_header.fee_collection_period,
+        )
+    };
+    let mut supply = total_pooltokens as u128;
+    for (fee_ratio, cycles) in [
+        (pool_header.fee_ratio, cycles_at_fee_ratio),
+        (
+            pool_header.pending_fee_ratio,
+            fee_cycles - cycles_at_fee_ratio,
+        ),
+    ]
+    .iter()
+    {
+        if *cycles == 0 {
+            continue;
+        }
+        let (_, feeless_ratio) = get_feeless_ratio(*fee_ratio, *cycles)?;
+        supply += ((!feeless_ratio) as u128) * supply / (feeless_ratio as u128);
+    }
+    (supply - total_pooltokens as u128)
+        .try_into()
+        .map_err(|_| BonfidaBotError::Overflow.into())
+}
+
+/// Returns the minimum amount of pooltokens a deposit should buy, in base units of a pooltoken
+/// mint with `decimals` decimals. Pooltokens without decimals can't be split, so any whole
+/// pooltoken is enough.
+pub fn get_min_deposit_pooltokens(decimals: u8) -> u64 {
+    max(
+        10u64.pow(decimals as u32) / MIN_DEPOSIT_POOLTOKEN_FRACTION,
+        1,
+    )
+}
+
+pub fn check_min_deposit(pool_token_effective_amount: u64, decimals: u8) -> ProgramResult {
+    let min_deposit_pooltokens = get_min_deposit_pooltokens(decimals);
+    if pool_token_effective_amount < min_deposit_pooltokens {
+        msg!(
+            "The deposit of {} pooltokens is below the minimum of {}.",
+            pool_token_effective_amount,
+            min_deposit_pooltokens
+        );
+        return Err(BonfidaBotError::OperationTooSmall.into());
+    }
+    Ok(())
+}
+
+/// Computes the deposit fee taken out of `pool_token_effective_amount` with the 16-bit fixed
+/// point `deposit_fee_ratio`. Returns the (target, fee) amounts. The fee is rounded down, so
+/// the target always receives pooltokens, even at the highest fee ratio.
+pub fn compute_deposit_fee(
+    deposit_fee_ratio: u16,
+    pool_token_effective_amount: u64,
+) -> Result<(u64, u64), ProgramError> {
+    let pool_token_fee =
+        ((deposit_fee_ratio as u128 * pool_token_effective_amount as u128) >> 16) as u64;
+    let pool_token_amount_after_fee = pool_token_effective_amount
+        .checked_sub(pool_token_fee)
+        .ok_or(BonfidaBotError::Overflow)?;
+    if pool_token_amount_after_fee == 0 {
+        msg!("The deposit is too small to mint any pooltokens to the target");
+        return Err(BonfidaBotError::OperationTooSmall.into());
+    }
+    Ok((pool_token_amount_after_fee, pool_token_fee))
+}
+
+/// Splits minted pooltoken fees between the signal provider (half), the Bonfida fee account
+/// (a quarter) and the Bonfida buy and burn account. Both shares are rounded down, the buy and
+/// burn account receives the remainder so that the whole fee is always minted.
+/// Returns the (signal provider, Bonfida fee, Bonfida buy and burn) amounts.
+pub fn split_fee(pool_token_fee: u64) -> (u64, u64, u64) {
+    split_fee_bps(
+        pool_token_fee,
+        DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+        DEFAULT_BONFIDA_FEE_BPS,
+    )
+}
+
+/// Splits minted pooltoken fees like `split_fee` with the given signal provider and Bonfida fee
+/// account shares in basis points, which must not exceed 10000 together.
+pub fn split_fee_bps(
+    pool_token_fee: u64,
+    signal_provider_fee_bps: u16,
+    bonfida_fee_bps: u16,
+) -> (u64, u64, u64) {
+    let signal_provider_fee =
+        (pool_token_fee as u128 * signal_provider_fee_bps as u128 / 10_000) as u64;
+    let bonfida_fee = (pool_token_fee as u128 * bonfida_fee_bps as u128 / 10_000) as u64;
+    (
+        signal_provider_fee,
+        bonfida_fee,
+        pool_token_fee - signal_provider_fee - bonfida_fee,
+    )
+}
+
+pub fn check_fee_split_bps(signal_provider_fee_bps: u16, bonfida_fee_bps: u16) -> ProgramResult {
+    if bonfida_fee_bps < MIN_BONFIDA_FEE_BPS {
+        msg!(
+            "The Bonfida fee share of {} bps is under the minimum of {} bps.",
+            bonfida_fee_bps,
+            MIN_BONFIDA_FEE_BPS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    if signal_provider_fee_bps as u32 + bonfida_fee_bps as u32 > 10_000 {
+        msg!(
+            "The signal provider and Bonfida fee shares of {} and {} bps exceed 10000 bps.",
+            signal_provider_fee_bps,
+            bonfida_fee_bps
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that a pool can move to a new serum program id, which requires it to have no
+/// pending order on the current one.
+pub fn check_dex_program_update(
+    pool_header: &PoolHeader,
+    new_program_id: &Pubkey,
+) -> ProgramResult {
+    if pool_header.status.has_pending_orders() {
+        msg!("The pool has pending orders. Settle them before updating the dex program.");
+        return Err(BonfidaBotError::LockedOperation.into());
+    }
+    if new_program_id == &pool_header.serum_program_id
+        || !SERUM_DEX_PROGRAM_IDS
+            .iter()
+            .any(|id| &Pubkey::from_str(id).unwrap() == new_program_id)
+    {
+        msg!("The new dex program id is invalid.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks the number of markets a pool is initialized for, which sizes its markets region
+pub fn check_number_of_markets(number_of_markets: usize) -> ProgramResult {
+    if number_of_markets == 0 || number_of_markets > MAX_NUMBER_OF_MARKETS {
+        msg!(
+            "The number of markets {} should be between 1 and {}.",
+            number_of_markets,
+            MAX_NUMBER_OF_MARKETS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that the markets and assets of a new pool fit the pool account allocated by `Init`
+pub fn check_pool_layout_fits(
+    pool_data_len: usize,
+    number_of_markets: usize,
+    number_of_assets: usize,
+) -> ProgramResult {
+    let required_len =
+        PoolHeader::LEN + PUBKEY_LENGTH * number_of_markets + PoolAsset::LEN * number_of_assets;
+    if required_len > pool_data_len {
+        msg!(
+            "{} markets and {} assets don't fit the pool account, which was initialized for \
+             fewer markets or assets.",
+            number_of_markets,
+            number_of_assets
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+pub fn check_pool_token_decimals(decimals: u8) -> ProgramResult {
+    if decimals > MAX_POOL_TOKEN_DECIMALS {
+        msg!(
+            "The pooltoken decimals {} exceed the maximum of {}.",
+            decimals,
+            MAX_POOL_TOKEN_DECIMALS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that minting the pooltokens of a deposit, fees included, keeps the pooltoken supply
+/// within the pool's cap. A zero cap means no cap.
+pub fn check_deposit_cap(
+    pool_header: &PoolHeader,
+    total_pooltokens: u64,
+    minted_pooltokens: u64,
+) -> ProgramResult {
+    if pool_header.max_pooltoken_supply == 0 {
+        return Ok(());
+    }
+    let new_supply = total_pooltokens
+        .checked_add(minted_pooltokens)
+        .ok_or(BonfidaBotError::Overflow)?;
+    if new_supply > pool_header.max_pooltoken_supply {
+        msg!(
+            "The deposit would bring the pooltoken supply to {}, above the cap of {}.",
+            new_supply,
+            pool_header.max_pooltoken_supply
+        );
+        return Err(BonfidaBotError::DepositCapReached.into());
+    }
+    Ok(())
+}
+
+pub fn check_fida_minimum(fida_amount: u64) -> ProgramResult {
+    if fida_amount < MIN_POOL_FIDA_AMOUNT {
+        msg!(
+            "The pool should hold at least {} FIDA, {} provided.",
+            MIN_POOL_FIDA_AMOUNT,
+            fida_amount
+        );
+        return Err(BonfidaBotError::NotEnoughFIDA.into());
+    }
+    Ok(())
+}
+
+/// Carves the referrer's share, given in basis points, out of a deposit fee.
+/// Returns the (referrer, remaining) amounts, the remainder going through `split_fee`.
+pub fn split_referrer_fee(pool_token_fee: u64, referrer_fee_bps: u16) -> (u64, u64) {
+    let referrer_fee = ((pool_token_fee as u128 * referrer_fee_bps as u128) / 10_000) as u64;
+    (referrer_fee, pool_token_fee - referrer_fee)
+}
+
+/// Checks the pooltoken account rewarded as the referrer of a deposit: it can't be the target
+/// account of the deposit or belong to the source owner, who would otherwise pay part of the
+/// deposit fee back to themselves.
+pub fn check_referrer_pt_account(
+    referrer_pt_account: &AccountInfo,
+    target_pool_token_account: &AccountInfo,
+    source_owner_key: &Pubkey,
+) -> ProgramResult {
+    check_account_writable(referrer_pt_account, "referrer pooltoken")?;
+    if referrer_pt_account.key == target_pool_token_account.key {
+        msg!("The referrer pooltoken account can't be the target of the deposit.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let referrer_pt = Account::unpack(&referrer_pt_account.data.borrow())?;
+    if &referrer_pt.owner == source_owner_key {
+        msg!("The source owner can't be the referrer of their own deposit.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+pub fn check_referrer_fee_bps(referrer_fee_bps: u16) -> ProgramResult {
+    if referrer_fee_bps > MAX_REFERRER_FEE_BPS {
+        msg!(
+            "The referrer fee of {} bps exceeds the maximum of {} bps.",
+            referrer_fee_bps,
+            MAX_REFERRER_FEE_BPS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Carves the extra fee recipient's share, given in basis points, out of a deposit or
+/// collected fee. Returns the (extra recipient, remaining) amounts.
+pub fn split_extra_fee(pool_token_fee: u64, extra_fee_bps: u16) -> (u64, u64) {
+    let extra_fee = ((pool_token_fee as u128 * extra_fee_bps as u128) / 10_000) as u64;
+    (extra_fee, pool_token_fee - extra_fee)
+}
+
+/// Checks the extra fee recipient settings of a pool. A nonzero share needs a recipient.
+pub fn check_extra_fee_recipient(recipient: &Pubkey, extra_fee_bps: u16) -> ProgramResult {
+    if extra_fee_bps > MAX_EXTRA_FEE_BPS {
+        msg!(
+            "The extra fee of {} bps exceeds the maximum of {} bps.",
+            extra_fee_bps,
+            MAX_EXTRA_FEE_BPS
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    if extra_fee_bps != 0 && *recipient == Pubkey::default() {
+        msg!("An extra fee share needs an extra fee recipient.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that the accounts receiving the fee split are the pooltoken associated token accounts
+/// of the pool's signal provider and of the Bonfida fee and buy and burn accounts, which the
+/// fees are then minted to.
+pub fn check_fee_pt_accounts(
+    pool_header: &PoolHeader,
+    pool_mint_key: &Pubkey,
+    signal_provider_pt_key: &Pubkey,
+    bonfida_fee_pt_key: &Pubkey,
+    bonfida_bnb_pt_key: &Pubkey,
+) -> ProgramResult {
+    if signal_provider_pt_key
+        != &get_associated_token_address(&pool_header.signal_provider, pool_mint_key)
+    {
+        msg!("The provided signal provider pool token account is invalid.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if bonfida_fee_pt_key
+        != &get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), pool_mint_key)
+    {
+        msg!("The provided bonfida fee pool token account is invalid.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if bonfida_bnb_pt_key
+        != &get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), pool_mint_key)
+    {
+        msg!("The provided bonfida buy and burn pool token account is invalid.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Checks that the account receiving the extra share of the fees is the pooltoken associated
+/// token account of the pool's extra fee recipient.
+pub fn check_extra_fee_pt_account(
+    pool_header: &PoolHeader,
+    pool_mint_key: &Pubkey,
+    extra_fee_pt_account: &AccountInfo,
+) -> ProgramResult {
+    let extra_fee_pt_key =
+        get_associated_token_address(&pool_header.extra_fee_recipient, pool_mint_key);
+    if extra_fee_pt_account.key != &extra_fee_pt_key {
+        msg!("The provided extra fee recipient pool token account is invalid.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    check_account_writable(extra_fee_pt_account, "extra fee recipient pooltoken")
+}
+
+/// Splits settled referral rebates between the pool, which retains a share given by
+/// `rebate_retention_ratio` (16-bit fixed point), and the signal provider.
+/// Returns the (retained, signal provider) amounts.
+pub fn split_rebate(rebate: u64, rebate_retention_ratio: u16) -> (u64, u64) {
+    let retained = (((rebate as u128) * (rebate_retention_ratio as u128)) >> 16) as u64;
+    (retained, rebate - retained)
+}
+
+pub fn check_fee_ratio(fee_ratio: u16) -> ProgramResult {
+    if fee_ratio > MAX_FEE_RATIO {
+        msg!(
+            "The fee ratio {} exceeds the maximum of {} (20%).",
+            fee_ratio,
+            MAX_FEE_RATIO
+        );
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Guards the fee computations against a zero fee collection period, which `Create` forbids
+/// but a malformed header could still hold.
+pub fn check_fee_collection_period(pool_header: &PoolHeader) -> ProgramResult {
+    if pool_header.fee_collection_period == 0 {
+        msg!("The pool has a zero fee collection period.");
+        return Err(BonfidaBotError::Overflow.into());
+    }
+    Ok(())
+}
+
+/// Returns the start of the fee collection period following the one accruing at `current_timestamp`
+pub fn get_next_fee_period_timestamp(pool_header: &PoolHeader, current_timestamp: u64) -> u64 {
+    let elapsed_periods = current_timestamp
+        .saturating_sub(pool_header.last_fee_collection_timestamp)
+        / pool_header.fee_collection_period;
+    pool_header.last_fee_collection_timestamp
+        + (elapsed_periods + 1) * pool_header.fee_collection_period
+}
+
+/// Switches the pool to a new fee collection period. Every complete cycle must have been
+/// collected beforehand. The cycle in progress keeps its start, so that the time it accrued
+/// under the previous period is still charged once it completes under the new one. A pending
+/// fee ratio change is moved to the end of that cycle.
+pub fn update_fee_collection_period(
+    pool_header: &mut PoolHeader,
+    new_period: u64,
+    current_timestamp: u64,
+) -> ProgramResult {
+    if new_period < MIN_FEE_COLLECTION_PERIOD {
+        msg!("Fee collection period should be longer than a week.");
+        return Err(ProgramError::InvalidArgument);
+    }
+    check_fee_collection_period(pool_header)?;
+    if current_timestamp.saturating_sub(pool_header.last_fee_collection_timestamp)
+        >= pool_header.fee_collection_period
+    {
+        msg!("Fees should be collected before changing the fee collection period.");
+        return Err(BonfidaBotError::LockedOperation.into());
+    }
+    pool_header.fee_collection_period = new_period;
+    if pool_header.pending_fee_ratio_timestamp != 0 {
+        pool_header.pending_fee_ratio_timestamp =
+            get_next_fee_period_timestamp(pool_header, current_timestamp);
+    }
+    Ok(())
+}
+
+/// Returns the amount of pooltokens a fee collection at `current_timestamp` mints, and moves
+/// the last fee collection timestamp of the pool header past the collected cycles. Taking the
+/// timestamp rather than the clock sysvar lets the fee computation be checked directly.
+pub fn collect_fees_at(
+    pool_header: &mut PoolHeader,
+    total_pooltokens: u64,
+    current_timestamp: u64,
+) -> Result<u64, ProgramError> {
+    let fee_cycles_to_collect = get_elapsed_fee_cycles(pool_header, current_timestamp);
+
+    if fee_cycles_to_collect == 0 {
+        msg!("There are currently no fees to collect");
+        return Err(BonfidaBotError::LockedOperation.into());
+    }
+
+    // Fee ratio changes only apply from the period following the change
+    let fee_cycles_to_collect = apply_pending_fee_ratio(pool_header, fee_cycles_to_collect);
+
+    // 2**-16 = 1.52587890625e-5_f32
+    // let feeless_ratio_u16 = (((!pool_header.fee_ratio) as f32 * 1.52587890625e-5_f32).powi(
+    //     fee_cycles_to_collect
+    //         .try_into()
+    //         .map_err(|_| BonfidaBotError::Overflow)?,
+    // ) * 65536.) as u16;
+    // Very overdue pools are collected in bounded chunks, see `get_feeless_ratio`
+    let (fee_cycles_to_collect, feeless_ratio_u16) =
+        get_feeless_ratio(pool_header.fee_ratio, fee_cycles_to_collect)?;
+    pool_header.last_fee_collection_timestamp = get_next_fee_collection_timestamp(
+        pool_header.last_fee_collection_timestamp,
+        fee_cycles_to_collect,
+        pool_header.fee_collection_period,
+    )?;
+
+    get_fee_pooltokens(total_pooltokens, feeless_ratio_u16)
+}
+
+/// Applies a pending fee ratio change if its effective period has been reached and returns
+/// the number of fee cycles which can be collected at the current fee ratio. Cycles accrued
+/// before the change takes effect are always collected at the previous fee ratio.
+pub fn apply_pending_fee_ratio(pool_header: &mut PoolHeader, fee_cycles_to_collect: u64) -> u64 {
+    if pool_header.pending_fee_ratio_timestamp == 0 {
+        return fee_cycles_to_collect;
+    }
+    let cycles_before_change = pool_header
+        .pending_fee_ratio_timestamp
+        .saturating_sub(pool_header.last_fee_collection_timestamp)
+        / pool_header.fee_collection_period;
+    if cycles_before_change == 0 {
+        pool_header.fee_ratio = pool_header.pending_fee_ratio;
+        pool_header.pending_fee_ratio = 0;
+        pool_header.pending_fee_ratio_timestamp = 0;
+        return fee_cycles_to_collect;
+    }
+    min(fee_cycles_to_collect, cycles_before_change)
+}
+
+/// Returns the minimum amount of pooltokens to redeem in order to receive at least
+/// `desired_amount` of the asset at `asset_index`, given the proportional payout of `Redeem`.
+pub fn pool_tokens_to_redeem_for_asset(
+    pool_balances: &[u64],
+    total_supply: u64,
+    asset_index: usize,
+    desired_amount: u64,
+) -> Result<u64, BonfidaBotError> {
+    let pool_balance = *pool_balances
+        .get(asset_index)
+        .ok_or(BonfidaBotError::InvalidInstruction)?;
+    if desired_amount > pool_balance {
+        return Err(BonfidaBotError::InsufficientAssetBalance);
+    }
+    if desired_amount == 0 {
+        return Ok(0);
+    }
+    // The payout is rounded down, so the pooltoken amount is rounded up
+    let numerator = (desired_amount as u128) * (total_supply as u128);
+    let pool_tokens = (numerator + (pool_balance as u128) - 1) / (pool_balance as u128);
+    pool_tokens
+        .try_into()
+        .map_err(|_| BonfidaBotError::Overflow)
+}
+
+/// Returns the fee collection timestamp after collecting `fee_cycles_to_collect` cycles,
+/// failing instead of wrapping around on overflow.
+pub fn get_next_fee_collection_timestamp(
+    last_fee_collection_timestamp: u64,
+    fee_cycles_to_collect: u64,
+    fee_collection_period: u64,
+) -> Result<u64, ProgramError> {
+    fee_cycles_to_collect
+        .checked_mul(fee_collection_period)
+        .and_then(|elapsed| last_fee_collection_timestamp.checked_add(elapsed))
+        .ok_or_else(|| BonfidaBotError::Overflow.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_pending_fee_ratio, check_account_writable, check_accounts_len, check_ata_program,
+        check_create_deposit_amounts, check_deposit_allowed, check_deposit_cap,
+        check_deposit_status, check_dex_program_update, check_discount_account,
+        check_emergency_withdraw_allowed, check_extra_fee_recipient, check_fee_collection_period,
+        check_fee_pt_accounts, check_fee_ratio, check_fee_split_bps, check_fida_minimum,
+        check_market_order_books, check_min_deposit, check_min_expected_lots, check_mint_authority,
+        check_new_pool_asset, check_number_of_markets, check_open_orders_owner,
+        check_order_asset_indices, check_order_covered, check_pool_closable, check_pool_has_assets,
+        check_pool_layout_fits, check_pool_token_decimals, check_redeem_allowed,
+        check_redeem_authorized, check_referrer_fee_bps, check_self_trade_behavior,
+        check_serum_result, check_settle_indices, check_signal_provider,
+        check_single_asset_deposits_allowed, check_skipped_settle_side, check_sysvar_account,
+        check_target_mint_allowed, check_token_account, check_untracked_mint, check_vault_signer,
+        collect_fees_at, compute_deposit_fee, format_deposit_log, format_redeem_log,
+        get_asset_share, get_best_order_price, get_elapsed_fee_cycles, get_fee_pooltokens,
+        get_feeless_ratio, get_market_value, get_min_deposit_pooltokens,
+        get_next_fee_collection_timestamp, get_next_fee_period_timestamp, get_open_orders,
+        get_order_accounts_checks, get_order_amounts, get_order_required_amount,
+        get_owed_fee_pooltokens, get_pool_asset_address, get_resting_bid_lots,
+        get_toggled_lock_status, get_token_program, is_order_fully_freed, is_token_program,
+        pack_deposit_return_data, pool_tokens_to_redeem_for_asset, pow_fixedpoint_u16,
+        read_open_orders_balances, serum_error_context, split_extra_fee, split_fee, split_fee_bps,
+        split_rebate, split_referrer_fee, transfer_checked_instruction, unpack_pool_asset_account,
+        unpack_token_account_data, update_fee_collection_period, verify_signal_provider_key,
+        OpenOrdersBalances, DEFAULT_BONFIDA_FEE_BPS, DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+        DEPOSIT_RETURN_DATA_LEN, MAX_EXTRA_FEE_BPS, MAX_FEE_CYCLES_PER_COLLECTION, MAX_FEE_RATIO,
+        MAX_REFERRER_FEE_BPS, MIN_BONFIDA_FEE_BPS, MIN_FEE_COLLECTION_PERIOD, MIN_POOL_FIDA_AMOUNT,
+        TOKEN_2022_PROGRAM_ID,
+    };
+    use crate::{
+        error::BonfidaBotError,
+        instruction::{
+            ORDER_CHECK_MARKET, ORDER_CHECK_SERUM_PROGRAM, ORDER_CHECK_SIGNAL_PROVIDER,
+            ORDER_CHECK_SOURCE_ACCOUNT,
+        },
+        state::{
+            add_allowed_mint, pack_assets, pack_markets, test_pool_header, unpack_pool_assets,
+            PoolAsset, PoolHeader, PoolMode, PoolStatus, ALLOWED_MINTS_ACCOUNT_LEN, BONFIDA_BNB,
+            BONFIDA_FEE, MAX_NUMBER_OF_MARKETS, MAX_POOL_TOKEN_DECIMALS, MSRM_MINT, PUBKEY_LENGTH,
+            SERUM_DEX_PROGRAM_IDS, SRM_MINT,
+        },
+    };
+    use serum_dex::{instruction::SelfTradeBehavior, matching::Side};
+    use solana_program::{
+        account_info::AccountInfo,
+        clock::Clock,
+        program_error::ProgramError,
+        program_option::COption,
+        program_pack::Pack,
+        pubkey::Pubkey,
+        sysvar::{clock, rent},
+    };
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_token::state::{Account, AccountState, Mint};
+    use std::{
+        num::{NonZeroU16, NonZeroU64},
+        str::FromStr,
+    };
+
+    #[test]
+    fn test_exp() {
+        let half: u16 = 1 << 15;
+        for i in 1..16 {
+            assert_eq!(pow_fixedpoint_u16(half as u32, i), 1 << (16 - i));
+        }
+    }
+
+    #[test]
+    fn test_exp_near_one() {
+        let one = 1u32 << 16;
+        for &n in [2, 3, 52, 1_000, 1 << 20, u64::MAX].iter() {
+            assert_eq!(pow_fixedpoint_u16(one, n), one);
+        }
+
+        let x = 0xFFFF;
+        assert_eq!(pow_fixedpoint_u16(x, 2), (x * x) >> 16);
+        let mut previous = x;
+        for &n in [2, 3, 52, 1_000, 1 << 20].iter() {
+            let power = pow_fixedpoint_u16(x, n);
+            assert!(power <= previous);
+            previous = power;
+        }
+        // (1 - 2^-16)^(2^20) is about e^-16, which truncates to zero
+        assert_eq!(pow_fixedpoint_u16(x, 1 << 20), 0);
+    }
+
+    #[test]
+    fn test_vault_signer() {
+        let dex_program_id = Pubkey::new_unique();
+        let market_key = Pubkey::new_unique();
+        let mut nonce = 0u64;
+        let vault_signer = loop {
+            if let Ok(key) = Pubkey::create_program_address(
+                &[&market_key.to_bytes(), &nonce.to_le_bytes()],
+                &dex_program_id,
+            ) {
+                break key;
+            }
+            nonce += 1;
+        };
+        let mut market_data = [0u8; 117];
+        market_data[45..53].copy_from_slice(&nonce.to_le_bytes());
+
+        assert!(
+            check_vault_signer(&dex_program_id, &market_key, &market_data, &vault_signer).is_ok()
+        );
+        assert_eq!(
+            check_vault_signer(
+                &dex_program_id,
+                &market_key,
+                &market_data,
+                &Pubkey::new_unique()
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_feeless_ratio_extreme_cycles() {
+        // Previously yielded a zero feeless ratio, making the fee computation divide by zero
+        let (fee_cycles, feeless_ratio) = get_feeless_ratio(1 << 12, std::u64::MAX).unwrap();
+        assert!(fee_cycles <= MAX_FEE_CYCLES_PER_COLLECTION);
+        assert!(fee_cycles > 0);
+        assert!(feeless_ratio > 0);
+
+        let (fee_cycles, feeless_ratio) = get_feeless_ratio(15, 3).unwrap();
+        assert_eq!(fee_cycles, 3);
+        assert_eq!(feeless_ratio as u32, pow_fixedpoint_u16(!15u16 as u32, 3));
+
+        assert!(get_feeless_ratio(std::u16::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn test_split_rebate() {
+        assert_eq!(split_rebate(1000, 1 << 15), (500, 500));
+        assert_eq!(split_rebate(1000, 1 << 14), (250, 750));
+        assert_eq!(split_rebate(1000, 0), (0, 1000));
+        assert_eq!(split_rebate(1000, std::u16::MAX), (999, 1));
+        assert_eq!(split_rebate(0, 1 << 15), (0, 0));
+    }
+
+    #[test]
+    fn test_check_token_account() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let token_account = Account {
+            mint,
+            owner,
+            ..Account::default()
+        };
+        assert!(check_token_account(&token_account, &mint, &owner).is_ok());
+        assert_eq!(
+            check_token_account(&token_account, &Pubkey::new_unique(), &owner),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_token_account(&token_account, &mint, &Pubkey::new_unique()),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_fee_ratio_change_applies_next_period() {
+        let mut pool_header = PoolHeader {
+            fee_collection_period: 100,
+            ..test_pool_header()
+        };
+        assert_eq!(apply_pending_fee_ratio(&mut pool_header, 3), 3);
+
+        // The fee ratio is changed in the middle of the second period
+        pool_header.pending_fee_ratio = 20;
+        pool_header.pending_fee_ratio_timestamp =
+            get_next_fee_period_timestamp(&pool_header, 1_150);
+        assert_eq!(pool_header.pending_fee_ratio_timestamp, 1_200);
+
+        // The current and previous periods are collected at the old rate
+        assert_eq!(apply_pending_fee_ratio(&mut pool_header, 3), 2);
+        assert_eq!(pool_header.fee_ratio, 10);
+        pool_header.last_fee_collection_timestamp += 2 * pool_header.fee_collection_period;
+
+        // Following periods are collected at the new rate
+        assert_eq!(apply_pending_fee_ratio(&mut pool_header, 1), 1);
+        assert_eq!(pool_header.fee_ratio, 20);
+        assert_eq!(pool_header.pending_fee_ratio_timestamp, 0);
+    }
+
+    #[test]
+    fn test_order_accounts_checks() {
+        let program_id = Pubkey::new_unique();
+        let pool_key = Pubkey::new_unique();
+        let signal_provider_key = Pubkey::new_unique();
+        let serum_program_id = Pubkey::new_unique();
+        let market_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let pool_header = PoolHeader {
+            serum_program_id,
+            signal_provider: signal_provider_key,
+            fee_collection_period: 100,
+            ..test_pool_header()
+        };
+        let mut pool_data = vec![0u8; PoolHeader::LEN + 32 + PoolAsset::LEN];
+        pool_header.pack_into_slice(&mut pool_data[..PoolHeader::LEN]);
+        pack_markets(&mut pool_data[PoolHeader::LEN..], &vec![market_key]).unwrap();
+        pack_assets(
+            &mut pool_data[PoolHeader::LEN + 32..],
+            &[PoolAsset { mint_address: mint }],
+        )
+        .unwrap();
+
+        let source_key = get_associated_token_address(&pool_key, &mint);
+        let mut source_data = vec![0u8; Account::LEN];
+        Account::pack(
+            Account {
+                mint,
+                owner: pool_key,
+                state: AccountState::Initialized,
+                ..Account::default()
+            },
+            &mut source_data,
+        )
+        .unwrap();
+
+        let wrong_market_key = Pubkey::new_unique();
+        let (mut lamports_0, mut lamports_1, mut lamports_2, mut lamports_3) = (0, 0, 0, 0);
+        let signal_provider = AccountInfo::new(
+            &signal_provider_key,
+            true,
+            false,
+            &mut lamports_0,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let market = AccountInfo::new(
+            &wrong_market_key,
+            false,
+            false,
+            &mut lamports_1,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+        let source = AccountInfo::new(
+            &source_key,
+            false,
+            false,
+            &mut lamports_2,
+            &mut source_data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        let dex_program = AccountInfo::new(
+            &serum_program_id,
+            false,
+            false,
+            &mut lamports_3,
+            &mut [],
+            &program_id,
+            true,
+            0,
+        );
+
+        let checks = get_order_accounts_checks(
+            &pool_data,
+            &pool_key,
+            0,
+            0,
+            &signal_provider,
+            &market,
+            &source,
+            &dex_program,
+        )
+        .unwrap();
+        assert_eq!(checks & ORDER_CHECK_MARKET, 0);
+        assert_eq!(
+            checks,
+            ORDER_CHECK_SOURCE_ACCOUNT | ORDER_CHECK_SERUM_PROGRAM | ORDER_CHECK_SIGNAL_PROVIDER
+        );
+    }
+
+    #[test]
+    fn test_closed_pool_asset_account() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let closed_account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut [],
+            &solana_program::system_program::id(),
+            false,
+            0,
+        );
+        assert_eq!(
+            unpack_pool_asset_account(&closed_account, 2).unwrap_err(),
+            BonfidaBotError::ClosedAssetAccount.into()
+        );
+
+        let mut uninitialized_data = vec![0u8; Account::LEN];
+        let mut lamports = 0;
+        let uninitialized_account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut uninitialized_data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        assert_eq!(
+            unpack_pool_asset_account(&uninitialized_account, 0).unwrap_err(),
+            BonfidaBotError::ClosedAssetAccount.into()
+        );
+
+        let mut data = vec![0u8; Account::LEN];
+        Account::pack(
+            Account {
+                amount: 7,
+                state: AccountState::Initialized,
+                ..Account::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        let mut lamports = 0;
+        let open_account = AccountInfo::new(
+            &key,
+            false,
+            true,
+            &mut lamports,
+            &mut data,
+            &spl_token::id(),
+            false,
+            0,
+        );
+        assert_eq!(
+            unpack_pool_asset_account(&open_account, 0).unwrap().amount,
+            7
+        );
+    }
+
+    #[test]
+    fn test_pool_modes() {
+        let mut pool_header = PoolHeader {
+            fee_collection_period: 100,
+            ..test_pool_header()
+        };
+        assert!(check_deposit_allowed(&pool_header).is_ok());
+        assert!(check_redeem_allowed(&pool_header).is_ok());
+
+        pool_header.pool_mode = PoolMode::DepositOnly;
+        assert!(check_deposit_allowed(&pool_header).is_ok());
+        assert_eq!(
+            check_redeem_allowed(&pool_header).unwrap_err(),
+            BonfidaBotError::ModeRestricted.into()
+        );
+
+        pool_header.pool_mode = PoolMode::RedeemOnly;
+        assert_eq!(
+            check_deposit_allowed(&pool_header).unwrap_err(),
+            BonfidaBotError::ModeRestricted.into()
+        );
+        assert!(check_redeem_allowed(&pool_header).is_ok());
+        assert!(check_emergency_withdraw_allowed(&pool_header).is_err());
+    }
+
+    #[test]
+    fn test_emergency_withdraw_from_frozen_pool() {
+        let mut pool_header = PoolHeader {
+            status: PoolStatus::PendingOrder(std::num::NonZeroU8::new(2).unwrap()),
+            fee_collection_period: 100,
+            ..test_pool_header()
+        };
+        assert!(check_emergency_withdraw_allowed(&pool_header).is_err());
+
+        pool_header.pool_mode = PoolMode::Frozen;
+        assert!(check_emergency_withdraw_allowed(&pool_header).is_ok());
+        assert!(check_deposit_allowed(&pool_header).is_err());
+        assert!(check_redeem_allowed(&pool_header).is_err());
+
+        // Withdrawing a quarter of the pooltokens pays out a quarter of the asset only
+        assert_eq!(
+            get_asset_share(250_000, 4_000_001, 1_000_000).unwrap(),
+            1_000_000
+        );
+        assert_eq!(get_asset_share(0, 4_000_001, 1_000_000).unwrap(), 0);
+        assert!(get_asset_share(1, 1, 0).is_err());
+    }
+
+    #[test]
+    fn test_fee_collection_timestamp_overflow() {
+        assert_eq!(
+            get_next_fee_collection_timestamp(1_000, 52, 100).unwrap(),
+            6_200
+        );
+        assert_eq!(
+            get_next_fee_collection_timestamp(1_000, 52, u64::MAX / 10).unwrap_err(),
+            BonfidaBotError::Overflow.into()
+        );
+        assert_eq!(
+            get_next_fee_collection_timestamp(u64::MAX - 10, 1, 100).unwrap_err(),
+            BonfidaBotError::Overflow.into()
+        );
+    }
+
+    #[test]
+    fn test_read_only_pool_account() {
+        let program_id = Pubkey::new_unique();
+        let pool_key = Pubkey::new_unique();
+        let mut pool_data = vec![0u8; PoolHeader::LEN];
+        let mut lamports = 0;
+        let read_only_pool = AccountInfo::new(
+            &pool_key,
+            false,
+            false,
+            &mut lamports,
+            &mut pool_data,
+            &program_id,
+            false,
+            0,
+        );
+        assert_eq!(
+            check_account_writable(&read_only_pool, "pool").unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        let mut pool_data = vec![0u8; PoolHeader::LEN];
+        let mut lamports = 0;
+        let writable_pool = AccountInfo::new(
+            &pool_key,
+            false,
+            true,
+            &mut lamports,
+            &mut pool_data,
+            &program_id,
+            false,
+            0,
+        );
+        assert!(check_account_writable(&writable_pool, "pool").is_ok());
+    }
+
+    #[test]
+    fn test_pool_tokens_to_redeem_for_asset() {
+        let pool_balances = [3_000_000, 7, 1_000_000_000_000];
+        let total_supply = 1_000_000;
+
+        for asset_index in 0..pool_balances.len() {
+            for desired_amount in [1, 2, 3, 5, 6, 7].iter() {
+                let pool_tokens = pool_tokens_to_redeem_for_asset(
+                    &pool_balances,
+                    total_supply,
+                    asset_index,
+                    *desired_amount,
+                )
+                .unwrap();
+                let payout = |pool_tokens: u64| {
+                    get_asset_share(pool_tokens, pool_balances[asset_index], total_supply).unwrap()
+                };
+                // Enough, and one pooltoken less would not be
+                assert!(payout(pool_tokens) >= *desired_amount);
+                assert!(payout(pool_tokens - 1) < *desired_amount);
+            }
+        }
+
+        // Exact boundaries
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 0, 3).unwrap(),
+            1
+        );
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 0, 4).unwrap(),
+            2
+        );
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 1, 7).unwrap(),
+            total_supply
+        );
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 2, 0).unwrap(),
+            0
+        );
+
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 1, 8).unwrap_err(),
+            BonfidaBotError::InsufficientAssetBalance
+        );
+        assert_eq!(
+            pool_tokens_to_redeem_for_asset(&pool_balances, total_supply, 3, 1).unwrap_err(),
+            BonfidaBotError::InvalidInstruction
+        );
+    }
+
+    #[test]
+    fn test_check_ata_program() {
+        let mut lamports = 0;
+        let ata_program_id = spl_associated_token_account::id();
+        let ata_program = AccountInfo::new(
+            &ata_program_id,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &Pubkey::new_unique(),
+            true,
+            0,
+        );
+        assert!(check_ata_program(&ata_program).is_ok());
+
+        let mut lamports = 0;
+        let spoofed_program_id = Pubkey::new_unique();
+        let spoofed_program = AccountInfo::new(
+            &spoofed_program_id,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &Pubkey::new_unique(),
+            true,
+            0,
+        );
+        assert_eq!(
+            check_ata_program(&spoofed_program).unwrap_err(),
+            ProgramError::IncorrectProgramId
+        );
+    }
+
+    #[test]
+    fn test_check_sysvar_account() {
+        let mut lamports = 0;
+        let clock_id = clock::id();
+        let clock_account = AccountInfo::new(
+            &clock_id,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &Pubkey::new_unique(),
+            false,
+            0,
+        );
+        assert!(check_sysvar_account(&clock_account, &clock::id(), "clock").is_ok());
+        assert_eq!(
+            check_sysvar_account(&clock_account, &rent::id(), "rent").unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        let mut lamports = 0;
+        let spoofed_id = Pubkey::new_unique();
+        let spoofed_clock = AccountInfo::new(
+            &spoofed_id,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &Pubkey::new_unique(),
+            false,
+            0,
+        );
+        assert_eq!(
+            check_sysvar_account(&spoofed_clock, &clock::id(), "clock").unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_redeem_requires_provider_until_maturity() {
+        let program_id = Pubkey::new_unique();
+        let signal_provider_key = Pubkey::new_unique();
+        let mut pool_header = PoolHeader {
+            signal_provider: signal_provider_key,
+            fee_collection_period: 100,
+            redeem_requires_provider: true,
+            maturity_timestamp: 5_000,
+            ..test_pool_header()
+        };
+        let mut lamports = 0;
+        let signal_provider = AccountInfo::new(
+            &signal_provider_key,
+            true,
+            false,
+            &mut lamports,
+            &mut [],
+            &program_id,
+            false,
+            0,
+        );
+
+        // Pre-maturity
+        assert_eq!(
+            check_redeem_authorized(&pool_header, None, 4_999).unwrap_err(),
+            ProgramError::MissingRequiredSignature
+        );
+        assert!(check_redeem_authorized(&pool_header, Some(&signal_provider), 4_999).is_ok());
+
+        // Post-maturity
+        assert!(check_redeem_authorized(&pool_header, None, 5_000).is_ok());
+
+        pool_header.redeem_requires_provider = false;
+        assert!(check_redeem_authorized(&pool_header, None, 0).is_ok());
+    }
+
+    #[test]
+    fn test_accounts_len() {
+        assert!(check_accounts_len(12, 12, "Deposit", 2).is_ok());
+        assert!(check_accounts_len(13, 12, "Deposit", 2).is_ok());
+        assert_eq!(
+            check_accounts_len(11, 12, "Deposit", 2),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(
+            check_accounts_len(0, 6, "Redeem", 0),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn test_min_deposit() {
+        assert_eq!(get_min_deposit_pooltokens(6), 100_000);
+        assert_eq!(get_min_deposit_pooltokens(9), 100_000_000);
+        assert_eq!(get_min_deposit_pooltokens(1), 1);
+        assert_eq!(get_min_deposit_pooltokens(0), 1);
+
+        for decimals in 0..=MAX_POOL_TOKEN_DECIMALS {
+            let min_deposit_pooltokens = get_min_deposit_pooltokens(decimals);
+            assert_eq!(
+                check_min_deposit(min_deposit_pooltokens - 1, decimals),
+                Err(BonfidaBotError::OperationTooSmall.into())
+            );
+            assert!(check_min_deposit(min_deposit_pooltokens, decimals).is_ok());
+            assert!(check_min_deposit(min_deposit_pooltokens + 1, decimals).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_deposit_fee() {
+        assert_eq!(compute_deposit_fee(0, 1_000), Ok((1_000, 0)));
+        assert_eq!(compute_deposit_fee(1 << 15, 1_000), Ok((500, 500)));
+
+        // The target keeps at least one pooltoken at the highest fee ratio
+        assert_eq!(compute_deposit_fee(0xFFFF, 1), Ok((1, 0)));
+        assert_eq!(compute_deposit_fee(0xFFFF, 1 << 16), Ok((1, 0xFFFF)));
+        assert_eq!(
+            compute_deposit_fee(0xFFFF, u64::MAX),
+            Ok((1 << 48, u64::MAX - (1 << 48)))
+        );
+        for amount in [2, 3, 1_000, 65_537, 1_000_000_007].iter() {
+            let (pool_token_amount_after_fee, pool_token_fee) =
+                compute_deposit_fee(0xFFFF, *amount).unwrap();
+            assert!(pool_token_amount_after_fee > 0);
+            assert_eq!(pool_token_amount_after_fee + pool_token_fee, *amount);
+        }
+
+        assert_eq!(
+            compute_deposit_fee(0xFFFF, 0),
+            Err(BonfidaBotError::OperationTooSmall.into())
+        );
+    }
+
+    #[test]
+    fn test_deposit_rejected_once_locked() {
+        assert!(check_deposit_status(&PoolStatus::Unlocked).is_ok());
+        // `DepositAndLock` locks the pool once its deposit went through
+        let locked = get_toggled_lock_status(&PoolStatus::Unlocked).unwrap();
+        assert_eq!(locked, PoolStatus::Locked);
+        assert_eq!(
+            check_deposit_status(&locked),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+
+        let pending_orders = std::num::NonZeroU8::new(1).unwrap();
+        assert_eq!(
+            check_deposit_status(&PoolStatus::PendingOrder(pending_orders)),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+        assert_eq!(
+            check_deposit_status(&PoolStatus::LockedPendingOrder(pending_orders)),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+        assert_eq!(
+            check_deposit_status(&PoolStatus::Uninitialized),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_cancelled_order_release() {
+        // An order resting on the book keeps part of the funds locked
+        assert!(!is_order_fully_freed(0, 1_000, 0, 0));
+        assert!(!is_order_fully_freed(500, 1_000, 2_000, 2_000));
+        // Once cancelled, its funds are free and its pending order is released
+        assert!(is_order_fully_freed(1_000, 1_000, 0, 0));
+        let status = PoolStatus::PendingOrder(std::num::NonZeroU8::new(1).unwrap());
+        assert_eq!(status.settle_one(), Ok(PoolStatus::Unlocked));
+        let status = PoolStatus::LockedPendingOrder(std::num::NonZeroU8::new(1).unwrap());
+        assert_eq!(status.settle_one(), Ok(PoolStatus::Locked));
+    }
+
+    #[test]
+    fn test_self_trade_behavior() {
+        assert!(check_self_trade_behavior(SelfTradeBehavior::DecrementTake).is_ok());
+        assert!(check_self_trade_behavior(SelfTradeBehavior::CancelProvide).is_ok());
+        assert_eq!(
+            check_self_trade_behavior(SelfTradeBehavior::AbortTransaction),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_resting_bid_lots() {
+        let half = NonZeroU16::new(1 << 15).unwrap();
+        let (amount_to_trade, ioc_lots) =
+            get_order_amounts(1_000_000, half, Side::Bid, 1_000, 10).unwrap();
+        assert_eq!((amount_to_trade, ioc_lots), (500_000, 50_000));
+
+        // At a limit price of 25 price lots per coin lot, a coin lot locks 250 of the price
+        // currency
+        let limit_price = NonZeroU64::new(25).unwrap();
+        let lots = get_resting_bid_lots(amount_to_trade, limit_price, 10).unwrap();
+        assert_eq!(lots, 2_000);
+        assert!(lots * 25 * 10 <= amount_to_trade);
+        assert_eq!(
+            get_order_required_amount(Side::Bid, amount_to_trade, lots, 1_000),
+            Ok(amount_to_trade)
+        );
+
+        // Too small to rest a single lot
+        assert_eq!(get_resting_bid_lots(249, limit_price, 10), Ok(0));
+        assert_eq!(
+            get_resting_bid_lots(1, NonZeroU64::new(u64::MAX).unwrap(), 2),
+            Err(BonfidaBotError::Overflow.into())
+        );
+        assert_eq!(
+            get_resting_bid_lots(1, limit_price, 0),
+            Err(BonfidaBotError::Overflow.into())
+        );
+    }
+
+    /// Writes a serum order book holding orders at the sorted `prices`, at most two of them
+    fn order_book_data(prices: &[u64]) -> Vec<u8> {
+        let mut data = vec![0; 45 + 3 * 72];
+        data[37..45].copy_from_slice(&(prices.len() as u64).to_le_bytes());
+        let first_leaf = if prices.len() > 1 {
+            // An inner node over the leaves, in increasing key order
+            data[45..49].copy_from_slice(&1u32.to_le_bytes());
+            data[45 + 24..45 + 28].copy_from_slice(&1u32.to_le_bytes());
+            data[45 + 28..45 + 32].copy_from_slice(&2u32.to_le_bytes());
+            1
+        } else {
+            0
+        };
+        for (i, price) in prices.iter().enumerate() {
+            let offset = 45 + (first_leaf + i) * 72;
+            let key = (*price as u128) << 64 | i as u128;
+            data[offset..offset + 4].copy_from_slice(&2u32.to_le_bytes());
+            data[offset + 8..offset + 24].copy_from_slice(&key.to_le_bytes());
+        }
+        data
+    }
+
+    fn market_data(
+        coin_mint: &Pubkey,
+        pc_mint: &Pubkey,
+        bids: &Pubkey,
+        asks: &Pubkey,
+        coin_lot_size: u64,
+        pc_lot_size: u64,
+    ) -> Vec<u8> {
+        let mut data = vec![0; 388];
+        data[53..85].copy_from_slice(&coin_mint.to_bytes());
+        data[85..117].copy_from_slice(&pc_mint.to_bytes());
+        data[285..317].copy_from_slice(&bids.to_bytes());
+        data[317..349].copy_from_slice(&asks.to_bytes());
+        data[349..357].copy_from_slice(&coin_lot_size.to_le_bytes());
+        data[357..365].copy_from_slice(&pc_lot_size.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_market_value() {
+        let bids = order_book_data(&[20, 25]);
+        let asks = order_book_data(&[30, 40]);
+        assert_eq!(get_best_order_price(&bids, Side::Bid), Ok(Some(25)));
+        assert_eq!(get_best_order_price(&asks, Side::Ask), Ok(Some(30)));
+        assert_eq!(
+            get_best_order_price(&order_book_data(&[40]), Side::Bid),
+            Ok(Some(40))
+        );
+        assert_eq!(
+            get_best_order_price(&order_book_data(&[]), Side::Ask),
+            Ok(None)
+        );
+        let mut looping_book = order_book_data(&[20, 25]);
+        looping_book[45 + 28..45 + 32].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            get_best_order_price(&looping_book, Side::Bid),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let (coin_mint, pc_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (bids_key, asks_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let market = market_data(&coin_mint, &pc_mint, &bids_key, &asks_key, 100, 10);
+        assert_eq!(
+            check_market_order_books(&market, &bids_key, &asks_key),
+            Ok(())
+        );
+        assert_eq!(
+            check_market_order_books(&market, &asks_key, &bids_key),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // A coin lot of 100 is asked for 30 price lots of 10
+        assert_eq!(
+            get_market_value(&market, &bids, &asks, &coin_mint, &pc_mint, 1_000),
+            Ok(3_000)
+        );
+        // A coin lot of 100 is bid 25 price lots of 10
+        assert_eq!(
+            get_market_value(&market, &bids, &asks, &pc_mint, &coin_mint, 3_000),
+            Ok(1_200)
+        );
+        // The values are rounded up
+        assert_eq!(
+            get_market_value(&market, &bids, &asks, &coin_mint, &pc_mint, 1),
+            Ok(3)
+        );
+        assert_eq!(
+            get_market_value(&market, &bids, &asks, &pc_mint, &coin_mint, 1),
+            Ok(1)
+        );
+        // Neither asset is valued on a book with an empty side, whichever side it would use
+        for (bids, asks) in [
+            (&order_book_data(&[]), &asks),
+            (&bids, &order_book_data(&[])),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                get_market_value(&market, bids, asks, &coin_mint, &pc_mint, 1),
+                Err(ProgramError::InvalidArgument)
+            );
+            assert_eq!(
+                get_market_value(&market, bids, asks, &pc_mint, &coin_mint, 1),
+                Err(ProgramError::InvalidArgument)
+            );
+        }
+        // A crossed book values the coin at the bid and the quote asset at the ask
+        assert_eq!(
+            get_market_value(&market, &asks, &bids, &coin_mint, &pc_mint, 1_000),
+            Ok(4_000)
+        );
+        assert_eq!(
+            get_market_value(&market, &asks, &bids, &pc_mint, &coin_mint, 2_000),
+            Ok(1_000)
+        );
+        assert_eq!(
+            get_market_value(&market, &bids, &asks, &coin_mint, &Pubkey::new_unique(), 1),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_order_amounts_follow_balance_changes() {
+        let half = NonZeroU16::new(1 << 15).unwrap();
+        let (amount_to_trade, lots_to_trade) =
+            get_order_amounts(1_000_000, half, Side::Ask, 1_000, 10).unwrap();
+        assert_eq!((amount_to_trade, lots_to_trade), (500_000, 500));
+        let required_amount =
+            get_order_required_amount(Side::Ask, amount_to_trade, lots_to_trade, 1_000).unwrap();
+        assert_eq!(required_amount, 500_000);
+
+        // The dex debits the coin lots, or less when the open orders account had free coins
+        assert!(check_order_covered(1_000_000, 500_000, required_amount).is_ok());
+        assert!(check_order_covered(1_000_000, 700_000, required_amount).is_ok());
+        // Anything debited past the order's lots isn't covered
+        assert_eq!(
+            check_order_covered(1_000_000, 499_999, required_amount),
+            Err(BonfidaBotError::InsufficientAssetBalance.into())
+        );
+        assert_eq!(
+            check_order_covered(1_000_000, 0, required_amount),
+            Err(BonfidaBotError::InsufficientAssetBalance.into())
+        );
+
+        // Recomputing from a fresh balance sizes the order after it
+        let (amount_to_trade, lots_to_trade) =
+            get_order_amounts(400_000, half, Side::Ask, 1_000, 10).unwrap();
+        assert_eq!((amount_to_trade, lots_to_trade), (200_000, 200));
+        assert_eq!(
+            get_order_required_amount(Side::Ask, amount_to_trade, lots_to_trade, 1_000),
+            Ok(200_000)
+        );
+        assert_eq!(
+            get_order_required_amount(Side::Ask, 1, u64::MAX, 2),
+            Err(BonfidaBotError::Overflow.into())
+        );
+
+        assert_eq!(
+            get_order_amounts(1_000_000, half, Side::Bid, 1_000, 0),
+            Err(BonfidaBotError::Overflow.into())
+        );
+    }
+
+    #[test]
+    fn test_serum_error_context() {
+        let pool_seed = [7u8; 32];
+        let market = Pubkey::new_unique();
+
+        let context = serum_error_context("new order", &pool_seed, &market);
+        assert!(context.contains("new order"));
+        assert!(context.contains(&Pubkey::new(&pool_seed).to_string()));
+        assert!(context.contains(&market.to_string()));
+
+        assert!(check_serum_result(Ok(()), "new order", &pool_seed, &market).is_ok());
+        // A simulated serum failure keeps its original error code
+        assert_eq!(
+            check_serum_result(
+                Err(ProgramError::Custom(0x10)),
+                "settle",
+                &pool_seed,
+                &market
+            ),
+            Err(ProgramError::Custom(0x10))
+        );
+    }
+
+    #[test]
+    fn test_target_mint_allow_list() {
+        let allowed_mint = Pubkey::new_unique();
+        let disallowed_mint = Pubkey::new_unique();
+
+        // Without an allow-list, any mint can be traded into
+        assert!(check_target_mint_allowed(&[], &disallowed_mint).is_ok());
+
+        let mut data = vec![0u8; ALLOWED_MINTS_ACCOUNT_LEN];
+        add_allowed_mint(&mut data, &allowed_mint).unwrap();
+        assert!(check_target_mint_allowed(&data, &allowed_mint).is_ok());
+        assert_eq!(
+            check_target_mint_allowed(&data, &disallowed_mint),
+            Err(BonfidaBotError::MintNotAllowed.into())
+        );
+    }
+
+    #[test]
+    fn test_asset_untracked() {
+        let tracked_mint = Pubkey::new_unique();
+        let mut assets_data = vec![0u8; 3 * PoolAsset::LEN];
+        pack_assets(
+            &mut assets_data[PoolAsset::LEN..],
+            &[PoolAsset {
+                mint_address: tracked_mint,
+            }],
+        )
+        .unwrap();
+
+        assert!(check_asset_untracked(&assets_data, &Pubkey::new_unique()).is_ok());
+        assert_eq!(
+            check_asset_untracked(&assets_data, &tracked_mint),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_toggle_lock() {
+        let pending_orders = std::num::NonZeroU8::new(3).unwrap();
+        assert_eq!(
+            get_toggled_lock_status(&PoolStatus::Unlocked),
+            Ok(PoolStatus::Locked)
+        );
+        assert_eq!(
+            get_toggled_lock_status(&PoolStatus::Locked),
+            Ok(PoolStatus::Unlocked)
+        );
+        assert_eq!(
+            get_toggled_lock_status(&PoolStatus::PendingOrder(pending_orders)),
+            Ok(PoolStatus::LockedPendingOrder(pending_orders))
+        );
+        assert_eq!(
+            get_toggled_lock_status(&PoolStatus::LockedPendingOrder(pending_orders)),
+            Ok(PoolStatus::PendingOrder(pending_orders))
+        );
+        assert_eq!(
+            get_toggled_lock_status(&PoolStatus::Uninitialized),
+            Err(ProgramError::UninitializedAccount)
+        );
+    }
+
+    #[test]
+    fn test_zero_fee_collection_period() {
+        let mut pool_header = test_pool_header();
+        assert!(check_fee_collection_period(&pool_header).is_ok());
+
+        pool_header.fee_collection_period = 0;
+        assert_eq!(
+            check_fee_collection_period(&pool_header),
+            Err(BonfidaBotError::Overflow.into())
+        );
+    }
+
+    #[test]
+    fn test_fee_ratio_ceiling() {
+        assert!(check_fee_ratio(0).is_ok());
+        assert!(check_fee_ratio(MAX_FEE_RATIO).is_ok());
+        assert_eq!(
+            check_fee_ratio(MAX_FEE_RATIO + 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_fee_ratio(u16::MAX),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_token_2022_helpers() {
+        let token_2022_id = Pubkey::from_str(TOKEN_2022_PROGRAM_ID).unwrap();
+        assert!(is_token_program(&spl_token::id()));
+        assert!(is_token_program(&token_2022_id));
+        assert!(!is_token_program(&Pubkey::new_unique()));
+
+        let pool_key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        assert_eq!(
+            get_pool_asset_address(&pool_key, &mint, &spl_token::id()),
+            get_associated_token_address(&pool_key, &mint)
+        );
+        assert_ne!(
+            get_pool_asset_address(&pool_key, &mint, &token_2022_id),
+            get_associated_token_address(&pool_key, &mint)
+        );
+
+        // Token-2022 accounts carry their extensions after the base account state
+        let token_account = Account {
+            mint,
+            owner: pool_key,
+            amount: 42,
+            state: AccountState::Initialized,
+            ..Account::default()
+        };
+        let mut data = vec![0u8; Account::LEN + 83];
+        token_account.pack_into_slice(&mut data[..Account::LEN]);
+        data[Account::LEN] = 2;
+        assert_eq!(unpack_token_account_data(&data).unwrap(), token_account);
+        assert_eq!(
+            unpack_token_account_data(&data[..Account::LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        let source = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let instruction = transfer_checked_instruction(
+            &token_2022_id,
+            &source,
+            &mint,
+            &destination,
+            &pool_key,
+            1_000,
+            6,
+        );
+        assert_eq!(instruction.program_id, token_2022_id);
+        assert_eq!(instruction.data[0], 12);
+        assert_eq!(instruction.data[1..9], 1_000u64.to_le_bytes());
+        assert_eq!(instruction.data[9], 6);
+        assert_eq!(instruction.accounts[1].pubkey, mint);
+        assert!(instruction.accounts[3].is_signer);
+
+        let mut lamports = 0;
+        let token_2022_program = AccountInfo::new(
+            &token_2022_id,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &token_2022_id,
+            true,
+            0,
+        );
+        let accounts = [token_2022_program];
+        assert_eq!(
+            get_token_program(&accounts, &token_2022_id).unwrap().key,
+            &token_2022_id
+        );
+        assert_eq!(
+            get_token_program(&accounts, &spl_token::id()).unwrap_err(),
+            ProgramError::NotEnoughAccountKeys
+        );
+    }
+
+    #[test]
+    fn test_structured_logs() {
+        assert_eq!(
+            format_deposit_log(9_950, 50),
+            "bonfida:deposit pool_tokens=9950 fee=50"
+        );
+        assert_eq!(
+            format_redeem_log(1_000, &[10, 0, 25]),
+            "bonfida:redeem pool_tokens=1000 amounts=10,0,25"
+        );
+        assert_eq!(
+            format_redeem_log(1_000, &[]),
+            "bonfida:redeem pool_tokens=1000 amounts="
+        );
+
+        let return_data = pack_deposit_return_data(9_950, 50);
+        assert_eq!(return_data.len(), DEPOSIT_RETURN_DATA_LEN);
+        assert_eq!(return_data[..8], 9_950u64.to_le_bytes());
+        assert_eq!(return_data[8..], 50u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_update_fee_collection_period() {
+        let mut pool_header = PoolHeader {
+            last_fee_collection_timestamp: 1_000_000,
+            fee_collection_period: MIN_FEE_COLLECTION_PERIOD,
+            ..test_pool_header()
+        };
+        let new_period = 2 * MIN_FEE_COLLECTION_PERIOD;
+        assert_eq!(
+            update_fee_collection_period(
+                &mut pool_header,
+                MIN_FEE_COLLECTION_PERIOD - 1,
+                1_000_000
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // Two complete cycles are owed, they have to be collected under the old period
+        let switch_timestamp = 1_000_000 + 2 * MIN_FEE_COLLECTION_PERIOD + 10;
+        let owed_cycles = (switch_timestamp - pool_header.last_fee_collection_timestamp)
+            / pool_header.fee_collection_period;
+        assert_eq!(owed_cycles, 2);
+        assert_eq!(
+            update_fee_collection_period(&mut pool_header, new_period, switch_timestamp),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+        assert_eq!(pool_header.fee_collection_period, MIN_FEE_COLLECTION_PERIOD);
+        let collection_timestamp = get_next_fee_collection_timestamp(
+            pool_header.last_fee_collection_timestamp,
+            owed_cycles,
+            pool_header.fee_collection_period,
+        )
+        .unwrap();
+        pool_header.last_fee_collection_timestamp = collection_timestamp;
+
+        // A fee ratio change is pending when the period is switched
+        pool_header.pending_fee_ratio = 20;
+        pool_header.pending_fee_ratio_timestamp =
+            get_next_fee_period_timestamp(&pool_header, switch_timestamp);
+        update_fee_collection_period(&mut pool_header, new_period, switch_timestamp).unwrap();
+        assert_eq!(pool_header.fee_collection_period, new_period);
+        assert_eq!(
+            pool_header.last_fee_collection_timestamp,
+            collection_timestamp
+        );
+        assert_eq!(
+            pool_header.pending_fee_ratio_timestamp,
+            collection_timestamp + new_period
+        );
+
+        // The seconds accrued before the switch aren't forgiven: the cycle in progress
+        // completes one new period after the last collection, not after the switch
+        assert_eq!(
+            get_elapsed_fee_cycles(&pool_header, collection_timestamp + new_period - 1),
+            0
+        );
+        assert_eq!(
+            get_elapsed_fee_cycles(&pool_header, collection_timestamp + new_period),
+            1
+        );
+        // That cycle is still charged at the old rate
+        assert_eq!(apply_pending_fee_ratio(&mut pool_header, 2), 1);
+        assert_eq!(pool_header.fee_ratio, 10);
+    }
+
+    #[test]
+    fn test_get_open_orders() {
+        let mut openorders_data = vec![0u8; 3228];
+        let free_slot_bits = !(1u128 | (1u128 << 5) | (1u128 << 127));
+        let is_bid_bits = 1u128 << 5;
+        openorders_data[109..125].copy_from_slice(&free_slot_bits.to_le_bytes());
+        openorders_data[125..141].copy_from_slice(&is_bid_bits.to_le_bytes());
+        for (slot, order_id) in [(0usize, 42u128), (5, 7 << 64), (127, u128::MAX)].iter() {
+            openorders_data[141 + 16 * slot..157 + 16 * slot]
+                .copy_from_slice(&order_id.to_le_bytes());
+        }
+        assert_eq!(
+            get_open_orders(&openorders_data).unwrap(),
+            vec![
+                (Side::Ask, 42),
+                (Side::Bid, 7 << 64),
+                (Side::Ask, u128::MAX)
+            ]
+        );
+
+        // No live orders
+        openorders_data[109..125].copy_from_slice(&u128::MAX.to_le_bytes());
+        assert_eq!(get_open_orders(&openorders_data).unwrap(), vec![]);
+
+        assert_eq!(
+            get_open_orders(&openorders_data[..140]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_order_asset_indices() {
+        assert!(check_order_asset_indices(0, 1, 2).is_ok());
+        assert!(check_order_asset_indices(3, 0, 4).is_ok());
+        // Equal indices would overwrite the source asset with the target mint
+        assert_eq!(
+            check_order_asset_indices(1, 1, 4),
+            Err(ProgramError::InvalidArgument)
+        );
+        // Out of range indices
+        assert_eq!(
+            check_order_asset_indices(2, 0, 2),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_order_asset_indices(0, 2, 2),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_order_asset_indices(usize::MAX, 0, 2),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_order_asset_indices(0, 1, 0),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_settle_indices() {
+        assert!(check_settle_indices(Some(0), Some(1)).is_ok());
+        assert!(check_settle_indices(Some(3), Some(2)).is_ok());
+        assert!(check_settle_indices(None, Some(2)).is_ok());
+        assert!(check_settle_indices(Some(2), None).is_ok());
+        assert_eq!(
+            check_settle_indices(Some(2), Some(2)),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_settle_indices(None, None),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        assert!(check_skipped_settle_side(Some(0), 10, "coin").is_ok());
+        assert!(check_skipped_settle_side(None, 0, "coin").is_ok());
+        assert_eq!(
+            check_skipped_settle_side(None, 10, "pc"),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_owed_fee_pooltokens() {
+        let mut pool_header = PoolHeader {
+            fee_ratio: 1 << 12,
+            fee_collection_period: 100,
+            ..test_pool_header()
+        };
+        let total_pooltokens = 1_000_000;
+
+        // Nothing is owed within the first period
+        assert_eq!(
+            get_owed_fee_pooltokens(&pool_header, total_pooltokens, 1_099).unwrap(),
+            0
+        );
+
+        // The fee period lapsed by several cycles, as much as a fee collection would mint
+        let (_, feeless_ratio) = get_feeless_ratio(pool_header.fee_ratio, 3).unwrap();
+        let collected =
+            ((!feeless_ratio) as u128 * total_pooltokens as u128 / feeless_ratio as u128) as u64;
+        let owed = get_owed_fee_pooltokens(&pool_header, total_pooltokens, 1_350).unwrap();
+        assert_eq!(owed, collected);
+        assert!(owed > 0);
+
+        // A redemption is diluted by the owed fees
+        let pool_asset_amount = 500_000;
+        assert!(
+            get_asset_share(1_000, pool_asset_amount, total_pooltokens + owed).unwrap()
+                < get_asset_share(1_000, pool_asset_amount, total_pooltokens).unwrap()
+        );
+
+        // Cycles after a fee ratio change are counted at the new, lower, fee ratio
+        pool_header.pending_fee_ratio = 1 << 8;
+        pool_header.pending_fee_ratio_timestamp = 1_100;
+        let (_, feeless_ratio) = get_feeless_ratio(pool_header.fee_ratio, 1).unwrap();
+        let collected_first_cycle =
+            ((!feeless_ratio) as u128 * total_pooltokens as u128 / feeless_ratio as u128) as u64;
+        let owed_with_change =
+            get_owed_fee_pooltokens(&pool_header, total_pooltokens, 1_350).unwrap();
+        assert!(owed_with_change > collected_first_cycle);
+        assert!(owed_with_change < owed);
+    }
+
+    #[test]
+    fn test_split_fee() {
+        for pool_token_fee in 0..=16 {
+            let (signal_provider_fee, bonfida_fee, bonfida_bnb_fee) = split_fee(pool_token_fee);
+            assert_eq!(
+                signal_provider_fee + bonfida_fee + bonfida_bnb_fee,
+                pool_token_fee
+            );
+            assert_eq!(signal_provider_fee, pool_token_fee / 2);
+            assert_eq!(bonfida_fee, pool_token_fee / 4);
+        }
+        assert_eq!(split_fee(3), (1, 0, 2));
+        assert_eq!(
+            split_fee(u64::MAX),
+            (u64::MAX / 2, u64::MAX / 4, u64::MAX / 4 + 2)
+        );
+    }
+
+    #[test]
+    fn test_split_fee_bps() {
+        for pool_token_fee in 0..=16 {
+            assert_eq!(
+                split_fee_bps(
+                    pool_token_fee,
+                    DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+                    DEFAULT_BONFIDA_FEE_BPS
+                ),
+                split_fee(pool_token_fee)
+            );
+        }
+        assert_eq!(split_fee_bps(1_000, 7_000, 1_000), (700, 100, 200));
+        assert_eq!(split_fee_bps(1_000, 10_000, 0), (1_000, 0, 0));
+        assert_eq!(split_fee_bps(1_000, 0, 0), (0, 0, 1_000));
+        assert_eq!(
+            split_fee_bps(u64::MAX, 5_000, 5_000),
+            (u64::MAX / 2, u64::MAX / 2, 1)
+        );
+
+        assert_eq!(check_fee_split_bps(7_000, 3_000), Ok(()));
+        assert_eq!(
+            check_fee_split_bps(DEFAULT_SIGNAL_PROVIDER_FEE_BPS, DEFAULT_BONFIDA_FEE_BPS),
+            Ok(())
+        );
+        assert_eq!(check_fee_split_bps(9_000, MIN_BONFIDA_FEE_BPS), Ok(()));
+        assert_eq!(
+            check_fee_split_bps(5_000, MIN_BONFIDA_FEE_BPS - 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_fee_split_bps(0, 0),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_fee_split_bps(7_000, 3_001),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_fee_split_bps(u16::MAX, u16::MAX),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_pool_closable() {
+        let mut pool_header = PoolHeader {
+            status: PoolStatus::Uninitialized,
+            ..test_pool_header()
+        };
+        assert!(check_pool_closable(&pool_header, 0).is_ok());
+        assert_eq!(
+            check_pool_closable(&pool_header, 1),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+
+        pool_header.status = PoolStatus::Unlocked;
+        assert_eq!(
+            check_pool_closable(&pool_header, 0),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+    }
+
+    #[test]
+    fn test_signal_provider_checks() {
+        let signal_provider = Pubkey::new_unique();
+        let pool_header = PoolHeader {
+            signal_provider,
+            ..test_pool_header()
+        };
+        assert!(verify_signal_provider_key(&pool_header, &signal_provider).is_ok());
+        assert_eq!(
+            verify_signal_provider_key(&pool_header, &Pubkey::new_unique()),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut signal_provider_account = AccountInfo::new(
+            &signal_provider,
+            false,
+            false,
+            &mut lamports,
+            &mut [],
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            check_signal_provider(&pool_header, &signal_provider_account),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        signal_provider_account.is_signer = true;
+        assert!(check_signal_provider(&pool_header, &signal_provider_account).is_ok());
+    }
+
+    #[test]
+    fn test_min_expected_lots() {
+        assert!(check_min_expected_lots(0, 0).is_ok());
+        assert!(check_min_expected_lots(10, 10).is_ok());
+        assert_eq!(
+            check_min_expected_lots(9, 10),
+            Err(BonfidaBotError::OperationTooSmall.into())
+        );
+    }
+
+    #[test]
+    fn test_open_orders_owner() {
+        let pool_key = Pubkey::new_unique();
+        let mut openorders_data = vec![0u8; 3228];
+        openorders_data[45..77].copy_from_slice(&pool_key.to_bytes());
+        assert!(check_open_orders_owner(&openorders_data, &pool_key).is_ok());
+        assert_eq!(
+            check_open_orders_owner(&openorders_data, &Pubkey::new_unique()),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_open_orders_owner(&openorders_data[..76], &pool_key),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+
+    #[test]
+    fn test_create_assets_checks() {
+        assert!(check_create_deposit_amounts(&[0, 10, 0]).is_ok());
+        assert_eq!(
+            check_create_deposit_amounts(&[0, 0, 0]),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_create_deposit_amounts(&[]),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        let mint = Pubkey::new_unique();
+        let pool_assets = vec![PoolAsset { mint_address: mint }];
+        assert!(check_new_pool_asset(&[], &mint).is_ok());
+        assert!(check_new_pool_asset(&pool_assets, &Pubkey::new_unique()).is_ok());
+        assert_eq!(
+            check_new_pool_asset(&pool_assets, &mint),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_split_referrer_fee() {
+        assert_eq!(split_referrer_fee(1_000, 0), (0, 1_000));
+        assert_eq!(split_referrer_fee(1_000, 2_500), (250, 750));
+        assert_eq!(split_referrer_fee(3, MAX_REFERRER_FEE_BPS), (1, 2));
+        assert_eq!(
+            split_referrer_fee(u64::MAX, MAX_REFERRER_FEE_BPS),
+            (u64::MAX / 2, u64::MAX - u64::MAX / 2)
+        );
+
+        assert!(check_referrer_fee_bps(0).is_ok());
+        assert!(check_referrer_fee_bps(MAX_REFERRER_FEE_BPS).is_ok());
+        assert_eq!(
+            check_referrer_fee_bps(MAX_REFERRER_FEE_BPS + 1),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_split_extra_fee() {
+        assert_eq!(split_extra_fee(1_000, 0), (0, 1_000));
+        assert_eq!(split_extra_fee(1_000, 1_000), (100, 900));
+        assert_eq!(split_extra_fee(3, MAX_EXTRA_FEE_BPS), (0, 3));
+        // The extra fee is carved out before the core split
+        let (extra_fee, rest) = split_extra_fee(1_000, MAX_EXTRA_FEE_BPS);
+        assert_eq!(extra_fee, 250);
+        assert_eq!(split_fee(rest), (375, 187, 188));
+
+        let recipient = Pubkey::new_unique();
+        assert!(check_extra_fee_recipient(&recipient, MAX_EXTRA_FEE_BPS).is_ok());
+        assert!(check_extra_fee_recipient(&Pubkey::default(), 0).is_ok());
+        assert_eq!(
+            check_extra_fee_recipient(&recipient, MAX_EXTRA_FEE_BPS + 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_extra_fee_recipient(&Pubkey::default(), 100),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_fida_minimum() {
+        assert!(check_fida_minimum(MIN_POOL_FIDA_AMOUNT).is_ok());
+        assert!(check_fida_minimum(u64::MAX).is_ok());
+        assert_eq!(
+            check_fida_minimum(MIN_POOL_FIDA_AMOUNT - 1),
+            Err(BonfidaBotError::NotEnoughFIDA.into())
+        );
+        // Pools without a FIDA asset hold none
+        assert_eq!(
+            check_fida_minimum(0),
+            Err(BonfidaBotError::NotEnoughFIDA.into())
+        );
+    }
+
+    #[test]
+    fn test_elapsed_fee_cycles() {
+        let pool_header = PoolHeader {
+            last_fee_collection_timestamp: 1_000_000,
+            ..test_pool_header()
+        };
+        assert_eq!(get_elapsed_fee_cycles(&pool_header, 1_000_000), 0);
+        assert_eq!(get_elapsed_fee_cycles(&pool_header, 1_604_799), 0);
+        assert_eq!(get_elapsed_fee_cycles(&pool_header, 1_604_800), 1);
+        assert_eq!(
+            get_elapsed_fee_cycles(&pool_header, 1_000_000 + 3 * 604_800 + 1),
+            3
+        );
+
+        // A last collection ahead of the clock doesn't underflow
+        assert_eq!(get_elapsed_fee_cycles(&pool_header, 999_999), 0);
+        assert_eq!(get_elapsed_fee_cycles(&pool_header, 0), 0);
+        assert_eq!(get_owed_fee_pooltokens(&pool_header, 1_000, 0), Ok(0));
+    }
+
+    #[test]
+    fn test_collect_fees_at() {
+        let fee_ratio = 655;
+        let period = 604_800;
+        let last_collection = 1_000_000;
+        let total_pooltokens = 1_000_000_000;
+        let get_pool_header = || PoolHeader {
+            fee_ratio,
+            last_fee_collection_timestamp: last_collection,
+            fee_collection_period: period,
+            deposit_fee_ratio: 0,
+            ..test_pool_header()
+        };
+        // A clock stub standing in for the clock sysvar read by the processor
+        let clock_at = |unix_timestamp: u64| Clock {
+            unix_timestamp: unix_timestamp as i64,
+            ..Clock::default()
+        };
+
+        for cycles in [1, 2, 5, 10, 52].iter() {
+            let current_timestamp =
+                clock_at(last_collection + cycles * period + 1).unix_timestamp as u64;
+            let mut pool_header = get_pool_header();
+            let owed =
+                get_owed_fee_pooltokens(&pool_header, total_pooltokens, current_timestamp).unwrap();
+            let minted =
+                collect_fees_at(&mut pool_header, total_pooltokens, current_timestamp).unwrap();
+            assert_eq!(minted, owed);
+            assert_eq!(
+                pool_header.last_fee_collection_timestamp,
+                last_collection + cycles * period
+            );
+
+            // Compounding the fee ratio over the cycles, within the fixed point precision
+            let feeless_ratio = (!fee_ratio) as f64 / 65_536.;
+            let reference = total_pooltokens as f64 * (feeless_ratio.powi(-(*cycles as i32)) - 1.);
+            assert!(((minted as f64 - reference) / reference).abs() < 2e-3);
+
+            // Nothing is left to collect until the next period
+            assert_eq!(
+                collect_fees_at(&mut pool_header, total_pooltokens, current_timestamp),
+                Err(BonfidaBotError::LockedOperation.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_pool_token_decimals() {
+        for decimals in 0..=MAX_POOL_TOKEN_DECIMALS {
+            assert!(check_pool_token_decimals(decimals).is_ok());
+        }
+        assert_eq!(
+            check_pool_token_decimals(MAX_POOL_TOKEN_DECIMALS + 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_pool_token_decimals(u8::MAX),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_deposit_cap() {
+        let mut pool_header = test_pool_header();
+        // No cap
+        assert!(check_deposit_cap(&pool_header, u64::MAX - 1, 1).is_ok());
+
+        pool_header.max_pooltoken_supply = 1_000;
+        assert!(check_deposit_cap(&pool_header, 900, 100).is_ok());
+        assert_eq!(
+            check_deposit_cap(&pool_header, 900, 101),
+            Err(BonfidaBotError::DepositCapReached.into())
+        );
+        assert_eq!(
+            check_deposit_cap(&pool_header, u64::MAX, 1),
+            Err(BonfidaBotError::Overflow.into())
+        );
+        // Supplies already above a lowered cap only block new deposits
+        assert_eq!(
+            check_deposit_cap(&pool_header, 2_000, 1),
+            Err(BonfidaBotError::DepositCapReached.into())
+        );
+    }
+
+    #[test]
+    fn test_untracked_mint() {
+        let pool_assets: Vec<PoolAsset> = (0..3)
+            .map(|_| PoolAsset {
+                mint_address: Pubkey::new_unique(),
+            })
+            .collect();
+        assert!(check_untracked_mint(&pool_assets, &Pubkey::new_unique()).is_ok());
+        assert!(check_untracked_mint(&[], &pool_assets[0].mint_address).is_ok());
+        // Registered assets cannot be swept
+        for pool_asset in pool_assets.iter() {
+            assert_eq!(
+                check_untracked_mint(&pool_assets, &pool_asset.mint_address),
+                Err(ProgramError::InvalidArgument)
+            );
+        }
+    }
+
+    #[test]
+    fn test_mint_authority() {
+        let pool_key = Pubkey::new_unique();
+        let mut pool_mint = Mint {
+            mint_authority: COption::Some(pool_key),
+            supply: 1_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        assert_eq!(check_mint_authority(&pool_mint, &pool_key), Ok(()));
+
+        pool_mint.mint_authority = COption::Some(Pubkey::new_unique());
+        assert_eq!(
+            check_mint_authority(&pool_mint, &pool_key),
+            Err(BonfidaBotError::InvalidMintAuthority.into())
+        );
+        pool_mint.mint_authority = COption::None;
+        assert_eq!(
+            check_mint_authority(&pool_mint, &pool_key),
+            Err(BonfidaBotError::InvalidMintAuthority.into())
+        );
+    }
+
+    #[test]
+    fn test_redeemed_amount() {
+        // The owed fees dilute the whole supply too, leaving the assets backing them
+        let total_pooltokens = 1_000_000;
+        let diluted_pooltokens = 1_000_003;
+        let pool_asset_amount = 999_999;
+        assert_eq!(
+            get_asset_share(total_pooltokens, pool_asset_amount, diluted_pooltokens),
+            Ok(999_996)
+        );
+        // Without fees owed, the whole supply is paid out the whole balance
+        assert_eq!(
+            get_asset_share(total_pooltokens, pool_asset_amount, total_pooltokens),
+            Ok(pool_asset_amount)
+        );
+        assert_eq!(get_asset_share(1, 1, 3), Ok(0));
+    }
+
+    #[test]
+    fn test_discount_account() {
+        let pool_key = Pubkey::new_unique();
+        let mut discount_account = Account {
+            mint: Pubkey::from_str(SRM_MINT).unwrap(),
+            owner: pool_key,
+            amount: 100,
+            state: AccountState::Initialized,
+            ..Account::default()
+        };
+        assert_eq!(check_discount_account(&discount_account, &pool_key), Ok(()));
+        discount_account.mint = Pubkey::from_str(MSRM_MINT).unwrap();
+        assert_eq!(check_discount_account(&discount_account, &pool_key), Ok(()));
+
+        discount_account.owner = Pubkey::new_unique();
+        assert_eq!(
+            check_discount_account(&discount_account, &pool_key),
+            Err(ProgramError::InvalidArgument)
+        );
+        discount_account.owner = pool_key;
+        discount_account.mint = Pubkey::new_unique();
+        assert_eq!(
+            check_discount_account(&discount_account, &pool_key),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_open_orders_balances() {
+        let mut openorders_data = vec![0u8; 3228];
+        for (i, amount) in [10u64, 20, 30, 40].iter().enumerate() {
+            openorders_data[77 + 8 * i..85 + 8 * i].copy_from_slice(&amount.to_le_bytes());
+        }
+        assert_eq!(
+            read_open_orders_balances(&openorders_data),
+            Ok(OpenOrdersBalances {
+                free_coin: 10,
+                total_coin: 20,
+                free_pc: 30,
+                total_pc: 40,
+            })
+        );
+        assert_eq!(
+            read_open_orders_balances(&openorders_data[..108]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert!(read_open_orders_balances(&openorders_data[..109]).is_ok());
+    }
+
+    #[test]
+    fn test_number_of_markets() {
+        assert_eq!(
+            check_number_of_markets(0),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(check_number_of_markets(1), Ok(()));
+        assert_eq!(check_number_of_markets(MAX_NUMBER_OF_MARKETS), Ok(()));
+        assert_eq!(
+            check_number_of_markets(MAX_NUMBER_OF_MARKETS + 1),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // A pool initialized for 2 markets and 3 assets
+        let pool_data_len = PoolHeader::LEN + 2 * PUBKEY_LENGTH + 3 * PoolAsset::LEN;
+        assert_eq!(check_pool_layout_fits(pool_data_len, 2, 3), Ok(()));
+        assert_eq!(check_pool_layout_fits(pool_data_len, 1, 3), Ok(()));
+        assert_eq!(
+            check_pool_layout_fits(pool_data_len, 3, 3),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_pool_layout_fits(pool_data_len, 2, 4),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_dex_program_update() {
+        let serum_program_id = Pubkey::from_str(SERUM_DEX_PROGRAM_IDS[1]).unwrap();
+        let mut pool_header = PoolHeader {
+            serum_program_id,
+            ..test_pool_header()
+        };
+        let new_program_id = Pubkey::from_str(SERUM_DEX_PROGRAM_IDS[2]).unwrap();
+        assert_eq!(
+            check_dex_program_update(&pool_header, &new_program_id),
+            Ok(())
+        );
+        // Programs outside of the known Serum deployments could take custody of the pool's funds
+        assert_eq!(
+            check_dex_program_update(&pool_header, &Pubkey::new_unique()),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_dex_program_update(&pool_header, &serum_program_id),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            check_dex_program_update(&pool_header, &Pubkey::default()),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // Orders pending on the current dex program block the update
+        pool_header.status = PoolStatus::Unlocked.begin_order().unwrap();
+        assert_eq!(
+            check_dex_program_update(&pool_header, &new_program_id),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+        pool_header.status = PoolStatus::Locked.begin_order().unwrap();
+        assert_eq!(
+            check_dex_program_update(&pool_header, &new_program_id),
+            Err(BonfidaBotError::LockedOperation.into())
+        );
+        pool_header.status = PoolStatus::Locked;
+        assert_eq!(
+            check_dex_program_update(&pool_header, &new_program_id),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_order_amounts_near_max_balance() {
+        let max_ratio = NonZeroU16::new(u16::MAX).unwrap();
+        let (amount_to_trade, lots_to_trade) =
+            get_order_amounts(u64::MAX, max_ratio, Side::Ask, 1, 1).unwrap();
+        assert_eq!(amount_to_trade, u64::MAX - (1 << 48));
+        assert_eq!(lots_to_trade, amount_to_trade);
+
+        let (amount_to_trade, lots_to_trade) =
+            get_order_amounts(u64::MAX, max_ratio, Side::Bid, 1, u64::MAX).unwrap();
+        assert_eq!(amount_to_trade, u64::MAX - (1 << 48));
+        assert_eq!(lots_to_trade, 0);
+
+        // A zero lot size doesn't divide
+        assert_eq!(
+            get_order_amounts(u64::MAX, max_ratio, Side::Ask, 0, 1),
+            Err(BonfidaBotError::Overflow.into())
+        );
+    }
+
+    #[test]
+    fn test_zero_asset_deposit() {
+        // The asset region of a pool without assets is left zeroed
+        let pool_data = vec![0u8; PoolHeader::LEN + PUBKEY_LENGTH + 4 * PoolAsset::LEN];
+        let pool_assets = unpack_pool_assets(&pool_data, 1).unwrap();
+        assert_eq!(
+            check_pool_has_assets(pool_assets.len()),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(check_pool_has_assets(1), Ok(()));
+        // Such a pool can't be created in the first place
+        assert_eq!(
+            check_create_deposit_amounts(&[0, 0]),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_fee_pooltokens() {
+        assert_eq!(get_fee_pooltokens(1_000, 1 << 15), Ok(999));
+        assert_eq!(get_fee_pooltokens(0, 1), Ok(0));
+        assert_eq!(
+            get_fee_pooltokens(1_000, 0),
+            Err(BonfidaBotError::Overflow.into())
+        );
+
+        // A large supply with a collapsed feeless ratio no longer wraps around on the cast
+        assert_eq!(
+            get_fee_pooltokens(u64::MAX / 2, 1),
+            Err(BonfidaBotError::Overflow.into())
+        );
+        assert_eq!(get_fee_pooltokens(1 << 40, 1), Ok(65_534 << 40));
+
+        // Many overdue cycles at the maximum fee ratio, with a large supply
+        let mut pool_header = PoolHeader {
+            fee_ratio: u16::MAX - 1,
+            last_fee_collection_timestamp: 0,
+            fee_collection_period: 1,
+            deposit_fee_ratio: 0,
+            ..test_pool_header()
+        };
+        assert_eq!(
+            collect_fees_at(&mut pool_header, u64::MAX / 2, 1_000_000),
+            Err(BonfidaBotError::Overflow.into())
+        );
+        assert_eq!(
+            get_owed_fee_pooltokens(&pool_header, u64::MAX / 2, 1_000_000),
+            Err(BonfidaBotError::Overflow.into())
+        );
+        assert!(collect_fees_at(&mut pool_header, 1_000_000, 1_000_000).unwrap() > 1_000_000);
+    }
+
+    #[test]
+    fn test_single_asset_deposits_allowed() {
+        let mut pool_header = PoolHeader {
+            fee_ratio: 0,
+            last_fee_collection_timestamp: 0,
+            deposit_fee_ratio: 0,
+            ..test_pool_header()
+        };
+        assert_eq!(
+            check_single_asset_deposits_allowed(&pool_header),
+            Err(BonfidaBotError::ModeRestricted.into())
+        );
+        pool_header.single_asset_deposits = true;
+        assert!(check_single_asset_deposits_allowed(&pool_header).is_ok());
+    }
+
+    #[test]
+    fn test_fee_pt_accounts() {
+        let pool_header = PoolHeader {
+            fee_ratio: 0,
+            last_fee_collection_timestamp: 0,
+            deposit_fee_ratio: 0,
+            ..test_pool_header()
+        };
+        let pool_mint_key = Pubkey::new_unique();
+        let signal_provider_pt_key =
+            get_associated_token_address(&pool_header.signal_provider, &pool_mint_key);
+        let bonfida_fee_pt_key =
+            get_associated_token_address(&Pubkey::from_str(BONFIDA_FEE).unwrap(), &pool_mint_key);
+        let bonfida_bnb_pt_key =
+            get_associated_token_address(&Pubkey::from_str(BONFIDA_BNB).unwrap(), &pool_mint_key);
+        assert!(check_fee_pt_accounts(
+            &pool_header,
+            &pool_mint_key,
+            &signal_provider_pt_key,
+            &bonfida_fee_pt_key,
+            &bonfida_bnb_pt_key
+        )
+        .is_ok());
+
+        // Fee accounts which are not the associated token accounts, or swapped ones
+        let other_key = Pubkey::new_unique();
+        for (sp_key, fee_key, bnb_key) in [
+            (&other_key, &bonfida_fee_pt_key, &bonfida_bnb_pt_key),
+            (&signal_provider_pt_key, &other_key, &bonfida_bnb_pt_key),
+            (&signal_provider_pt_key, &bonfida_fee_pt_key, &other_key),
+            (
+                &signal_provider_pt_key,
+                &bonfida_bnb_pt_key,
+                &bonfida_fee_pt_key,
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                check_fee_pt_accounts(&pool_header, &pool_mint_key, sp_key, fee_key, bnb_key),
+                Err(ProgramError::InvalidArgument)
+            );
+        }
+
+        // The associated token accounts of another pool mint
+        assert_eq!(
+            check_fee_pt_accounts(
+                &pool_header,
+                &Pubkey::new_unique(),
+                &signal_provider_pt_key,
+                &bonfida_fee_pt_key,
+                &bonfida_bnb_pt_key
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
     }
 }
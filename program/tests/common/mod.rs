@@ -0,0 +1,94 @@
+//! Helpers shared by the program tests. The accounts of a pool are written directly into the
+//! test bank, so that instructions can be tested without setting up Serum markets.
+use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey};
+use solana_program_test::BanksClient;
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+    transport::TransportError,
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+/// Lamports funding the test accounts, enough for any of them to be rent exempt
+pub const ACCOUNT_LAMPORTS: u64 = 1_000_000_000;
+
+/// Returns a pool seed for which both the pool and the pooltoken mint addresses exist
+pub fn find_pool_seed(program_id: &Pubkey) -> [u8; 32] {
+    loop {
+        let seed = Pubkey::new_unique().to_bytes();
+        if Pubkey::create_program_address(&[&seed], program_id).is_ok()
+            && Pubkey::create_program_address(&[&seed, &[1]], program_id).is_ok()
+        {
+            return seed;
+        }
+    }
+}
+
+pub fn program_account(program_id: &Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: ACCOUNT_LAMPORTS,
+        data,
+        owner: *program_id,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub fn mint_account(mint_authority: &Pubkey, supply: u64, decimals: u8) -> Account {
+    let mut data = vec![0; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(*mint_authority),
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .unwrap();
+    program_account(&spl_token::id(), data)
+}
+
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    program_account(&spl_token::id(), data)
+}
+
+pub async fn sign_send_instructions(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: Hash,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&payer.pubkey()));
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, recent_blockhash);
+    banks_client.process_transaction(transaction).await
+}
+
+pub async fn get_token_balance(banks_client: &mut BanksClient, key: &Pubkey) -> u64 {
+    let account = banks_client.get_account(*key).await.unwrap().unwrap();
+    TokenAccount::unpack(&account.data).unwrap().amount
+}
+
+pub async fn get_mint_supply(banks_client: &mut BanksClient, key: &Pubkey) -> u64 {
+    let account = banks_client.get_account(*key).await.unwrap().unwrap();
+    Mint::unpack(&account.data).unwrap().supply
+}
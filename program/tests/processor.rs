@@ -0,0 +1,1540 @@
+use std::{
+    num::{NonZeroU16, NonZeroU64, NonZeroU8},
+    str::FromStr,
+};
+
+use serum_dex::{
+    instruction::SelfTradeBehavior,
+    matching::{OrderType, Side},
+};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock, rent},
+};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+    transport::TransportError,
+};
+use solindex_bot::{
+    entrypoint::process_instruction,
+    error::BonfidaBotError,
+    instruction::{
+        add_allowed_mint, bootstrap_pool_instructions, cancel_order, collect_fees, create,
+        create_order, deposit, deposit_single, redeem, redeem_single, set_referrer_fee, snapshot,
+        sweep_dust, update_fee_period,
+    },
+    state::{
+        find_nav_snapshots_key, find_open_orders_key, pack_assets, unpack_nav_snapshots, PoolAsset,
+        PoolHeader, PoolMode, PoolStatus, BONFIDA_BNB, BONFIDA_FEE, METADATA_URI_LEN,
+        OPEN_ORDERS_ACCOUNT_LEN, POOL_HEADER_LENS, POOL_HEADER_VERSION, PUBKEY_LENGTH,
+    },
+    utils::{
+        get_owed_fee_pooltokens, DEFAULT_BONFIDA_FEE_BPS, DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+        MAX_FEE_CYCLES_PER_COLLECTION, MAX_FEE_RATIO, MIN_BONFIDA_FEE_BPS,
+        MIN_FEE_COLLECTION_PERIOD,
+    },
+};
+use spl_associated_token_account::get_associated_token_address;
+
+mod common;
+
+use common::{
+    find_pool_seed, get_mint_supply, get_token_balance, mint_account, program_account,
+    sign_send_instructions, token_account,
+};
+
+/// Maximum number of assets of the test pools, unless they start with more
+const ASSET_SLOTS: usize = 4;
+
+struct TestPool {
+    program_id: Pubkey,
+    dex_program_id: Pubkey,
+    market: Pubkey,
+    seed: [u8; 32],
+    key: Pubkey,
+    mint_key: Pubkey,
+    asset_mints: Vec<Pubkey>,
+    signal_provider: Keypair,
+}
+
+impl TestPool {
+    fn asset_key(&self, asset_index: usize) -> Pubkey {
+        get_associated_token_address(&self.key, &self.asset_mints[asset_index])
+    }
+}
+
+/// An unlocked pool on one market, whose fees aren't due before the end of the test
+fn pool_header(pool: &TestPool) -> PoolHeader {
+    PoolHeader {
+        serum_program_id: pool.dex_program_id,
+        seed: pool.seed,
+        signal_provider: pool.signal_provider.pubkey(),
+        status: PoolStatus::Unlocked,
+        number_of_markets: 1,
+        fee_ratio: 0,
+        last_fee_collection_timestamp: u32::MAX as u64,
+        fee_collection_period: 604_800,
+        deposit_fee_ratio: 0,
+        rebate_retention_ratio: 0,
+        pending_fee_ratio: 0,
+        pending_fee_ratio_timestamp: 0,
+        pool_mode: PoolMode::Normal,
+        redeem_requires_provider: false,
+        maturity_timestamp: 0,
+        version: POOL_HEADER_VERSION,
+        max_pooltoken_supply: 0,
+        extra_fee_recipient: Pubkey::default(),
+        extra_fee_bps: 0,
+        signal_provider_fee_bps: DEFAULT_SIGNAL_PROVIDER_FEE_BPS,
+        bonfida_fee_bps: DEFAULT_BONFIDA_FEE_BPS,
+        single_asset_deposits: false,
+        metadata_uri: [0u8; METADATA_URI_LEN],
+        referrer_fee_bps: 0,
+    }
+}
+
+/// Draws the keys of a pool on `number_of_assets` new assets
+fn new_test_pool(program_id: &Pubkey, number_of_assets: usize) -> TestPool {
+    let seed = find_pool_seed(program_id);
+    TestPool {
+        program_id: *program_id,
+        dex_program_id: Pubkey::new_unique(),
+        market: Pubkey::new_unique(),
+        seed,
+        key: Pubkey::create_program_address(&[&seed], program_id).unwrap(),
+        mint_key: Pubkey::create_program_address(&[&seed, &[1]], program_id).unwrap(),
+        asset_mints: (0..number_of_assets)
+            .map(|_| Pubkey::new_unique())
+            .collect(),
+        signal_provider: Keypair::new(),
+    }
+}
+
+/// Writes a pool holding `balances` of new assets, with a pooltoken supply of `supply`
+fn add_pool(
+    program_test: &mut ProgramTest,
+    program_id: &Pubkey,
+    header: impl Fn(&TestPool) -> PoolHeader,
+    balances: &[u64],
+    supply: u64,
+) -> TestPool {
+    let pool = new_test_pool(program_id, balances.len());
+
+    let asset_offset = PoolHeader::LEN + PUBKEY_LENGTH;
+    let asset_slots = std::cmp::max(ASSET_SLOTS, balances.len());
+    let mut data = vec![0; asset_offset + asset_slots * PoolAsset::LEN];
+    header(&pool).pack_into_slice(&mut data[..PoolHeader::LEN]);
+    data[PoolHeader::LEN..asset_offset].copy_from_slice(&pool.market.to_bytes());
+    let assets: Vec<PoolAsset> = pool
+        .asset_mints
+        .iter()
+        .map(|mint_address| PoolAsset {
+            mint_address: *mint_address,
+        })
+        .collect();
+    pack_assets(&mut data[asset_offset..], &assets).unwrap();
+    program_test.add_account(pool.key, program_account(program_id, data));
+
+    program_test.add_account(pool.mint_key, mint_account(&pool.key, supply, 6));
+    for (asset_index, balance) in balances.iter().enumerate() {
+        program_test.add_account(
+            pool.asset_key(asset_index),
+            token_account(&pool.asset_mints[asset_index], &pool.key, *balance),
+        );
+    }
+    pool
+}
+
+/// Gives `owner` a token account holding `amount` of `mint`
+fn add_token_account(
+    program_test: &mut ProgramTest,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    amount: u64,
+) -> Pubkey {
+    let key = Pubkey::new_unique();
+    program_test.add_account(key, token_account(mint, owner, amount));
+    key
+}
+
+/// Gives the signal provider and Bonfida the pooltoken accounts receiving the fees of `pool`
+fn add_fee_accounts(program_test: &mut ProgramTest, pool: &TestPool) {
+    for owner in [
+        pool.signal_provider.pubkey(),
+        Pubkey::from_str(BONFIDA_FEE).unwrap(),
+        Pubkey::from_str(BONFIDA_BNB).unwrap(),
+    ]
+    .iter()
+    {
+        program_test.add_account(
+            get_associated_token_address(owner, &pool.mint_key),
+            token_account(&pool.mint_key, owner, 0),
+        );
+    }
+}
+
+fn new_program_test(program_id: &Pubkey) -> ProgramTest {
+    ProgramTest::new("solindex_bot", *program_id, processor!(process_instruction))
+}
+
+/// Redeems `pool_token_amount` pooltokens of `redeemer` into the asset at `asset_index`
+fn redeem_single_instruction(
+    pool: &TestPool,
+    redeemer: &Pubkey,
+    pooltoken_key: &Pubkey,
+    target_key: &Pubkey,
+    pool_token_amount: u64,
+    asset_index: usize,
+) -> Instruction {
+    let other_pool_asset_keys: Vec<Pubkey> = (0..pool.asset_mints.len())
+        .filter(|i| *i != asset_index)
+        .map(|i| pool.asset_key(i))
+        .collect();
+    redeem_single(
+        &spl_token::id(),
+        &clock::id(),
+        &pool.program_id,
+        &pool.mint_key,
+        &pool.key,
+        &pool.asset_key(asset_index),
+        redeemer,
+        pooltoken_key,
+        target_key,
+        pool.seed,
+        pool_token_amount,
+        asset_index as u64,
+        &other_pool_asset_keys,
+        None,
+    )
+    .unwrap()
+}
+
+/// Sets up a pool on one asset through `Init` and `Create`, the creator paying for the accounts
+async fn bootstrap_pool(
+    program_id: &Pubkey,
+    fee_ratio: u16,
+    deposit_fee_ratio: u16,
+    fee_split_bps: Option<(u16, u16)>,
+) -> Result<(), TransportError> {
+    let mut program_test = new_program_test(program_id);
+    let creator = Keypair::new();
+    let asset_mint = Pubkey::new_unique();
+    program_test.add_account(asset_mint, mint_account(&Pubkey::new_unique(), 1_000, 6));
+    let source_asset_key =
+        add_token_account(&mut program_test, &asset_mint, &creator.pubkey(), 1_000);
+    program_test.add_account(
+        creator.pubkey(),
+        program_account(&system_program::id(), vec![]),
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instructions = bootstrap_pool_instructions(
+        program_id,
+        &creator.pubkey(),
+        find_pool_seed(program_id),
+        ASSET_SLOTS as u32,
+        &[asset_mint],
+        &creator.pubkey(),
+        &vec![source_asset_key],
+        &Pubkey::new_unique(),
+        &Keypair::new().pubkey(),
+        MIN_FEE_COLLECTION_PERIOD,
+        fee_ratio,
+        Some(deposit_fee_ratio),
+        0,
+        PoolMode::Normal,
+        false,
+        0,
+        fee_split_bps,
+        vec![1_000],
+        vec![Pubkey::new_unique()],
+    )
+    .unwrap();
+    for instruction in instructions {
+        sign_send_instructions(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &[instruction],
+            &[&creator],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_caps_deposit_fee_ratio() {
+    let program_id = Pubkey::new_unique();
+    bootstrap_pool(&program_id, 0, MAX_FEE_RATIO, None)
+        .await
+        .unwrap();
+    assert!(bootstrap_pool(&program_id, 0, MAX_FEE_RATIO + 1, None)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_create_keeps_a_minimum_bonfida_fee_share() {
+    let program_id = Pubkey::new_unique();
+    bootstrap_pool(&program_id, 0, 0, Some((9_000, MIN_BONFIDA_FEE_BPS)))
+        .await
+        .unwrap();
+    assert!(bootstrap_pool(
+        &program_id,
+        0,
+        0,
+        Some((10_000 - MIN_BONFIDA_FEE_BPS + 1, MIN_BONFIDA_FEE_BPS))
+    )
+    .await
+    .is_err());
+    assert!(
+        bootstrap_pool(&program_id, 0, 0, Some((5_000, MIN_BONFIDA_FEE_BPS - 1)))
+            .await
+            .is_err()
+    );
+    assert!(bootstrap_pool(&program_id, 0, 0, Some((0, 0)))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_redeem_single_pays_the_whole_share() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // The second asset was traded away entirely
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        pool_header,
+        &[1_000, 0],
+        100,
+    );
+    let redeemer = Keypair::new();
+    let pooltoken_key =
+        add_token_account(&mut program_test, &pool.mint_key, &redeemer.pubkey(), 50);
+    let target_key = add_token_account(
+        &mut program_test,
+        &pool.asset_mints[0],
+        &redeemer.pubkey(),
+        0,
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[redeem_single_instruction(
+            &pool,
+            &redeemer.pubkey(),
+            &pooltoken_key,
+            &target_key,
+            50,
+            0,
+        )],
+        &[&redeemer],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(get_token_balance(&mut banks_client, &target_key).await, 500);
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pool.asset_key(0)).await,
+        500
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pooltoken_key).await,
+        0
+    );
+    assert_eq!(get_mint_supply(&mut banks_client, &pool.mint_key).await, 50);
+}
+
+#[tokio::test]
+async fn test_redeem_single_refuses_pools_holding_other_assets() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        pool_header,
+        &[1_000, 500],
+        100,
+    );
+    let redeemer = Keypair::new();
+    let pooltoken_key =
+        add_token_account(&mut program_test, &pool.mint_key, &redeemer.pubkey(), 50);
+    let target_key = add_token_account(
+        &mut program_test,
+        &pool.asset_mints[0],
+        &redeemer.pubkey(),
+        0,
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // The redeemer would forfeit their share of the second asset
+    assert!(sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[redeem_single_instruction(
+            &pool,
+            &redeemer.pubkey(),
+            &pooltoken_key,
+            &target_key,
+            50,
+            0,
+        )],
+        &[&redeemer],
+    )
+    .await
+    .is_err());
+
+    assert_eq!(get_token_balance(&mut banks_client, &target_key).await, 0);
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pooltoken_key).await,
+        50
+    );
+}
+
+#[tokio::test]
+async fn test_snapshot_into_a_prefunded_account() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        pool_header,
+        &[1_000, 500],
+        2_000_000,
+    );
+    program_test.add_account(
+        pool.signal_provider.pubkey(),
+        program_account(&system_program::id(), vec![]),
+    );
+    // Lamports sent to the snapshots address ahead of its creation don't prevent it
+    let (nav_snapshots_key, _) = find_nav_snapshots_key(&program_id, &pool.key);
+    program_test.add_account(
+        nav_snapshots_key,
+        program_account(&system_program::id(), vec![]),
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    for prices in [vec![2, 3], vec![4, 6]].iter() {
+        let instruction = snapshot(
+            &program_id,
+            &system_program::id(),
+            &clock::id(),
+            &rent::id(),
+            &pool.signal_provider.pubkey(),
+            &pool.key,
+            &pool.mint_key,
+            &vec![pool.asset_key(0), pool.asset_key(1)],
+            pool.seed,
+            prices.clone(),
+        )
+        .unwrap();
+        sign_send_instructions(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &[instruction],
+            &[&pool.signal_provider],
+        )
+        .await
+        .unwrap();
+    }
+
+    let nav_snapshots_account = banks_client
+        .get_account(nav_snapshots_key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(nav_snapshots_account.owner, program_id);
+    let nav_per_token: Vec<u64> = unpack_nav_snapshots(&nav_snapshots_account.data)
+        .unwrap()
+        .iter()
+        .map(|snapshot| snapshot.nav_per_token)
+        .collect();
+    // Two whole pooltokens of 6 decimals share the value of the assets
+    assert_eq!(nav_per_token, vec![1_750, 3_500]);
+}
+
+#[tokio::test]
+async fn test_update_fee_period_keeps_the_cycle_in_progress() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // A long first cycle, which started well before the current time
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        |pool| PoolHeader {
+            last_fee_collection_timestamp: 1,
+            fee_collection_period: u32::MAX as u64,
+            ..pool_header(pool)
+        },
+        &[1_000],
+        100,
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let new_period = 2 * MIN_FEE_COLLECTION_PERIOD;
+    let instruction = update_fee_period(
+        &program_id,
+        &clock::id(),
+        &pool.signal_provider.pubkey(),
+        &pool.key,
+        pool.seed,
+        new_period,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await
+    .unwrap();
+
+    // The time accrued since the last collection is still owed under the new period
+    let pool_account = banks_client.get_account(pool.key).await.unwrap().unwrap();
+    let header = PoolHeader::unpack(&pool_account.data[..PoolHeader::LEN]).unwrap();
+    assert_eq!(header.fee_collection_period, new_period);
+    assert_eq!(header.last_fee_collection_timestamp, 1);
+}
+
+/// Places an order of the first asset of `pool` for the target mint at `target_index`
+fn create_order_instruction(
+    pool: &TestPool,
+    target_mint: &Pubkey,
+    target_index: u64,
+) -> Instruction {
+    create_order(
+        &pool.program_id,
+        &pool.signal_provider.pubkey(),
+        &pool.market,
+        &pool.asset_key(0),
+        0,
+        target_index,
+        &find_open_orders_key(&pool.program_id, &pool.key, &pool.market).0,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &pool.key,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &spl_token::id(),
+        &pool.dex_program_id,
+        &rent::id(),
+        None,
+        pool.seed,
+        Side::Ask,
+        NonZeroU64::new(1).unwrap(),
+        0,
+        1,
+        1,
+        target_mint,
+        NonZeroU16::new(u16::MAX).unwrap(),
+        OrderType::ImmediateOrCancel,
+        0,
+        SelfTradeBehavior::DecrementTake,
+        u16::MAX,
+        0,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_create_order_checks_new_targets_against_the_allowed_mints() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    let pool = add_pool(&mut program_test, &program_id, pool_header, &[1_000], 100);
+    program_test.add_account(
+        pool.signal_provider.pubkey(),
+        program_account(&system_program::id(), vec![]),
+    );
+    program_test.add_account(
+        find_open_orders_key(&program_id, &pool.key, &pool.market).0,
+        program_account(&pool.dex_program_id, vec![0; OPEN_ORDERS_ACCOUNT_LEN]),
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let allowed_mint = Pubkey::new_unique();
+    let instruction = add_allowed_mint(
+        &program_id,
+        &system_program::id(),
+        &rent::id(),
+        &pool.signal_provider.pubkey(),
+        &pool.key,
+        pool.seed,
+        &allowed_mint,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await
+    .unwrap();
+
+    // The order is refused before reaching the dex
+    let disallowed_mint = Pubkey::new_unique();
+    let result = sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[create_order_instruction(&pool, &disallowed_mint, 1)],
+        &[&pool.signal_provider],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BonfidaBotError::MintNotAllowed as u32)
+        )
+    );
+
+    // Leaving out the allowed mints account doesn't bypass the allow-list
+    let mut instruction = create_order_instruction(&pool, &disallowed_mint, 2);
+    instruction.accounts.pop();
+    let result = sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+}
+
+/// Stands in for the dex program, cancelling an order by freeing all the funds of the
+/// OpenOrders account, told apart from the other accounts by its length
+fn process_dex_cancel(_: &Pubkey, accounts: &[AccountInfo], _: &[u8]) -> ProgramResult {
+    let openorders_account = accounts
+        .iter()
+        .find(|account| account.data_len() == OPEN_ORDERS_ACCOUNT_LEN)
+        .unwrap();
+    let mut data = openorders_account.data.borrow_mut();
+    // The free and total coin amounts, then the free and total pc amounts
+    let (total_coin, total_pc) = (data[85..93].to_vec(), data[101..109].to_vec());
+    data[77..85].copy_from_slice(&total_coin);
+    data[93..101].copy_from_slice(&total_pc);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_the_only_pending_order_unlocks_the_pool() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        |pool| PoolHeader {
+            status: PoolStatus::PendingOrder(NonZeroU8::new(1).unwrap()),
+            ..pool_header(pool)
+        },
+        &[1_000],
+        100,
+    );
+    program_test.add_program(
+        "serum_dex",
+        pool.dex_program_id,
+        processor!(process_dex_cancel),
+    );
+    // The pool's OpenOrders account, with half of its 1_000 coins on the book
+    let openorders_key = find_open_orders_key(&program_id, &pool.key, &pool.market).0;
+    let mut openorders_data = vec![0; OPEN_ORDERS_ACCOUNT_LEN];
+    openorders_data[45..77].copy_from_slice(&pool.key.to_bytes());
+    openorders_data[77..85].copy_from_slice(&500u64.to_le_bytes());
+    openorders_data[85..93].copy_from_slice(&1_000u64.to_le_bytes());
+    program_test.add_account(
+        openorders_key,
+        program_account(&pool.dex_program_id, openorders_data),
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = cancel_order(
+        &program_id,
+        &pool.signal_provider.pubkey(),
+        &pool.market,
+        &openorders_key,
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &pool.key,
+        &pool.dex_program_id,
+        pool.seed,
+        Side::Ask,
+        1,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await
+    .unwrap();
+
+    // The freed coins are left to be settled into the pool
+    let pool_account = banks_client.get_account(pool.key).await.unwrap().unwrap();
+    assert_eq!(
+        PoolHeader::unpack(&pool_account.data[..PoolHeader::LEN])
+            .unwrap()
+            .status,
+        PoolStatus::Unlocked
+    );
+}
+
+#[tokio::test]
+async fn test_redeem_from_a_full_pool_of_the_unversioned_layout() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // A pool account written by the program before the header was versioned, every asset
+    // slot used
+    let pool = new_test_pool(&program_id, 2);
+    let legacy_len = POOL_HEADER_LENS[0];
+    let asset_offset = legacy_len + PUBKEY_LENGTH;
+    let mut packed_header = vec![0; PoolHeader::LEN];
+    pool_header(&pool).pack_into_slice(&mut packed_header);
+    let mut data = vec![0; asset_offset + pool.asset_mints.len() * PoolAsset::LEN];
+    data[..legacy_len].copy_from_slice(&packed_header[..legacy_len]);
+    data[legacy_len..asset_offset].copy_from_slice(&pool.market.to_bytes());
+    let assets: Vec<PoolAsset> = pool
+        .asset_mints
+        .iter()
+        .map(|mint_address| PoolAsset {
+            mint_address: *mint_address,
+        })
+        .collect();
+    pack_assets(&mut data[asset_offset..], &assets).unwrap();
+    program_test.add_account(pool.key, program_account(&program_id, data));
+    program_test.add_account(pool.mint_key, mint_account(&pool.key, 100, 6));
+    for asset_index in 0..2 {
+        program_test.add_account(
+            pool.asset_key(asset_index),
+            token_account(&pool.asset_mints[asset_index], &pool.key, 1_000),
+        );
+    }
+    add_fee_accounts(&mut program_test, &pool);
+    let owner = Keypair::new();
+    let pooltoken_key = add_token_account(&mut program_test, &pool.mint_key, &owner.pubkey(), 50);
+    let user_asset_keys: Vec<Pubkey> = pool
+        .asset_mints
+        .iter()
+        .map(|mint| add_token_account(&mut program_test, mint, &owner.pubkey(), 1_000))
+        .collect();
+    let pool_asset_keys = vec![pool.asset_key(0), pool.asset_key(1)];
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // The pool can't take deposits anymore
+    let instruction = deposit(
+        &spl_token::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &pool_asset_keys,
+        &pooltoken_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        &owner.pubkey(),
+        &user_asset_keys,
+        pool.seed,
+        10,
+        None,
+        None,
+    )
+    .unwrap();
+    let result = sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&owner],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+
+    // But its pooltokens are still redeemed
+    let instruction = redeem(
+        &spl_token::id(),
+        &clock::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &pool_asset_keys,
+        &owner.pubkey(),
+        &pooltoken_key,
+        &user_asset_keys,
+        pool.seed,
+        50,
+        None,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    for (pool_asset_key, user_asset_key) in pool_asset_keys.iter().zip(user_asset_keys.iter()) {
+        assert_eq!(
+            get_token_balance(&mut banks_client, pool_asset_key).await,
+            500
+        );
+        assert_eq!(
+            get_token_balance(&mut banks_client, user_asset_key).await,
+            1_500
+        );
+    }
+    assert_eq!(get_mint_supply(&mut banks_client, &pool.mint_key).await, 50);
+}
+
+#[tokio::test]
+async fn test_sweep_dust_left_by_the_redemption_of_the_whole_supply() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // A management fee of 1/64 per period, overdue since the epoch
+    let header = |pool: &TestPool| PoolHeader {
+        fee_ratio: 1 << 10,
+        last_fee_collection_timestamp: 1,
+        ..pool_header(pool)
+    };
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        header,
+        &[999_999],
+        1_000_000,
+    );
+    let owner = Keypair::new();
+    let pooltoken_key = add_token_account(
+        &mut program_test,
+        &pool.mint_key,
+        &owner.pubkey(),
+        1_000_000,
+    );
+    let target_key = add_token_account(&mut program_test, &pool.asset_mints[0], &owner.pubkey(), 0);
+    let destination_key = add_token_account(
+        &mut program_test,
+        &pool.asset_mints[0],
+        &pool.signal_provider.pubkey(),
+        0,
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = redeem(
+        &spl_token::id(),
+        &clock::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &vec![pool.asset_key(0)],
+        &owner.pubkey(),
+        &pooltoken_key,
+        &vec![target_key],
+        pool.seed,
+        1_000_000,
+        None,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    // The owed fees dilute the redemption, which is rounded down. The collection is capped to
+    // `MAX_FEE_CYCLES_PER_COLLECTION` periods.
+    let overdue_timestamp = 1 + 604_800 * (MAX_FEE_CYCLES_PER_COLLECTION + 1);
+    let owed_fee_pooltokens =
+        get_owed_fee_pooltokens(&header(&pool), 1_000_000, overdue_timestamp).unwrap();
+    let redeemed_amount =
+        (999_999u128 * 1_000_000 / (1_000_000 + owed_fee_pooltokens) as u128) as u64;
+    assert_eq!(
+        get_token_balance(&mut banks_client, &target_key).await,
+        redeemed_amount
+    );
+    let dust = 999_999 - redeemed_amount;
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pool.asset_key(0)).await,
+        dust
+    );
+    let pool_account = banks_client.get_account(pool.key).await.unwrap().unwrap();
+    assert_eq!(
+        PoolHeader::unpack_unchecked(&pool_account.data[..PoolHeader::LEN])
+            .unwrap()
+            .status,
+        PoolStatus::Uninitialized
+    );
+
+    let instruction = sweep_dust(
+        &spl_token::id(),
+        &program_id,
+        &pool.signal_provider.pubkey(),
+        &pool.mint_key,
+        &pool.key,
+        &pool.asset_key(0),
+        &destination_key,
+        pool.seed,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, &destination_key).await,
+        dust
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pool.asset_key(0)).await,
+        0
+    );
+}
+
+/// Deposits the first asset of a single asset `pool` from `source_key` for `pool_token_amount`
+/// pooltokens
+fn deposit_instruction(
+    pool: &TestPool,
+    source_owner: &Pubkey,
+    source_key: &Pubkey,
+    target_key: &Pubkey,
+    pool_token_amount: u64,
+    referrer_key: Option<&Pubkey>,
+) -> Instruction {
+    deposit(
+        &spl_token::id(),
+        &pool.program_id,
+        &pool.mint_key,
+        &pool.key,
+        &vec![pool.asset_key(0)],
+        target_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        source_owner,
+        &vec![*source_key],
+        pool.seed,
+        pool_token_amount,
+        referrer_key,
+        None,
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_deposit_and_management_fees_apply_their_own_ratio() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // A deposit fee of 1/8 and a management fee of 1/64 per period, with fees overdue since
+    // the epoch
+    let header = |pool: &TestPool| PoolHeader {
+        fee_ratio: 1 << 10,
+        deposit_fee_ratio: 1 << 13,
+        last_fee_collection_timestamp: 1,
+        ..pool_header(pool)
+    };
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        header,
+        &[1_000_000],
+        1_000_000,
+    );
+    add_fee_accounts(&mut program_test, &pool);
+    let depositor = Keypair::new();
+    let source_key = add_token_account(
+        &mut program_test,
+        &pool.asset_mints[0],
+        &depositor.pubkey(),
+        1_000_000,
+    );
+    let target_key = add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[deposit_instruction(
+            &pool,
+            &depositor.pubkey(),
+            &source_key,
+            &target_key,
+            200_000,
+            None,
+        )],
+        &[&depositor],
+    )
+    .await
+    .unwrap();
+    // The deposit fee ratio alone applies to deposits
+    assert_eq!(
+        get_token_balance(&mut banks_client, &target_key).await,
+        175_000
+    );
+    assert_eq!(
+        get_mint_supply(&mut banks_client, &pool.mint_key).await,
+        1_200_000
+    );
+
+    let instruction = collect_fees(
+        &spl_token::id(),
+        &clock::id(),
+        &program_id,
+        &pool.key,
+        &pool.mint_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        pool.seed,
+        None,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[],
+    )
+    .await
+    .unwrap();
+    // The fee ratio alone applies to the collection, which is capped to
+    // `MAX_FEE_CYCLES_PER_COLLECTION` periods
+    let overdue_timestamp = 1 + 604_800 * (MAX_FEE_CYCLES_PER_COLLECTION + 1);
+    let management_fee =
+        get_owed_fee_pooltokens(&header(&pool), 1_200_000, overdue_timestamp).unwrap();
+    let fee_at_deposit_fee_ratio = get_owed_fee_pooltokens(
+        &PoolHeader {
+            fee_ratio: 1 << 13,
+            ..header(&pool)
+        },
+        1_200_000,
+        overdue_timestamp,
+    )
+    .unwrap();
+    assert!(management_fee < fee_at_deposit_fee_ratio);
+    assert_eq!(
+        get_mint_supply(&mut banks_client, &pool.mint_key).await,
+        1_200_000 + management_fee
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_pays_the_referrer_fee_set_by_the_signal_provider() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // A deposit fee of 1/8, half of which goes to the referrer
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        |pool| PoolHeader {
+            deposit_fee_ratio: 1 << 13,
+            ..pool_header(pool)
+        },
+        &[1_000_000],
+        1_000_000,
+    );
+    add_fee_accounts(&mut program_test, &pool);
+    let depositor = Keypair::new();
+    let source_key = add_token_account(
+        &mut program_test,
+        &pool.asset_mints[0],
+        &depositor.pubkey(),
+        1_000_000,
+    );
+    let target_key = add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let own_referrer_key =
+        add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let referrer_key =
+        add_token_account(&mut program_test, &pool.mint_key, &Pubkey::new_unique(), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = set_referrer_fee(
+        &program_id,
+        &pool.signal_provider.pubkey(),
+        &pool.key,
+        pool.seed,
+        5_000,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&pool.signal_provider],
+    )
+    .await
+    .unwrap();
+
+    // The depositor can't be their own referrer
+    for self_referrer_key in [target_key, own_referrer_key].iter() {
+        let result = sign_send_instructions(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &[deposit_instruction(
+                &pool,
+                &depositor.pubkey(),
+                &source_key,
+                &target_key,
+                200_000,
+                Some(self_referrer_key),
+            )],
+            &[&depositor],
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+        );
+    }
+
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[deposit_instruction(
+            &pool,
+            &depositor.pubkey(),
+            &source_key,
+            &target_key,
+            200_000,
+            Some(&referrer_key),
+        )],
+        &[&depositor],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        get_token_balance(&mut banks_client, &target_key).await,
+        175_000
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &referrer_key).await,
+        12_500
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &own_referrer_key).await,
+        0
+    );
+}
+
+/// Writes the market of `pool` trading its second asset for its first one, with a single order
+/// asking `ask_price` price lots of `pc_lot_size` per coin lot of `coin_lot_size`, and a single
+/// order bidding `bid_price` unless it is `None`. Returns the keys of the bids and asks accounts.
+fn add_market(
+    program_test: &mut ProgramTest,
+    pool: &TestPool,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+    bid_price: Option<u64>,
+    ask_price: u64,
+) -> (Pubkey, Pubkey) {
+    let (bids_key, asks_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+    // The fields of the serum market state read by the program
+    let mut market = vec![0; 388];
+    market[53..85].copy_from_slice(&pool.asset_mints[1].to_bytes());
+    market[85..117].copy_from_slice(&pool.asset_mints[0].to_bytes());
+    market[285..317].copy_from_slice(&bids_key.to_bytes());
+    market[317..349].copy_from_slice(&asks_key.to_bytes());
+    market[349..357].copy_from_slice(&coin_lot_size.to_le_bytes());
+    market[357..365].copy_from_slice(&pc_lot_size.to_le_bytes());
+    program_test.add_account(pool.market, program_account(&pool.dex_program_id, market));
+
+    // An order book whose root node is the leaf of its order, the price making up the upper
+    // half of the key
+    let order_book = |price: Option<u64>| {
+        let mut data = vec![0; 45 + 72];
+        if let Some(price) = price {
+            data[37..45].copy_from_slice(&1u64.to_le_bytes());
+            data[45..49].copy_from_slice(&2u32.to_le_bytes());
+            data[45 + 16..45 + 24].copy_from_slice(&price.to_le_bytes());
+        }
+        data
+    };
+    program_test.add_account(
+        asks_key,
+        program_account(&pool.dex_program_id, order_book(Some(ask_price))),
+    );
+    program_test.add_account(
+        bids_key,
+        program_account(&pool.dex_program_id, order_book(bid_price)),
+    );
+    (bids_key, asks_key)
+}
+
+#[tokio::test]
+async fn test_deposit_single_values_the_other_assets_at_the_market() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // The pool holds a single unit of its first asset and a billion units of the second one,
+    // which is asked at a millionth of the first and bid a little lower
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        |pool| PoolHeader {
+            single_asset_deposits: true,
+            ..pool_header(pool)
+        },
+        &[1, 1_000_000_000],
+        1_000_000_000,
+    );
+    add_fee_accounts(&mut program_test, &pool);
+    let (bids_key, asks_key) = add_market(&mut program_test, &pool, 10_000_000, 1, Some(9), 10);
+    let depositor = Keypair::new();
+    let source_keys: Vec<Pubkey> = pool
+        .asset_mints
+        .iter()
+        .map(|mint| add_token_account(&mut program_test, mint, &depositor.pubkey(), 10))
+        .collect();
+    let target_key = add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let deposit_single_instruction = |price_market_keys: &[(Pubkey, Pubkey, Pubkey)]| {
+        deposit_single(
+            &spl_token::id(),
+            &program_id,
+            &pool.mint_key,
+            &pool.key,
+            &vec![pool.asset_key(0), pool.asset_key(1)],
+            &target_key,
+            &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+            &depositor.pubkey(),
+            &source_keys,
+            pool.seed,
+            0,
+            1,
+            None,
+            price_market_keys,
+        )
+        .unwrap()
+    };
+
+    // The second asset has to be valued on one of the pool's markets
+    for (price_market_keys, error) in [
+        (vec![], InstructionError::NotEnoughAccountKeys),
+        (
+            vec![(asks_key, bids_key, asks_key)],
+            InstructionError::InvalidArgument,
+        ),
+        (
+            vec![(pool.market, asks_key, bids_key)],
+            InstructionError::InvalidArgument,
+        ),
+    ]
+    .iter()
+    {
+        let result = sign_send_instructions(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &[deposit_single_instruction(price_market_keys)],
+            &[&depositor],
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, error.clone())
+        );
+    }
+
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[deposit_single_instruction(&[(
+            pool.market,
+            bids_key,
+            asks_key,
+        )])],
+        &[&depositor],
+    )
+    .await
+    .unwrap();
+
+    // The pool is worth 1 + 1_000 units of the first asset, a unit buys 1/1_001 of the supply
+    // rather than half of it
+    assert_eq!(
+        get_token_balance(&mut banks_client, &target_key).await,
+        1_000_000_000 / 1_001
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pool.asset_key(0)).await,
+        2
+    );
+    assert_eq!(
+        get_token_balance(&mut banks_client, &pool.asset_key(1)).await,
+        1_000_000_000
+    );
+}
+
+#[tokio::test]
+async fn test_deposit_single_needs_orders_on_both_sides_of_the_book() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    // Nothing bounds the ask of the second asset, the depositor's own order could set it
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        |pool| PoolHeader {
+            single_asset_deposits: true,
+            ..pool_header(pool)
+        },
+        &[1, 1_000_000_000],
+        1_000_000_000,
+    );
+    add_fee_accounts(&mut program_test, &pool);
+    let (bids_key, asks_key) = add_market(&mut program_test, &pool, 10_000_000, 1, None, 1);
+    let depositor = Keypair::new();
+    let source_keys: Vec<Pubkey> = pool
+        .asset_mints
+        .iter()
+        .map(|mint| add_token_account(&mut program_test, mint, &depositor.pubkey(), 10))
+        .collect();
+    let target_key = add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = deposit_single(
+        &spl_token::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &vec![pool.asset_key(0), pool.asset_key(1)],
+        &target_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        &depositor.pubkey(),
+        &source_keys,
+        pool.seed,
+        0,
+        1,
+        None,
+        &[(pool.market, bids_key, asks_key)],
+    )
+    .unwrap();
+    let result = sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&depositor],
+    )
+    .await;
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+    );
+    assert_eq!(get_token_balance(&mut banks_client, &target_key).await, 0);
+}
+
+#[tokio::test]
+async fn test_short_account_lists_are_caught_upfront() {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = new_program_test(&program_id);
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        pool_header,
+        &[1_000_000, 1_000_000],
+        1_000_000,
+    );
+    let owner = Keypair::new();
+    let user_asset_keys: Vec<Pubkey> = pool
+        .asset_mints
+        .iter()
+        .map(|mint| add_token_account(&mut program_test, mint, &owner.pubkey(), 1_000_000))
+        .collect();
+    let pool_token_key = add_token_account(
+        &mut program_test,
+        &pool.mint_key,
+        &owner.pubkey(),
+        1_000_000,
+    );
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let pool_asset_keys = vec![pool.asset_key(0), pool.asset_key(1)];
+    let create_instruction = create(
+        &spl_token::id(),
+        &clock::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        pool.seed,
+        &pool_asset_keys,
+        &pool_token_key,
+        &owner.pubkey(),
+        &user_asset_keys,
+        &pool.dex_program_id,
+        &pool.signal_provider.pubkey(),
+        MIN_FEE_COLLECTION_PERIOD,
+        0,
+        None,
+        0,
+        PoolMode::Normal,
+        false,
+        0,
+        None,
+        vec![1_000, 1_000],
+        vec![pool.market],
+    )
+    .unwrap();
+    let deposit_instruction = deposit(
+        &spl_token::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &pool_asset_keys,
+        &pool_token_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        &owner.pubkey(),
+        &user_asset_keys,
+        pool.seed,
+        1_000,
+        None,
+        None,
+    )
+    .unwrap();
+    let redeem_instruction = redeem(
+        &spl_token::id(),
+        &clock::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &pool_asset_keys,
+        &owner.pubkey(),
+        &pool_token_key,
+        &user_asset_keys,
+        pool.seed,
+        1_000,
+        None,
+    )
+    .unwrap();
+
+    // Without the last asset account, the other accounts would be bound to the wrong assets
+    for mut instruction in vec![create_instruction, deposit_instruction, redeem_instruction] {
+        instruction.accounts.pop();
+        let result = sign_send_instructions(
+            &mut banks_client,
+            &payer,
+            recent_blockhash,
+            &[instruction],
+            &[&owner],
+        )
+        .await;
+        assert_eq!(
+            result.unwrap_err().unwrap(),
+            TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+        );
+    }
+}
+
+/// Number of assets of the pool on which deposits are benchmarked
+#[cfg(feature = "test-bpf")]
+const BENCH_ASSETS: usize = 20;
+
+/// Deposits `pool_token_amount` pooltokens into a pool on `BENCH_ASSETS` assets with the BPF
+/// build of the program, within a budget of `compute_max_units`
+#[cfg(feature = "test-bpf")]
+async fn bench_deposit(
+    compute_max_units: u64,
+    pool_token_amount: u64,
+) -> Result<(), TransportError> {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("solindex_bot", program_id, None);
+    program_test.set_bpf_compute_max_units(compute_max_units);
+    let pool = add_pool(
+        &mut program_test,
+        &program_id,
+        pool_header,
+        &[1_000_000; BENCH_ASSETS],
+        1_000_000_000_000,
+    );
+    add_fee_accounts(&mut program_test, &pool);
+    let depositor = Keypair::new();
+    let source_keys: Vec<Pubkey> = pool
+        .asset_mints
+        .iter()
+        .map(|mint| add_token_account(&mut program_test, mint, &depositor.pubkey(), 1_000_000))
+        .collect();
+    let target_key = add_token_account(&mut program_test, &pool.mint_key, &depositor.pubkey(), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = deposit(
+        &spl_token::id(),
+        &program_id,
+        &pool.mint_key,
+        &pool.key,
+        &(0..BENCH_ASSETS)
+            .map(|i| pool.asset_key(i))
+            .collect::<Vec<Pubkey>>(),
+        &target_key,
+        &get_associated_token_address(&pool.signal_provider.pubkey(), &pool.mint_key),
+        &depositor.pubkey(),
+        &source_keys,
+        pool.seed,
+        pool_token_amount,
+        None,
+        None,
+    )
+    .unwrap();
+    sign_send_instructions(
+        &mut banks_client,
+        &payer,
+        recent_blockhash,
+        &[instruction],
+        &[&depositor],
+    )
+    .await
+}
+
+/// Deposits rounding to zero are rejected before the transfer loop of a pool on
+/// `BENCH_ASSETS` assets, run with `cargo test-bpf --features test-bpf`
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_deposit_rounding_to_zero_on_many_assets() {
+    // A thousandth of a millionth of the supply rounds down to nothing of each asset
+    assert_eq!(
+        bench_deposit(1_000_000, 1_000).await.unwrap_err().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BonfidaBotError::OperationTooSmall as u32)
+        )
+    );
+    bench_deposit(1_000_000, 1_000_000_000).await.unwrap();
+}